@@ -0,0 +1,5 @@
+// the migrations are embedded into the binary, so adding one has to
+// trigger a rebuild even when no rust file changed
+fn main() {
+  println!("cargo:rerun-if-changed=migrations");
+}
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX IF EXISTS sessions_expires_at_idx;
+
+ALTER TABLE sessions
+DROP COLUMN expires_at;
@@ -0,0 +1,17 @@
+-- Your SQL goes here
+-- existing sessions get the default 30 day lifetime and 7 day idle timeout
+ALTER TABLE sessions
+ADD COLUMN expires_at BIGINT NOT NULL DEFAULT 0;
+
+UPDATE sessions
+SET
+  expires_at = LEAST(
+    created_at + 30 * 24 * 60 * 60 * 1000::BIGINT,
+    accessed_at + 7 * 24 * 60 * 60 * 1000::BIGINT
+  );
+
+ALTER TABLE sessions
+ALTER COLUMN expires_at
+DROP DEFAULT;
+
+CREATE INDEX sessions_expires_at_idx ON sessions (expires_at);
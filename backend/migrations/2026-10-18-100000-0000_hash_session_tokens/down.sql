@@ -0,0 +1,9 @@
+-- This file should undo anything in `up.sql`
+-- the raw tokens can't be recovered from their hashes, so every session is
+-- invalidated when reverting
+DELETE FROM sessions;
+
+DROP INDEX IF EXISTS sessions_token_hash_idx;
+
+ALTER TABLE sessions
+DROP COLUMN token_hash;
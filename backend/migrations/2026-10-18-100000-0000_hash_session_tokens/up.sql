@@ -0,0 +1,18 @@
+-- Your SQL goes here
+-- sessions used to be authenticated with their id, so existing sessions keep
+-- working by storing the hash of the old id as their token hash, and get a
+-- fresh public id since the old one is a credential that may have leaked
+ALTER TABLE sessions
+ADD COLUMN token_hash VARCHAR(255);
+
+UPDATE sessions
+SET
+  token_hash = encode(sha256(convert_to(id, 'UTF8')), 'hex'),
+  id = gen_random_uuid()::VARCHAR;
+
+ALTER TABLE sessions
+ALTER COLUMN token_hash
+SET
+  NOT NULL;
+
+CREATE UNIQUE INDEX sessions_token_hash_idx ON sessions (token_hash);
@@ -0,0 +1,22 @@
+-- Your SQL goes here
+-- confirmed_at stays NULL until the user proves their authenticator works,
+-- two-factor is only enforced once it is set
+CREATE TABLE
+  user_totp (
+    user_id VARCHAR(255) PRIMARY KEY REFERENCES users (id),
+    secret VARCHAR(255) NOT NULL,
+    created_at BIGINT NOT NULL,
+    confirmed_at BIGINT,
+    last_used_step BIGINT
+  );
+
+CREATE TABLE
+  recovery_codes (
+    id VARCHAR(255) PRIMARY KEY,
+    user_id VARCHAR(255) NOT NULL REFERENCES users (id),
+    code_hash VARCHAR(255) NOT NULL,
+    created_at BIGINT NOT NULL,
+    used_at BIGINT
+  );
+
+CREATE INDEX recovery_codes_user_id_idx ON recovery_codes (user_id);
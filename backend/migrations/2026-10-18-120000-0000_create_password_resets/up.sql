@@ -0,0 +1,12 @@
+-- Your SQL goes here
+CREATE TABLE
+  password_resets (
+    id VARCHAR(255) PRIMARY KEY,
+    user_id VARCHAR(255) NOT NULL REFERENCES users (id),
+    token_hash VARCHAR(255) NOT NULL UNIQUE,
+    created_at BIGINT NOT NULL,
+    expires_at BIGINT NOT NULL,
+    used_at BIGINT
+  );
+
+CREATE INDEX password_resets_user_id_idx ON password_resets (user_id);
@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE email_verifications;
+
+ALTER TABLE users
+DROP COLUMN email_verified;
@@ -0,0 +1,22 @@
+-- Your SQL goes here
+-- accounts created before verification existed are treated as verified,
+-- new accounts start out unverified
+ALTER TABLE users
+ADD COLUMN email_verified BOOLEAN NOT NULL DEFAULT TRUE;
+
+ALTER TABLE users
+ALTER COLUMN email_verified SET DEFAULT FALSE;
+
+-- email is the address being verified, which differs from users.email while
+-- an email change is pending
+CREATE TABLE
+  email_verifications (
+    id VARCHAR(255) PRIMARY KEY,
+    user_id VARCHAR(255) NOT NULL REFERENCES users (id),
+    email VARCHAR(255) NOT NULL,
+    token_hash VARCHAR(255) NOT NULL UNIQUE,
+    created_at BIGINT NOT NULL,
+    expires_at BIGINT NOT NULL
+  );
+
+CREATE INDEX email_verifications_user_id_idx ON email_verifications (user_id);
@@ -0,0 +1,10 @@
+-- Your SQL goes here
+-- key is a hash of what is being throttled (a client ip or an email), so
+-- addresses tried against the login form aren't stored in the clear
+CREATE TABLE
+  auth_throttles (
+    key VARCHAR(255) PRIMARY KEY,
+    failures INTEGER NOT NULL,
+    last_failure_at BIGINT NOT NULL,
+    locked_until BIGINT NOT NULL
+  );
@@ -0,0 +1,12 @@
+-- Your SQL goes here
+-- existing accounts haven't changed their password since signing up, so the
+-- creation time is the best value there is for them
+ALTER TABLE users
+ADD COLUMN password_changed_at BIGINT;
+
+UPDATE users
+SET
+  password_changed_at = created_at;
+
+ALTER TABLE users
+ALTER COLUMN password_changed_at SET NOT NULL;
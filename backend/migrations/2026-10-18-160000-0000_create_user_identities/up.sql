@@ -0,0 +1,30 @@
+-- Your SQL goes here
+-- an account at an external identity provider, identified by the issuer and
+-- the provider's subject id, email is what the provider reported last time
+CREATE TABLE
+  user_identities (
+    id VARCHAR(255) PRIMARY KEY,
+    user_id VARCHAR(255) NOT NULL REFERENCES users (id),
+    issuer VARCHAR(255) NOT NULL,
+    subject VARCHAR(255) NOT NULL,
+    email VARCHAR(255),
+    created_at BIGINT NOT NULL,
+    last_used_at BIGINT NOT NULL,
+    UNIQUE (issuer, subject)
+  );
+
+CREATE INDEX user_identities_user_id_idx ON user_identities (user_id);
+
+-- a login that has been sent to the identity provider but hasn't come back
+-- yet, user_id is set when an existing user is linking a new identity
+CREATE TABLE
+  oidc_logins (
+    id VARCHAR(255) PRIMARY KEY,
+    state_hash VARCHAR(255) NOT NULL UNIQUE,
+    code_verifier VARCHAR(255) NOT NULL,
+    nonce VARCHAR(255) NOT NULL,
+    user_id VARCHAR(255) REFERENCES users (id),
+    invite VARCHAR(255),
+    created_at BIGINT NOT NULL,
+    expires_at BIGINT NOT NULL
+  );
@@ -0,0 +1,16 @@
+-- Your SQL goes here
+-- long lived tokens for scripts, limited to the scopes they were created
+-- with, expires_at is null for tokens that never expire
+CREATE TABLE
+  personal_access_tokens (
+    id VARCHAR(255) PRIMARY KEY,
+    user_id VARCHAR(255) NOT NULL REFERENCES users (id),
+    name VARCHAR(255) NOT NULL,
+    token_hash VARCHAR(255) NOT NULL UNIQUE,
+    scopes TEXT[] NOT NULL,
+    created_at BIGINT NOT NULL,
+    expires_at BIGINT,
+    last_used_at BIGINT
+  );
+
+CREATE INDEX personal_access_tokens_user_id_idx ON personal_access_tokens (user_id);
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE users
+DROP COLUMN is_admin,
+DROP COLUMN disabled_at;
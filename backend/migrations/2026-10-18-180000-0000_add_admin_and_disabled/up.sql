@@ -0,0 +1,5 @@
+-- Your SQL goes here
+-- disabled_at is set while an admin has disabled the account
+ALTER TABLE users
+ADD COLUMN is_admin BOOLEAN NOT NULL DEFAULT FALSE,
+ADD COLUMN disabled_at BIGINT;
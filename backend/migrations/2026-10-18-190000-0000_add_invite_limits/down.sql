@@ -0,0 +1,14 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX invites_code_idx;
+
+ALTER TABLE invites
+ADD COLUMN used BOOLEAN NOT NULL DEFAULT FALSE;
+
+UPDATE invites SET used = use_count > 0;
+
+ALTER TABLE invites
+DROP COLUMN expires_at,
+DROP COLUMN max_uses,
+DROP COLUMN use_count,
+DROP COLUMN created_by,
+DROP COLUMN note;
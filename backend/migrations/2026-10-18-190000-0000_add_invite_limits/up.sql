@@ -0,0 +1,16 @@
+-- Your SQL goes here
+-- an invite can be redeemed max_uses times before expires_at, created_by is
+-- empty for invites made outside the api
+ALTER TABLE invites
+ADD COLUMN expires_at BIGINT,
+ADD COLUMN max_uses INTEGER NOT NULL DEFAULT 1,
+ADD COLUMN use_count INTEGER NOT NULL DEFAULT 0,
+ADD COLUMN created_by VARCHAR(255) REFERENCES users (id) ON DELETE SET NULL,
+ADD COLUMN note VARCHAR(1023);
+
+UPDATE invites SET use_count = 1 WHERE used;
+
+ALTER TABLE invites
+DROP COLUMN used;
+
+CREATE UNIQUE INDEX invites_code_idx ON invites (code);
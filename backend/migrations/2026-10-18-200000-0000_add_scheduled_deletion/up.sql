@@ -0,0 +1,5 @@
+-- Your SQL goes here
+-- scheduled_deletion_at is when an account waiting to be deleted is purged,
+-- until then the user can restore it by logging in
+ALTER TABLE users
+ADD COLUMN scheduled_deletion_at BIGINT;
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE audit_events;
+
+DROP FUNCTION audit_events_append_only;
@@ -0,0 +1,26 @@
+-- Your SQL goes here
+-- a record of security relevant things that happened to an account, rows
+-- are only ever added, they go away with the account itself. ip_address and
+-- user_agent are null for events that weren't caused by the user's own request
+CREATE TABLE
+  audit_events (
+    id VARCHAR(255) PRIMARY KEY,
+    user_id VARCHAR(255) NOT NULL REFERENCES users (id),
+    event VARCHAR(255) NOT NULL,
+    created_at BIGINT NOT NULL,
+    ip_address VARCHAR(255),
+    user_agent VARCHAR(255),
+    details VARCHAR(1023)
+  );
+
+CREATE INDEX audit_events_user_id_created_at_idx ON audit_events (user_id, created_at);
+
+CREATE FUNCTION audit_events_append_only () RETURNS TRIGGER AS $$
+BEGIN
+  RAISE EXCEPTION 'audit_events is append only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER audit_events_append_only BEFORE
+UPDATE ON audit_events FOR EACH ROW
+EXECUTE FUNCTION audit_events_append_only ();
@@ -0,0 +1,13 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE known_devices;
+
+ALTER TABLE audit_events
+ALTER COLUMN user_agent TYPE VARCHAR(255) USING LEFT(user_agent, 255);
+
+ALTER TABLE sessions
+DROP COLUMN new_device,
+DROP COLUMN device_type,
+DROP COLUMN os,
+DROP COLUMN browser,
+DROP COLUMN name,
+ALTER COLUMN user_agent TYPE VARCHAR(255) USING LEFT(user_agent, 255);
@@ -0,0 +1,25 @@
+-- Your SQL goes here
+-- the browser, os and kind of device are read from the user agent when the
+-- session is created, sessions from before that are unknown. user agents
+-- were cut off at an arbitrary length before, now they're truncated at 1023
+ALTER TABLE sessions
+ALTER COLUMN user_agent TYPE VARCHAR(1023),
+ADD COLUMN name VARCHAR(255),
+ADD COLUMN browser VARCHAR(255) NOT NULL DEFAULT 'unknown',
+ADD COLUMN os VARCHAR(255) NOT NULL DEFAULT 'unknown',
+ADD COLUMN device_type VARCHAR(255) NOT NULL DEFAULT 'unknown',
+ADD COLUMN new_device BOOLEAN NOT NULL DEFAULT FALSE;
+
+ALTER TABLE audit_events
+ALTER COLUMN user_agent TYPE VARCHAR(1023);
+
+-- every device a user has logged in from, kept after its sessions are gone
+-- so logging in again from it isn't mistaken for a new device
+CREATE TABLE
+  known_devices (
+    user_id VARCHAR(255) NOT NULL REFERENCES users (id),
+    fingerprint VARCHAR(255) NOT NULL,
+    first_seen_at BIGINT NOT NULL,
+    last_seen_at BIGINT NOT NULL,
+    PRIMARY KEY (user_id, fingerprint)
+  );
@@ -0,0 +1,12 @@
+-- Your SQL goes here
+-- refresh tokens that have been swapped for a new one, a retired token
+-- being used again means it was copied, so the session it belonged to is
+-- revoked. they go away with their session
+CREATE TABLE
+  retired_refresh_tokens (
+    token_hash VARCHAR(255) PRIMARY KEY,
+    session_id VARCHAR(255) NOT NULL REFERENCES sessions (id) ON DELETE CASCADE,
+    retired_at BIGINT NOT NULL
+  );
+
+CREATE INDEX retired_refresh_tokens_session_id_idx ON retired_refresh_tokens (session_id);
@@ -0,0 +1,4 @@
+-- Your SQL goes here
+-- a single sign-on login started to restore an account that is waiting to
+-- be deleted, like POST /v1/auth/restore does for passwords
+ALTER TABLE oidc_logins ADD COLUMN restore BOOLEAN NOT NULL DEFAULT FALSE;
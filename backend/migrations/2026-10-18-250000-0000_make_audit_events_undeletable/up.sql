@@ -0,0 +1,17 @@
+-- Your SQL goes here
+-- events can't be deleted either, except by the transaction deleting the
+-- account they belong to, which names the user in
+-- ephemeride.deleting_user first
+CREATE OR REPLACE FUNCTION audit_events_append_only () RETURNS TRIGGER AS $$
+BEGIN
+  IF TG_OP = 'DELETE'
+    AND current_setting('ephemeride.deleting_user', true) = OLD.user_id THEN
+    RETURN OLD;
+  END IF;
+
+  RAISE EXCEPTION 'audit_events is append only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER audit_events_undeletable BEFORE DELETE ON audit_events FOR EACH ROW
+EXECUTE FUNCTION audit_events_append_only ();
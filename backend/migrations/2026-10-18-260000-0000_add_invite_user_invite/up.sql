@@ -0,0 +1,8 @@
+-- Your SQL goes here
+-- invites made through the user endpoint count against the user's quota,
+-- the ones an admin makes through the admin api don't. every invite made by
+-- a user so far has an invite_created audit event pointing at it
+ALTER TABLE invites ADD COLUMN user_invite BOOLEAN NOT NULL DEFAULT FALSE;
+
+UPDATE invites SET user_invite = TRUE
+WHERE id IN (SELECT details FROM audit_events WHERE event = 'invite_created');
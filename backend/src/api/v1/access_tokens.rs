@@ -0,0 +1,75 @@
+use crate::{
+  run_blocking,
+  services::{access_token, authorize_session},
+  util::{error::error_response, response, EphemerideError},
+  DbPool,
+};
+use poem::{
+  handler,
+  http::StatusCode,
+  web::{Data, Json, Path},
+  Request, Response,
+};
+
+#[handler]
+pub async fn get_access_tokens(request: &Request, Data(pool): Data<&DbPool>) -> Response {
+  let session = match authorize_session(pool, request).await {
+    Ok(session) => session,
+    Err(error) => return error_response(error),
+  };
+
+  let access_tokens = run_blocking(pool, move |conn| {
+    access_token::get_user_access_tokens(conn, &session.user_id)
+  })
+  .await;
+
+  match access_tokens {
+    Ok(access_tokens) => response(StatusCode::OK, &access_tokens),
+    Err(error) => error_response(error),
+  }
+}
+
+#[handler]
+pub async fn create_access_token(
+  Json(access_token): Json<access_token::CreateAccessToken>,
+  request: &Request,
+  Data(pool): Data<&DbPool>,
+) -> Response {
+  let session = match authorize_session(pool, request).await {
+    Ok(session) => session,
+    Err(error) => return error_response(error),
+  };
+
+  let created = run_blocking(pool, move |conn| {
+    access_token::create_access_token(conn, &session.user_id, access_token)
+  })
+  .await;
+
+  match created {
+    Ok(created) => response(StatusCode::CREATED, &created),
+    Err(error) => error_response(error),
+  }
+}
+
+#[handler]
+pub async fn delete_access_token(
+  Path(id): Path<String>,
+  request: &Request,
+  Data(pool): Data<&DbPool>,
+) -> Response {
+  let session = match authorize_session(pool, request).await {
+    Ok(session) => session,
+    Err(error) => return error_response(error),
+  };
+
+  let deleted = run_blocking(pool, move |conn| {
+    access_token::delete_access_token(conn, &id, &session.user_id)
+  })
+  .await;
+
+  match deleted {
+    Ok(true) => response(StatusCode::NO_CONTENT, &()),
+    Ok(false) => error_response(EphemerideError::AccessTokenNotFound),
+    Err(error) => error_response(error),
+  }
+}
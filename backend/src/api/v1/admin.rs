@@ -0,0 +1,192 @@
+use crate::{
+  mailer::{send_in_background, SharedMailer},
+  run_blocking,
+  services::{admin, authorize_admin, invite, user},
+  util::{error::error_response, response, EphemerideError},
+  DbPool,
+};
+use poem::{
+  handler,
+  http::StatusCode,
+  web::{Data, Json, Path, Query},
+  Request, Response,
+};
+#[handler]
+pub async fn get_users(
+  Query(search): Query<admin::SearchUsers>,
+  request: &Request,
+  Data(pool): Data<&DbPool>,
+) -> Response {
+  match authorize_admin(pool, request).await {
+    Ok(_) => (),
+    Err(error) => return error_response(error),
+  };
+
+  let users = run_blocking(pool, move |conn| admin::search_users(conn, search)).await;
+
+  match users {
+    Ok(users) => response(StatusCode::OK, &users),
+    Err(error) => error_response(error),
+  }
+}
+
+#[handler]
+pub async fn get_user(
+  Path(id): Path<String>,
+  request: &Request,
+  Data(pool): Data<&DbPool>,
+) -> Response {
+  match authorize_admin(pool, request).await {
+    Ok(_) => (),
+    Err(error) => return error_response(error),
+  };
+
+  let found_user = run_blocking(pool, move |conn| user::get_user(conn, &id)).await;
+
+  match found_user {
+    Ok(found_user) => response(StatusCode::OK, &found_user),
+    Err(error) => error_response(error),
+  }
+}
+
+async fn set_user_disabled(
+  id: String,
+  request: &Request,
+  pool: &DbPool,
+  disabled: bool,
+) -> Response {
+  let session = match authorize_admin(pool, request).await {
+    Ok(session) => session,
+    Err(error) => return error_response(error),
+  };
+
+  let updated = run_blocking(pool, move |conn| {
+    admin::set_user_disabled(conn, &session.user_id, &id, disabled)
+  })
+  .await;
+
+  match updated {
+    Ok(true) => response(StatusCode::NO_CONTENT, &()),
+    Ok(false) => error_response(EphemerideError::UserNotFound),
+    Err(error) => error_response(error),
+  }
+}
+
+#[handler]
+pub async fn disable_user(
+  Path(id): Path<String>,
+  request: &Request,
+  Data(pool): Data<&DbPool>,
+) -> Response {
+  set_user_disabled(id, request, pool, true).await
+}
+
+#[handler]
+pub async fn enable_user(
+  Path(id): Path<String>,
+  request: &Request,
+  Data(pool): Data<&DbPool>,
+) -> Response {
+  set_user_disabled(id, request, pool, false).await
+}
+
+#[handler]
+pub async fn force_password_reset(
+  Path(id): Path<String>,
+  request: &Request,
+  Data(pool): Data<&DbPool>,
+  Data(mailer): Data<&SharedMailer>,
+) -> Response {
+  match authorize_admin(pool, request).await {
+    Ok(_) => (),
+    Err(error) => return error_response(error),
+  };
+
+  let reset = run_blocking(pool, move |conn| admin::force_password_reset(conn, &id)).await;
+
+  match reset {
+    Ok(Some(email)) => {
+      send_in_background(mailer.clone(), email);
+      response(StatusCode::NO_CONTENT, &())
+    }
+    Ok(None) => error_response(EphemerideError::UserNotFound),
+    Err(error) => error_response(error),
+  }
+}
+
+#[handler]
+pub async fn revoke_user_sessions(
+  Path(id): Path<String>,
+  request: &Request,
+  Data(pool): Data<&DbPool>,
+) -> Response {
+  match authorize_admin(pool, request).await {
+    Ok(_) => (),
+    Err(error) => return error_response(error),
+  };
+
+  let revoked = run_blocking(pool, move |conn| admin::revoke_user_sessions(conn, &id)).await;
+
+  match revoked {
+    Ok(true) => response(StatusCode::NO_CONTENT, &()),
+    Ok(false) => error_response(EphemerideError::UserNotFound),
+    Err(error) => error_response(error),
+  }
+}
+
+#[handler]
+pub async fn get_invites(request: &Request, Data(pool): Data<&DbPool>) -> Response {
+  match authorize_admin(pool, request).await {
+    Ok(_) => (),
+    Err(error) => return error_response(error),
+  };
+
+  let invites = run_blocking(pool, invite::get_invites).await;
+
+  match invites {
+    Ok(invites) => response(StatusCode::OK, &invites),
+    Err(error) => error_response(error),
+  }
+}
+
+#[handler]
+pub async fn create_invite(
+  Json(new_invite): Json<invite::CreateInvite>,
+  request: &Request,
+  Data(pool): Data<&DbPool>,
+) -> Response {
+  let session = match authorize_admin(pool, request).await {
+    Ok(session) => session,
+    Err(error) => return error_response(error),
+  };
+
+  let created = run_blocking(pool, move |conn| {
+    invite::create_invite(conn, Some(&session.user_id), new_invite)
+  })
+  .await;
+
+  match created {
+    Ok(created) => response(StatusCode::CREATED, &created),
+    Err(error) => error_response(error),
+  }
+}
+
+#[handler]
+pub async fn revoke_invite(
+  Path(id): Path<String>,
+  request: &Request,
+  Data(pool): Data<&DbPool>,
+) -> Response {
+  match authorize_admin(pool, request).await {
+    Ok(_) => (),
+    Err(error) => return error_response(error),
+  };
+
+  let revoked = run_blocking(pool, move |conn| invite::revoke_invite(conn, &id)).await;
+
+  match revoked {
+    Ok(true) => response(StatusCode::NO_CONTENT, &()),
+    Ok(false) => error_response(EphemerideError::InviteNotFound),
+    Err(error) => error_response(error),
+  }
+}
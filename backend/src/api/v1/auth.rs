@@ -1,11 +1,24 @@
 use crate::{
-  services::{auth, user, AuthConfig, UserCredentials},
+  mailer::{send_in_background, SharedMailer},
+  run_blocking,
+  services::{
+    auth, authorize_session, email_verification, jwt, oidc, password_reset, throttle, user,
+    AuthConfig, UserCredentials,
+  },
   util::{
-    error::{error_response, EphemerideError},
+    self,
+    error::{error_response, too_many_requests_response, EphemerideError},
     response,
   },
+  DbPool,
+};
+use diesel::PgConnection;
+use poem::{
+  handler,
+  http::{header, HeaderValue, StatusCode},
+  web::{Data, Json},
+  Request, Response,
 };
-use poem::{handler, http::StatusCode, web::Json, Request, Response};
 
 use validator::Validate;
 
@@ -13,32 +26,316 @@ use dotenvy::dotenv;
 use std::env;
 
 #[handler]
-pub fn authenticate_user(Json(user): Json<user::AuthUser>, request: &Request) -> Response {
+pub async fn authenticate_user(
+  Json(user): Json<user::AuthUser>,
+  request: &Request,
+  Data(pool): Data<&DbPool>,
+  Data(settings): Data<&auth::SessionSettings>,
+) -> Response {
+  log_in(user, request, pool, settings, auth::create_user_session).await
+}
+
+// offered when logging in fails with AccountPendingDeletion, it takes the
+// same credentials and cancels the deletion
+#[handler]
+pub async fn restore_user(
+  Json(user): Json<user::AuthUser>,
+  request: &Request,
+  Data(pool): Data<&DbPool>,
+  Data(settings): Data<&auth::SessionSettings>,
+) -> Response {
+  log_in(user, request, pool, settings, auth::restore_user_session).await
+}
+
+// both ways of logging in with a password share the same throttling
+async fn log_in(
+  user: user::AuthUser,
+  request: &Request,
+  pool: &DbPool,
+  settings: &auth::SessionSettings,
+  start_session: fn(
+    &mut PgConnection,
+    UserCredentials,
+    auth::SessionMetadata,
+  ) -> Result<auth::SessionWithToken, EphemerideError>,
+) -> Response {
   match user.validate() {
     Ok(_) => (),
     Err(_) => return error_response(EphemerideError::BadRequest),
   }
 
-  let session = auth::create_user_session(
-    UserCredentials {
-      email: String::from(&user.email),
-      password: String::from(&user.password),
+  let metadata = auth::session_metadata(request);
+  let ip_key = throttle::ip_key(&auth::client_ip(request));
+  let account_key = throttle::account_key(&user.email);
+  let keys = vec![ip_key, account_key.clone()];
+
+  let locked_keys = keys.clone();
+  let locked = run_blocking(pool, move |conn| throttle::retry_after(conn, &locked_keys)).await;
+
+  match locked {
+    Ok(Some(retry_after)) => return too_many_requests_response(retry_after),
+    Ok(None) => (),
+    Err(error) => return error_response(error),
+  }
+
+  let session = run_blocking(pool, move |conn| {
+    let session = start_session(
+      conn,
+      UserCredentials {
+        email: String::from(&user.email),
+        password: String::from(&user.password),
+        totp_code: user.totp_code,
+      },
+      metadata,
+    );
+
+    // a successful login clears the account's backoff, the ip keeps its
+    // history so one good password doesn't reset a spraying attempt
+    match &session {
+      Ok(_) => throttle::clear(conn, &[account_key])?,
+      Err(EphemerideError::InvalidCredentials) | Err(EphemerideError::InvalidTotpCode) => {
+        throttle::record_attempt(conn, &keys)?
+      }
+      Err(_) => (),
+    }
+
+    session
+  })
+  .await;
+
+  match session {
+    Ok(session) => session_response(settings, session),
+    Err(error) => error_response(error),
+  }
+}
+
+// every way of logging in answers with the new session, with session
+// cookies enabled the token is set as a cookie instead of being returned
+pub fn session_response(
+  settings: &auth::SessionSettings,
+  session: auth::SessionWithToken,
+) -> Response {
+  // token mode answers with an access and a refresh token, session cookies
+  // only ever carry session tokens so they aren't set
+  if let Some(jwt_config) = &settings.jwt {
+    return match jwt::with_tokens(jwt_config, session) {
+      Ok(session) => response(StatusCode::CREATED, &session),
+      Err(error) => error_response(error),
+    };
+  }
+
+  let cookie_config = match &settings.cookies {
+    Some(cookie_config) => cookie_config,
+    None => return response(StatusCode::CREATED, &session),
+  };
+
+  let csrf_token = util::generate_token();
+  let max_age_seconds = settings.session.lifetime / 1000;
+  let cookies = cookie_config.session_cookies(&session.token, &csrf_token, max_age_seconds);
+
+  let mut response = response(
+    StatusCode::CREATED,
+    &auth::SessionWithCsrfToken {
+      session: session.session,
+      csrf_token,
     },
-    auth::session_metadata(request),
   );
+  for cookie in cookies {
+    if let Ok(cookie) = HeaderValue::from_str(&cookie) {
+      response.headers_mut().append(header::SET_COOKIE, cookie);
+    }
+  }
+  response
+}
 
-  match session {
-    Ok(session) => response(StatusCode::CREATED, &session),
+#[handler]
+pub async fn refresh_session(
+  Json(refresh): Json<jwt::RefreshSession>,
+  request: &Request,
+  Data(pool): Data<&DbPool>,
+  Data(settings): Data<&auth::SessionSettings>,
+) -> Response {
+  let jwt_config = match settings.jwt.clone() {
+    Some(jwt_config) => jwt_config,
+    None => return error_response(EphemerideError::AccessTokensNotConfigured),
+  };
+
+  let metadata = auth::session_metadata(request);
+
+  let refreshed = run_blocking(pool, move |conn| {
+    let refreshed = jwt::refresh_session(conn, refresh, &metadata)?;
+    jwt::with_tokens(&jwt_config, refreshed)
+  })
+  .await;
+
+  match refreshed {
+    Ok(refreshed) => response(StatusCode::OK, &refreshed),
+    Err(error) => error_response(error),
+  }
+}
+
+#[handler]
+pub async fn logout(
+  request: &Request,
+  Data(pool): Data<&DbPool>,
+  Data(settings): Data<&auth::SessionSettings>,
+) -> Response {
+  let session = match authorize_session(pool, request).await {
+    Ok(session) => session,
+    Err(error) => return error_response(error),
+  };
+
+  let deleted_session = run_blocking(pool, move |conn| {
+    auth::delete_user_session(conn, &session.id, &session.user_id)
+  })
+  .await;
+
+  match deleted_session {
+    Ok(true) => {
+      let mut response = response(StatusCode::NO_CONTENT, &());
+      if let Some(cookie_config) = &settings.cookies {
+        for cookie in cookie_config.cleared_cookies() {
+          if let Ok(cookie) = HeaderValue::from_str(&cookie) {
+            response.headers_mut().append(header::SET_COOKIE, cookie);
+          }
+        }
+      }
+      response
+    }
+    Ok(false) => error_response(EphemerideError::SessionNotFound),
+    Err(error) => error_response(error),
+  }
+}
+
+#[handler]
+pub async fn request_password_reset(
+  Json(reset): Json<password_reset::RequestPasswordReset>,
+  request: &Request,
+  Data(pool): Data<&DbPool>,
+  Data(mailer): Data<&SharedMailer>,
+) -> Response {
+  let keys = vec![
+    throttle::mail_ip_key(&auth::client_ip(request)),
+    throttle::mail_account_key(&reset.email),
+  ];
+
+  let locked_keys = keys.clone();
+  let locked = run_blocking(pool, move |conn| throttle::retry_after(conn, &locked_keys)).await;
+
+  match locked {
+    Ok(Some(retry_after)) => return too_many_requests_response(retry_after),
+    Ok(None) => (),
+    Err(error) => return error_response(error),
+  }
+
+  let requested = run_blocking(pool, move |conn| {
+    // every request counts, whether or not the email has an account
+    throttle::record_attempt(conn, &keys)?;
+    password_reset::request_password_reset(conn, reset)
+  })
+  .await;
+
+  // known and unknown emails answer the same way, the mail is sent after
+  // the connection is back in the pool and the response doesn't wait on it
+  match requested {
+    Ok(email) => {
+      if let Some(email) = email {
+        send_in_background(mailer.clone(), email);
+      }
+      response(StatusCode::ACCEPTED, &())
+    }
+    Err(error) => error_response(error),
+  }
+}
+
+#[handler]
+pub async fn confirm_password_reset(
+  Json(confirm): Json<password_reset::ConfirmPasswordReset>,
+  request: &Request,
+  Data(pool): Data<&DbPool>,
+) -> Response {
+  let metadata = auth::session_metadata(request);
+
+  let confirmed = run_blocking(pool, move |conn| {
+    password_reset::confirm_password_reset(conn, confirm, &metadata)
+  })
+  .await;
+
+  match confirmed {
+    Ok(_) => response(StatusCode::NO_CONTENT, &()),
+    Err(error) => error_response(error),
+  }
+}
+
+#[handler]
+pub async fn confirm_email_verification(
+  Json(confirm): Json<email_verification::ConfirmEmailVerification>,
+  request: &Request,
+  Data(pool): Data<&DbPool>,
+) -> Response {
+  let metadata = auth::session_metadata(request);
+
+  let confirmed = run_blocking(pool, move |conn| {
+    email_verification::confirm_email_verification(conn, confirm, &metadata)
+  })
+  .await;
+
+  match confirmed {
+    Ok(_) => response(StatusCode::NO_CONTENT, &()),
+    Err(error) => error_response(error),
+  }
+}
+
+#[handler]
+pub async fn resend_email_verification(
+  Json(resend): Json<email_verification::ResendEmailVerification>,
+  request: &Request,
+  Data(pool): Data<&DbPool>,
+  Data(mailer): Data<&SharedMailer>,
+) -> Response {
+  let keys = vec![
+    throttle::mail_ip_key(&auth::client_ip(request)),
+    throttle::mail_account_key(&resend.email),
+  ];
+
+  let locked_keys = keys.clone();
+  let locked = run_blocking(pool, move |conn| throttle::retry_after(conn, &locked_keys)).await;
+
+  match locked {
+    Ok(Some(retry_after)) => return too_many_requests_response(retry_after),
+    Ok(None) => (),
+    Err(error) => return error_response(error),
+  }
+
+  let resent = run_blocking(pool, move |conn| {
+    throttle::record_attempt(conn, &keys)?;
+    email_verification::resend_email_verification(conn, resend)
+  })
+  .await;
+
+  // answers the same whether or not there was anything to send, like
+  // password resets
+  match resent {
+    Ok(email) => {
+      if let Some(email) = email {
+        send_in_background(mailer.clone(), email);
+      }
+      response(StatusCode::ACCEPTED, &())
+    }
     Err(error) => error_response(error),
   }
 }
 
 #[handler]
-pub fn auth_config() -> Response {
+pub fn auth_config(Data(settings): Data<&auth::SessionSettings>) -> Response {
   dotenv().ok();
 
   let auth_config: AuthConfig = AuthConfig {
     invite_required: env::var("INVITE_REQUIRED").unwrap_or("false".to_string()) == "true",
+    email_verification_required: email_verification::email_verification_required(),
+    oidc_enabled: oidc::OidcConfig::from_env().is_some(),
+    session_cookies: settings.cookies.is_some(),
+    access_tokens: settings.jwt.is_some(),
   };
 
   response(StatusCode::OK, &auth_config)
@@ -1,11 +1,13 @@
 use crate::{
-  services::{authorize_request, log},
+  run_blocking,
+  services::{authorize_request, log, Scope},
   util::{error::error_response, response, EphemerideError},
+  DbPool,
 };
 use poem::{
   handler,
   http::StatusCode,
-  web::{Json, Path},
+  web::{Data, Json, Path},
   Request, Response,
 };
 use serde::{Deserialize, Serialize};
@@ -21,16 +23,26 @@ struct EditCategoryRequest {
 }
 
 #[handler]
-pub fn create_category(Json(category): Json<CreateCategoryRequest>, request: &Request) -> Response {
-  let session = match authorize_request(request) {
-    Ok(session) => session,
+pub async fn create_category(
+  Json(category): Json<CreateCategoryRequest>,
+  request: &Request,
+  Data(pool): Data<&DbPool>,
+) -> Response {
+  let authorization = match authorize_request(pool, request, Scope::TagsWrite).await {
+    Ok(authorization) => authorization,
     Err(error) => return error_response(error),
   };
 
-  let created_category = log::create_category(log::CreateCategory {
-    name: category.name,
-    user_id: session.user_id,
-  });
+  let created_category = run_blocking(pool, move |conn| {
+    log::create_category(
+      conn,
+      log::CreateCategory {
+        name: category.name,
+        user_id: authorization.user_id,
+      },
+    )
+  })
+  .await;
 
   match created_category {
     Ok(created_category) => response(StatusCode::CREATED, &created_category),
@@ -39,21 +51,28 @@ pub fn create_category(Json(category): Json<CreateCategoryRequest>, request: &Re
 }
 
 #[handler]
-pub fn edit_category(
+pub async fn edit_category(
   Path(id): Path<String>,
   Json(category): Json<EditCategoryRequest>,
   request: &Request,
+  Data(pool): Data<&DbPool>,
 ) -> Response {
-  let session = match authorize_request(request) {
-    Ok(session) => session,
+  let authorization = match authorize_request(pool, request, Scope::TagsWrite).await {
+    Ok(authorization) => authorization,
     Err(error) => return error_response(error),
   };
 
-  let edited_category = log::edit_category(log::EditCategory {
-    id,
-    name: category.name,
-    user_id: session.user_id,
-  });
+  let edited_category = run_blocking(pool, move |conn| {
+    log::edit_category(
+      conn,
+      log::EditCategory {
+        id,
+        name: category.name,
+        user_id: authorization.user_id,
+      },
+    )
+  })
+  .await;
 
   match edited_category {
     Ok(edited_category) => response(StatusCode::OK, &edited_category),
@@ -62,19 +81,24 @@ pub fn edit_category(
 }
 
 #[handler]
-pub fn delete_category(Path(id): Path<String>, request: &Request) -> Response {
-  let session = match authorize_request(request) {
-    Ok(session) => session,
+pub async fn delete_category(
+  Path(id): Path<String>,
+  request: &Request,
+  Data(pool): Data<&DbPool>,
+) -> Response {
+  let authorization = match authorize_request(pool, request, Scope::TagsWrite).await {
+    Ok(authorization) => authorization,
     Err(error) => return error_response(error),
   };
 
-  let deleted_category = match log::delete_category(&id, &session.user_id) {
-    Ok(deleted) => deleted,
-    Err(error) => return error_response(error),
-  };
+  let deleted_category = run_blocking(pool, move |conn| {
+    log::delete_category(conn, &id, &authorization.user_id)
+  })
+  .await;
 
   match deleted_category {
-    true => response(StatusCode::NO_CONTENT, &()),
-    false => error_response(EphemerideError::CategoryNotFound),
+    Ok(true) => response(StatusCode::NO_CONTENT, &()),
+    Ok(false) => error_response(EphemerideError::CategoryNotFound),
+    Err(error) => error_response(error),
   }
 }
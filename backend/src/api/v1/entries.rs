@@ -1,8 +1,15 @@
 use crate::{
-  services::{authorize_request, log, GetEntriesOptions},
+  run_blocking,
+  services::{authorize_request, log, GetEntriesOptions, Scope},
   util::{error::error_response, response},
+  DbPool,
+};
+use poem::{
+  handler,
+  http::StatusCode,
+  web::{Data, Query},
+  Request, Response,
 };
-use poem::{handler, http::StatusCode, web::Query, Request, Response};
 use serde::Deserialize;
 
 #[derive(Debug, Deserialize)]
@@ -18,9 +25,13 @@ pub struct EntryParams {
 }
 
 #[handler]
-pub fn get_entries(Query(_options): Query<EntryParams>, request: &Request) -> Response {
-  let session = match authorize_request(request) {
-    Ok(session) => session,
+pub async fn get_entries(
+  Query(_options): Query<EntryParams>,
+  request: &Request,
+  Data(pool): Data<&DbPool>,
+) -> Response {
+  let authorization = match authorize_request(pool, request, Scope::EntriesRead).await {
+    Ok(authorization) => authorization,
     Err(error) => return error_response(error),
   };
 
@@ -43,7 +54,10 @@ pub fn get_entries(Query(_options): Query<EntryParams>, request: &Request) -> Re
     offset: _options.offset,
   };
 
-  let entries = log::get_entries(&session.user_id, Some(options));
+  let entries = run_blocking(pool, move |conn| {
+    log::get_entries(conn, &authorization.user_id, Some(options))
+  })
+  .await;
 
   match entries {
     Ok(entries) => response(StatusCode::OK, &entries),
@@ -1,11 +1,13 @@
 use crate::{
-  services::{authorize_request, log},
+  run_blocking,
+  services::{authorize_request, log, Scope},
   util::{error::error_response, response, EphemerideError},
+  DbPool,
 };
 use poem::{
   handler,
   http::StatusCode,
-  web::{Json, Path},
+  web::{Data, Json, Path},
   Request, Response,
 };
 use serde::{Deserialize, Serialize};
@@ -27,19 +29,29 @@ struct EditEntryRequest {
 }
 
 #[handler]
-pub fn create_entry(Json(entry): Json<CreateEntryRequest>, request: &Request) -> Response {
-  let session = match authorize_request(request) {
-    Ok(session) => session,
+pub async fn create_entry(
+  Json(entry): Json<CreateEntryRequest>,
+  request: &Request,
+  Data(pool): Data<&DbPool>,
+) -> Response {
+  let authorization = match authorize_request(pool, request, Scope::EntriesWrite).await {
+    Ok(authorization) => authorization,
     Err(error) => return error_response(error),
   };
 
-  let created_entry = log::create_entry(log::CreateEntry {
-    date: entry.date,
-    mood: entry.mood,
-    entry: entry.entry,
-    selected_tags: entry.selected_tags,
-    user_id: session.user_id,
-  });
+  let created_entry = run_blocking(pool, move |conn| {
+    log::create_entry(
+      conn,
+      log::CreateEntry {
+        date: entry.date,
+        mood: entry.mood,
+        entry: entry.entry,
+        selected_tags: entry.selected_tags,
+        user_id: authorization.user_id,
+      },
+    )
+  })
+  .await;
 
   match created_entry {
     Ok(created_entry) => response(StatusCode::CREATED, &created_entry),
@@ -48,24 +60,31 @@ pub fn create_entry(Json(entry): Json<CreateEntryRequest>, request: &Request) ->
 }
 
 #[handler]
-pub fn edit_entry(
+pub async fn edit_entry(
   Path(id): Path<String>,
   Json(entry): Json<EditEntryRequest>,
   request: &Request,
+  Data(pool): Data<&DbPool>,
 ) -> Response {
-  let session = match authorize_request(request) {
-    Ok(session) => session,
+  let authorization = match authorize_request(pool, request, Scope::EntriesWrite).await {
+    Ok(authorization) => authorization,
     Err(error) => return error_response(error),
   };
 
-  let edited_entry = log::edit_entry(log::EditEntry {
-    id,
-    date: entry.date,
-    mood: entry.mood,
-    entry: entry.entry,
-    selected_tags: entry.selected_tags,
-    user_id: session.user_id,
-  });
+  let edited_entry = run_blocking(pool, move |conn| {
+    log::edit_entry(
+      conn,
+      log::EditEntry {
+        id,
+        date: entry.date,
+        mood: entry.mood,
+        entry: entry.entry,
+        selected_tags: entry.selected_tags,
+        user_id: authorization.user_id,
+      },
+    )
+  })
+  .await;
 
   match edited_entry {
     Ok(edited_entry) => response(StatusCode::OK, &edited_entry),
@@ -74,19 +93,24 @@ pub fn edit_entry(
 }
 
 #[handler]
-pub fn delete_entry(Path(id): Path<String>, request: &Request) -> Response {
-  let session = match authorize_request(request) {
-    Ok(session) => session,
+pub async fn delete_entry(
+  Path(id): Path<String>,
+  request: &Request,
+  Data(pool): Data<&DbPool>,
+) -> Response {
+  let authorization = match authorize_request(pool, request, Scope::EntriesWrite).await {
+    Ok(authorization) => authorization,
     Err(error) => return error_response(error),
   };
 
-  let deleted_entry = match log::delete_entry(&id, &session.user_id) {
-    Ok(deleted) => deleted,
-    Err(error) => return error_response(error),
-  };
+  let deleted_entry = run_blocking(pool, move |conn| {
+    log::delete_entry(conn, &id, &authorization.user_id)
+  })
+  .await;
 
   match deleted_entry {
-    true => response(StatusCode::NO_CONTENT, &()),
-    false => error_response(EphemerideError::EntryNotFound),
+    Ok(true) => response(StatusCode::NO_CONTENT, &()),
+    Ok(false) => error_response(EphemerideError::EntryNotFound),
+    Err(error) => error_response(error),
   }
 }
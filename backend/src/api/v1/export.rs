@@ -0,0 +1,147 @@
+use crate::{
+  services::{authorize_session, export},
+  util::{error::error_response, unix_ms, EphemerideError},
+  DbPool,
+};
+use poem::{
+  handler,
+  http::StatusCode,
+  web::{Data, Query},
+  Body, Request, Response,
+};
+use std::{
+  io::{self, BufWriter, Write},
+  sync::{Arc, OnceLock},
+  time::Duration,
+};
+use tokio::{
+  runtime::Handle,
+  sync::{
+    mpsc::{self, error::SendTimeoutError},
+    Semaphore,
+  },
+};
+use tokio_stream::wrappers::ReceiverStream;
+
+use dotenvy::dotenv;
+use std::env;
+
+const EXPORT_CHUNK_SIZE: usize = 64 * 1024;
+const DEFAULT_MAX_CONCURRENT_EXPORTS: usize = 4;
+const DEFAULT_EXPORT_SEND_TIMEOUT_MS: u64 = 30 * 1000;
+
+// every running export keeps a blocking thread busy for as long as the
+// download takes, so only a few are allowed at once
+fn export_slots() -> &'static Arc<Semaphore> {
+  static EXPORT_SLOTS: OnceLock<Arc<Semaphore>> = OnceLock::new();
+
+  EXPORT_SLOTS.get_or_init(|| {
+    dotenv().ok();
+
+    let max_concurrent_exports = match env::var("MAX_CONCURRENT_EXPORTS") {
+      Ok(val) => val
+        .parse::<usize>()
+        .unwrap_or(DEFAULT_MAX_CONCURRENT_EXPORTS),
+      Err(_) => DEFAULT_MAX_CONCURRENT_EXPORTS,
+    };
+
+    Arc::new(Semaphore::new(max_concurrent_exports))
+  })
+}
+
+fn export_send_timeout() -> Duration {
+  dotenv().ok();
+
+  match env::var("EXPORT_SEND_TIMEOUT_MS") {
+    Ok(val) => Duration::from_millis(val.parse::<u64>().unwrap_or(DEFAULT_EXPORT_SEND_TIMEOUT_MS)),
+    Err(_) => Duration::from_millis(DEFAULT_EXPORT_SEND_TIMEOUT_MS),
+  }
+}
+
+// hands what the export writes over to the response body a chunk at a time,
+// the export waits whenever the client falls behind, but not forever, a
+// client that stops reading would otherwise keep its export slot
+struct ChannelWriter {
+  sender: mpsc::Sender<io::Result<Vec<u8>>>,
+  runtime: Handle,
+  timeout: Duration,
+}
+
+impl ChannelWriter {
+  fn send(&self, chunk: io::Result<Vec<u8>>) -> io::Result<()> {
+    match self
+      .runtime
+      .block_on(self.sender.send_timeout(chunk, self.timeout))
+    {
+      Ok(_) => Ok(()),
+      Err(SendTimeoutError::Timeout(_)) => Err(io::Error::from(io::ErrorKind::TimedOut)),
+      Err(SendTimeoutError::Closed(_)) => Err(io::Error::from(io::ErrorKind::BrokenPipe)),
+    }
+  }
+}
+
+impl Write for ChannelWriter {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    self.send(Ok(buf.to_vec()))?;
+    Ok(buf.len())
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    Ok(())
+  }
+}
+
+#[handler]
+pub async fn export_user_data(
+  Query(options): Query<export::ExportOptions>,
+  request: &Request,
+  Data(pool): Data<&DbPool>,
+) -> Response {
+  let session = match authorize_session(pool, request).await {
+    Ok(session) => session,
+    Err(error) => return error_response(error),
+  };
+
+  let slot = match export_slots().clone().try_acquire_owned() {
+    Ok(slot) => slot,
+    Err(_) => return error_response(EphemerideError::TooManyRequests),
+  };
+
+  let format = options.format.unwrap_or_default();
+  let pool = pool.clone();
+  let (sender, receiver) = mpsc::channel(16);
+  let channel = ChannelWriter {
+    sender,
+    runtime: Handle::current(),
+    timeout: export_send_timeout(),
+  };
+
+  // the response has already started by the time the export could fail, so
+  // a failure ends the body early instead of leaving a file that looks whole
+  tokio::task::spawn_blocking(move || {
+    let mut writer = BufWriter::with_capacity(EXPORT_CHUNK_SIZE, channel);
+
+    let exported = export::write_export(&pool, &session.user_id, format, &mut writer);
+
+    if exported.is_err() {
+      let _ = writer
+        .get_ref()
+        .send(Err(io::Error::other("export failed")));
+    }
+
+    drop(slot);
+  });
+
+  Response::builder()
+    .status(StatusCode::OK)
+    .header("Content-Type", format.content_type())
+    .header(
+      "Content-Disposition",
+      format!(
+        "attachment; filename=\"ephemeride-export-{}.{}\"",
+        unix_ms(),
+        format.extension()
+      ),
+    )
+    .body(Body::from_bytes_stream(ReceiverStream::new(receiver)))
+}
@@ -1,5 +1,5 @@
 use crate::api::v1;
-use poem::{get, patch, post, Route};
+use poem::{delete, get, patch, post, Route};
 
 #[rustfmt::skip]
 pub fn endpoint() -> poem::Route {
@@ -12,6 +12,25 @@ pub fn endpoint() -> poem::Route {
 
     .at("/user/password", patch(v1::user::update_password))
 
+    .at("/user/totp", get(v1::totp::get_totp_status)
+    .post(v1::totp::start_totp_enrollment)
+    .delete(v1::totp::disable_totp))
+    .at("/user/totp/confirm", post(v1::totp::confirm_totp_enrollment))
+
+    .at("/user/identities", get(v1::oidc::get_user_identities))
+    .at("/user/identities/:id", delete(v1::oidc::delete_user_identity))
+
+    .at("/user/tokens", get(v1::access_tokens::get_access_tokens)
+    .post(v1::access_tokens::create_access_token))
+    .at("/user/tokens/:id", delete(v1::access_tokens::delete_access_token))
+
+    .at("/user/invites", get(v1::invites::get_user_invites)
+    .post(v1::invites::create_user_invite))
+
+    .at("/user/export", get(v1::export::export_user_data))
+
+    .at("/user/security-events", get(v1::security_events::get_security_events))
+
     .at("/user/categories", get(v1::user::get_user_categories_with_tags))
 
     .at("/category", post(v1::category::create_category))
@@ -27,11 +46,32 @@ pub fn endpoint() -> poem::Route {
     .delete(v1::entry::delete_entry))
     .at("/entries", get(v1::entries::get_entries))
 
-    // #TODO: add delete all sessions endpoint and delete session by id endpoint
-    .at("/sessions", get(v1::sessions::get_sessions))
+    .at("/sessions", get(v1::sessions::get_sessions)
+    .delete(v1::sessions::delete_other_sessions))
+    .at("/sessions/:id", patch(v1::sessions::rename_session)
+    .delete(v1::sessions::delete_session))
 
     .at("/auth", post(v1::auth::authenticate_user))
+    .at("/auth/restore", post(v1::auth::restore_user))
+    .at("/auth/refresh", post(v1::auth::refresh_session))
+    .at("/auth/logout", post(v1::auth::logout))
     .at("/auth/config", get(v1::auth::auth_config))
+    .at("/auth/password-reset", post(v1::auth::request_password_reset))
+    .at("/auth/password-reset/confirm", post(v1::auth::confirm_password_reset))
+    .at("/auth/verify-email", post(v1::auth::confirm_email_verification))
+    .at("/auth/verify-email/resend", post(v1::auth::resend_email_verification))
+    .at("/auth/oidc/authorize", post(v1::oidc::start_oidc_login))
+    .at("/auth/oidc/callback", post(v1::oidc::complete_oidc_login))
+
+    .at("/admin/users", get(v1::admin::get_users))
+    .at("/admin/users/:id", get(v1::admin::get_user))
+    .at("/admin/users/:id/disable", post(v1::admin::disable_user))
+    .at("/admin/users/:id/enable", post(v1::admin::enable_user))
+    .at("/admin/users/:id/password-reset", post(v1::admin::force_password_reset))
+    .at("/admin/users/:id/sessions", delete(v1::admin::revoke_user_sessions))
+    .at("/admin/invites", get(v1::admin::get_invites)
+    .post(v1::admin::create_invite))
+    .at("/admin/invites/:id", delete(v1::admin::revoke_invite))
 
     .at("/metrics", get(v1::metrics::metrics))
 }
@@ -0,0 +1,57 @@
+use crate::{
+  run_blocking,
+  services::{auth, authorize_session, invite},
+  util::{error::error_response, response},
+  DbPool,
+};
+use poem::{
+  handler,
+  http::StatusCode,
+  web::{Data, Json},
+  Request, Response,
+};
+
+#[handler]
+pub async fn get_user_invites(request: &Request, Data(pool): Data<&DbPool>) -> Response {
+  let session = match authorize_session(pool, request).await {
+    Ok(session) => session,
+    Err(error) => return error_response(error),
+  };
+
+  let quota = invite::user_invite_quota();
+
+  let invites = run_blocking(pool, move |conn| {
+    invite::get_user_invites(conn, &session.user_id, quota)
+  })
+  .await;
+
+  match invites {
+    Ok(invites) => response(StatusCode::OK, &invites),
+    Err(error) => error_response(error),
+  }
+}
+
+#[handler]
+pub async fn create_user_invite(
+  Json(new_invite): Json<invite::CreateUserInvite>,
+  request: &Request,
+  Data(pool): Data<&DbPool>,
+) -> Response {
+  let session = match authorize_session(pool, request).await {
+    Ok(session) => session,
+    Err(error) => return error_response(error),
+  };
+
+  let quota = invite::user_invite_quota();
+  let metadata = auth::session_metadata(request);
+
+  let created = run_blocking(pool, move |conn| {
+    invite::create_user_invite(conn, &session.user_id, quota, new_invite, &metadata)
+  })
+  .await;
+
+  match created {
+    Ok(created) => response(StatusCode::CREATED, &created),
+    Err(error) => error_response(error),
+  }
+}
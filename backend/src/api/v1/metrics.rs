@@ -1,8 +1,10 @@
 use crate::{
-  services::user,
+  run_blocking,
+  services::{authorize_admin, user},
   util::{error_response, response, unix_ms, EphemerideError},
+  DbPool,
 };
-use poem::{handler, http::StatusCode, Response};
+use poem::{handler, http::StatusCode, web::Data, Request, Response};
 
 #[derive(Debug, serde::Serialize)]
 pub struct MetricsResponse {
@@ -14,36 +16,46 @@ pub struct MetricsResponse {
 }
 
 #[handler]
-pub fn metrics() -> Response {
-  let total_users = match user::user_count() {
-    Ok(count) => count,
-    Err(_) => return error_response(EphemerideError::InternalServerError),
-  };
-  let active_1h = match user::active_user_count(unix_ms() - 60 * 60 * 1000) {
-    Ok(count) => count,
-    Err(_) => return error_response(EphemerideError::InternalServerError),
-  };
-  let active_24h = match user::active_user_count(unix_ms() - 24 * 60 * 60 * 1000) {
-    Ok(count) => count,
-    Err(_) => return error_response(EphemerideError::InternalServerError),
-  };
-  let active_7d = match user::active_user_count(unix_ms() - 7 * 24 * 60 * 60 * 1000) {
-    Ok(count) => count,
-    Err(_) => return error_response(EphemerideError::InternalServerError),
-  };
-  let active_30d = match user::active_user_count(unix_ms() - 30 * 24 * 60 * 60 * 1000) {
-    Ok(count) => count,
-    Err(_) => return error_response(EphemerideError::InternalServerError),
+pub async fn metrics(request: &Request, Data(pool): Data<&DbPool>) -> Response {
+  match authorize_admin(pool, request).await {
+    Ok(_) => (),
+    Err(error) => return error_response(error),
   };
 
-  response(
-    StatusCode::OK,
-    &MetricsResponse {
+  let metrics = run_blocking(pool, |conn| {
+    let total_users = match user::user_count(conn) {
+      Ok(count) => count,
+      Err(_) => return Err(EphemerideError::InternalServerError),
+    };
+    let active_1h = match user::active_user_count(conn, unix_ms() - 60 * 60 * 1000) {
+      Ok(count) => count,
+      Err(_) => return Err(EphemerideError::InternalServerError),
+    };
+    let active_24h = match user::active_user_count(conn, unix_ms() - 24 * 60 * 60 * 1000) {
+      Ok(count) => count,
+      Err(_) => return Err(EphemerideError::InternalServerError),
+    };
+    let active_7d = match user::active_user_count(conn, unix_ms() - 7 * 24 * 60 * 60 * 1000) {
+      Ok(count) => count,
+      Err(_) => return Err(EphemerideError::InternalServerError),
+    };
+    let active_30d = match user::active_user_count(conn, unix_ms() - 30 * 24 * 60 * 60 * 1000) {
+      Ok(count) => count,
+      Err(_) => return Err(EphemerideError::InternalServerError),
+    };
+
+    Ok(MetricsResponse {
       total_users,
       active_1h,
       active_24h,
       active_7d,
       active_30d,
-    },
-  )
+    })
+  })
+  .await;
+
+  match metrics {
+    Ok(metrics) => response(StatusCode::OK, &metrics),
+    Err(error) => error_response(error),
+  }
 }
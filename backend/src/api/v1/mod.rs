@@ -15,5 +15,19 @@ pub mod entries;
 pub use entries::*;
 pub mod sessions;
 pub use sessions::*;
+pub mod access_tokens;
+pub use access_tokens::*;
+pub mod invites;
+pub use invites::*;
+pub mod export;
+pub use export::*;
+pub mod security_events;
+pub use security_events::*;
+pub mod totp;
+pub use totp::*;
+pub mod oidc;
+pub use oidc::*;
 pub mod metrics;
 pub use metrics::*;
+pub mod admin;
+pub use admin::*;
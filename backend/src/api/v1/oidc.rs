@@ -0,0 +1,170 @@
+use super::session_response;
+use crate::{
+  run_blocking,
+  services::{auth, authorize_session, oidc},
+  util::{error::error_response, response, EphemerideError},
+  DbPool,
+};
+use poem::{
+  handler,
+  http::StatusCode,
+  web::{Data, Json, Path},
+  Request, Response,
+};
+
+use dotenvy::dotenv;
+use std::env;
+
+// requests to the identity provider can take a while, they run on the
+// blocking pool like run_blocking but without holding a database connection
+async fn call_provider<F, T>(f: F) -> Result<T, EphemerideError>
+where
+  F: FnOnce() -> Result<T, EphemerideError> + Send + 'static,
+  T: Send + 'static,
+{
+  match tokio::task::spawn_blocking(f).await {
+    Ok(result) => result,
+    Err(_) => Err(EphemerideError::InternalServerError),
+  }
+}
+
+#[handler]
+pub async fn start_oidc_login(
+  Json(start): Json<oidc::StartOidcLogin>,
+  request: &Request,
+  Data(pool): Data<&DbPool>,
+) -> Response {
+  let config = match oidc::OidcConfig::from_env() {
+    Some(config) => config,
+    None => return error_response(EphemerideError::OidcNotConfigured),
+  };
+
+  // a request made with a session links the identity to that user instead
+  // of logging in with it
+  let user_id = match auth::has_credentials(request) {
+    true => match authorize_session(pool, request).await {
+      Ok(session) => Some(session.user_id),
+      Err(error) => return error_response(error),
+    },
+    false => None,
+  };
+
+  let discover_config = config.clone();
+  let metadata = match call_provider(move || oidc::discover(&discover_config)).await {
+    Ok(metadata) => metadata,
+    Err(error) => return error_response(error),
+  };
+
+  let authorization = run_blocking(pool, move |conn| {
+    oidc::start_oidc_login(conn, &config, &metadata, user_id, start)
+  })
+  .await;
+
+  match authorization {
+    Ok(authorization) => response(StatusCode::OK, &authorization),
+    Err(error) => error_response(error),
+  }
+}
+
+#[handler]
+pub async fn complete_oidc_login(
+  Json(callback): Json<oidc::OidcCallback>,
+  request: &Request,
+  Data(pool): Data<&DbPool>,
+  Data(settings): Data<&auth::SessionSettings>,
+) -> Response {
+  dotenv().ok();
+
+  let config = match oidc::OidcConfig::from_env() {
+    Some(config) => config,
+    None => return error_response(EphemerideError::OidcNotConfigured),
+  };
+
+  // only needed when the callback finishes linking an identity, a stale
+  // session doesn't get in the way of logging in
+  let session_user_id = match auth::has_credentials(request) {
+    true => match authorize_session(pool, request).await {
+      Ok(session) => Some(session.user_id),
+      Err(_) => None,
+    },
+    false => None,
+  };
+
+  let invite_required = env::var("INVITE_REQUIRED").unwrap_or("false".to_string()) == "true";
+  let metadata = auth::session_metadata(request);
+
+  let claimed = run_blocking(pool, move |conn| {
+    let login = oidc::claim_oidc_login(conn, &callback, session_user_id)?;
+    Ok((login, callback))
+  })
+  .await;
+
+  let (login, callback) = match claimed {
+    Ok(claimed) => claimed,
+    Err(error) => return error_response(error),
+  };
+
+  let provider_config = config.clone();
+  let verified = call_provider(move || {
+    let claims = oidc::verify_oidc_callback(&provider_config, &callback, &login)?;
+    Ok((login, claims))
+  })
+  .await;
+
+  let (login, claims) = match verified {
+    Ok(verified) => verified,
+    Err(error) => return error_response(error),
+  };
+
+  let completed = run_blocking(pool, move |conn| {
+    oidc::complete_oidc_login(conn, &config, login, claims, invite_required, metadata)
+  })
+  .await;
+
+  match completed {
+    Ok(oidc::OidcCallbackResult::LoggedIn(session)) => session_response(settings, session),
+    Ok(oidc::OidcCallbackResult::Linked(identity)) => response(StatusCode::CREATED, &identity),
+    Err(error) => error_response(error),
+  }
+}
+
+#[handler]
+pub async fn get_user_identities(request: &Request, Data(pool): Data<&DbPool>) -> Response {
+  let session = match authorize_session(pool, request).await {
+    Ok(session) => session,
+    Err(error) => return error_response(error),
+  };
+
+  let identities = run_blocking(pool, move |conn| {
+    oidc::get_user_identities(conn, &session.user_id)
+  })
+  .await;
+
+  match identities {
+    Ok(identities) => response(StatusCode::OK, &identities),
+    Err(error) => error_response(error),
+  }
+}
+
+#[handler]
+pub async fn delete_user_identity(
+  Path(id): Path<String>,
+  request: &Request,
+  Data(pool): Data<&DbPool>,
+) -> Response {
+  let session = match authorize_session(pool, request).await {
+    Ok(session) => session,
+    Err(error) => return error_response(error),
+  };
+
+  let deleted = run_blocking(pool, move |conn| {
+    oidc::delete_user_identity(conn, &id, &session.user_id)
+  })
+  .await;
+
+  match deleted {
+    Ok(true) => response(StatusCode::NO_CONTENT, &()),
+    Ok(false) => error_response(EphemerideError::IdentityNotFound),
+    Err(error) => error_response(error),
+  }
+}
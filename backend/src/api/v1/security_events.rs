@@ -0,0 +1,34 @@
+use crate::{
+  run_blocking,
+  services::{audit, authorize_session},
+  util::{error::error_response, response},
+  DbPool,
+};
+use poem::{
+  handler,
+  http::StatusCode,
+  web::{Data, Query},
+  Request, Response,
+};
+
+#[handler]
+pub async fn get_security_events(
+  Query(options): Query<audit::AuditEventOptions>,
+  request: &Request,
+  Data(pool): Data<&DbPool>,
+) -> Response {
+  let session = match authorize_session(pool, request).await {
+    Ok(session) => session,
+    Err(error) => return error_response(error),
+  };
+
+  let audit_events = run_blocking(pool, move |conn| {
+    audit::get_user_audit_events(conn, &session.user_id, options)
+  })
+  .await;
+
+  match audit_events {
+    Ok(audit_events) => response(StatusCode::OK, &audit_events),
+    Err(error) => error_response(error),
+  }
+}
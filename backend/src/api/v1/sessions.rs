@@ -1,20 +1,110 @@
 use crate::{
-  services::{auth, authorize_request},
-  util::{error::error_response, response},
+  run_blocking,
+  services::{auth, authorize_session, SessionDetails},
+  util::{error::error_response, response, EphemerideError},
+  DbPool,
+};
+use poem::{
+  handler,
+  http::StatusCode,
+  web::{Data, Json, Path},
+  Request, Response,
 };
-use poem::{handler, http::StatusCode, Request, Response};
 
 #[handler]
-pub fn get_sessions(request: &Request) -> Response {
-  let session = match authorize_request(request) {
+pub async fn get_sessions(request: &Request, Data(pool): Data<&DbPool>) -> Response {
+  let session = match authorize_session(pool, request).await {
     Ok(session) => session,
     Err(error) => return error_response(error),
   };
 
-  let sessions = auth::get_all_user_sessions(&session.user_id);
+  let current_session_id = session.id.clone();
+  let sessions = run_blocking(pool, move |conn| {
+    auth::get_all_user_sessions(conn, &session.user_id)
+  })
+  .await;
 
   match sessions {
-    Ok(sessions) => response(StatusCode::OK, &sessions),
+    Ok(sessions) => {
+      let sessions: Vec<SessionDetails> = sessions
+        .into_iter()
+        .map(|session| SessionDetails {
+          current: session.id == current_session_id,
+          session,
+        })
+        .collect();
+
+      response(StatusCode::OK, &sessions)
+    }
+    Err(error) => error_response(error),
+  }
+}
+
+#[handler]
+pub async fn rename_session(
+  Path(id): Path<String>,
+  Json(rename): Json<auth::RenameSession>,
+  request: &Request,
+  Data(pool): Data<&DbPool>,
+) -> Response {
+  let session = match authorize_session(pool, request).await {
+    Ok(session) => session,
+    Err(error) => return error_response(error),
+  };
+
+  let renamed_session = run_blocking(pool, move |conn| {
+    auth::rename_user_session(conn, &id, &session.user_id, rename)
+  })
+  .await;
+
+  match renamed_session {
+    Ok(true) => response(StatusCode::NO_CONTENT, &()),
+    Ok(false) => error_response(EphemerideError::SessionNotFound),
+    Err(error) => error_response(error),
+  }
+}
+
+#[handler]
+pub async fn delete_session(
+  Path(id): Path<String>,
+  request: &Request,
+  Data(pool): Data<&DbPool>,
+) -> Response {
+  let session = match authorize_session(pool, request).await {
+    Ok(session) => session,
+    Err(error) => return error_response(error),
+  };
+
+  let metadata = auth::session_metadata(request);
+
+  let deleted_session = run_blocking(pool, move |conn| {
+    auth::revoke_user_session(conn, &id, &session.user_id, &metadata)
+  })
+  .await;
+
+  match deleted_session {
+    Ok(true) => response(StatusCode::NO_CONTENT, &()),
+    Ok(false) => error_response(EphemerideError::SessionNotFound),
+    Err(error) => error_response(error),
+  }
+}
+
+#[handler]
+pub async fn delete_other_sessions(request: &Request, Data(pool): Data<&DbPool>) -> Response {
+  let session = match authorize_session(pool, request).await {
+    Ok(session) => session,
+    Err(error) => return error_response(error),
+  };
+
+  let metadata = auth::session_metadata(request);
+
+  let deleted_sessions = run_blocking(pool, move |conn| {
+    auth::revoke_other_user_sessions(conn, &session.user_id, &session.id, &metadata)
+  })
+  .await;
+
+  match deleted_sessions {
+    Ok(_) => response(StatusCode::NO_CONTENT, &()),
     Err(error) => error_response(error),
   }
 }
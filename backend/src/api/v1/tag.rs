@@ -1,11 +1,13 @@
 use crate::{
-  services::{authorize_request, log},
+  run_blocking,
+  services::{authorize_request, log, Scope},
   util::{error::error_response, response, EphemerideError},
+  DbPool,
 };
 use poem::{
   handler,
   http::StatusCode,
-  web::{Json, Path},
+  web::{Data, Json, Path},
   Request, Response,
 };
 use serde::{Deserialize, Serialize};
@@ -24,18 +26,28 @@ struct EditTagRequest {
 }
 
 #[handler]
-pub fn create_tag(Json(tag): Json<CreateTagRequest>, request: &Request) -> Response {
-  let session = match authorize_request(request) {
-    Ok(session) => session,
+pub async fn create_tag(
+  Json(tag): Json<CreateTagRequest>,
+  request: &Request,
+  Data(pool): Data<&DbPool>,
+) -> Response {
+  let authorization = match authorize_request(pool, request, Scope::TagsWrite).await {
+    Ok(authorization) => authorization,
     Err(error) => return error_response(error),
   };
 
-  let created_tag = log::create_tag(log::CreateTag {
-    name: tag.name,
-    color: tag.color,
-    category_id: tag.category_id,
-    user_id: session.user_id,
-  });
+  let created_tag = run_blocking(pool, move |conn| {
+    log::create_tag(
+      conn,
+      log::CreateTag {
+        name: tag.name,
+        color: tag.color,
+        category_id: tag.category_id,
+        user_id: authorization.user_id,
+      },
+    )
+  })
+  .await;
 
   match created_tag {
     Ok(created_tag) => response(StatusCode::CREATED, &created_tag),
@@ -44,22 +56,29 @@ pub fn create_tag(Json(tag): Json<CreateTagRequest>, request: &Request) -> Respo
 }
 
 #[handler]
-pub fn edit_tag(
+pub async fn edit_tag(
   Path(id): Path<String>,
   Json(tag): Json<EditTagRequest>,
   request: &Request,
+  Data(pool): Data<&DbPool>,
 ) -> Response {
-  let session = match authorize_request(request) {
-    Ok(session) => session,
+  let authorization = match authorize_request(pool, request, Scope::TagsWrite).await {
+    Ok(authorization) => authorization,
     Err(error) => return error_response(error),
   };
 
-  let edited_tag = log::edit_tag(log::EditTag {
-    id,
-    name: tag.name,
-    color: tag.color,
-    user_id: session.user_id,
-  });
+  let edited_tag = run_blocking(pool, move |conn| {
+    log::edit_tag(
+      conn,
+      log::EditTag {
+        id,
+        name: tag.name,
+        color: tag.color,
+        user_id: authorization.user_id,
+      },
+    )
+  })
+  .await;
 
   match edited_tag {
     Ok(edited_tag) => response(StatusCode::OK, &edited_tag),
@@ -68,19 +87,24 @@ pub fn edit_tag(
 }
 
 #[handler]
-pub fn delete_tag(Path(id): Path<String>, request: &Request) -> Response {
-  let session = match authorize_request(request) {
-    Ok(session) => session,
+pub async fn delete_tag(
+  Path(id): Path<String>,
+  request: &Request,
+  Data(pool): Data<&DbPool>,
+) -> Response {
+  let authorization = match authorize_request(pool, request, Scope::TagsWrite).await {
+    Ok(authorization) => authorization,
     Err(error) => return error_response(error),
   };
 
-  let deleted_tag = match log::delete_tag(&id, &session.user_id) {
-    Ok(deleted) => deleted,
-    Err(error) => return error_response(error),
-  };
+  let deleted_tag = run_blocking(pool, move |conn| {
+    log::delete_tag(conn, &id, &authorization.user_id)
+  })
+  .await;
 
   match deleted_tag {
-    true => response(StatusCode::NO_CONTENT, &()),
-    false => error_response(EphemerideError::TagNotFound),
+    Ok(true) => response(StatusCode::NO_CONTENT, &()),
+    Ok(false) => error_response(EphemerideError::TagNotFound),
+    Err(error) => error_response(error),
   }
 }
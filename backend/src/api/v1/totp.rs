@@ -0,0 +1,96 @@
+use crate::{
+  run_blocking,
+  services::{authorize_session, totp},
+  util::{
+    error::{error_response, EphemerideError},
+    response,
+  },
+  DbPool,
+};
+use poem::{
+  handler,
+  http::StatusCode,
+  web::{Data, Json},
+  Request, Response,
+};
+
+#[handler]
+pub async fn get_totp_status(request: &Request, Data(pool): Data<&DbPool>) -> Response {
+  let session = match authorize_session(pool, request).await {
+    Ok(session) => session,
+    Err(error) => return error_response(error),
+  };
+
+  let enabled = run_blocking(pool, move |conn| {
+    totp::is_totp_enabled(conn, &session.user_id)
+  })
+  .await;
+
+  match enabled {
+    Ok(enabled) => response(StatusCode::OK, &totp::TotpStatus { enabled }),
+    Err(error) => error_response(error),
+  }
+}
+
+#[handler]
+pub async fn start_totp_enrollment(request: &Request, Data(pool): Data<&DbPool>) -> Response {
+  let session = match authorize_session(pool, request).await {
+    Ok(session) => session,
+    Err(error) => return error_response(error),
+  };
+
+  let enrollment = run_blocking(pool, move |conn| {
+    totp::start_totp_enrollment(conn, &session.user_id)
+  })
+  .await;
+
+  match enrollment {
+    Ok(enrollment) => response(StatusCode::CREATED, &enrollment),
+    Err(error) => error_response(error),
+  }
+}
+
+#[handler]
+pub async fn confirm_totp_enrollment(
+  Json(confirm): Json<totp::ConfirmTotp>,
+  request: &Request,
+  Data(pool): Data<&DbPool>,
+) -> Response {
+  let session = match authorize_session(pool, request).await {
+    Ok(session) => session,
+    Err(error) => return error_response(error),
+  };
+
+  let recovery_codes = run_blocking(pool, move |conn| {
+    totp::confirm_totp_enrollment(conn, &session.user_id, confirm)
+  })
+  .await;
+
+  match recovery_codes {
+    Ok(recovery_codes) => response(StatusCode::OK, &recovery_codes),
+    Err(error) => error_response(error),
+  }
+}
+
+#[handler]
+pub async fn disable_totp(
+  Json(disable): Json<totp::DisableTotp>,
+  request: &Request,
+  Data(pool): Data<&DbPool>,
+) -> Response {
+  let session = match authorize_session(pool, request).await {
+    Ok(session) => session,
+    Err(error) => return error_response(error),
+  };
+
+  let disabled = run_blocking(pool, move |conn| {
+    totp::disable_totp(conn, &session.user_id, disable)
+  })
+  .await;
+
+  match disabled {
+    Ok(true) => response(StatusCode::NO_CONTENT, &()),
+    Ok(false) => error_response(EphemerideError::TotpNotFound),
+    Err(error) => error_response(error),
+  }
+}
@@ -1,57 +1,128 @@
+use super::session_response;
 use crate::{
-  services::{auth, authorize_request, invite, log, user, UserCredentials},
+  mailer::{send_in_background, SharedMailer},
+  run_blocking,
+  services::{
+    auth, authorize_request, authorize_session, email_verification, invite, log, throttle, user,
+    Scope, UserCredentials,
+  },
   util::{
-    error::{error_response, EphemerideError},
+    error::{error_response, too_many_requests_response, EphemerideError},
     response,
   },
+  DbPool,
+};
+use diesel::Connection;
+use poem::{
+  handler,
+  http::StatusCode,
+  web::{Data, Json},
+  Request, Response,
 };
-use poem::{handler, http::StatusCode, web::Json, Request, Response};
 
 use dotenvy::dotenv;
 use std::env;
 
 #[handler]
-pub fn create_user(Json(user): Json<user::CreateUser>, request: &Request) -> Response {
+pub async fn create_user(
+  Json(user): Json<user::CreateUser>,
+  request: &Request,
+  Data(pool): Data<&DbPool>,
+  Data(mailer): Data<&SharedMailer>,
+  Data(settings): Data<&auth::SessionSettings>,
+) -> Response {
   dotenv().ok();
 
-  if env::var("INVITE_REQUIRED").unwrap_or("false".to_string()) == "true" {
-    match &user.invite {
-      Some(invite) => match invite::use_invite(invite) {
-        Ok(_) => (),
-        Err(_) => return error_response(EphemerideError::InviteNotFound),
-      },
-      None => return error_response(EphemerideError::InviteNotFound),
-    }
-  }
+  let invite_required = env::var("INVITE_REQUIRED").unwrap_or("false".to_string()) == "true";
+  let verification_required = email_verification::email_verification_required();
+  let metadata = auth::session_metadata(request);
+  let mailer = mailer.clone();
 
-  let password = user.password.clone();
-  let created_user = match user::create_user(user) {
-    Ok(user) => user,
-    Err(error) => return error_response(error),
-  };
+  let keys = vec![throttle::signup_key(&auth::client_ip(request))];
 
-  let session = auth::create_user_session(
-    UserCredentials {
-      email: created_user.email,
-      password,
-    },
-    auth::session_metadata(request),
-  );
+  let locked_keys = keys.clone();
+  let locked = run_blocking(pool, move |conn| throttle::retry_after(conn, &locked_keys)).await;
 
-  match session {
-    Ok(session) => response(StatusCode::CREATED, &session),
+  match locked {
+    Ok(Some(retry_after)) => return too_many_requests_response(retry_after),
+    Ok(None) => (),
+    Err(error) => return error_response(error),
+  }
+
+  // redeeming the invite, creating the user and signing them in either all
+  // happen or none of them do, so a failed signup doesn't burn the invite
+  let created = run_blocking(pool, move |conn| {
+    // every signup counts, successful or not, and is recorded outside the
+    // transaction so a failed attempt isn't rolled back with the rest
+    throttle::record_attempt(conn, &keys)?;
+
+    let (created_user, token, session) = conn.transaction(|conn| {
+      // the user references the invite that was redeemed, not the code they
+      // typed in, and the code is ignored when no invite is required
+      let invite_id = match (invite_required, &user.invite) {
+        (true, Some(code)) => Some(invite::use_invite(conn, code)?.id),
+        (true, None) => return Err(EphemerideError::InviteNotFound),
+        (false, _) => None,
+      };
+      let user = user::CreateUser {
+        invite: invite_id,
+        ..user
+      };
+
+      let password = user.password.clone();
+      let created_user = user::create_user(conn, user)?;
+      let token =
+        email_verification::create_email_verification(conn, &created_user.id, &created_user.email)?;
+
+      // unverified accounts can't log in when verification is required, so
+      // they only get a session once the email has been confirmed
+      let session = match verification_required {
+        true => None,
+        false => Some(auth::create_user_session(
+          conn,
+          UserCredentials {
+            email: created_user.email.clone(),
+            password,
+            totp_code: None,
+          },
+          metadata,
+        )?),
+      };
+
+      Ok((created_user, token, session))
+    })?;
+
+    let verification = email_verification::verification_email(&created_user.email, &token);
+
+    Ok((created_user, session, verification))
+  })
+  .await;
+
+  // the verification email goes out once the connection is back in the pool
+  match created {
+    Ok((_, Some(session), verification)) => {
+      send_in_background(mailer, verification);
+      session_response(settings, session)
+    }
+    Ok((created_user, None, verification)) => {
+      send_in_background(mailer, verification);
+      response(StatusCode::CREATED, &created_user)
+    }
     Err(error) => error_response(error),
   }
 }
 
 #[handler]
-pub fn get_current_user(request: &Request) -> Response {
-  let session = match authorize_request(request) {
-    Ok(session) => session,
+pub async fn get_current_user(request: &Request, Data(pool): Data<&DbPool>) -> Response {
+  let authorization = match authorize_request(pool, request, Scope::UserRead).await {
+    Ok(authorization) => authorization,
     Err(error) => return error_response(error),
   };
 
-  let user = user::get_user(&session.user_id);
+  let user = run_blocking(pool, move |conn| {
+    user::get_user(conn, &authorization.user_id)
+  })
+  .await;
 
   match user {
     Ok(user) => response(StatusCode::OK, &user),
@@ -60,70 +131,98 @@ pub fn get_current_user(request: &Request) -> Response {
 }
 
 #[handler]
-pub fn delete_user(request: &Request) -> Response {
-  let session = match authorize_request(request) {
+pub async fn delete_user(
+  Json(deletion): Json<user::DeleteUser>,
+  request: &Request,
+  Data(pool): Data<&DbPool>,
+) -> Response {
+  let session = match authorize_session(pool, request).await {
     Ok(session) => session,
     Err(error) => return error_response(error),
   };
 
-  let deleted = match user::delete_user(&session.user_id) {
-    Ok(deleted) => deleted,
-    Err(error) => return error_response(error),
-  };
+  let scheduled = run_blocking(pool, move |conn| {
+    user::schedule_user_deletion(conn, &session.user_id, deletion)
+  })
+  .await;
 
-  match deleted {
-    true => response(StatusCode::NO_CONTENT, &()),
-    false => error_response(EphemerideError::UserNotFound),
+  match scheduled {
+    Ok(scheduled) => response(StatusCode::ACCEPTED, &scheduled),
+    Err(error) => error_response(error),
   }
 }
 
 #[handler]
-pub fn update_user(Json(user): Json<user::UpdateUser>, request: &Request) -> Response {
-  let session = match authorize_request(request) {
+pub async fn update_user(
+  Json(user): Json<user::UpdateUser>,
+  request: &Request,
+  Data(pool): Data<&DbPool>,
+  Data(mailer): Data<&SharedMailer>,
+) -> Response {
+  let session = match authorize_session(pool, request).await {
     Ok(session) => session,
     Err(error) => return error_response(error),
   };
 
-  let updated_user = match user::update_user(&session.user_id, user) {
-    Ok(user) => user,
-    Err(error) => return error_response(error),
-  };
+  let metadata = auth::session_metadata(request);
+
+  let updated_user = run_blocking(pool, move |conn| {
+    user::update_user(conn, &session.user_id, user, &metadata)
+  })
+  .await;
 
   match updated_user {
-    true => response(StatusCode::NO_CONTENT, &()),
-    false => error_response(EphemerideError::UserNotFound),
+    Ok(Some(verification)) => {
+      send_in_background(mailer.clone(), verification);
+      response(StatusCode::NO_CONTENT, &())
+    }
+    Ok(None) => response(StatusCode::NO_CONTENT, &()),
+    Err(error) => error_response(error),
   }
 }
 
 #[handler]
-pub fn update_password(Json(password): Json<user::UpdatePassword>, request: &Request) -> Response {
-  let session = match authorize_request(request) {
+pub async fn update_password(
+  Json(password): Json<user::UpdatePassword>,
+  request: &Request,
+  Data(pool): Data<&DbPool>,
+) -> Response {
+  let session = match authorize_session(pool, request).await {
     Ok(session) => session,
     Err(error) => return error_response(error),
   };
 
-  let updated_password = match user::update_password(&session.user_id, password) {
-    Ok(updated) => updated,
-    Err(error) => return error_response(error),
-  };
+  let metadata = auth::session_metadata(request);
+
+  let updated_password = run_blocking(pool, move |conn| {
+    user::update_password(conn, &session.user_id, &session.id, password, &metadata)
+  })
+  .await;
 
   match updated_password {
-    true => response(StatusCode::NO_CONTENT, &()),
-    false => error_response(EphemerideError::UserNotFound),
+    Ok(true) => response(StatusCode::NO_CONTENT, &()),
+    Ok(false) => error_response(EphemerideError::UserNotFound),
+    Err(error) => error_response(error),
   }
 }
 
 #[handler]
-pub fn get_user_categories_with_tags(request: &Request) -> Response {
-  let session = match authorize_request(request) {
-    Ok(session) => session,
+pub async fn get_user_categories_with_tags(
+  request: &Request,
+  Data(pool): Data<&DbPool>,
+) -> Response {
+  let authorization = match authorize_request(pool, request, Scope::TagsRead).await {
+    Ok(authorization) => authorization,
     Err(error) => return error_response(error),
   };
 
-  let categories = match log::get_user_categories_with_tags(&session.user_id) {
-    Ok(categories) => categories,
-    Err(error) => return error_response(error),
-  };
+  let categories = run_blocking(pool, move |conn| {
+    log::get_user_categories_with_tags(conn, &authorization.user_id)
+  })
+  .await;
 
-  response(StatusCode::OK, &categories)
+  match categories {
+    Ok(categories) => response(StatusCode::OK, &categories),
+    Err(error) => error_response(error),
+  }
 }
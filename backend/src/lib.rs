@@ -1,13 +1,26 @@
 #![forbid(unsafe_code)]
 
 pub mod api;
+pub mod mailer;
+pub mod migrations;
 pub mod schema;
 pub mod services;
 pub mod util;
 
-use diesel::{pg, Connection};
+use diesel::{
+  pg,
+  r2d2::{ConnectionManager, Pool, PooledConnection},
+  Connection,
+};
 use dotenvy::dotenv;
-use std::env;
+use std::{env, time::Duration};
+use util::EphemerideError;
+
+pub type DbPool = Pool<ConnectionManager<pg::PgConnection>>;
+pub type DbConnection = PooledConnection<ConnectionManager<pg::PgConnection>>;
+
+const DEFAULT_POOL_SIZE: u32 = 10;
+const DEFAULT_POOL_TIMEOUT_MS: u64 = 5000;
 
 pub fn establish_connection() -> pg::PgConnection {
   dotenv().ok();
@@ -16,3 +29,54 @@ pub fn establish_connection() -> pg::PgConnection {
   pg::PgConnection::establish(&database_url)
     .unwrap_or_else(|_| panic!("Error connecting to {database_url}"))
 }
+
+pub fn establish_pool() -> DbPool {
+  dotenv().ok();
+
+  let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+
+  let pool_size = match env::var("DATABASE_POOL_SIZE") {
+    Ok(val) => val.parse::<u32>().unwrap_or(DEFAULT_POOL_SIZE),
+    Err(_) => DEFAULT_POOL_SIZE,
+  };
+  let pool_timeout = match env::var("DATABASE_POOL_TIMEOUT_MS") {
+    Ok(val) => val.parse::<u64>().unwrap_or(DEFAULT_POOL_TIMEOUT_MS),
+    Err(_) => DEFAULT_POOL_TIMEOUT_MS,
+  };
+
+  Pool::builder()
+    .max_size(pool_size)
+    .connection_timeout(Duration::from_millis(pool_timeout))
+    .build(ConnectionManager::<pg::PgConnection>::new(&database_url))
+    .unwrap_or_else(|_| panic!("Error creating connection pool for {database_url}"))
+}
+
+// checking out a connection only fails once the pool has been
+// exhausted for the whole checkout timeout
+pub fn get_connection(pool: &DbPool) -> Result<DbConnection, EphemerideError> {
+  match pool.get() {
+    Ok(conn) => Ok(conn),
+    Err(_) => Err(EphemerideError::PoolExhausted),
+  }
+}
+
+// diesel and password hashing are both blocking, so anything touching them is
+// moved off the async workers and onto tokio's blocking thread pool
+pub async fn run_blocking<F, T>(pool: &DbPool, f: F) -> Result<T, EphemerideError>
+where
+  F: FnOnce(&mut pg::PgConnection) -> Result<T, EphemerideError> + Send + 'static,
+  T: Send + 'static,
+{
+  let pool = pool.clone();
+
+  let result = tokio::task::spawn_blocking(move || {
+    let mut conn = get_connection(&pool)?;
+    f(&mut conn)
+  })
+  .await;
+
+  match result {
+    Ok(result) => result,
+    Err(_) => Err(EphemerideError::InternalServerError),
+  }
+}
@@ -0,0 +1,86 @@
+use crate::mailer::{Email, Mailer, MailerError};
+use std::{fs::OpenOptions, io::Write, path::PathBuf, sync::Mutex};
+
+// writes every email as plain text instead of sending it, either appended
+// to a file or printed to stdout
+pub struct FileMailer {
+  path: Option<PathBuf>,
+  lock: Mutex<()>,
+}
+
+impl FileMailer {
+  pub fn new(path: impl Into<PathBuf>) -> FileMailer {
+    FileMailer {
+      path: Some(path.into()),
+      lock: Mutex::new(()),
+    }
+  }
+
+  pub fn stdout() -> FileMailer {
+    FileMailer {
+      path: None,
+      lock: Mutex::new(()),
+    }
+  }
+}
+
+fn format_email(email: &Email) -> String {
+  format!(
+    "To: {}\nSubject: {}\n\n{}\n---\n",
+    email.to, email.subject, email.body
+  )
+}
+
+impl Mailer for FileMailer {
+  fn send(&self, email: &Email) -> Result<(), MailerError> {
+    let _guard = match self.lock.lock() {
+      Ok(guard) => guard,
+      Err(poisoned) => poisoned.into_inner(),
+    };
+
+    let path = match &self.path {
+      Some(path) => path,
+      None => {
+        print!("{}", format_email(email));
+        return Ok(());
+      }
+    };
+
+    let mut file = match OpenOptions::new().create(true).append(true).open(path) {
+      Ok(file) => file,
+      Err(error) => return Err(MailerError(error.to_string())),
+    };
+
+    match file.write_all(format_email(email).as_bytes()) {
+      Ok(_) => Ok(()),
+      Err(error) => Err(MailerError(error.to_string())),
+    }
+  }
+}
+
+#[cfg(test)]
+mod ci_unit {
+  use super::*;
+
+  #[test]
+  fn test_file_mailer_appends() {
+    let path = std::env::temp_dir().join(format!("ephemeride-mail-{}", uuid::Uuid::new_v4()));
+    let mailer = FileMailer::new(&path);
+
+    for subject in ["first", "second"] {
+      mailer
+        .send(&Email {
+          to: "user@example.com".to_string(),
+          subject: subject.to_string(),
+          body: "body".to_string(),
+        })
+        .unwrap();
+    }
+
+    let contents = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(contents.starts_with("To: user@example.com\nSubject: first\n\nbody\n---\n"));
+    assert!(contents.contains("Subject: second"));
+  }
+}
@@ -0,0 +1,57 @@
+pub mod file;
+pub use file::*;
+pub mod smtp;
+pub use smtp::*;
+
+use dotenvy::dotenv;
+use std::{env, fmt, sync::Arc};
+
+#[derive(Debug, Clone)]
+pub struct Email {
+  pub to: String,
+  pub subject: String,
+  pub body: String,
+}
+
+#[derive(Debug)]
+pub struct MailerError(pub String);
+
+impl fmt::Display for MailerError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.0)
+  }
+}
+
+// sending is blocking, mailers are called from the blocking pool with
+// `send_in_background`, never while holding a database connection
+pub trait Mailer: Send + Sync {
+  fn send(&self, email: &Email) -> Result<(), MailerError>;
+}
+
+pub type SharedMailer = Arc<dyn Mailer>;
+
+// the request doesn't wait for the mail server, so how long it takes can't
+// tell anyone whether an email was sent at all, and a failed delivery is
+// only logged, the user can always ask for another email
+pub fn send_in_background(mailer: SharedMailer, email: Email) {
+  tokio::task::spawn_blocking(move || {
+    if let Err(error) = mailer.send(&email) {
+      tracing::warn!("failed to send \"{}\" email: {error}", email.subject);
+    }
+  });
+}
+
+// MAILER=smtp sends real mail, MAILER=file appends to MAIL_FILE and anything
+// else prints to stdout, which is enough for development
+pub fn mailer_from_env() -> SharedMailer {
+  dotenv().ok();
+
+  match env::var("MAILER").unwrap_or("stdout".to_string()).as_str() {
+    "smtp" => Arc::new(SmtpMailer::from_env()),
+    "file" => {
+      let path = env::var("MAIL_FILE").expect("MAIL_FILE must be set when MAILER=file");
+      Arc::new(FileMailer::new(path))
+    }
+    _ => Arc::new(FileMailer::stdout()),
+  }
+}
@@ -0,0 +1,67 @@
+use crate::mailer::{Email, Mailer, MailerError};
+use dotenvy::dotenv;
+use lettre::{
+  message::Mailbox, transport::smtp::authentication::Credentials, Message, SmtpTransport, Transport,
+};
+use std::env;
+
+pub struct SmtpMailer {
+  transport: SmtpTransport,
+  from: Mailbox,
+}
+
+impl SmtpMailer {
+  // SMTP_TLS picks between STARTTLS (default), implicit TLS ("tls") and
+  // plain text ("none") for local catch-all servers
+  pub fn from_env() -> SmtpMailer {
+    dotenv().ok();
+
+    let host = env::var("SMTP_HOST").expect("SMTP_HOST must be set when MAILER=smtp");
+    let from = env::var("MAIL_FROM").expect("MAIL_FROM must be set when MAILER=smtp");
+    let tls = env::var("SMTP_TLS").unwrap_or("starttls".to_string());
+
+    let mut builder = match tls.as_str() {
+      "tls" => SmtpTransport::relay(&host),
+      "none" => Ok(SmtpTransport::builder_dangerous(&host)),
+      _ => SmtpTransport::starttls_relay(&host),
+    }
+    .unwrap_or_else(|_| panic!("Error configuring SMTP relay {host}"));
+
+    if let Ok(port) = env::var("SMTP_PORT") {
+      builder = builder.port(port.parse::<u16>().expect("SMTP_PORT must be a valid port"));
+    }
+
+    if let (Ok(username), Ok(password)) = (env::var("SMTP_USERNAME"), env::var("SMTP_PASSWORD")) {
+      builder = builder.credentials(Credentials::new(username, password));
+    }
+
+    SmtpMailer {
+      transport: builder.build(),
+      from: from.parse().expect("MAIL_FROM must be a valid mailbox"),
+    }
+  }
+}
+
+impl Mailer for SmtpMailer {
+  fn send(&self, email: &Email) -> Result<(), MailerError> {
+    let to = match email.to.parse::<Mailbox>() {
+      Ok(to) => to,
+      Err(error) => return Err(MailerError(error.to_string())),
+    };
+
+    let message = match Message::builder()
+      .from(self.from.clone())
+      .to(to)
+      .subject(&email.subject)
+      .body(email.body.clone())
+    {
+      Ok(message) => message,
+      Err(error) => return Err(MailerError(error.to_string())),
+    };
+
+    match self.transport.send(&message) {
+      Ok(_) => Ok(()),
+      Err(error) => Err(MailerError(error.to_string())),
+    }
+  }
+}
@@ -1,8 +1,14 @@
 use dotenvy::dotenv;
-use std::env;
+use std::{env, time::Duration};
 use tracing_subscriber::fmt::format::FmtSpan;
 
-use ephemeride_backend::api;
+use ephemeride_backend::{
+  api, establish_connection, establish_pool,
+  mailer::mailer_from_env,
+  migrations::{self, MigrationMode},
+  run_blocking,
+  services::{admin, auth, oidc, throttle, user},
+};
 use poem::{
   endpoint::StaticFilesEndpoint,
   listener::TcpListener,
@@ -10,20 +16,31 @@ use poem::{
   EndpointExt, Route, Server,
 };
 
+const MIGRATE_USAGE: &str = "usage: ephemeride-backend migrate <list|run|revert [count]>";
+
 #[tokio::main]
 async fn main() -> Result<(), std::io::Error> {
   dotenv().ok();
 
+  let args = env::args().skip(1).collect::<Vec<String>>();
+  if args.first().map(String::as_str) == Some("migrate") {
+    migrate(&args[1..]);
+    return Ok(());
+  }
+
   let port = env::var("PORT").unwrap_or("3000".to_string());
   let environment = env::var("ENVIRONMENT").unwrap_or("development".to_string());
   let url = env::var("URL").unwrap_or(format!("http://localhost:{port}"));
 
+  // credentials are allowed so session cookies are sent along from the
+  // frontend's origin
   let dev_cors = Cors::new()
     .allow_origin("http://localhost:5173")
     .allow_origin("http://127.0.0.1:5173")
     .allow_origin(format!("http://localhost:{port}"))
-    .allow_origin(format!("http://127.0.0.1:{port}"));
-  let prod_cors = Cors::new().allow_origin(url);
+    .allow_origin(format!("http://127.0.0.1:{port}"))
+    .allow_credentials(true);
+  let prod_cors = Cors::new().allow_origin(url).allow_credentials(true);
 
   let cors = if environment == "development" {
     dev_cors
@@ -39,6 +56,57 @@ async fn main() -> Result<(), std::io::Error> {
 
   use poem::middleware::Tracing;
 
+  let migration_mode = MigrationMode::from_env();
+  match tokio::task::spawn_blocking(move || migrate_on_startup(migration_mode)).await {
+    Ok(Ok(())) => (),
+    Ok(Err(message)) => {
+      eprintln!("{message}");
+      std::process::exit(1);
+    }
+    Err(_) => {
+      eprintln!("failed to check database migrations");
+      std::process::exit(1);
+    }
+  }
+
+  let pool = establish_pool();
+  let mailer = mailer_from_env();
+  let session_settings = auth::SessionSettings::from_env();
+
+  match run_blocking(&pool, admin::promote_admin_from_env).await {
+    Ok(true) => println!("ADMIN_EMAIL has admin access"),
+    Ok(false) => (),
+    Err(_) => println!("failed to promote ADMIN_EMAIL to admin"),
+  }
+
+  // expired sessions are also removed when they are used, this catches
+  // the ones that are simply abandoned, along with throttles nobody has
+  // tripped in a while, single sign-on logins that never came back and
+  // accounts whose deletion grace period is over
+  let purge_pool = pool.clone();
+  tokio::spawn(async move {
+    let mut interval = tokio::time::interval(Duration::from_secs(60 * 60));
+    loop {
+      interval.tick().await;
+      match run_blocking(&purge_pool, auth::delete_expired_sessions).await {
+        Ok(purged) if purged > 0 => println!("purged {purged} expired sessions"),
+        _ => (),
+      }
+      match run_blocking(&purge_pool, throttle::delete_stale_throttles).await {
+        Ok(purged) if purged > 0 => println!("purged {purged} stale login throttles"),
+        _ => (),
+      }
+      match run_blocking(&purge_pool, oidc::delete_expired_oidc_logins).await {
+        Ok(purged) if purged > 0 => println!("purged {purged} abandoned single sign-on logins"),
+        _ => (),
+      }
+      match run_blocking(&purge_pool, user::purge_deleted_users).await {
+        Ok(purged) if purged > 0 => println!("purged {purged} accounts past their deletion date"),
+        _ => (),
+      }
+    }
+  });
+
   let app = Route::new()
     .nest("/api", api::index::endpoint())
     .nest(
@@ -50,7 +118,10 @@ async fn main() -> Result<(), std::io::Error> {
         .fallback_to_index(),
     )
     .with((NormalizePath::new(TrailingSlash::Trim), cors))
-    .with(Tracing);
+    .with(Tracing)
+    .data(pool)
+    .data(mailer)
+    .data(session_settings);
 
   println!("listening on port {port}");
 
@@ -58,3 +129,84 @@ async fn main() -> Result<(), std::io::Error> {
     .run(app)
     .await
 }
+
+// migrations get a connection of their own, so the advisory lock is let go
+// of before the server starts handing out pooled connections
+fn migrate_on_startup(mode: MigrationMode) -> Result<(), String> {
+  if mode == MigrationMode::Off {
+    return Ok(());
+  }
+
+  let mut conn = establish_connection();
+
+  if mode == MigrationMode::Verify {
+    return match migrations::pending_migrations(&mut conn) {
+      Ok(pending) if pending.is_empty() => Ok(()),
+      Ok(pending) => Err(format!(
+        "database has pending migrations: {}, run `ephemeride-backend migrate run` or set DATABASE_MIGRATIONS=run",
+        pending.join(", ")
+      )),
+      Err(error) => Err(format!("failed to check database migrations: {error}")),
+    };
+  }
+
+  match migrations::run_pending_migrations(&mut conn) {
+    Ok(applied) => {
+      for version in applied {
+        println!("applied migration {version}");
+      }
+      Ok(())
+    }
+    Err(error) => Err(format!("failed to run database migrations: {error}")),
+  }
+}
+
+// ephemeride-backend migrate list|run|revert [count]
+fn migrate(args: &[String]) {
+  let mut conn = establish_connection();
+
+  let result = match args.first().map(String::as_str) {
+    Some("list") => migrations::migration_status(&mut conn).map(|status| {
+      for migration in status {
+        let applied = if migration.applied { "x" } else { " " };
+        println!("[{applied}] {}", migration.name);
+      }
+    }),
+    Some("run") => migrations::run_pending_migrations(&mut conn).map(|applied| {
+      if applied.is_empty() {
+        println!("no pending migrations");
+      }
+      for version in applied {
+        println!("applied migration {version}");
+      }
+    }),
+    Some("revert") => {
+      let count = match args.get(1).map(|count| count.parse::<usize>()) {
+        Some(Ok(count)) => count,
+        Some(Err(_)) => {
+          eprintln!("{MIGRATE_USAGE}");
+          std::process::exit(1);
+        }
+        None => 1,
+      };
+
+      migrations::revert_migrations(&mut conn, count).map(|reverted| {
+        if reverted.is_empty() {
+          println!("no migrations to revert");
+        }
+        for version in reverted {
+          println!("reverted migration {version}");
+        }
+      })
+    }
+    _ => {
+      eprintln!("{MIGRATE_USAGE}");
+      std::process::exit(1);
+    }
+  };
+
+  if let Err(error) = result {
+    eprintln!("migration failed: {error}");
+    std::process::exit(1);
+  }
+}
@@ -0,0 +1,127 @@
+use diesel::{
+  migration::{MigrationSource, Result},
+  pg::Pg,
+  sql_query,
+  sql_types::BigInt,
+  PgConnection, RunQueryDsl,
+};
+use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
+use dotenvy::dotenv;
+use std::env;
+
+// the migrations directory is compiled into the binary, so deploying
+// doesn't need diesel_cli or the sql files next to it
+pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations");
+
+// any fixed number works as long as nothing else in the database takes an
+// advisory lock with it, this one is "ephemeri" in ascii
+pub const MIGRATION_LOCK_KEY: i64 = 0x6570_6865_6d65_7269;
+
+// DATABASE_MIGRATIONS decides what happens to pending migrations when the
+// server starts, run applies them, verify refuses to start while any are
+// pending and off leaves the database alone
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MigrationMode {
+  Run,
+  Verify,
+  Off,
+}
+
+impl MigrationMode {
+  pub fn from_env() -> MigrationMode {
+    dotenv().ok();
+
+    match env::var("DATABASE_MIGRATIONS").as_deref() {
+      Ok("verify") => MigrationMode::Verify,
+      Ok("off") => MigrationMode::Off,
+      _ => MigrationMode::Run,
+    }
+  }
+}
+
+#[derive(Debug)]
+pub struct MigrationStatus {
+  pub version: String,
+  pub name: String,
+  pub applied: bool,
+}
+
+// advisory locks are held by the connection rather than a transaction, so
+// every migration can still run in its own transaction while a second
+// replica starting at the same time waits here until the first is done
+pub fn with_migration_lock<F, T>(conn: &mut PgConnection, f: F) -> Result<T>
+where
+  F: FnOnce(&mut PgConnection) -> Result<T>,
+{
+  sql_query("SELECT pg_advisory_lock($1)")
+    .bind::<BigInt, _>(MIGRATION_LOCK_KEY)
+    .execute(conn)?;
+
+  let result = f(conn);
+
+  sql_query("SELECT pg_advisory_unlock($1)")
+    .bind::<BigInt, _>(MIGRATION_LOCK_KEY)
+    .execute(conn)?;
+
+  result
+}
+
+// every embedded migration oldest first, along with whether it has been
+// applied to the database yet
+pub fn migration_status(conn: &mut PgConnection) -> Result<Vec<MigrationStatus>> {
+  let applied = conn.applied_migrations()?;
+
+  let migrations = MigrationSource::<Pg>::migrations(&MIGRATIONS)?
+    .iter()
+    .map(|migration| MigrationStatus {
+      version: migration.name().version().to_string(),
+      name: migration.name().to_string(),
+      applied: applied.contains(&migration.name().version()),
+    })
+    .collect();
+
+  Ok(migrations)
+}
+
+pub fn pending_migrations(conn: &mut PgConnection) -> Result<Vec<String>> {
+  let pending = conn
+    .pending_migrations(MIGRATIONS)?
+    .iter()
+    .map(|migration| migration.name().to_string())
+    .collect();
+
+  Ok(pending)
+}
+
+// returns the versions that were applied, nothing when the database was
+// already up to date
+pub fn run_pending_migrations(conn: &mut PgConnection) -> Result<Vec<String>> {
+  with_migration_lock(conn, |conn| {
+    let applied = conn
+      .run_pending_migrations(MIGRATIONS)?
+      .iter()
+      .map(|version| version.to_string())
+      .collect();
+
+    Ok(applied)
+  })
+}
+
+// reverts the newest migrations one at a time, returns the versions that
+// were reverted newest first
+pub fn revert_migrations(conn: &mut PgConnection, count: usize) -> Result<Vec<String>> {
+  with_migration_lock(conn, |conn| {
+    let mut reverted = Vec::new();
+
+    for _ in 0..count {
+      if conn.applied_migrations()?.is_empty() {
+        break;
+      }
+
+      let version = conn.revert_last_migration(MIGRATIONS)?;
+      reverted.push(version.to_string());
+    }
+
+    Ok(reverted)
+  })
+}
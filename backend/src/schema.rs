@@ -1,5 +1,33 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    audit_events (id) {
+        #[max_length = 255]
+        id -> Varchar,
+        #[max_length = 255]
+        user_id -> Varchar,
+        #[max_length = 255]
+        event -> Varchar,
+        created_at -> Int8,
+        #[max_length = 255]
+        ip_address -> Nullable<Varchar>,
+        #[max_length = 1023]
+        user_agent -> Nullable<Varchar>,
+        #[max_length = 1023]
+        details -> Nullable<Varchar>,
+    }
+}
+
+diesel::table! {
+    auth_throttles (key) {
+        #[max_length = 255]
+        key -> Varchar,
+        failures -> Int4,
+        last_failure_at -> Int8,
+        locked_until -> Int8,
+    }
+}
+
 diesel::table! {
     categories (id) {
         #[max_length = 255]
@@ -12,6 +40,21 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    email_verifications (id) {
+        #[max_length = 255]
+        id -> Varchar,
+        #[max_length = 255]
+        user_id -> Varchar,
+        #[max_length = 255]
+        email -> Varchar,
+        #[max_length = 255]
+        token_hash -> Varchar,
+        created_at -> Int8,
+        expires_at -> Int8,
+    }
+}
+
 diesel::table! {
     entries (id) {
         #[max_length = 255]
@@ -44,7 +87,99 @@ diesel::table! {
         created_at -> Int8,
         #[max_length = 255]
         code -> Varchar,
-        used -> Bool,
+        expires_at -> Nullable<Int8>,
+        max_uses -> Int4,
+        use_count -> Int4,
+        #[max_length = 255]
+        created_by -> Nullable<Varchar>,
+        #[max_length = 1023]
+        note -> Nullable<Varchar>,
+        user_invite -> Bool,
+    }
+}
+
+diesel::table! {
+    known_devices (user_id, fingerprint) {
+        #[max_length = 255]
+        user_id -> Varchar,
+        #[max_length = 255]
+        fingerprint -> Varchar,
+        first_seen_at -> Int8,
+        last_seen_at -> Int8,
+    }
+}
+
+diesel::table! {
+    oidc_logins (id) {
+        #[max_length = 255]
+        id -> Varchar,
+        #[max_length = 255]
+        state_hash -> Varchar,
+        #[max_length = 255]
+        code_verifier -> Varchar,
+        #[max_length = 255]
+        nonce -> Varchar,
+        #[max_length = 255]
+        user_id -> Nullable<Varchar>,
+        #[max_length = 255]
+        invite -> Nullable<Varchar>,
+        created_at -> Int8,
+        expires_at -> Int8,
+        restore -> Bool,
+    }
+}
+
+diesel::table! {
+    password_resets (id) {
+        #[max_length = 255]
+        id -> Varchar,
+        #[max_length = 255]
+        user_id -> Varchar,
+        #[max_length = 255]
+        token_hash -> Varchar,
+        created_at -> Int8,
+        expires_at -> Int8,
+        used_at -> Nullable<Int8>,
+    }
+}
+
+diesel::table! {
+    personal_access_tokens (id) {
+        #[max_length = 255]
+        id -> Varchar,
+        #[max_length = 255]
+        user_id -> Varchar,
+        #[max_length = 255]
+        name -> Varchar,
+        #[max_length = 255]
+        token_hash -> Varchar,
+        scopes -> Array<Text>,
+        created_at -> Int8,
+        expires_at -> Nullable<Int8>,
+        last_used_at -> Nullable<Int8>,
+    }
+}
+
+diesel::table! {
+    recovery_codes (id) {
+        #[max_length = 255]
+        id -> Varchar,
+        #[max_length = 255]
+        user_id -> Varchar,
+        #[max_length = 255]
+        code_hash -> Varchar,
+        created_at -> Int8,
+        used_at -> Nullable<Int8>,
+    }
+}
+
+diesel::table! {
+    retired_refresh_tokens (token_hash) {
+        #[max_length = 255]
+        token_hash -> Varchar,
+        #[max_length = 255]
+        session_id -> Varchar,
+        retired_at -> Int8,
     }
 }
 
@@ -58,8 +193,20 @@ diesel::table! {
         accessed_at -> Int8,
         #[max_length = 255]
         ip_address -> Varchar,
-        #[max_length = 255]
+        #[max_length = 1023]
         user_agent -> Varchar,
+        expires_at -> Int8,
+        #[max_length = 255]
+        token_hash -> Varchar,
+        #[max_length = 255]
+        name -> Nullable<Varchar>,
+        #[max_length = 255]
+        browser -> Varchar,
+        #[max_length = 255]
+        os -> Varchar,
+        #[max_length = 255]
+        device_type -> Varchar,
+        new_device -> Bool,
     }
 }
 
@@ -79,6 +226,35 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    user_identities (id) {
+        #[max_length = 255]
+        id -> Varchar,
+        #[max_length = 255]
+        user_id -> Varchar,
+        #[max_length = 255]
+        issuer -> Varchar,
+        #[max_length = 255]
+        subject -> Varchar,
+        #[max_length = 255]
+        email -> Nullable<Varchar>,
+        created_at -> Int8,
+        last_used_at -> Int8,
+    }
+}
+
+diesel::table! {
+    user_totp (user_id) {
+        #[max_length = 255]
+        user_id -> Varchar,
+        #[max_length = 255]
+        secret -> Varchar,
+        created_at -> Int8,
+        confirmed_at -> Nullable<Int8>,
+        last_used_step -> Nullable<Int8>,
+    }
+}
+
 diesel::table! {
     users (id) {
         #[max_length = 255]
@@ -92,18 +268,50 @@ diesel::table! {
         password -> Varchar,
         #[max_length = 255]
         invite -> Nullable<Varchar>,
+        email_verified -> Bool,
+        password_changed_at -> Int8,
+        is_admin -> Bool,
+        disabled_at -> Nullable<Int8>,
+        scheduled_deletion_at -> Nullable<Int8>,
     }
 }
 
+diesel::joinable!(audit_events -> users (user_id));
 diesel::joinable!(categories -> users (user_id));
+diesel::joinable!(email_verifications -> users (user_id));
 diesel::joinable!(entries -> users (user_id));
 diesel::joinable!(entry_tags -> entries (entry_id));
 diesel::joinable!(entry_tags -> tags (tag_id));
+diesel::joinable!(known_devices -> users (user_id));
+diesel::joinable!(oidc_logins -> users (user_id));
+diesel::joinable!(password_resets -> users (user_id));
+diesel::joinable!(personal_access_tokens -> users (user_id));
+diesel::joinable!(recovery_codes -> users (user_id));
+diesel::joinable!(retired_refresh_tokens -> sessions (session_id));
 diesel::joinable!(sessions -> users (user_id));
 diesel::joinable!(tags -> categories (category_id));
 diesel::joinable!(tags -> users (user_id));
+diesel::joinable!(user_identities -> users (user_id));
+diesel::joinable!(user_totp -> users (user_id));
 diesel::joinable!(users -> invites (invite));
 
 diesel::allow_tables_to_appear_in_same_query!(
-  categories, entries, entry_tags, invites, sessions, tags, users,
+  audit_events,
+  auth_throttles,
+  categories,
+  email_verifications,
+  entries,
+  entry_tags,
+  invites,
+  known_devices,
+  oidc_logins,
+  password_resets,
+  personal_access_tokens,
+  recovery_codes,
+  retired_refresh_tokens,
+  sessions,
+  tags,
+  user_identities,
+  user_totp,
+  users,
 );
@@ -0,0 +1,246 @@
+use crate::{
+  schema::{self, personal_access_tokens},
+  util::{self, error::EphemerideError},
+};
+use diesel::{
+  deserialize::Queryable, prelude::Insertable, BoolExpressionMethods, ExpressionMethods,
+  OptionalExtension, PgConnection, QueryDsl, RunQueryDsl,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::Validate;
+
+// lets a token be told apart from a session token without a lookup
+pub const ACCESS_TOKEN_PREFIX: &str = "eph_";
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+  #[serde(rename = "entries:read")]
+  EntriesRead,
+  #[serde(rename = "entries:write")]
+  EntriesWrite,
+  // categories are part of how tags are organised, so they share the scope
+  #[serde(rename = "tags:read")]
+  TagsRead,
+  #[serde(rename = "tags:write")]
+  TagsWrite,
+  #[serde(rename = "user:read")]
+  UserRead,
+}
+
+impl Scope {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      Scope::EntriesRead => "entries:read",
+      Scope::EntriesWrite => "entries:write",
+      Scope::TagsRead => "tags:read",
+      Scope::TagsWrite => "tags:write",
+      Scope::UserRead => "user:read",
+    }
+  }
+}
+
+#[derive(Debug, Deserialize, Serialize, Insertable, Queryable)]
+#[diesel(table_name = personal_access_tokens)]
+pub struct PersonalAccessToken {
+  pub id: String,
+  pub user_id: String,
+  pub name: String,
+  #[serde(skip)]
+  pub token_hash: String,
+  pub scopes: Vec<String>,
+  pub created_at: i64,
+  pub expires_at: Option<i64>,
+  pub last_used_at: Option<i64>,
+}
+
+impl PersonalAccessToken {
+  pub fn has_scope(&self, scope: Scope) -> bool {
+    self.scopes.iter().any(|granted| granted == scope.as_str())
+  }
+}
+
+// only returned when the token is created, after that it can't be read again
+#[derive(Debug, Serialize)]
+pub struct PersonalAccessTokenWithToken {
+  #[serde(flatten)]
+  pub access_token: PersonalAccessToken,
+  pub token: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate)]
+pub struct CreateAccessToken {
+  #[validate(length(min = 1, max = 255))]
+  pub name: String,
+  #[validate(length(min = 1))]
+  pub scopes: Vec<Scope>,
+  pub expires_at: Option<i64>,
+}
+
+pub fn is_access_token(token: &str) -> bool {
+  token.starts_with(ACCESS_TOKEN_PREFIX)
+}
+
+pub fn create_access_token(
+  conn: &mut PgConnection,
+  user_id: &str,
+  access_token: CreateAccessToken,
+) -> Result<PersonalAccessTokenWithToken, EphemerideError> {
+  match access_token.validate() {
+    Ok(_) => (),
+    Err(_) => return Err(EphemerideError::BadRequest),
+  }
+
+  let now = util::unix_time::unix_ms();
+
+  if let Some(expires_at) = access_token.expires_at {
+    if expires_at <= now {
+      return Err(EphemerideError::BadRequest);
+    }
+  }
+
+  let mut scopes: Vec<String> = access_token
+    .scopes
+    .iter()
+    .map(|scope| scope.as_str().to_string())
+    .collect();
+  scopes.sort();
+  scopes.dedup();
+
+  let token = format!("{ACCESS_TOKEN_PREFIX}{}", util::generate_token());
+
+  let new_access_token = PersonalAccessToken {
+    id: Uuid::new_v4().to_string(),
+    user_id: user_id.to_string(),
+    name: access_token.name,
+    token_hash: util::hash_token(&token),
+    scopes,
+    created_at: now,
+    expires_at: access_token.expires_at,
+    last_used_at: None,
+  };
+
+  diesel::insert_into(schema::personal_access_tokens::table)
+    .values(&new_access_token)
+    .execute(conn)?;
+
+  Ok(PersonalAccessTokenWithToken {
+    access_token: new_access_token,
+    token,
+  })
+}
+
+// looks the token up and marks it as used, unknown and expired tokens are
+// both just unauthorized
+pub fn get_access_token_by_token(
+  conn: &mut PgConnection,
+  token: &str,
+) -> Result<PersonalAccessToken, EphemerideError> {
+  let now = util::unix_time::unix_ms();
+
+  let access_token = diesel::update(
+    schema::personal_access_tokens::table
+      .filter(schema::personal_access_tokens::token_hash.eq(util::hash_token(token)))
+      .filter(
+        schema::personal_access_tokens::expires_at
+          .is_null()
+          .or(schema::personal_access_tokens::expires_at.gt(now)),
+      )
+      // tokens stop working while their user is disabled or waiting to be
+      // deleted
+      .filter(
+        schema::personal_access_tokens::user_id.eq_any(
+          schema::users::table
+            .filter(schema::users::disabled_at.is_null())
+            .filter(schema::users::scheduled_deletion_at.is_null())
+            .select(schema::users::id),
+        ),
+      ),
+  )
+  .set(schema::personal_access_tokens::last_used_at.eq(now))
+  .get_result::<PersonalAccessToken>(conn)
+  .optional()?;
+
+  match access_token {
+    Some(access_token) => Ok(access_token),
+    None => Err(EphemerideError::Unauthorized),
+  }
+}
+
+pub fn get_user_access_tokens(
+  conn: &mut PgConnection,
+  user_id: &str,
+) -> Result<Vec<PersonalAccessToken>, EphemerideError> {
+  let access_tokens = schema::personal_access_tokens::table
+    .filter(schema::personal_access_tokens::user_id.eq(user_id))
+    .order(schema::personal_access_tokens::created_at.desc())
+    .load::<PersonalAccessToken>(conn)?;
+
+  Ok(access_tokens)
+}
+
+pub fn delete_access_token(
+  conn: &mut PgConnection,
+  id: &str,
+  user_id: &str,
+) -> Result<bool, EphemerideError> {
+  let deleted = diesel::delete(
+    schema::personal_access_tokens::table
+      .filter(schema::personal_access_tokens::id.eq(id))
+      .filter(schema::personal_access_tokens::user_id.eq(user_id)),
+  )
+  .execute(conn)?;
+
+  Ok(deleted > 0)
+}
+
+pub fn delete_user_access_tokens(
+  conn: &mut PgConnection,
+  user_id: &str,
+) -> Result<usize, EphemerideError> {
+  let deleted = diesel::delete(
+    schema::personal_access_tokens::table
+      .filter(schema::personal_access_tokens::user_id.eq(user_id)),
+  )
+  .execute(conn)?;
+
+  Ok(deleted)
+}
+
+#[cfg(test)]
+mod ci_unit {
+  use super::*;
+
+  #[test]
+  fn test_scope_names_match_serde() {
+    for scope in [
+      Scope::EntriesRead,
+      Scope::EntriesWrite,
+      Scope::TagsRead,
+      Scope::TagsWrite,
+      Scope::UserRead,
+    ] {
+      assert_eq!(
+        serde_json::to_string(&scope).unwrap(),
+        format!("\"{}\"", scope.as_str())
+      );
+    }
+  }
+
+  #[test]
+  fn test_has_scope() {
+    let access_token = PersonalAccessToken {
+      id: "id".to_string(),
+      user_id: "user".to_string(),
+      name: "name".to_string(),
+      token_hash: "hash".to_string(),
+      scopes: vec!["entries:read".to_string()],
+      created_at: 0,
+      expires_at: None,
+      last_used_at: None,
+    };
+
+    assert!(access_token.has_scope(Scope::EntriesRead));
+    assert!(!access_token.has_scope(Scope::EntriesWrite));
+  }
+}
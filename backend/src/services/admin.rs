@@ -0,0 +1,187 @@
+use crate::{
+  mailer::Email,
+  schema::{self, users},
+  services::{delete_all_user_sessions, password_reset, user, Paginated, PaginationObject},
+  util::{self, error::EphemerideError},
+};
+use diesel::{
+  pg::Pg, BoolExpressionMethods, Connection, ExpressionMethods, PgConnection,
+  PgTextExpressionMethods, QueryDsl, RunQueryDsl,
+};
+use serde::Deserialize;
+
+use dotenvy::dotenv;
+use std::env;
+
+const DEFAULT_USER_LIMIT: i64 = 50;
+const MAX_USER_LIMIT: i64 = 200;
+
+#[derive(Debug, Deserialize)]
+pub struct SearchUsers {
+  // matched against both the name and the email
+  pub query: Option<String>,
+  pub limit: Option<i64>,
+  pub offset: Option<i64>,
+}
+
+pub fn is_admin(conn: &mut PgConnection, user_id: &str) -> Result<bool, EphemerideError> {
+  let result = schema::users::table
+    .filter(schema::users::id.eq(user_id))
+    .select(schema::users::is_admin)
+    .first::<bool>(conn);
+
+  match result {
+    Ok(is_admin) => Ok(is_admin),
+    Err(_) => Err(EphemerideError::UserNotFound),
+  }
+}
+
+// the first admin can't be made through the api, so the operator names
+// them with ADMIN_EMAIL and they are promoted whenever the server starts
+pub fn promote_admin_from_env(conn: &mut PgConnection) -> Result<bool, EphemerideError> {
+  dotenv().ok();
+
+  let email = match env::var("ADMIN_EMAIL") {
+    Ok(email) => email,
+    Err(_) => return Ok(false),
+  };
+
+  let promoted = diesel::update(schema::users::table.filter(schema::users::email.eq(&email)))
+    .set(schema::users::is_admin.eq(true))
+    .execute(conn)?;
+
+  Ok(promoted > 0)
+}
+
+fn matching_users(query: &Option<String>) -> users::BoxedQuery<'static, Pg> {
+  let mut users = schema::users::table.into_boxed();
+
+  if let Some(query) = query {
+    let pattern = format!(
+      "%{}%",
+      query
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+    );
+
+    users = users.filter(
+      schema::users::email
+        .ilike(pattern.clone())
+        .or(schema::users::name.ilike(pattern)),
+    );
+  }
+
+  users
+}
+
+pub fn search_users(
+  conn: &mut PgConnection,
+  search: SearchUsers,
+) -> Result<Paginated<user::UserDetails>, EphemerideError> {
+  let limit = search
+    .limit
+    .unwrap_or(DEFAULT_USER_LIMIT)
+    .clamp(1, MAX_USER_LIMIT);
+  let offset = search.offset.unwrap_or(0).max(0);
+
+  let total_count = matching_users(&search.query)
+    .count()
+    .get_result::<i64>(conn)?;
+
+  let users = matching_users(&search.query)
+    .order(schema::users::created_at.desc())
+    .limit(limit)
+    .offset(offset)
+    .load::<user::User>(conn)?;
+
+  Ok(Paginated {
+    data: users.into_iter().map(user::user_details).collect(),
+    pagination: PaginationObject {
+      limit,
+      offset,
+      total_count,
+    },
+  })
+}
+
+// disabling signs the user out everywhere, access tokens are kept but
+// stop working until the account is enabled again
+pub fn set_user_disabled(
+  conn: &mut PgConnection,
+  admin_id: &str,
+  user_id: &str,
+  disabled: bool,
+) -> Result<bool, EphemerideError> {
+  // an admin locking themselves out leaves nobody to undo it
+  if admin_id == user_id {
+    return Err(EphemerideError::BadRequest);
+  }
+
+  let disabled_at = match disabled {
+    true => Some(util::unix_time::unix_ms()),
+    false => None,
+  };
+
+  conn.transaction::<_, EphemerideError, _>(|conn| {
+    let updated = diesel::update(schema::users::table.filter(schema::users::id.eq(user_id)))
+      .set(schema::users::disabled_at.eq(disabled_at))
+      .execute(conn)?;
+
+    if disabled {
+      delete_all_user_sessions(conn, user_id)?;
+    }
+
+    Ok(updated > 0)
+  })
+}
+
+pub fn is_user_disabled(conn: &mut PgConnection, user_id: &str) -> Result<bool, EphemerideError> {
+  let result = schema::users::table
+    .filter(schema::users::id.eq(user_id))
+    .select(schema::users::disabled_at)
+    .first::<Option<i64>>(conn);
+
+  match result {
+    Ok(disabled_at) => Ok(disabled_at.is_some()),
+    Err(_) => Err(EphemerideError::UserNotFound),
+  }
+}
+
+// the current password stops working straight away, the user gets a reset
+// link and every session is signed out, the link is returned to be sent
+// once the connection is given back, or None if there's no such user
+pub fn force_password_reset(
+  conn: &mut PgConnection,
+  user_id: &str,
+) -> Result<Option<Email>, EphemerideError> {
+  let email = match user::get_user(conn, user_id) {
+    Ok(user) => user.email,
+    Err(_) => return Ok(None),
+  };
+
+  let password_hash = util::hash_password(&util::generate_token())?;
+
+  conn.transaction::<_, EphemerideError, _>(|conn| {
+    user::update_password_hash(conn, user_id, &password_hash)?;
+    delete_all_user_sessions(conn, user_id)?;
+
+    Ok(())
+  })?;
+
+  password_reset::request_password_reset(conn, password_reset::RequestPasswordReset { email })
+}
+
+pub fn revoke_user_sessions(
+  conn: &mut PgConnection,
+  user_id: &str,
+) -> Result<bool, EphemerideError> {
+  match user::get_user(conn, user_id) {
+    Ok(_) => (),
+    Err(_) => return Ok(false),
+  }
+
+  delete_all_user_sessions(conn, user_id)?;
+
+  Ok(true)
+}
@@ -0,0 +1,169 @@
+use crate::{
+  schema::{self, audit_events},
+  services::{auth::SessionMetadata, Paginated, PaginationObject},
+  util::{self, error::EphemerideError},
+};
+use diesel::{
+  deserialize::Queryable, prelude::Insertable, sql_query, sql_types::Text, ExpressionMethods,
+  PgConnection, QueryDsl, RunQueryDsl,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+const DEFAULT_AUDIT_EVENT_LIMIT: i64 = 50;
+const MAX_AUDIT_EVENT_LIMIT: i64 = 100;
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditEventKind {
+  Login,
+  PasswordChanged,
+  PasswordReset,
+  NameChanged,
+  EmailChangeRequested,
+  EmailChanged,
+  SessionRevoked,
+  OtherSessionsRevoked,
+  InviteCreated,
+  InviteRedeemed,
+  RefreshTokenReused,
+}
+
+impl AuditEventKind {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      AuditEventKind::Login => "login",
+      AuditEventKind::PasswordChanged => "password_changed",
+      AuditEventKind::PasswordReset => "password_reset",
+      AuditEventKind::NameChanged => "name_changed",
+      AuditEventKind::EmailChangeRequested => "email_change_requested",
+      AuditEventKind::EmailChanged => "email_changed",
+      AuditEventKind::SessionRevoked => "session_revoked",
+      AuditEventKind::OtherSessionsRevoked => "other_sessions_revoked",
+      AuditEventKind::InviteCreated => "invite_created",
+      AuditEventKind::InviteRedeemed => "invite_redeemed",
+      AuditEventKind::RefreshTokenReused => "refresh_token_reused",
+    }
+  }
+}
+
+#[derive(Debug, Deserialize, Serialize, Insertable, Queryable)]
+#[diesel(table_name = audit_events)]
+pub struct AuditEvent {
+  pub id: String,
+  pub user_id: String,
+  pub event: String,
+  pub created_at: i64,
+  pub ip_address: Option<String>,
+  pub user_agent: Option<String>,
+  // whatever the event was about, like the id of the session or invite
+  pub details: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuditEventOptions {
+  pub limit: Option<i64>,
+  pub offset: Option<i64>,
+}
+
+// metadata is left out for events the user didn't cause themselves, like
+// someone else redeeming their invite, so nobody else's address ends up in
+// the user's log
+pub fn record_audit_event(
+  conn: &mut PgConnection,
+  user_id: &str,
+  event: AuditEventKind,
+  metadata: Option<&SessionMetadata>,
+  details: Option<String>,
+) -> Result<AuditEvent, EphemerideError> {
+  let audit_event = AuditEvent {
+    id: Uuid::new_v4().to_string(),
+    user_id: user_id.to_string(),
+    event: event.as_str().to_string(),
+    created_at: util::unix_time::unix_ms(),
+    ip_address: metadata.map(|metadata| metadata.ip_address.clone()),
+    user_agent: metadata.map(|metadata| metadata.user_agent.clone()),
+    details,
+  };
+
+  let result = diesel::insert_into(schema::audit_events::table)
+    .values(&audit_event)
+    .execute(conn);
+
+  match result {
+    Ok(_) => Ok(audit_event),
+    Err(_) => Err(EphemerideError::DatabaseError),
+  }
+}
+
+// newest first, the limit is capped so the whole log can't be asked for
+// in one go
+pub fn get_user_audit_events(
+  conn: &mut PgConnection,
+  user_id: &str,
+  options: AuditEventOptions,
+) -> Result<Paginated<AuditEvent>, EphemerideError> {
+  let limit = options
+    .limit
+    .unwrap_or(DEFAULT_AUDIT_EVENT_LIMIT)
+    .clamp(1, MAX_AUDIT_EVENT_LIMIT);
+  let offset = options.offset.unwrap_or(0).max(0);
+
+  let total_count = schema::audit_events::table
+    .filter(schema::audit_events::user_id.eq(user_id))
+    .count()
+    .get_result::<i64>(conn)?;
+
+  let audit_events = schema::audit_events::table
+    .filter(schema::audit_events::user_id.eq(user_id))
+    .order((
+      schema::audit_events::created_at.desc(),
+      schema::audit_events::id.desc(),
+    ))
+    .limit(limit)
+    .offset(offset)
+    .load::<AuditEvent>(conn)?;
+
+  Ok(Paginated {
+    data: audit_events,
+    pagination: PaginationObject {
+      limit,
+      offset,
+      total_count,
+    },
+  })
+}
+
+// the whole log, for the account export
+pub fn get_all_user_audit_events(
+  conn: &mut PgConnection,
+  user_id: &str,
+) -> Result<Vec<AuditEvent>, EphemerideError> {
+  let audit_events = schema::audit_events::table
+    .filter(schema::audit_events::user_id.eq(user_id))
+    .order(schema::audit_events::created_at.asc())
+    .load::<AuditEvent>(conn)?;
+
+  Ok(audit_events)
+}
+
+// only used when the account itself is deleted, the trigger on audit_events
+// refuses deletes unless the transaction has said which user is being
+// deleted, so this has to run inside the transaction deleting the user
+pub fn delete_user_audit_events(
+  conn: &mut PgConnection,
+  user_id: &str,
+) -> Result<usize, EphemerideError> {
+  sql_query("SELECT set_config('ephemeride.deleting_user', $1, true)")
+    .bind::<Text, _>(user_id)
+    .execute(conn)?;
+
+  let result =
+    diesel::delete(schema::audit_events::table.filter(schema::audit_events::user_id.eq(user_id)))
+      .execute(conn);
+
+  match result {
+    Ok(rows_affected) => Ok(rows_affected),
+    Err(_) => Err(EphemerideError::DatabaseError),
+  }
+}
@@ -1,14 +1,22 @@
-use diesel::{deserialize::Queryable, ExpressionMethods, Insertable, QueryDsl, RunQueryDsl};
-use poem::Request;
+use diesel::{
+  deserialize::Queryable, Connection, ExpressionMethods, Insertable, PgConnection, QueryDsl,
+  RunQueryDsl,
+};
+use poem::{http::Method, Request};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
+use validator::Validate;
+
+use dotenvy::dotenv;
+use std::env;
 
 use crate::{
-  establish_connection,
+  run_blocking,
   schema::{self, sessions},
-  services::user,
+  services::{access_token, admin, audit, email_verification, jwt, totp, user, Scope},
   util,
   util::error::EphemerideError,
+  DbPool,
 };
 
 #[derive(Debug, Deserialize, Serialize, Insertable, Queryable)]
@@ -19,6 +27,50 @@ pub struct Session {
   pub accessed_at: i64,
   pub ip_address: String,
   pub user_agent: String,
+  pub expires_at: i64,
+  #[serde(skip)]
+  pub token_hash: String,
+  // set by the user to tell their sessions apart
+  pub name: Option<String>,
+  pub browser: String,
+  pub os: String,
+  pub device_type: String,
+  // the first session from a device the user hasn't logged in from before
+  pub new_device: bool,
+}
+
+// only returned when the session is created, this is the one time the
+// raw bearer token leaves the server
+#[derive(Debug, Serialize)]
+pub struct SessionWithToken {
+  #[serde(flatten)]
+  pub session: Session,
+  pub token: String,
+}
+
+// what logging in returns when the token went into a cookie instead, the
+// csrf token is the same one as in the csrf cookie, for frontends that
+// aren't served from the same site as the api and can't read it
+#[derive(Debug, Serialize)]
+pub struct SessionWithCsrfToken {
+  #[serde(flatten)]
+  pub session: Session,
+  pub csrf_token: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate)]
+pub struct RenameSession {
+  // null clears the name again
+  #[validate(length(min = 1, max = 255))]
+  pub name: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SessionDetails {
+  #[serde(flatten)]
+  pub session: Session,
+  // whether this is the session the request was made with
+  pub current: bool,
 }
 
 pub struct SessionMetadata {
@@ -30,116 +82,655 @@ pub struct SessionMetadata {
 pub struct UserCredentials {
   pub email: String,
   pub password: String,
+  pub totp_code: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct AuthConfig {
   pub invite_required: bool,
+  pub email_verification_required: bool,
+  pub oidc_enabled: bool,
+  pub session_cookies: bool,
+  pub access_tokens: bool,
+}
+
+const DEFAULT_SESSION_LIFETIME_MS: i64 = 30 * 24 * 60 * 60 * 1000;
+const DEFAULT_SESSION_IDLE_TIMEOUT_MS: i64 = 7 * 24 * 60 * 60 * 1000;
+
+#[derive(Debug, Clone, Copy)]
+pub struct SessionConfig {
+  // absolute lifetime, counted from when the session was created
+  pub lifetime: i64,
+  // sliding window, renewed every time the session is used
+  pub idle_timeout: i64,
+}
+
+impl SessionConfig {
+  pub fn from_env() -> SessionConfig {
+    dotenv().ok();
+
+    let lifetime = match env::var("SESSION_LIFETIME_MS") {
+      Ok(val) => val.parse::<i64>().unwrap_or(DEFAULT_SESSION_LIFETIME_MS),
+      Err(_) => DEFAULT_SESSION_LIFETIME_MS,
+    };
+    let idle_timeout = match env::var("SESSION_IDLE_TIMEOUT_MS") {
+      Ok(val) => val
+        .parse::<i64>()
+        .unwrap_or(DEFAULT_SESSION_IDLE_TIMEOUT_MS),
+      Err(_) => DEFAULT_SESSION_IDLE_TIMEOUT_MS,
+    };
+
+    SessionConfig {
+      lifetime,
+      idle_timeout,
+    }
+  }
+
+  pub fn expires_at(&self, created_at: i64, accessed_at: i64) -> i64 {
+    std::cmp::min(
+      created_at.saturating_add(self.lifetime),
+      accessed_at.saturating_add(self.idle_timeout),
+    )
+  }
+}
+
+pub const SESSION_COOKIE: &str = "ephemeride_session";
+pub const CSRF_COOKIE: &str = "ephemeride_csrf";
+pub const CSRF_HEADER: &str = "X-CSRF-Token";
+
+// with SESSION_COOKIES=true logging in puts the token in an http only
+// cookie so the frontend never has to keep it anywhere javascript can read,
+// the Authorization header keeps working either way
+#[derive(Debug, Clone)]
+pub struct SessionCookieConfig {
+  pub same_site: &'static str,
+}
+
+impl SessionCookieConfig {
+  pub fn from_env() -> Option<SessionCookieConfig> {
+    dotenv().ok();
+
+    if env::var("SESSION_COOKIES").unwrap_or("false".to_string()) != "true" {
+      return None;
+    }
+
+    // SESSION_COOKIE_SAME_SITE can loosen it for a frontend on another site
+    let same_site = match env::var("SESSION_COOKIE_SAME_SITE").as_deref() {
+      Ok("Lax") => "Lax",
+      Ok("None") => "None",
+      _ => "Strict",
+    };
+
+    Some(SessionCookieConfig { same_site })
+  }
+
+  pub fn session_cookies(
+    &self,
+    token: &str,
+    csrf_token: &str,
+    max_age_seconds: i64,
+  ) -> [String; 2] {
+    [
+      util::set_cookie(util::SetCookie {
+        name: SESSION_COOKIE,
+        value: token,
+        max_age_seconds,
+        http_only: true,
+        same_site: self.same_site,
+      }),
+      util::set_cookie(util::SetCookie {
+        name: CSRF_COOKIE,
+        value: csrf_token,
+        max_age_seconds,
+        http_only: false,
+        same_site: self.same_site,
+      }),
+    ]
+  }
+
+  pub fn cleared_cookies(&self) -> [String; 2] {
+    self.session_cookies("", "", 0)
+  }
+}
+
+// everything authorizing a request and answering a login needs, read once at
+// startup and handed to every request as data like the mailer
+#[derive(Clone)]
+pub struct SessionSettings {
+  pub session: SessionConfig,
+  pub cookies: Option<SessionCookieConfig>,
+  pub jwt: Option<jwt::JwtConfig>,
+}
+
+impl SessionSettings {
+  pub fn from_env() -> SessionSettings {
+    SessionSettings {
+      session: SessionConfig::from_env(),
+      cookies: SessionCookieConfig::from_env(),
+      jwt: jwt::JwtConfig::from_env(),
+    }
+  }
+}
+
+fn session_settings(request: &Request) -> Result<&SessionSettings, EphemerideError> {
+  match request.data::<SessionSettings>() {
+    Some(settings) => Ok(settings),
+    None => Err(EphemerideError::InternalServerError),
+  }
 }
 
 pub fn session_metadata(request: &Request) -> SessionMetadata {
   SessionMetadata {
-    ip_address: request.remote_addr().to_string(),
-    user_agent: request
-      .header("user-agent")
-      .unwrap_or("unknown")
-      .to_string(),
+    ip_address: client_ip(request),
+    user_agent: util::truncate_user_agent(request.header("user-agent").unwrap_or("unknown")),
   }
 }
 
+// just the address, without the port the connection happened to use, when
+// the request came through a trusted proxy it's the address the proxy
+// forwarded instead, in the header picked with TRUSTED_PROXY_HEADER
+pub fn client_ip(request: &Request) -> String {
+  let peer = match request.remote_addr().as_socket_addr() {
+    Some(addr) => addr.ip(),
+    None => return request.remote_addr().to_string(),
+  };
+
+  util::client_ip_from_headers(
+    peer,
+    request.headers(),
+    util::ProxyHeader::from_env(),
+    &util::TrustedProxies::from_env(),
+  )
+  .to_string()
+}
+
 fn token_from_header(request: &Request) -> Option<String> {
   let token = request.header("Authorization");
   token.map(|token| token.replace("Bearer ", ""))
 }
 
-pub fn authorize_request(request: &Request) -> Result<Session, EphemerideError> {
-  match token_from_header(request) {
-    Some(token) => get_user_session_by_id(&token),
+fn request_cookie(request: &Request, name: &str) -> Option<String> {
+  request
+    .headers()
+    .get_all("cookie")
+    .iter()
+    .filter_map(|value| value.to_str().ok())
+    .find_map(|header| util::cookie_value(header, name))
+    .filter(|value| !value.is_empty())
+    .map(|value| value.to_string())
+}
+
+// the csrf cookie can only be read by pages on our own site, so another
+// site can make the browser send it but can't copy it into the header
+fn verify_csrf_token(request: &Request) -> Result<(), EphemerideError> {
+  let cookie = request_cookie(request, CSRF_COOKIE);
+  let header = request.header(CSRF_HEADER);
+
+  match (cookie, header) {
+    (Some(cookie), Some(header)) if util::constant_time_eq(&cookie, header) => Ok(()),
+    _ => Err(EphemerideError::InvalidCsrfToken),
+  }
+}
+
+// the Authorization header wins, the session cookie is only looked at when
+// cookies are enabled and there is no header, browsers send cookies with
+// requests other sites make too so anything that changes state needs the
+// csrf token as well
+fn token_from_request(
+  request: &Request,
+  cookies_enabled: bool,
+) -> Result<Option<String>, EphemerideError> {
+  if let Some(token) = token_from_header(request) {
+    return Ok(Some(token));
+  }
+
+  let token = match cookies_enabled {
+    true => request_cookie(request, SESSION_COOKIE),
+    false => None,
+  };
+
+  match token {
+    Some(token) => {
+      if !matches!(
+        *request.method(),
+        Method::GET | Method::HEAD | Method::OPTIONS
+      ) {
+        verify_csrf_token(request)?;
+      }
+      Ok(Some(token))
+    }
+    None => Ok(None),
+  }
+}
+
+// whether the request was made with a session or token at all, without
+// checking that it's valid
+pub fn has_credentials(request: &Request) -> bool {
+  let cookies_enabled = match session_settings(request) {
+    Ok(settings) => settings.cookies.is_some(),
+    Err(_) => false,
+  };
+
+  token_from_header(request).is_some()
+    || (cookies_enabled && request_cookie(request, SESSION_COOKIE).is_some())
+}
+
+fn access_token_claims(
+  settings: &SessionSettings,
+  token: &str,
+) -> Result<jwt::AccessTokenClaims, EphemerideError> {
+  match &settings.jwt {
+    Some(config) => jwt::verify_access_token(config, token),
+    None => Err(EphemerideError::Unauthorized),
+  }
+}
+
+// who a request was made for, either through a session or a personal
+// access token that has been granted the scope the handler asked for
+#[derive(Debug)]
+pub struct Authorization {
+  pub user_id: String,
+}
+
+// for handlers that scripts are allowed to use, sessions can do anything,
+// access tokens only what their scopes allow
+pub async fn authorize_request(
+  pool: &DbPool,
+  request: &Request,
+  scope: Scope,
+) -> Result<Authorization, EphemerideError> {
+  let settings = session_settings(request)?;
+
+  let token = match token_from_request(request, settings.cookies.is_some())? {
+    Some(token) => token,
+    None => return Err(EphemerideError::Unauthorized),
+  };
+
+  // signed access tokens aren't looked up, only the account is checked so
+  // disabling it or scheduling its deletion takes effect before they expire
+  if jwt::is_jwt(&token) {
+    let claims = access_token_claims(settings, &token)?;
+
+    let user_id = claims.sub.clone();
+    run_blocking(pool, move |conn| check_user_active(conn, &user_id)).await?;
+
+    return Ok(Authorization {
+      user_id: claims.sub,
+    });
+  }
+
+  if jwt::is_refresh_token(&token) {
+    return Err(EphemerideError::Unauthorized);
+  }
+
+  if !access_token::is_access_token(&token) {
+    let session = run_blocking(pool, move |conn| get_user_session_by_token(conn, &token)).await?;
+
+    return Ok(Authorization {
+      user_id: session.user_id,
+    });
+  }
+
+  let access_token = run_blocking(pool, move |conn| {
+    access_token::get_access_token_by_token(conn, &token)
+  })
+  .await?;
+
+  match access_token.has_scope(scope) {
+    true => Ok(Authorization {
+      user_id: access_token.user_id,
+    }),
+    false => Err(EphemerideError::InsufficientScope),
+  }
+}
+
+// for managing the account itself, which needs a real session so a leaked
+// access token can't be used to take the account over
+pub async fn authorize_session(
+  pool: &DbPool,
+  request: &Request,
+) -> Result<Session, EphemerideError> {
+  let settings = session_settings(request)?;
+
+  match token_from_request(request, settings.cookies.is_some())? {
+    Some(token) if access_token::is_access_token(&token) => Err(EphemerideError::InsufficientScope),
+    Some(token) if jwt::is_refresh_token(&token) => Err(EphemerideError::Unauthorized),
+    // managing the account is rare enough that the session is still looked
+    // up, so revoking it takes effect straight away here
+    Some(token) if jwt::is_jwt(&token) => {
+      let claims = access_token_claims(settings, &token)?;
+      run_blocking(pool, move |conn| {
+        get_user_session_by_id(conn, &claims.sid, &claims.sub)
+      })
+      .await
+    }
+    Some(token) => run_blocking(pool, move |conn| get_user_session_by_token(conn, &token)).await,
     None => Err(EphemerideError::Unauthorized),
   }
 }
 
+// for the admin api, on top of a session the user has to be an admin
+pub async fn authorize_admin(pool: &DbPool, request: &Request) -> Result<Session, EphemerideError> {
+  let session = authorize_session(pool, request).await?;
+
+  let user_id = session.user_id.clone();
+  let is_admin = run_blocking(pool, move |conn| admin::is_admin(conn, &user_id)).await?;
+
+  match is_admin {
+    true => Ok(session),
+    false => Err(EphemerideError::AdminRequired),
+  }
+}
+
 pub fn create_user_session(
+  conn: &mut PgConnection,
   user_credentials: UserCredentials,
   metadata: SessionMetadata,
-) -> Result<Session, EphemerideError> {
-  let mut conn = establish_connection();
+) -> Result<SessionWithToken, EphemerideError> {
+  // a recovery code used to log in is only spent if the session is actually
+  // started, a disabled account or one waiting to be deleted keeps it
+  conn.transaction::<_, EphemerideError, _>(|conn| {
+    let user_id = verify_user_credentials(conn, &user_credentials)?;
+
+    start_user_session(conn, user_id, metadata)
+  })
+}
 
-  let user_id = match user::get_user_id(&user_credentials.email) {
+// logging in to an account that is waiting to be deleted is turned away
+// until the user asks for it back, this cancels the deletion and logs in
+pub fn restore_user_session(
+  conn: &mut PgConnection,
+  user_credentials: UserCredentials,
+  metadata: SessionMetadata,
+) -> Result<SessionWithToken, EphemerideError> {
+  conn.transaction::<_, EphemerideError, _>(|conn| {
+    let user_id = verify_user_credentials(conn, &user_credentials)?;
+    user::cancel_user_deletion(conn, &user_id)?;
+
+    start_user_session(conn, user_id, metadata)
+  })
+}
+
+// returns the id of the user the credentials belong to
+fn verify_user_credentials(
+  conn: &mut PgConnection,
+  user_credentials: &UserCredentials,
+) -> Result<String, EphemerideError> {
+  // unknown emails and wrong passwords get the same error, so logging in
+  // can't be used to find out who has an account
+  let user_id = match user::get_user_id(conn, &user_credentials.email) {
     Ok(id) => id,
-    Err(_) => return Err(EphemerideError::UserNotFound),
+    Err(_) => {
+      user::verify_dummy_password(&user_credentials.password);
+      return Err(EphemerideError::InvalidCredentials);
+    }
   };
 
-  let password_hash = match user::get_password_hash(&user_id) {
+  let password_hash = match user::get_password_hash(conn, &user_id) {
     Ok(hash) => hash,
     Err(_) => return Err(EphemerideError::DatabaseError),
   };
 
-  match bcrypt::verify(&user_credentials.password, &password_hash) {
-    Ok(valid) => match valid {
-      true => (),
-      false => return Err(EphemerideError::InvalidPassword),
-    },
-    Err(_) => return Err(EphemerideError::InternalServerError),
-  };
+  match util::verify_password(&user_credentials.password, &password_hash)? {
+    true => (),
+    false => return Err(EphemerideError::InvalidCredentials),
+  }
+
+  // the plain password is only available here, so this is where legacy
+  // bcrypt hashes and outdated argon2 parameters get upgraded
+  if util::needs_rehash(&password_hash) {
+    let upgraded_hash = util::hash_password(&user_credentials.password)?;
+    user::update_password_hash(conn, &user_id, &upgraded_hash)?;
+  }
+
+  if email_verification::email_verification_required()
+    && !user::get_user(conn, &user_id)?.email_verified
+  {
+    return Err(EphemerideError::EmailNotVerified);
+  }
+
+  // only checked once the password is known to be right, so the response
+  // doesn't reveal whether an account has two-factor enabled
+  if totp::is_totp_enabled(conn, &user_id)? {
+    match &user_credentials.totp_code {
+      Some(code) => totp::verify_second_factor(conn, &user_id, code)?,
+      None => return Err(EphemerideError::TotpRequired),
+    }
+  }
+
+  Ok(user_id)
+}
+
+// a disabled account or one waiting to be deleted can't log in, refresh its
+// tokens or use the access tokens it still has
+pub fn check_user_active(conn: &mut PgConnection, user_id: &str) -> Result<(), EphemerideError> {
+  if admin::is_user_disabled(conn, user_id)? {
+    return Err(EphemerideError::AccountDisabled);
+  }
+
+  if user::is_user_pending_deletion(conn, user_id)? {
+    return Err(EphemerideError::AccountPendingDeletion);
+  }
 
-  let session = Session {
-    id: Uuid::new_v4().to_string(),
-    user_id,
-    created_at: util::unix_time::unix_ms(),
-    accessed_at: util::unix_time::unix_ms(),
-    ip_address: metadata.ip_address,
-    user_agent: metadata.user_agent,
+  Ok(())
+}
+
+// creates the session itself, callers are responsible for having checked
+// the user's credentials first
+pub fn start_user_session(
+  conn: &mut PgConnection,
+  user_id: String,
+  metadata: SessionMetadata,
+) -> Result<SessionWithToken, EphemerideError> {
+  // every way of logging in ends up here, so this is the one place a
+  // disabled account has to be turned away
+  check_user_active(conn, &user_id)?;
+
+  let now = util::unix_time::unix_ms();
+  // in token mode the session token is only used to get access tokens
+  let token = match jwt::JwtConfig::from_env() {
+    Some(_) => jwt::generate_refresh_token(),
+    None => util::generate_token(),
   };
+  let device = util::parse_user_agent(&metadata.user_agent);
+
+  let session = conn.transaction::<_, EphemerideError, _>(|conn| {
+    let new_device = remember_device(conn, &user_id, &util::device_fingerprint(&device), now)?;
+
+    let session = Session {
+      id: Uuid::new_v4().to_string(),
+      user_id,
+      created_at: now,
+      accessed_at: now,
+      ip_address: metadata.ip_address.clone(),
+      user_agent: metadata.user_agent.clone(),
+      expires_at: SessionConfig::from_env().expires_at(now, now),
+      token_hash: util::hash_token(&token),
+      name: None,
+      browser: device.browser,
+      os: device.os,
+      device_type: device.device_type,
+      new_device,
+    };
+
+    match diesel::insert_into(schema::sessions::table)
+      .values(&session)
+      .execute(conn)
+    {
+      Ok(_) => (),
+      Err(_) => return Err(EphemerideError::DatabaseError),
+    }
+
+    audit::record_audit_event(
+      conn,
+      &session.user_id,
+      audit::AuditEventKind::Login,
+      Some(&metadata),
+      Some(session.id.clone()),
+    )?;
+
+    Ok(session)
+  })?;
+
+  Ok(SessionWithToken { session, token })
+}
+
+// whether this is a device the user hasn't logged in from before, the very
+// first one isn't counted since every account starts out on a new device
+fn remember_device(
+  conn: &mut PgConnection,
+  user_id: &str,
+  fingerprint: &str,
+  now: i64,
+) -> Result<bool, EphemerideError> {
+  let known_fingerprints = schema::known_devices::table
+    .filter(schema::known_devices::user_id.eq(user_id))
+    .select(schema::known_devices::fingerprint)
+    .load::<String>(conn)?;
 
-  let result = diesel::insert_into(schema::sessions::table)
-    .values(&session)
-    .execute(&mut conn);
+  let new_device = !known_fingerprints.is_empty()
+    && !known_fingerprints
+      .iter()
+      .any(|known_fingerprint| known_fingerprint == fingerprint);
+
+  diesel::insert_into(schema::known_devices::table)
+    .values((
+      schema::known_devices::user_id.eq(user_id),
+      schema::known_devices::fingerprint.eq(fingerprint),
+      schema::known_devices::first_seen_at.eq(now),
+      schema::known_devices::last_seen_at.eq(now),
+    ))
+    .on_conflict((
+      schema::known_devices::user_id,
+      schema::known_devices::fingerprint,
+    ))
+    .do_update()
+    .set(schema::known_devices::last_seen_at.eq(now))
+    .execute(conn)?;
+
+  Ok(new_device)
+}
+
+pub fn delete_user_known_devices(
+  conn: &mut PgConnection,
+  user_id: &str,
+) -> Result<usize, EphemerideError> {
+  let result =
+    diesel::delete(schema::known_devices::table.filter(schema::known_devices::user_id.eq(user_id)))
+      .execute(conn);
 
   match result {
-    Ok(_) => Ok(session),
+    Ok(rows_affected) => Ok(rows_affected),
     Err(_) => Err(EphemerideError::DatabaseError),
   }
 }
 
-pub fn update_accessed_at(session_id: &str) -> Result<bool, EphemerideError> {
-  let mut conn = establish_connection();
+pub fn rename_user_session(
+  conn: &mut PgConnection,
+  session_id: &str,
+  user_id: &str,
+  rename: RenameSession,
+) -> Result<bool, EphemerideError> {
+  match rename.validate() {
+    Ok(_) => (),
+    Err(_) => return Err(EphemerideError::BadRequest),
+  }
 
-  let result = diesel::update(schema::sessions::table.filter(schema::sessions::id.eq(session_id)))
-    .set(schema::sessions::accessed_at.eq(util::unix_time::unix_ms()))
-    .execute(&mut conn);
+  let result = diesel::update(
+    schema::sessions::table
+      .filter(schema::sessions::id.eq(session_id))
+      .filter(schema::sessions::user_id.eq(user_id)),
+  )
+  .set(schema::sessions::name.eq(&rename.name))
+  .execute(conn);
 
   match result {
-    Ok(_) => Ok(true),
+    Ok(rows_affected) => Ok(rows_affected > 0),
     Err(_) => Err(EphemerideError::DatabaseError),
   }
 }
 
-pub fn get_user_session_by_id(session_id: &str) -> Result<Session, EphemerideError> {
-  let mut conn = establish_connection();
+pub fn update_accessed_at(
+  conn: &mut PgConnection,
+  session: &Session,
+) -> Result<Session, EphemerideError> {
+  let now = util::unix_time::unix_ms();
+  let expires_at = SessionConfig::from_env().expires_at(session.created_at, now);
 
-  let result = schema::sessions::table
-    .filter(schema::sessions::id.eq(session_id))
-    .first::<Session>(&mut conn);
-
-  match update_accessed_at(session_id) {
-    Ok(_) => (),
-    Err(_) => return Err(EphemerideError::DatabaseError),
-  }
+  let result = diesel::update(schema::sessions::table.filter(schema::sessions::id.eq(&session.id)))
+    .set((
+      schema::sessions::accessed_at.eq(now),
+      schema::sessions::expires_at.eq(expires_at),
+    ))
+    .get_result::<Session>(conn);
 
   match result {
     Ok(session) => Ok(session),
-    Err(_) => Err(EphemerideError::SessionNotFound),
+    Err(_) => Err(EphemerideError::DatabaseError),
   }
 }
 
-pub fn get_all_user_sessions(user_id: &str) -> Result<Vec<Session>, EphemerideError> {
-  let mut conn = establish_connection();
+pub fn get_user_session_by_token(
+  conn: &mut PgConnection,
+  token: &str,
+) -> Result<Session, EphemerideError> {
+  let result = schema::sessions::table
+    .filter(schema::sessions::token_hash.eq(util::hash_token(token)))
+    .first::<Session>(conn);
+
+  let session = match result {
+    Ok(session) => session,
+    Err(_) => return Err(EphemerideError::SessionNotFound),
+  };
+
+  renew_session(conn, session)
+}
+
+// for access tokens, which carry the session id instead of its token
+pub fn get_user_session_by_id(
+  conn: &mut PgConnection,
+  session_id: &str,
+  user_id: &str,
+) -> Result<Session, EphemerideError> {
+  let result = schema::sessions::table
+    .filter(schema::sessions::id.eq(session_id))
+    .filter(schema::sessions::user_id.eq(user_id))
+    .first::<Session>(conn);
+
+  let session = match result {
+    Ok(session) => session,
+    Err(_) => return Err(EphemerideError::SessionNotFound),
+  };
+
+  renew_session(conn, session)
+}
+
+fn renew_session(conn: &mut PgConnection, session: Session) -> Result<Session, EphemerideError> {
+  // checked against the current config rather than the stored expires_at
+  // so that shortening the lifetime applies to existing sessions as well
+  let expires_at = SessionConfig::from_env().expires_at(session.created_at, session.accessed_at);
+
+  if expires_at <= util::unix_time::unix_ms() {
+    match delete_user_session(conn, &session.id, &session.user_id) {
+      Ok(_) => (),
+      Err(_) => return Err(EphemerideError::DatabaseError),
+    }
 
+    return Err(EphemerideError::SessionExpired);
+  }
+
+  update_accessed_at(conn, &session)
+}
+
+pub fn get_all_user_sessions(
+  conn: &mut PgConnection,
+  user_id: &str,
+) -> Result<Vec<Session>, EphemerideError> {
   let result = schema::sessions::table
     .filter(schema::sessions::user_id.eq(&user_id))
+    .filter(schema::sessions::expires_at.gt(util::unix_time::unix_ms()))
     .order(schema::sessions::accessed_at.desc())
-    .load::<Session>(&mut conn);
+    .load::<Session>(conn);
 
   match result {
     Ok(sessions) => Ok(sessions),
@@ -147,11 +738,17 @@ pub fn get_all_user_sessions(user_id: &str) -> Result<Vec<Session>, EphemerideEr
   }
 }
 
-pub fn delete_user_session(session_id: &str) -> Result<bool, EphemerideError> {
-  let mut conn = establish_connection();
-
-  let result = diesel::delete(schema::sessions::table.filter(schema::sessions::id.eq(session_id)))
-    .execute(&mut conn);
+pub fn delete_user_session(
+  conn: &mut PgConnection,
+  session_id: &str,
+  user_id: &str,
+) -> Result<bool, EphemerideError> {
+  let result = diesel::delete(
+    schema::sessions::table
+      .filter(schema::sessions::id.eq(session_id))
+      .filter(schema::sessions::user_id.eq(user_id)),
+  )
+  .execute(conn);
 
   match result {
     Ok(rows_affected) => Ok(rows_affected > 0),
@@ -159,15 +756,177 @@ pub fn delete_user_session(session_id: &str) -> Result<bool, EphemerideError> {
   }
 }
 
-pub fn delete_all_user_sessions(user_id: &str) -> Result<bool, EphemerideError> {
-  let mut conn = establish_connection();
+pub fn delete_other_user_sessions(
+  conn: &mut PgConnection,
+  user_id: &str,
+  current_session_id: &str,
+) -> Result<usize, EphemerideError> {
+  let result = diesel::delete(
+    schema::sessions::table
+      .filter(schema::sessions::user_id.eq(user_id))
+      .filter(schema::sessions::id.ne(current_session_id)),
+  )
+  .execute(conn);
+
+  match result {
+    Ok(rows_affected) => Ok(rows_affected),
+    Err(_) => Err(EphemerideError::DatabaseError),
+  }
+}
+
+// signing a session out from the list of sessions is kept in the audit log,
+// logging out and expiring aren't
+pub fn revoke_user_session(
+  conn: &mut PgConnection,
+  session_id: &str,
+  user_id: &str,
+  metadata: &SessionMetadata,
+) -> Result<bool, EphemerideError> {
+  conn.transaction::<_, EphemerideError, _>(|conn| {
+    let deleted = delete_user_session(conn, session_id, user_id)?;
+
+    if deleted {
+      audit::record_audit_event(
+        conn,
+        user_id,
+        audit::AuditEventKind::SessionRevoked,
+        Some(metadata),
+        Some(session_id.to_string()),
+      )?;
+    }
+
+    Ok(deleted)
+  })
+}
+
+pub fn revoke_other_user_sessions(
+  conn: &mut PgConnection,
+  user_id: &str,
+  current_session_id: &str,
+  metadata: &SessionMetadata,
+) -> Result<usize, EphemerideError> {
+  conn.transaction::<_, EphemerideError, _>(|conn| {
+    let deleted = delete_other_user_sessions(conn, user_id, current_session_id)?;
+
+    if deleted > 0 {
+      audit::record_audit_event(
+        conn,
+        user_id,
+        audit::AuditEventKind::OtherSessionsRevoked,
+        Some(metadata),
+        Some(deleted.to_string()),
+      )?;
+    }
+
+    Ok(deleted)
+  })
+}
 
+pub fn delete_all_user_sessions(
+  conn: &mut PgConnection,
+  user_id: &str,
+) -> Result<bool, EphemerideError> {
   let result =
     diesel::delete(schema::sessions::table.filter(schema::sessions::user_id.eq(user_id)))
-      .execute(&mut conn);
+      .execute(conn);
 
   match result {
     Ok(rows_affected) => Ok(rows_affected > 0),
     Err(_) => Err(EphemerideError::DatabaseError),
   }
 }
+
+pub fn delete_expired_sessions(conn: &mut PgConnection) -> Result<usize, EphemerideError> {
+  let result = diesel::delete(
+    schema::sessions::table.filter(schema::sessions::expires_at.le(util::unix_time::unix_ms())),
+  )
+  .execute(conn);
+
+  match result {
+    Ok(rows_affected) => Ok(rows_affected),
+    Err(_) => Err(EphemerideError::DatabaseError),
+  }
+}
+
+#[cfg(test)]
+mod ci_unit {
+  use super::*;
+
+  #[test]
+  fn expires_after_lifetime() {
+    let config = SessionConfig {
+      lifetime: 1000,
+      idle_timeout: 500,
+    };
+
+    assert_eq!(config.expires_at(0, 800), 1000);
+  }
+
+  #[test]
+  fn expires_after_idle_timeout() {
+    let config = SessionConfig {
+      lifetime: 1000,
+      idle_timeout: 500,
+    };
+
+    assert_eq!(config.expires_at(0, 100), 600);
+  }
+
+  fn request(method: Method, cookie: &str, csrf_header: Option<&str>) -> Request {
+    let builder = Request::builder().method(method).header("Cookie", cookie);
+    match csrf_header {
+      Some(value) => builder.header(CSRF_HEADER, value).finish(),
+      None => builder.finish(),
+    }
+  }
+
+  #[test]
+  fn session_cookie_is_ignored_when_disabled() {
+    let request = request(Method::GET, "ephemeride_session=abc", None);
+
+    assert_eq!(token_from_request(&request, false), Ok(None));
+    assert_eq!(
+      token_from_request(&request, true),
+      Ok(Some("abc".to_string()))
+    );
+  }
+
+  #[test]
+  fn authorization_header_wins_over_cookie() {
+    let request = Request::builder()
+      .method(Method::POST)
+      .header("Authorization", "Bearer header")
+      .header("Cookie", "ephemeride_session=cookie")
+      .finish();
+
+    assert_eq!(
+      token_from_request(&request, true),
+      Ok(Some("header".to_string()))
+    );
+  }
+
+  #[test]
+  fn state_changing_cookie_requests_need_csrf_token() {
+    let cookie = "ephemeride_session=abc; ephemeride_csrf=csrf";
+
+    assert_eq!(
+      token_from_request(&request(Method::POST, cookie, None), true),
+      Err(EphemerideError::InvalidCsrfToken)
+    );
+    assert_eq!(
+      token_from_request(&request(Method::DELETE, cookie, Some("wrong")), true),
+      Err(EphemerideError::InvalidCsrfToken)
+    );
+    assert_eq!(
+      token_from_request(
+        &request(Method::PATCH, "ephemeride_session=abc", Some("")),
+        true
+      ),
+      Err(EphemerideError::InvalidCsrfToken)
+    );
+    assert_eq!(
+      token_from_request(&request(Method::POST, cookie, Some("csrf")), true),
+      Ok(Some("abc".to_string()))
+    );
+  }
+}
@@ -1,5 +1,4 @@
 use crate::{
-  establish_connection,
   schema::categories,
   services::{
     get_user,
@@ -9,7 +8,7 @@ use crate::{
 };
 use diesel::{
   prelude::{Insertable, Queryable},
-  ExpressionMethods, QueryDsl, RunQueryDsl,
+  Connection, ExpressionMethods, PgConnection, QueryDsl, RunQueryDsl,
 };
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
@@ -51,20 +50,21 @@ pub struct CategoryWithTags {
   pub tags: Vec<Tag>,
 }
 
-pub fn create_category(category: CreateCategory) -> Result<Category, EphemerideError> {
+pub fn create_category(
+  conn: &mut PgConnection,
+  category: CreateCategory,
+) -> Result<Category, EphemerideError> {
   match category.validate() {
     Ok(_) => (),
     Err(_) => return Err(EphemerideError::BadRequest),
   }
 
-  let user = get_user(&category.user_id);
+  let user = get_user(conn, &category.user_id);
 
   if user.is_err() {
     return Err(EphemerideError::UserNotFound);
   }
 
-  let mut conn = establish_connection();
-
   let new_category = Category {
     id: Uuid::new_v4().to_string(),
     name: category.name,
@@ -74,7 +74,7 @@ pub fn create_category(category: CreateCategory) -> Result<Category, EphemerideE
 
   let result = diesel::insert_into(categories::table)
     .values(&new_category)
-    .execute(&mut conn);
+    .execute(conn);
 
   match result {
     Ok(_) => Ok(new_category),
@@ -82,41 +82,44 @@ pub fn create_category(category: CreateCategory) -> Result<Category, EphemerideE
   }
 }
 
-pub fn edit_category(category: EditCategory) -> Result<Category, EphemerideError> {
+pub fn edit_category(
+  conn: &mut PgConnection,
+  category: EditCategory,
+) -> Result<Category, EphemerideError> {
   match category.validate() {
     Ok(_) => (),
     Err(_) => return Err(EphemerideError::BadRequest),
   }
 
-  let user = get_user(&category.user_id);
+  let user = get_user(conn, &category.user_id);
 
   if user.is_err() {
     return Err(EphemerideError::UserNotFound);
   }
 
-  let mut conn = establish_connection();
-
   let result = diesel::update(
     categories::table
       .filter(categories::id.eq(&category.id))
       .filter(categories::user_id.eq(&category.user_id)),
   )
   .set(categories::name.eq(&category.name))
-  .execute(&mut conn);
+  .execute(conn);
 
   match result {
-    Ok(_) => get_category(&category.id, &category.user_id),
+    Ok(_) => get_category(conn, &category.id, &category.user_id),
     Err(_) => Err(EphemerideError::DatabaseError),
   }
 }
 
-pub fn get_category(category_id: &str, user_id: &str) -> Result<Category, EphemerideError> {
-  let mut conn = establish_connection();
-
+pub fn get_category(
+  conn: &mut PgConnection,
+  category_id: &str,
+  user_id: &str,
+) -> Result<Category, EphemerideError> {
   let result = categories::table
     .filter(categories::id.eq(category_id))
     .filter(categories::user_id.eq(user_id))
-    .first::<Category>(&mut conn);
+    .first::<Category>(conn);
 
   match result {
     Ok(category) => Ok(category),
@@ -125,15 +128,16 @@ pub fn get_category(category_id: &str, user_id: &str) -> Result<Category, Epheme
 }
 
 pub fn get_category_with_tags(
+  conn: &mut PgConnection,
   category_id: &str,
   user_id: &str,
 ) -> Result<CategoryWithTags, EphemerideError> {
-  let category = match get_category(category_id, user_id) {
+  let category = match get_category(conn, category_id, user_id) {
     Ok(category) => category,
     Err(_) => return Err(EphemerideError::CategoryNotFound),
   };
 
-  let tags = match get_category_tags(category_id, user_id) {
+  let tags = match get_category_tags(conn, category_id, user_id) {
     Ok(tags) => tags,
     Err(_) => return Err(EphemerideError::DatabaseError),
   };
@@ -150,9 +154,10 @@ pub fn get_category_with_tags(
 }
 
 pub fn get_user_categories_with_tags(
+  conn: &mut PgConnection,
   user_id: &str,
 ) -> Result<Vec<CategoryWithTags>, EphemerideError> {
-  let categories = match get_all_categories(user_id) {
+  let categories = match get_all_categories(conn, user_id) {
     Ok(categories) => categories,
     Err(_) => return Err(EphemerideError::DatabaseError),
   };
@@ -160,7 +165,7 @@ pub fn get_user_categories_with_tags(
   let mut categories_with_tags: Vec<CategoryWithTags> = Vec::new();
 
   for category in categories {
-    let tags = match get_category_tags(&category.id, user_id) {
+    let tags = match get_category_tags(conn, &category.id, user_id) {
       Ok(tags) => tags,
       Err(_) => return Err(EphemerideError::DatabaseError),
     };
@@ -179,27 +184,33 @@ pub fn get_user_categories_with_tags(
   Ok(categories_with_tags)
 }
 
-pub fn delete_category(category_id: &str, user_id: &str) -> Result<bool, EphemerideError> {
-  let user = get_user(user_id);
+pub fn delete_category(
+  conn: &mut PgConnection,
+  category_id: &str,
+  user_id: &str,
+) -> Result<bool, EphemerideError> {
+  let user = get_user(conn, user_id);
 
   if user.is_err() {
     return Err(EphemerideError::UserNotFound);
   }
 
-  let mut conn = establish_connection();
+  let result = conn.transaction::<_, EphemerideError, _>(|conn| {
+    let deleted_tags = delete_all_category_tags(conn, category_id, user_id);
 
-  let deleted_tags = delete_all_category_tags(category_id, user_id);
+    if deleted_tags.is_err() {
+      return Err(EphemerideError::DatabaseError);
+    }
 
-  if deleted_tags.is_err() {
-    return Err(EphemerideError::DatabaseError);
-  }
+    let count = diesel::delete(
+      categories::table
+        .filter(categories::id.eq(category_id))
+        .filter(categories::user_id.eq(user_id)),
+    )
+    .execute(conn)?;
 
-  let result = diesel::delete(
-    categories::table
-      .filter(categories::id.eq(category_id))
-      .filter(categories::user_id.eq(user_id)),
-  )
-  .execute(&mut conn);
+    Ok(count)
+  });
 
   match result {
     Ok(count) => Ok(count > 0),
@@ -207,19 +218,20 @@ pub fn delete_category(category_id: &str, user_id: &str) -> Result<bool, Ephemer
   }
 }
 
-pub fn get_all_categories(user_id: &str) -> Result<Vec<Category>, EphemerideError> {
-  let user = get_user(user_id);
+pub fn get_all_categories(
+  conn: &mut PgConnection,
+  user_id: &str,
+) -> Result<Vec<Category>, EphemerideError> {
+  let user = get_user(conn, user_id);
 
   if user.is_err() {
     return Err(EphemerideError::UserNotFound);
   }
 
-  let mut conn = establish_connection();
-
   let result = categories::table
     .filter(categories::user_id.eq(user_id))
     .order(categories::name.asc())
-    .load::<Category>(&mut conn);
+    .load::<Category>(conn);
 
   match result {
     Ok(categories) => Ok(categories),
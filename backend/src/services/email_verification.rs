@@ -0,0 +1,217 @@
+use crate::{
+  mailer::Email,
+  schema::{self, email_verifications},
+  services::{audit, auth::SessionMetadata, user},
+  util::{self, error::EphemerideError},
+};
+use diesel::{
+  deserialize::Queryable, prelude::Insertable, Connection, ExpressionMethods, OptionalExtension,
+  PgConnection, QueryDsl, RunQueryDsl,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::Validate;
+
+use dotenvy::dotenv;
+use std::env;
+
+const DEFAULT_EMAIL_VERIFICATION_TTL_MS: i64 = 24 * 60 * 60 * 1000;
+
+#[derive(Debug, Deserialize, Serialize, Insertable, Queryable)]
+#[diesel(table_name = email_verifications)]
+pub struct EmailVerification {
+  pub id: String,
+  pub user_id: String,
+  pub email: String,
+  pub token_hash: String,
+  pub created_at: i64,
+  pub expires_at: i64,
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate)]
+pub struct ConfirmEmailVerification {
+  #[validate(length(min = 1, max = 255))]
+  pub token: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate)]
+pub struct ResendEmailVerification {
+  #[validate(email)]
+  pub email: String,
+}
+
+pub fn email_verification_required() -> bool {
+  dotenv().ok();
+
+  env::var("EMAIL_VERIFICATION_REQUIRED").unwrap_or("false".to_string()) == "true"
+}
+
+fn email_verification_ttl() -> i64 {
+  dotenv().ok();
+
+  match env::var("EMAIL_VERIFICATION_TTL_MS") {
+    Ok(val) => val
+      .parse::<i64>()
+      .unwrap_or(DEFAULT_EMAIL_VERIFICATION_TTL_MS),
+    Err(_) => DEFAULT_EMAIL_VERIFICATION_TTL_MS,
+  }
+}
+
+pub fn verification_email(to: &str, token: &str) -> Email {
+  dotenv().ok();
+
+  let url = env::var("URL").unwrap_or("http://localhost:3000".to_string());
+
+  Email {
+    to: to.to_string(),
+    subject: "Verify your Ephemeride email address".to_string(),
+    body: format!(
+      "Open the link below to confirm this email address for your Ephemeride account:\n\n\
+       {url}/verify-email?token={token}\n\n\
+       If you didn't sign up or change your email, you can ignore this email."
+    ),
+  }
+}
+
+// replaces any earlier verification for the user and returns the raw token,
+// the email itself is sent separately so this can run inside a transaction
+pub fn create_email_verification(
+  conn: &mut PgConnection,
+  user_id: &str,
+  email: &str,
+) -> Result<String, EphemerideError> {
+  let now = util::unix_time::unix_ms();
+  let token = util::generate_token();
+
+  conn.transaction::<_, EphemerideError, _>(|conn| {
+    delete_user_email_verifications(conn, user_id)?;
+
+    diesel::insert_into(schema::email_verifications::table)
+      .values(EmailVerification {
+        id: Uuid::new_v4().to_string(),
+        user_id: user_id.to_string(),
+        email: email.to_string(),
+        token_hash: util::hash_token(&token),
+        created_at: now,
+        expires_at: now.saturating_add(email_verification_ttl()),
+      })
+      .execute(conn)?;
+
+    Ok(())
+  })?;
+
+  Ok(token)
+}
+
+// returns the verification email rather than sending it, so it can go out
+// once the connection has been given back
+pub fn request_email_verification(
+  conn: &mut PgConnection,
+  user_id: &str,
+  email: &str,
+) -> Result<Email, EphemerideError> {
+  let token = create_email_verification(conn, user_id, email)?;
+
+  Ok(verification_email(email, &token))
+}
+
+// like password resets this succeeds for any well formed email, so it can't
+// be used to find out which addresses have accounts, there's only an email
+// to send for unverified ones
+pub fn resend_email_verification(
+  conn: &mut PgConnection,
+  resend: ResendEmailVerification,
+) -> Result<Option<Email>, EphemerideError> {
+  match resend.validate() {
+    Ok(_) => (),
+    Err(_) => return Err(EphemerideError::BadRequest),
+  }
+
+  let user_id = match user::get_user_id(conn, &resend.email) {
+    Ok(id) => id,
+    Err(_) => return Ok(None),
+  };
+
+  if user::get_user(conn, &user_id)?.email_verified {
+    return Ok(None);
+  }
+
+  Ok(Some(request_email_verification(
+    conn,
+    &user_id,
+    &resend.email,
+  )?))
+}
+
+pub fn confirm_email_verification(
+  conn: &mut PgConnection,
+  confirm: ConfirmEmailVerification,
+  metadata: &SessionMetadata,
+) -> Result<(), EphemerideError> {
+  match confirm.validate() {
+    Ok(_) => (),
+    Err(_) => return Err(EphemerideError::BadRequest),
+  }
+
+  let now = util::unix_time::unix_ms();
+
+  conn.transaction::<_, EphemerideError, _>(|conn| {
+    let verification = diesel::delete(
+      schema::email_verifications::table
+        .filter(schema::email_verifications::token_hash.eq(util::hash_token(&confirm.token)))
+        .filter(schema::email_verifications::expires_at.gt(now)),
+    )
+    .returning((
+      schema::email_verifications::user_id,
+      schema::email_verifications::email,
+    ))
+    .get_result::<(String, String)>(conn)
+    .optional()?;
+
+    let (user_id, email) = match verification {
+      Some(verification) => verification,
+      None => return Err(EphemerideError::InvalidVerificationToken),
+    };
+
+    // someone else may have taken the address since the change was requested
+    if let Ok(existing_user_id) = user::get_user_id(conn, &email) {
+      if existing_user_id != user_id {
+        return Err(EphemerideError::EmailAlreadyInUse);
+      }
+    }
+
+    let previous_email = user::get_user(conn, &user_id)?.email;
+
+    diesel::update(schema::users::table.filter(schema::users::id.eq(&user_id)))
+      .set((
+        schema::users::email.eq(&email),
+        schema::users::email_verified.eq(true),
+      ))
+      .execute(conn)?;
+
+    // verifying the address the account was created with isn't a change
+    if previous_email != email {
+      audit::record_audit_event(
+        conn,
+        &user_id,
+        audit::AuditEventKind::EmailChanged,
+        Some(metadata),
+        Some(email),
+      )?;
+    }
+
+    Ok(())
+  })
+}
+
+pub fn delete_user_email_verifications(
+  conn: &mut PgConnection,
+  user_id: &str,
+) -> Result<usize, EphemerideError> {
+  let deleted = diesel::delete(
+    schema::email_verifications::table.filter(schema::email_verifications::user_id.eq(user_id)),
+  )
+  .execute(conn)?;
+
+  Ok(deleted)
+}
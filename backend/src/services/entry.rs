@@ -1,7 +1,6 @@
 use std::sync::LazyLock;
 
 use crate::{
-  establish_connection,
   schema::{self},
   services::{
     get_user,
@@ -15,7 +14,8 @@ use diesel::{
   dsl::sql,
   prelude::{Insertable, Queryable},
   sql_types::{Bool, Nullable, VarChar},
-  ExpressionMethods, JoinOnDsl, NullableExpressionMethods, QueryDsl, RunQueryDsl,
+  Connection, ExpressionMethods, JoinOnDsl, NullableExpressionMethods, PgConnection, QueryDsl,
+  RunQueryDsl,
 };
 use regex::Regex;
 use serde::{Deserialize, Serialize};
@@ -83,7 +83,10 @@ pub struct EntryTag {
   pub tag_id: String,
 }
 
-pub fn create_entry(entry: CreateEntry) -> Result<EntryWithTags, EphemerideError> {
+pub fn create_entry(
+  conn: &mut PgConnection,
+  entry: CreateEntry,
+) -> Result<EntryWithTags, EphemerideError> {
   match entry.validate() {
     Ok(_) => (),
     Err(_) => return Err(EphemerideError::BadRequest),
@@ -94,28 +97,26 @@ pub fn create_entry(entry: CreateEntry) -> Result<EntryWithTags, EphemerideError
     Err(_) => return Err(EphemerideError::BadRequest),
   };
 
-  if get_entry_by_date(naive_date, &entry.user_id).is_ok() {
+  if get_entry_by_date(conn, naive_date, &entry.user_id).is_ok() {
     return Err(EphemerideError::EntryAlreadyExistsForDate);
   }
 
   let mut tags: Vec<Tag> = Vec::new();
 
   for tag_id in &entry.selected_tags {
-    let tag = get_tag(tag_id, &entry.user_id);
+    let tag = get_tag(conn, tag_id, &entry.user_id);
 
     if let Ok(tag) = tag {
       tags.push(tag);
     }
   }
 
-  let user = get_user(&entry.user_id);
+  let user = get_user(conn, &entry.user_id);
 
   if user.is_err() {
     return Err(EphemerideError::UserNotFound);
   }
 
-  let mut conn = establish_connection();
-
   let new_entry = Entry {
     id: Uuid::new_v4().to_string(),
     user_id: entry.user_id.clone(),
@@ -125,30 +126,31 @@ pub fn create_entry(entry: CreateEntry) -> Result<EntryWithTags, EphemerideError
     entry: entry.entry.clone(),
   };
 
-  let result = diesel::insert_into(schema::entries::table)
-    .values(&new_entry)
-    .execute(&mut conn);
+  // the entry and its tags are written together or not at all
+  let result = conn.transaction::<_, EphemerideError, _>(|conn| {
+    diesel::insert_into(schema::entries::table)
+      .values(&new_entry)
+      .execute(conn)?;
+
+    for tag in &tags {
+      let entry_tag = EntryTag {
+        id: Uuid::new_v4().to_string(),
+        entry_id: new_entry.id.clone(),
+        tag_id: tag.id.clone(),
+      };
+
+      diesel::insert_into(schema::entry_tags::table)
+        .values(&entry_tag)
+        .execute(conn)?;
+    }
+
+    Ok(())
+  });
 
   if result.is_err() {
     return Err(EphemerideError::DatabaseError);
   }
 
-  for tag in &tags {
-    let entry_tag = EntryTag {
-      id: Uuid::new_v4().to_string(),
-      entry_id: new_entry.id.clone(),
-      tag_id: tag.id.clone(),
-    };
-
-    let tag_result = diesel::insert_into(schema::entry_tags::table)
-      .values(&entry_tag)
-      .execute(&mut conn);
-
-    if tag_result.is_err() {
-      return Err(EphemerideError::DatabaseError);
-    }
-  }
-
   let entry_with_tags = EntryWithTags {
     id: new_entry.id,
     user_id: new_entry.user_id,
@@ -162,7 +164,10 @@ pub fn create_entry(entry: CreateEntry) -> Result<EntryWithTags, EphemerideError
   Ok(entry_with_tags)
 }
 
-pub fn edit_entry(entry: EditEntry) -> Result<EntryWithTags, EphemerideError> {
+pub fn edit_entry(
+  conn: &mut PgConnection,
+  entry: EditEntry,
+) -> Result<EntryWithTags, EphemerideError> {
   match entry.validate() {
     Ok(_) => (),
     Err(_) => return Err(EphemerideError::BadRequest),
@@ -173,7 +178,7 @@ pub fn edit_entry(entry: EditEntry) -> Result<EntryWithTags, EphemerideError> {
     Err(_) => return Err(EphemerideError::BadRequest),
   };
 
-  if let Ok(entry_for_date) = get_entry_by_date(naive_date, &entry.user_id) {
+  if let Ok(entry_for_date) = get_entry_by_date(conn, naive_date, &entry.user_id) {
     if entry_for_date.id != entry.id {
       return Err(EphemerideError::EntryAlreadyExistsForDate);
     }
@@ -182,66 +187,63 @@ pub fn edit_entry(entry: EditEntry) -> Result<EntryWithTags, EphemerideError> {
   let mut tags: Vec<Tag> = Vec::new();
 
   for tag_id in &entry.selected_tags {
-    let tag = get_tag(tag_id, &entry.user_id);
+    let tag = get_tag(conn, tag_id, &entry.user_id);
 
     if let Ok(tag) = tag {
       tags.push(tag);
     }
   }
 
-  let mut conn = establish_connection();
+  // replacing the tags must not leave the entry half-updated
+  let result = conn.transaction::<_, EphemerideError, _>(|conn| {
+    diesel::update(
+      schema::entries::table
+        .filter(schema::entries::id.eq(&entry.id))
+        .filter(schema::entries::user_id.eq(&entry.user_id)),
+    )
+    .set((
+      schema::entries::date.eq(&naive_date),
+      schema::entries::mood.eq(entry.mood),
+      schema::entries::entry.eq(&entry.entry),
+    ))
+    .execute(conn)?;
+
+    diesel::delete(
+      crate::schema::entry_tags::table.filter(crate::schema::entry_tags::entry_id.eq(&entry.id)),
+    )
+    .execute(conn)?;
+
+    for tag in &tags {
+      let entry_tag = EntryTag {
+        id: Uuid::new_v4().to_string(),
+        entry_id: entry.id.to_string(),
+        tag_id: tag.id.to_string(),
+      };
+
+      diesel::insert_into(crate::schema::entry_tags::table)
+        .values(&entry_tag)
+        .execute(conn)?;
+    }
 
-  let result = diesel::update(
-    schema::entries::table
-      .filter(schema::entries::id.eq(&entry.id))
-      .filter(schema::entries::user_id.eq(&entry.user_id)),
-  )
-  .set((
-    schema::entries::date.eq(&naive_date),
-    schema::entries::mood.eq(entry.mood),
-    schema::entries::entry.eq(&entry.entry),
-  ))
-  .execute(&mut conn);
+    Ok(())
+  });
 
   if result.is_err() {
     return Err(EphemerideError::DatabaseError);
   }
 
-  let delete_result = diesel::delete(
-    crate::schema::entry_tags::table.filter(crate::schema::entry_tags::entry_id.eq(&entry.id)),
-  )
-  .execute(&mut conn);
-
-  if delete_result.is_err() {
-    return Err(EphemerideError::DatabaseError);
-  }
-
-  for tag in &tags {
-    let entry_tag = EntryTag {
-      id: Uuid::new_v4().to_string(),
-      entry_id: entry.id.to_string(),
-      tag_id: tag.id.to_string(),
-    };
-
-    let tag_result = diesel::insert_into(crate::schema::entry_tags::table)
-      .values(&entry_tag)
-      .execute(&mut conn);
-
-    if tag_result.is_err() {
-      return Err(EphemerideError::DatabaseError);
-    }
-  }
-
-  get_entry_with_tags(&entry.id, &entry.user_id)
+  get_entry_with_tags(conn, &entry.id, &entry.user_id)
 }
 
-pub fn get_entry_by_date(date: chrono::NaiveDate, user_id: &str) -> Result<Entry, EphemerideError> {
-  let mut conn = establish_connection();
-
+pub fn get_entry_by_date(
+  conn: &mut PgConnection,
+  date: chrono::NaiveDate,
+  user_id: &str,
+) -> Result<Entry, EphemerideError> {
   let result = schema::entries::table
     .filter(schema::entries::date.eq(date))
     .filter(schema::entries::user_id.eq(user_id))
-    .first::<Entry>(&mut conn);
+    .first::<Entry>(conn);
 
   match result {
     Ok(entry) => Ok(entry),
@@ -250,15 +252,14 @@ pub fn get_entry_by_date(date: chrono::NaiveDate, user_id: &str) -> Result<Entry
 }
 
 pub fn get_entry_with_tags(
+  conn: &mut PgConnection,
   entry_id: &str,
   user_id: &str,
 ) -> Result<EntryWithTags, EphemerideError> {
-  let mut conn = establish_connection();
-
   let entry_result = schema::entries::table
     .filter(schema::entries::id.eq(entry_id))
     .filter(schema::entries::user_id.eq(user_id))
-    .first::<Entry>(&mut conn);
+    .first::<Entry>(conn);
 
   let entry = match entry_result {
     Ok(entry) => entry,
@@ -267,7 +268,7 @@ pub fn get_entry_with_tags(
 
   let entry_tags_result = schema::entry_tags::table
     .filter(schema::entry_tags::entry_id.eq(entry_id))
-    .load::<EntryTag>(&mut conn);
+    .load::<EntryTag>(conn);
 
   let entry_tags = match entry_tags_result {
     Ok(entry_tags) => entry_tags,
@@ -289,21 +290,23 @@ pub fn get_entry_with_tags(
   Ok(entry_with_tags)
 }
 
-pub fn delete_entry(entry_id: &str, user_id: &str) -> Result<bool, EphemerideError> {
-  let user = get_user(user_id);
+pub fn delete_entry(
+  conn: &mut PgConnection,
+  entry_id: &str,
+  user_id: &str,
+) -> Result<bool, EphemerideError> {
+  let user = get_user(conn, user_id);
 
   if user.is_err() {
     return Err(EphemerideError::UserNotFound);
   }
 
-  let mut conn = establish_connection();
-
   let result = diesel::delete(
     schema::entries::table
       .filter(schema::entries::id.eq(entry_id))
       .filter(schema::entries::user_id.eq(user_id)),
   )
-  .execute(&mut conn);
+  .execute(conn);
 
   match result {
     Ok(count) => Ok(count > 0),
@@ -347,6 +350,7 @@ impl Default for GetEntriesOptions {
 }
 
 pub fn get_entries(
+  conn: &mut PgConnection,
   user_id: &str,
   options: Option<GetEntriesOptions>,
 ) -> Result<Paginated<EntryWithTags>, EphemerideError> {
@@ -355,8 +359,6 @@ pub fn get_entries(
     fn array_agg(x: Nullable<VarChar>) -> Array<Nullable<VarChar>>;
   );
 
-  let mut conn = establish_connection();
-
   let selected_tags = array_agg(schema::entry_tags::tag_id.nullable());
   let row_count = sql::<diesel::sql_types::BigInt>("COUNT(*) OVER()");
 
@@ -481,7 +483,7 @@ pub fn get_entries(
     chrono::NaiveDate,
     Vec<Option<String>>,
     i64,
-  )>(&mut conn);
+  )>(conn);
 
   let rows = match result {
     Ok(rows) => rows,
@@ -0,0 +1,350 @@
+use crate::{
+  get_connection, schema,
+  services::{
+    access_token, audit, auth,
+    category::Category,
+    entry::{Entry, EntryWithTags},
+    oidc,
+    tag::Tag,
+    user,
+  },
+  util::{self, EphemerideError},
+  DbPool,
+};
+use diesel::{ExpressionMethods, PgConnection, QueryDsl, RunQueryDsl};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, io::Write};
+use zip::{write::SimpleFileOptions, ZipWriter};
+
+// bumped whenever the layout of the export changes, so anything reading an
+// older export can tell what it's looking at
+pub const EXPORT_VERSION: i32 = 1;
+
+// entries are read and written a batch at a time so a long journal is never
+// held in memory all at once, and a connection is only checked out while a
+// batch is read, writing it out takes as long as the client does
+const EXPORT_BATCH_SIZE: i64 = 500;
+
+#[derive(Debug, Default, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+  #[default]
+  Json,
+  Zip,
+}
+
+impl ExportFormat {
+  pub fn content_type(&self) -> &'static str {
+    match self {
+      ExportFormat::Json => "application/json",
+      ExportFormat::Zip => "application/zip",
+    }
+  }
+
+  pub fn extension(&self) -> &'static str {
+    match self {
+      ExportFormat::Json => "json",
+      ExportFormat::Zip => "zip",
+    }
+  }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExportOptions {
+  pub format: Option<ExportFormat>,
+}
+
+// everything about the account except the entries, which are streamed
+// after it
+#[derive(Debug, Serialize)]
+struct ExportAccount {
+  version: i32,
+  exported_at: i64,
+  user: user::UserDetails,
+  categories: Vec<Category>,
+  tags: Vec<Tag>,
+  sessions: Vec<auth::Session>,
+  identities: Vec<oidc::UserIdentity>,
+  access_tokens: Vec<access_token::PersonalAccessToken>,
+  security_events: Vec<audit::AuditEvent>,
+}
+
+fn get_export_account(pool: &DbPool, user_id: &str) -> Result<ExportAccount, EphemerideError> {
+  let conn = &mut *get_connection(pool)?;
+
+  let user = user::get_user(conn, user_id)?;
+
+  let categories = schema::categories::table
+    .filter(schema::categories::user_id.eq(user_id))
+    .order(schema::categories::created_at.asc())
+    .load::<Category>(conn)?;
+
+  let tags = schema::tags::table
+    .filter(schema::tags::user_id.eq(user_id))
+    .order(schema::tags::created_at.asc())
+    .load::<Tag>(conn)?;
+
+  Ok(ExportAccount {
+    version: EXPORT_VERSION,
+    exported_at: util::unix_time::unix_ms(),
+    user,
+    categories,
+    tags,
+    sessions: auth::get_all_user_sessions(conn, user_id)?,
+    identities: oidc::get_user_identities(conn, user_id)?,
+    access_tokens: access_token::get_user_access_tokens(conn, user_id)?,
+    security_events: audit::get_all_user_audit_events(conn, user_id)?,
+  })
+}
+
+// the next batch of entries after the given date, oldest first
+fn load_entry_batch(
+  conn: &mut PgConnection,
+  user_id: &str,
+  after: Option<chrono::NaiveDate>,
+) -> Result<Vec<EntryWithTags>, EphemerideError> {
+  let mut query = schema::entries::table
+    .filter(schema::entries::user_id.eq(user_id))
+    .into_boxed();
+
+  if let Some(after) = after {
+    query = query.filter(schema::entries::date.gt(after));
+  }
+
+  let entries = query
+    .order(schema::entries::date.asc())
+    .limit(EXPORT_BATCH_SIZE)
+    .load::<Entry>(conn)?;
+
+  let entry_ids = entries
+    .iter()
+    .map(|entry| entry.id.clone())
+    .collect::<Vec<String>>();
+
+  let mut selected_tags: HashMap<String, Vec<String>> = HashMap::new();
+  let entry_tags = schema::entry_tags::table
+    .filter(schema::entry_tags::entry_id.eq_any(&entry_ids))
+    .select((schema::entry_tags::entry_id, schema::entry_tags::tag_id))
+    .load::<(String, String)>(conn)?;
+  for (entry_id, tag_id) in entry_tags {
+    selected_tags.entry(entry_id).or_default().push(tag_id);
+  }
+
+  let entries = entries
+    .into_iter()
+    .map(|entry| EntryWithTags {
+      selected_tags: selected_tags.remove(&entry.id).unwrap_or_default(),
+      id: entry.id,
+      user_id: entry.user_id,
+      date: entry.date,
+      created_at: entry.created_at,
+      mood: entry.mood,
+      entry: entry.entry,
+    })
+    .collect();
+
+  Ok(entries)
+}
+
+// calls `f` with every entry the user has, oldest first, there is only one
+// entry per date so the date is enough to carry on from
+fn for_each_entry(
+  pool: &DbPool,
+  user_id: &str,
+  mut f: impl FnMut(EntryWithTags) -> Result<(), EphemerideError>,
+) -> Result<(), EphemerideError> {
+  let mut after: Option<chrono::NaiveDate> = None;
+
+  loop {
+    // the connection goes back to the pool before the batch is written
+    let entries = load_entry_batch(&mut *get_connection(pool)?, user_id, after)?;
+
+    let batch_size = entries.len() as i64;
+    after = entries.last().map(|entry| entry.date);
+
+    for entry in entries {
+      f(entry)?;
+    }
+
+    if batch_size < EXPORT_BATCH_SIZE {
+      return Ok(());
+    }
+  }
+}
+
+fn write_error(_: impl std::error::Error) -> EphemerideError {
+  EphemerideError::InternalServerError
+}
+
+pub fn write_export(
+  pool: &DbPool,
+  user_id: &str,
+  format: ExportFormat,
+  writer: &mut impl Write,
+) -> Result<(), EphemerideError> {
+  match format {
+    ExportFormat::Json => write_json_export(pool, user_id, writer),
+    ExportFormat::Zip => write_zip_export(pool, user_id, writer),
+  }
+}
+
+// the account is written as a single json object with the entries last, the
+// object is closed by hand so the entries can be written one at a time
+fn write_json_export(
+  pool: &DbPool,
+  user_id: &str,
+  writer: &mut impl Write,
+) -> Result<(), EphemerideError> {
+  let account = get_export_account(pool, user_id)?;
+
+  let account = serde_json::to_string(&account).map_err(write_error)?;
+  let account = account.strip_suffix('}').unwrap_or(&account);
+  write!(writer, "{account},\"entries\":[").map_err(write_error)?;
+
+  let mut first = true;
+  for_each_entry(pool, user_id, |entry| {
+    if !first {
+      writer.write_all(b",").map_err(write_error)?;
+    }
+    first = false;
+
+    serde_json::to_writer(&mut *writer, &entry).map_err(write_error)
+  })?;
+
+  writer.write_all(b"]}").map_err(write_error)?;
+  writer.flush().map_err(write_error)
+}
+
+// the zip has the profile as json and a csv file for each kind of data,
+// it's written as a stream so it never has to be seeked back into
+fn write_zip_export(
+  pool: &DbPool,
+  user_id: &str,
+  writer: &mut impl Write,
+) -> Result<(), EphemerideError> {
+  let account = get_export_account(pool, user_id)?;
+
+  let mut zip = ZipWriter::new_stream(writer);
+  let options = SimpleFileOptions::default();
+
+  zip
+    .start_file("account.json", options)
+    .map_err(write_error)?;
+  serde_json::to_writer_pretty(
+    &mut zip,
+    &serde_json::json!({
+      "version": account.version,
+      "exported_at": account.exported_at,
+      "user": account.user,
+      "identities": account.identities,
+      "access_tokens": account.access_tokens,
+      "security_events": account.security_events,
+    }),
+  )
+  .map_err(write_error)?;
+
+  zip
+    .start_file("categories.csv", options)
+    .map_err(write_error)?;
+  {
+    let mut csv = csv::Writer::from_writer(&mut zip);
+    csv
+      .write_record(["id", "name", "created_at"])
+      .map_err(write_error)?;
+    for category in &account.categories {
+      csv
+        .write_record([
+          category.id.as_str(),
+          category.name.as_str(),
+          &category.created_at.to_string(),
+        ])
+        .map_err(write_error)?;
+    }
+    csv.flush().map_err(write_error)?;
+  }
+
+  zip.start_file("tags.csv", options).map_err(write_error)?;
+  {
+    let mut csv = csv::Writer::from_writer(&mut zip);
+    csv
+      .write_record(["id", "category_id", "name", "color", "created_at"])
+      .map_err(write_error)?;
+    for tag in &account.tags {
+      csv
+        .write_record([
+          tag.id.as_str(),
+          tag.category_id.as_str(),
+          tag.name.as_str(),
+          tag.color.as_str(),
+          &tag.created_at.to_string(),
+        ])
+        .map_err(write_error)?;
+    }
+    csv.flush().map_err(write_error)?;
+  }
+
+  zip
+    .start_file("sessions.csv", options)
+    .map_err(write_error)?;
+  {
+    let mut csv = csv::Writer::from_writer(&mut zip);
+    csv
+      .write_record([
+        "id",
+        "created_at",
+        "accessed_at",
+        "expires_at",
+        "ip_address",
+        "user_agent",
+        "name",
+        "browser",
+        "os",
+        "device_type",
+      ])
+      .map_err(write_error)?;
+    for session in &account.sessions {
+      csv
+        .write_record([
+          session.id.as_str(),
+          &session.created_at.to_string(),
+          &session.accessed_at.to_string(),
+          &session.expires_at.to_string(),
+          session.ip_address.as_str(),
+          session.user_agent.as_str(),
+          session.name.as_deref().unwrap_or(""),
+          session.browser.as_str(),
+          session.os.as_str(),
+          session.device_type.as_str(),
+        ])
+        .map_err(write_error)?;
+    }
+    csv.flush().map_err(write_error)?;
+  }
+
+  zip
+    .start_file("entries.csv", options)
+    .map_err(write_error)?;
+  {
+    // tags are listed by id, separated by spaces, tags.csv has their names
+    let mut csv = csv::Writer::from_writer(&mut zip);
+    csv
+      .write_record(["id", "date", "mood", "entry", "tag_ids", "created_at"])
+      .map_err(write_error)?;
+    for_each_entry(pool, user_id, |entry| {
+      csv
+        .write_record([
+          entry.id.as_str(),
+          &entry.date.to_string(),
+          &entry.mood.to_string(),
+          entry.entry.as_deref().unwrap_or(""),
+          &entry.selected_tags.join(" "),
+          &entry.created_at.to_string(),
+        ])
+        .map_err(write_error)
+    })?;
+    csv.flush().map_err(write_error)?;
+  }
+
+  let writer = zip.finish().map_err(write_error)?;
+  writer.into_inner().flush().map_err(write_error)
+}
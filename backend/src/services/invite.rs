@@ -1,30 +1,118 @@
 use crate::{
-  establish_connection,
   schema::{self, invites},
+  services::{audit, SessionMetadata},
   util::error::EphemerideError,
-  util::generate_invite_code,
+  util::{self, generate_invite_code, normalize_invite_code},
 };
 use diesel::{
   prelude::{Insertable, Queryable},
-  ExpressionMethods, QueryDsl, RunQueryDsl,
+  result::{DatabaseErrorKind, Error::DatabaseError},
+  BoolExpressionMethods, Connection, ExpressionMethods, OptionalExtension, PgConnection, QueryDsl,
+  RunQueryDsl,
 };
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
+use validator::Validate;
+
+use dotenvy::dotenv;
+use std::env;
+
+const DEFAULT_USER_INVITE_QUOTA: i64 = 5;
 
 #[derive(Debug, Deserialize, Serialize, Insertable, Queryable)]
 pub struct Invite {
   pub id: String,
   pub created_at: i64,
   pub code: String,
-  pub used: bool,
+  pub expires_at: Option<i64>,
+  pub max_uses: i32,
+  pub use_count: i32,
+  pub created_by: Option<String>,
+  pub note: Option<String>,
+  // made through the user endpoint and counted against the user's quota
+  pub user_invite: bool,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, Validate)]
+pub struct CreateInvite {
+  #[validate(length(min = 1, max = 255))]
+  pub code: Option<String>,
+  pub expires_at: Option<i64>,
+  // defaults to a single use
+  #[validate(range(min = 1))]
+  pub max_uses: Option<i32>,
+  #[validate(length(max = 1023))]
+  pub note: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize, Validate)]
+pub struct CreateUserInvite {
+  pub expires_at: Option<i64>,
+  #[validate(length(max = 1023))]
+  pub note: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Queryable)]
+pub struct InvitedUser {
+  pub id: String,
+  pub name: String,
+  pub created_at: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UserInvite {
+  #[serde(flatten)]
+  pub invite: Invite,
+  pub redeemed_by: Vec<InvitedUser>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UserInvites {
+  pub quota: i64,
+  pub remaining: i64,
+  pub invites: Vec<UserInvite>,
+}
+
+// how many invites each user can make, admins making invites through the
+// admin api aren't limited
+pub fn user_invite_quota() -> i64 {
+  dotenv().ok();
+
+  match env::var("USER_INVITE_QUOTA") {
+    Ok(val) => val.parse::<i64>().unwrap_or(DEFAULT_USER_INVITE_QUOTA),
+    Err(_) => DEFAULT_USER_INVITE_QUOTA,
+  }
 }
 
-pub fn get_invite(code: &str) -> Result<Invite, EphemerideError> {
-  let mut conn = establish_connection();
+// codes from before they were generated in groups were stored exactly as
+// they were given, one that happens to look like a generated code is still
+// found as it is when nothing matches it normalized
+fn find_invite_code(
+  conn: &mut PgConnection,
+  code: &str,
+) -> Result<Option<String>, EphemerideError> {
+  let normalized = normalize_invite_code(code);
+
+  let codes = schema::invites::table
+    .filter(schema::invites::code.eq_any([&normalized, code]))
+    .select(schema::invites::code)
+    .load::<String>(conn)?;
+
+  match codes.contains(&normalized) {
+    true => Ok(Some(normalized)),
+    false => Ok(codes.into_iter().next()),
+  }
+}
+
+pub fn get_invite(conn: &mut PgConnection, code: &str) -> Result<Invite, EphemerideError> {
+  let code = match find_invite_code(conn, code)? {
+    Some(code) => code,
+    None => return Err(EphemerideError::InviteNotFound),
+  };
 
   let result = schema::invites::table
-    .filter(schema::invites::code.eq(&code))
-    .first(&mut conn);
+    .filter(schema::invites::code.eq(code))
+    .first(conn);
 
   match result {
     Ok(invite) => Ok(invite),
@@ -32,49 +120,280 @@ pub fn get_invite(code: &str) -> Result<Invite, EphemerideError> {
   }
 }
 
-pub fn use_invite(code: &str) -> Result<Invite, EphemerideError> {
-  let mut conn = establish_connection();
+// the use is counted in the same statement that checks the invite is still
+// valid, so two signups racing for the last use can't both get it
+pub fn use_invite(conn: &mut PgConnection, code: &str) -> Result<Invite, EphemerideError> {
+  let code = match find_invite_code(conn, code)? {
+    Some(code) => code,
+    None => return Err(EphemerideError::InviteNotFound),
+  };
+  let now = util::unix_time::unix_ms();
 
-  let invite = get_invite(code)?;
+  let redeemed = diesel::update(
+    schema::invites::table
+      .filter(schema::invites::code.eq(&code))
+      .filter(schema::invites::use_count.lt(schema::invites::max_uses))
+      .filter(
+        schema::invites::expires_at
+          .is_null()
+          .or(schema::invites::expires_at.gt(now)),
+      ),
+  )
+  .set(schema::invites::use_count.eq(schema::invites::use_count + 1))
+  .get_result::<Invite>(conn)
+  .optional()?;
 
-  if invite.used {
-    return Err(EphemerideError::InviteUsed);
+  // whoever made the invite can see it was used, but not who by or from
+  // where, that belongs to the new user
+  if let Some(invite) = redeemed {
+    if let Some(created_by) = &invite.created_by {
+      audit::record_audit_event(
+        conn,
+        created_by,
+        audit::AuditEventKind::InviteRedeemed,
+        None,
+        Some(invite.id.clone()),
+      )?;
+    }
+    return Ok(invite);
   }
 
-  let result = diesel::update(schema::invites::table.filter(schema::invites::code.eq(&code)))
-    .set(schema::invites::used.eq(true))
-    .get_result(&mut conn);
+  // nothing was redeemed, look the invite up again to tell the user why
+  let invite = get_invite(conn, &code)?;
 
-  match result {
-    Ok(invite) => Ok(invite),
-    Err(_) => Err(EphemerideError::DatabaseError),
+  match invite.expires_at {
+    Some(expires_at) if expires_at <= now => Err(EphemerideError::InviteExpired),
+    _ => Err(EphemerideError::InviteUsed),
   }
 }
 
-pub fn generate_invite(code: Option<&str>) -> Result<Invite, EphemerideError> {
-  let mut conn = establish_connection();
+pub fn create_invite(
+  conn: &mut PgConnection,
+  created_by: Option<&str>,
+  invite: CreateInvite,
+) -> Result<Invite, EphemerideError> {
+  insert_invite(conn, created_by, false, invite)
+}
+
+fn insert_invite(
+  conn: &mut PgConnection,
+  created_by: Option<&str>,
+  user_invite: bool,
+  invite: CreateInvite,
+) -> Result<Invite, EphemerideError> {
+  match invite.validate() {
+    Ok(_) => (),
+    Err(_) => return Err(EphemerideError::BadRequest),
+  }
+
+  let now = util::unix_time::unix_ms();
+
+  if let Some(expires_at) = invite.expires_at {
+    if expires_at <= now {
+      return Err(EphemerideError::BadRequest);
+    }
+  }
 
-  let code = match code {
-    Some(c) => match get_invite(c) {
-      Ok(_) => generate_invite_code(),
-      Err(_) => c.to_string(),
+  let code = match invite.code {
+    Some(c) => match find_invite_code(conn, &c)? {
+      Some(_) => return Err(EphemerideError::InviteCodeTaken),
+      None => normalize_invite_code(&c),
     },
     None => generate_invite_code(),
   };
 
   let new_invite = Invite {
     id: Uuid::new_v4().to_string(),
-    created_at: crate::util::unix_time::unix_ms(),
+    created_at: now,
     code,
-    used: false,
+    expires_at: invite.expires_at,
+    max_uses: invite.max_uses.unwrap_or(1),
+    use_count: 0,
+    created_by: created_by.map(|id| id.to_string()),
+    note: invite.note,
+    user_invite,
   };
 
   let result = diesel::insert_into(schema::invites::table)
     .values(&new_invite)
-    .execute(&mut conn);
+    .execute(conn);
 
+  // the same custom code can still be picked twice at once, the unique
+  // index turns the second one away
   match result {
     Ok(_) => Ok(new_invite),
+    Err(DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => {
+      Err(EphemerideError::InviteCodeTaken)
+    }
+    Err(_) => Err(EphemerideError::DatabaseError),
+  }
+}
+
+// a single use invite that never expires
+pub fn generate_invite(
+  conn: &mut PgConnection,
+  code: Option<&str>,
+) -> Result<Invite, EphemerideError> {
+  create_invite(
+    conn,
+    None,
+    CreateInvite {
+      code: code.map(|c| c.to_string()),
+      ..Default::default()
+    },
+  )
+}
+
+// invites made by users are always single use with a generated code, the
+// quota counts every invite the user has made, redeemed or not, but not the
+// ones an admin made through the admin api
+pub fn create_user_invite(
+  conn: &mut PgConnection,
+  user_id: &str,
+  quota: i64,
+  invite: CreateUserInvite,
+  metadata: &SessionMetadata,
+) -> Result<Invite, EphemerideError> {
+  match invite.validate() {
+    Ok(_) => (),
+    Err(_) => return Err(EphemerideError::BadRequest),
+  }
+
+  conn.transaction::<_, EphemerideError, _>(|conn| {
+    // locking the user makes concurrent requests wait for each other, so
+    // they can't both fit under the quota
+    let locked = schema::users::table
+      .filter(schema::users::id.eq(user_id))
+      .select(schema::users::id)
+      .for_update()
+      .first::<String>(conn)
+      .optional()?;
+
+    if locked.is_none() {
+      return Err(EphemerideError::UserNotFound);
+    }
+
+    if count_user_invites(conn, user_id)? >= quota {
+      return Err(EphemerideError::InviteQuotaExceeded);
+    }
+
+    let created = insert_invite(
+      conn,
+      Some(user_id),
+      true,
+      CreateInvite {
+        expires_at: invite.expires_at,
+        note: invite.note,
+        ..Default::default()
+      },
+    )?;
+
+    audit::record_audit_event(
+      conn,
+      user_id,
+      audit::AuditEventKind::InviteCreated,
+      Some(metadata),
+      Some(created.id.clone()),
+    )?;
+
+    Ok(created)
+  })
+}
+
+fn count_user_invites(conn: &mut PgConnection, user_id: &str) -> Result<i64, EphemerideError> {
+  let count = schema::invites::table
+    .filter(schema::invites::created_by.eq(user_id))
+    .filter(schema::invites::user_invite.eq(true))
+    .count()
+    .get_result::<i64>(conn)?;
+
+  Ok(count)
+}
+
+pub fn get_user_invites(
+  conn: &mut PgConnection,
+  user_id: &str,
+  quota: i64,
+) -> Result<UserInvites, EphemerideError> {
+  let invites = schema::invites::table
+    .filter(schema::invites::created_by.eq(user_id))
+    .filter(schema::invites::user_invite.eq(true))
+    .order(schema::invites::created_at.desc())
+    .load::<Invite>(conn)?;
+
+  let invite_ids = invites
+    .iter()
+    .map(|invite| invite.id.clone())
+    .collect::<Vec<String>>();
+
+  let redeemed = schema::users::table
+    .filter(schema::users::invite.eq_any(&invite_ids))
+    .order(schema::users::created_at.asc())
+    .select((
+      schema::users::invite,
+      (
+        schema::users::id,
+        schema::users::name,
+        schema::users::created_at,
+      ),
+    ))
+    .load::<(Option<String>, InvitedUser)>(conn)?;
+
+  let mut user_invites = invites
+    .into_iter()
+    .map(|invite| UserInvite {
+      invite,
+      redeemed_by: vec![],
+    })
+    .collect::<Vec<UserInvite>>();
+
+  for (invite_id, invited_user) in redeemed {
+    if let Some(user_invite) = user_invites
+      .iter_mut()
+      .find(|user_invite| Some(&user_invite.invite.id) == invite_id.as_ref())
+    {
+      user_invite.redeemed_by.push(invited_user);
+    }
+  }
+
+  Ok(UserInvites {
+    quota,
+    remaining: (quota - user_invites.len() as i64).max(0),
+    invites: user_invites,
+  })
+}
+
+pub fn get_invites(conn: &mut PgConnection) -> Result<Vec<Invite>, EphemerideError> {
+  let result = schema::invites::table
+    .order(schema::invites::created_at.desc())
+    .load::<Invite>(conn);
+
+  match result {
+    Ok(invites) => Ok(invites),
     Err(_) => Err(EphemerideError::DatabaseError),
   }
 }
+
+// only unused invites can be revoked, a used one is still referenced by the
+// user that signed up with it
+pub fn revoke_invite(conn: &mut PgConnection, id: &str) -> Result<bool, EphemerideError> {
+  let invite = schema::invites::table
+    .filter(schema::invites::id.eq(id))
+    .first::<Invite>(conn)
+    .optional()?;
+
+  match invite {
+    Some(invite) if invite.use_count > 0 => return Err(EphemerideError::InviteUsed),
+    Some(_) => (),
+    None => return Ok(false),
+  }
+
+  let deleted = diesel::delete(
+    schema::invites::table
+      .filter(schema::invites::id.eq(id))
+      .filter(schema::invites::use_count.eq(0)),
+  )
+  .execute(conn)?;
+
+  Ok(deleted > 0)
+}
@@ -0,0 +1,330 @@
+use crate::{
+  schema,
+  services::{
+    audit,
+    auth::{check_user_active, Session, SessionConfig, SessionMetadata, SessionWithToken},
+  },
+  util::{self, error::EphemerideError},
+};
+use diesel::{
+  Connection, ExpressionMethods, OptionalExtension, PgConnection, QueryDsl, RunQueryDsl,
+};
+use jsonwebtoken::{errors::ErrorKind, Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+
+use dotenvy::dotenv;
+use std::env;
+
+// refresh tokens can only be swapped for new tokens, the prefix lets them be
+// turned away anywhere else without a lookup
+pub const REFRESH_TOKEN_PREFIX: &str = "ref_";
+
+const DEFAULT_ACCESS_TOKEN_TTL_MS: i64 = 15 * 60 * 1000;
+
+// with ACCESS_TOKENS=true and an ACCESS_TOKEN_SECRET logging in hands out a
+// short lived signed access token that is checked without the database,
+// and the session token becomes a refresh token for getting new ones
+#[derive(Clone)]
+pub struct JwtConfig {
+  secret: String,
+  pub access_token_ttl: i64,
+}
+
+impl JwtConfig {
+  pub fn from_env() -> Option<JwtConfig> {
+    dotenv().ok();
+
+    if env::var("ACCESS_TOKENS").unwrap_or("false".to_string()) != "true" {
+      return None;
+    }
+
+    let secret = env::var("ACCESS_TOKEN_SECRET").ok()?;
+    if secret.is_empty() {
+      return None;
+    }
+
+    let access_token_ttl = match env::var("ACCESS_TOKEN_TTL_MS") {
+      Ok(val) => val.parse::<i64>().unwrap_or(DEFAULT_ACCESS_TOKEN_TTL_MS),
+      Err(_) => DEFAULT_ACCESS_TOKEN_TTL_MS,
+    };
+
+    Some(JwtConfig::new(&secret, access_token_ttl))
+  }
+
+  pub fn new(secret: &str, access_token_ttl: i64) -> JwtConfig {
+    JwtConfig {
+      secret: secret.to_string(),
+      access_token_ttl,
+    }
+  }
+}
+
+// exp and iat are in seconds like every other jwt, sid is the session the
+// token was issued for
+#[derive(Debug, Deserialize, Serialize)]
+pub struct AccessTokenClaims {
+  pub sub: String,
+  pub sid: String,
+  pub iat: i64,
+  pub exp: i64,
+}
+
+// what logging in returns in token mode, the refresh token is the session
+// token and is only shown this once, like the session token otherwise is
+#[derive(Debug, Serialize)]
+pub struct SessionWithTokens {
+  #[serde(flatten)]
+  pub session: Session,
+  pub access_token: String,
+  pub access_token_expires_at: i64,
+  pub refresh_token: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RefreshSession {
+  pub refresh_token: String,
+}
+
+pub fn generate_refresh_token() -> String {
+  format!("{REFRESH_TOKEN_PREFIX}{}", util::generate_token())
+}
+
+pub fn is_refresh_token(token: &str) -> bool {
+  token.starts_with(REFRESH_TOKEN_PREFIX)
+}
+
+// session and refresh tokens are hex and access tokens for scripts have a
+// prefix, so three dot separated parts can only be a jwt
+pub fn is_jwt(token: &str) -> bool {
+  token.split('.').count() == 3
+}
+
+// returns the token and when it expires, in milliseconds like every other
+// timestamp in the api
+pub fn issue_access_token(
+  config: &JwtConfig,
+  session: &Session,
+) -> Result<(String, i64), EphemerideError> {
+  let now = util::unix_time::unix_ms();
+  let expires_at = now + config.access_token_ttl;
+
+  let claims = AccessTokenClaims {
+    sub: session.user_id.clone(),
+    sid: session.id.clone(),
+    iat: now / 1000,
+    exp: expires_at / 1000,
+  };
+
+  let token = jsonwebtoken::encode(
+    &Header::new(Algorithm::HS256),
+    &claims,
+    &EncodingKey::from_secret(config.secret.as_bytes()),
+  );
+
+  match token {
+    Ok(token) => Ok((token, expires_at)),
+    Err(_) => Err(EphemerideError::InternalServerError),
+  }
+}
+
+pub fn verify_access_token(
+  config: &JwtConfig,
+  token: &str,
+) -> Result<AccessTokenClaims, EphemerideError> {
+  let mut validation = Validation::new(Algorithm::HS256);
+  validation.leeway = 0;
+  validation.set_required_spec_claims(&["exp", "sub"]);
+
+  let claims = jsonwebtoken::decode::<AccessTokenClaims>(
+    token,
+    &DecodingKey::from_secret(config.secret.as_bytes()),
+    &validation,
+  );
+
+  match claims {
+    Ok(claims) => Ok(claims.claims),
+    Err(error) => match error.kind() {
+      ErrorKind::ExpiredSignature => Err(EphemerideError::AccessTokenExpired),
+      _ => Err(EphemerideError::Unauthorized),
+    },
+  }
+}
+
+pub fn with_tokens(
+  config: &JwtConfig,
+  session: SessionWithToken,
+) -> Result<SessionWithTokens, EphemerideError> {
+  let (access_token, access_token_expires_at) = issue_access_token(config, &session.session)?;
+
+  Ok(SessionWithTokens {
+    session: session.session,
+    access_token,
+    access_token_expires_at,
+    refresh_token: session.token,
+  })
+}
+
+// swaps a refresh token for a new one, the old one is kept as retired so
+// that if it's ever used again, by whoever copied it or by the client after
+// someone else already used it, the whole session is revoked
+pub fn refresh_session(
+  conn: &mut PgConnection,
+  refresh: RefreshSession,
+  metadata: &SessionMetadata,
+) -> Result<SessionWithToken, EphemerideError> {
+  let token_hash = util::hash_token(&refresh.refresh_token);
+  let now = util::unix_time::unix_ms();
+
+  // the revocation has to be committed, so reuse comes out of the
+  // transaction as None rather than as an error that would roll it back
+  let refreshed = conn.transaction::<_, EphemerideError, _>(|conn| {
+    // locking the session makes two refreshes with the same token wait for
+    // each other, the second one then finds the token retired
+    let session = schema::sessions::table
+      .filter(schema::sessions::token_hash.eq(&token_hash))
+      .for_update()
+      .first::<Session>(conn)
+      .optional()?;
+
+    let session = match session {
+      Some(session) => session,
+      None => return revoke_reused_session(conn, &token_hash, metadata).map(|_| None),
+    };
+
+    let session_config = SessionConfig::from_env();
+    if session_config.expires_at(session.created_at, session.accessed_at) <= now {
+      return Err(EphemerideError::SessionExpired);
+    }
+
+    check_user_active(conn, &session.user_id)?;
+
+    diesel::insert_into(schema::retired_refresh_tokens::table)
+      .values((
+        schema::retired_refresh_tokens::token_hash.eq(&token_hash),
+        schema::retired_refresh_tokens::session_id.eq(&session.id),
+        schema::retired_refresh_tokens::retired_at.eq(now),
+      ))
+      .execute(conn)?;
+
+    let token = generate_refresh_token();
+
+    let session =
+      diesel::update(schema::sessions::table.filter(schema::sessions::id.eq(&session.id)))
+        .set((
+          schema::sessions::token_hash.eq(util::hash_token(&token)),
+          schema::sessions::accessed_at.eq(now),
+          schema::sessions::expires_at.eq(session_config.expires_at(session.created_at, now)),
+        ))
+        .get_result::<Session>(conn)?;
+
+    Ok(Some(SessionWithToken { session, token }))
+  })?;
+
+  match refreshed {
+    Some(refreshed) => Ok(refreshed),
+    None => Err(EphemerideError::RefreshTokenReused),
+  }
+}
+
+fn revoke_reused_session(
+  conn: &mut PgConnection,
+  token_hash: &str,
+  metadata: &SessionMetadata,
+) -> Result<(), EphemerideError> {
+  let session = schema::retired_refresh_tokens::table
+    .inner_join(schema::sessions::table)
+    .filter(schema::retired_refresh_tokens::token_hash.eq(token_hash))
+    .select((schema::sessions::id, schema::sessions::user_id))
+    .first::<(String, String)>(conn)
+    .optional()?;
+
+  let (session_id, user_id) = match session {
+    Some(session) => session,
+    None => return Err(EphemerideError::InvalidRefreshToken),
+  };
+
+  diesel::delete(schema::sessions::table.filter(schema::sessions::id.eq(&session_id)))
+    .execute(conn)?;
+
+  audit::record_audit_event(
+    conn,
+    &user_id,
+    audit::AuditEventKind::RefreshTokenReused,
+    Some(metadata),
+    Some(session_id),
+  )?;
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod ci_unit {
+  use super::*;
+
+  fn session() -> Session {
+    Session {
+      id: "session".to_string(),
+      user_id: "user".to_string(),
+      created_at: 0,
+      accessed_at: 0,
+      ip_address: "SYSTEM".to_string(),
+      user_agent: "SYSTEM".to_string(),
+      expires_at: 0,
+      token_hash: "".to_string(),
+      name: None,
+      browser: "unknown".to_string(),
+      os: "unknown".to_string(),
+      device_type: "unknown".to_string(),
+      new_device: false,
+    }
+  }
+
+  #[test]
+  fn verifies_issued_access_tokens() {
+    let config = JwtConfig::new("secret", 60_000);
+
+    let (token, expires_at) = issue_access_token(&config, &session()).unwrap();
+    assert!(is_jwt(&token));
+    assert!(expires_at > util::unix_time::unix_ms());
+
+    let claims = verify_access_token(&config, &token).unwrap();
+    assert_eq!(claims.sub, "user");
+    assert_eq!(claims.sid, "session");
+  }
+
+  #[test]
+  fn rejects_expired_access_tokens() {
+    let config = JwtConfig::new("secret", -60_000);
+
+    let (token, _) = issue_access_token(&config, &session()).unwrap();
+
+    assert_eq!(
+      verify_access_token(&config, &token).err(),
+      Some(EphemerideError::AccessTokenExpired)
+    );
+  }
+
+  #[test]
+  fn rejects_access_tokens_signed_with_another_secret() {
+    let (token, _) = issue_access_token(&JwtConfig::new("secret", 60_000), &session()).unwrap();
+
+    assert_eq!(
+      verify_access_token(&JwtConfig::new("another secret", 60_000), &token).err(),
+      Some(EphemerideError::Unauthorized)
+    );
+    assert_eq!(
+      verify_access_token(&JwtConfig::new("secret", 60_000), "not.a.jwt").err(),
+      Some(EphemerideError::Unauthorized)
+    );
+  }
+
+  #[test]
+  fn tells_tokens_apart() {
+    let refresh_token = generate_refresh_token();
+
+    assert!(is_refresh_token(&refresh_token));
+    assert!(!is_jwt(&refresh_token));
+    assert!(!is_refresh_token(&util::generate_token()));
+    assert!(!is_jwt(&util::generate_token()));
+  }
+}
@@ -6,14 +6,16 @@ pub use super::category::*;
 pub use super::entry::*;
 pub use super::tag::*;
 use crate::{
-  establish_connection,
   schema::{categories, entries, tags},
   services::get_user,
   util::EphemerideError,
 };
-use diesel::{ExpressionMethods, QueryDsl, RunQueryDsl};
+use diesel::{ExpressionMethods, PgConnection, QueryDsl, RunQueryDsl};
 
-pub fn create_default_data(user_id: String) -> Result<bool, EphemerideError> {
+pub fn create_default_data(
+  conn: &mut PgConnection,
+  user_id: String,
+) -> Result<bool, EphemerideError> {
   let default_categories = vec!["Activities", "Tags"];
 
   let default_tags = vec![
@@ -29,10 +31,13 @@ pub fn create_default_data(user_id: String) -> Result<bool, EphemerideError> {
   ];
 
   for category_name in default_categories {
-    let category_result = create_category(CreateCategory {
-      name: category_name.to_string(),
-      user_id: user_id.clone(),
-    });
+    let category_result = create_category(
+      conn,
+      CreateCategory {
+        name: category_name.to_string(),
+        user_id: user_id.clone(),
+      },
+    );
 
     let category = match category_result {
       Ok(category) => category,
@@ -41,12 +46,15 @@ pub fn create_default_data(user_id: String) -> Result<bool, EphemerideError> {
 
     for (cat_name, tag_name, color) in &default_tags {
       if *cat_name == category_name {
-        let tag_result = create_tag(CreateTag {
-          name: tag_name.to_string(),
-          color: color.to_string(),
-          category_id: category.id.clone(),
-          user_id: user_id.clone(),
-        });
+        let tag_result = create_tag(
+          conn,
+          CreateTag {
+            name: tag_name.to_string(),
+            color: color.to_string(),
+            category_id: category.id.clone(),
+            user_id: user_id.clone(),
+          },
+        );
 
         if tag_result.is_err() {
           return Err(EphemerideError::DatabaseError);
@@ -58,31 +66,31 @@ pub fn create_default_data(user_id: String) -> Result<bool, EphemerideError> {
   Ok(true)
 }
 
-pub fn delete_all_user_data(user_id: &str) -> Result<bool, EphemerideError> {
-  let user = get_user(user_id);
+pub fn delete_all_user_data(
+  conn: &mut PgConnection,
+  user_id: &str,
+) -> Result<bool, EphemerideError> {
+  let user = get_user(conn, user_id);
 
   if user.is_err() {
     return Err(EphemerideError::UserNotFound);
   }
 
-  let mut conn = establish_connection();
-
   let delete_entries =
-    diesel::delete(entries::table.filter(entries::user_id.eq(user_id))).execute(&mut conn);
+    diesel::delete(entries::table.filter(entries::user_id.eq(user_id))).execute(conn);
 
   if delete_entries.is_err() {
     return Err(EphemerideError::DatabaseError);
   }
 
-  let delete_tags =
-    diesel::delete(tags::table.filter(tags::user_id.eq(user_id))).execute(&mut conn);
+  let delete_tags = diesel::delete(tags::table.filter(tags::user_id.eq(user_id))).execute(conn);
 
   if delete_tags.is_err() {
     return Err(EphemerideError::DatabaseError);
   }
 
   let delete_categories =
-    diesel::delete(categories::table.filter(categories::user_id.eq(user_id))).execute(&mut conn);
+    diesel::delete(categories::table.filter(categories::user_id.eq(user_id))).execute(conn);
 
   if delete_categories.is_err() {
     return Err(EphemerideError::DatabaseError);
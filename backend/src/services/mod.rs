@@ -4,6 +4,12 @@ pub mod user;
 pub use user::*;
 pub mod auth;
 pub use auth::*;
+pub mod access_token;
+pub use access_token::*;
+pub mod totp;
+pub use totp::*;
+pub mod oidc;
+pub use oidc::*;
 pub mod category;
 pub use category::*;
 pub mod tag;
@@ -12,5 +18,19 @@ pub mod entry;
 pub use entry::*;
 pub mod log;
 pub use log::*;
+pub mod email_verification;
+pub use email_verification::*;
+pub mod password_reset;
+pub use password_reset::*;
 pub mod pagination;
 pub use pagination::*;
+pub mod throttle;
+pub use throttle::*;
+pub mod admin;
+pub use admin::*;
+pub mod export;
+pub use export::*;
+pub mod audit;
+pub use audit::*;
+pub mod jwt;
+pub use jwt::*;
@@ -0,0 +1,588 @@
+use crate::{
+  schema::{self, oidc_logins, user_identities},
+  services::{
+    auth::{start_user_session, SessionMetadata, SessionWithToken},
+    email_verification, invite, user,
+  },
+  util::{self, error::EphemerideError},
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use diesel::{
+  deserialize::Queryable, prelude::Insertable, Connection, ExpressionMethods, OptionalExtension,
+  PgConnection, QueryDsl, RunQueryDsl,
+};
+use jsonwebtoken::{jwk::JwkSet, Algorithm, DecodingKey, Validation};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use url::Url;
+use uuid::Uuid;
+use validator::Validate;
+
+use dotenvy::dotenv;
+use std::{env, time::Duration};
+
+const DEFAULT_OIDC_LOGIN_TTL_MS: i64 = 10 * 60 * 1000;
+const DEFAULT_OIDC_SCOPES: &str = "openid email profile";
+const PROVIDER_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone)]
+pub struct OidcConfig {
+  pub issuer: String,
+  pub client_id: String,
+  pub client_secret: Option<String>,
+  // where the provider sends the browser back to, the frontend picks the
+  // code and state up from there and posts them to the callback endpoint
+  pub redirect_uri: String,
+  pub scopes: String,
+}
+
+impl OidcConfig {
+  // single sign-on is only enabled when both the issuer and client id are set
+  pub fn from_env() -> Option<OidcConfig> {
+    dotenv().ok();
+
+    let issuer = env::var("OIDC_ISSUER").ok()?;
+    let client_id = env::var("OIDC_CLIENT_ID").ok()?;
+    let url = env::var("URL").unwrap_or("http://localhost:3000".to_string());
+
+    Some(OidcConfig {
+      issuer,
+      client_id,
+      client_secret: env::var("OIDC_CLIENT_SECRET").ok(),
+      redirect_uri: env::var("OIDC_REDIRECT_URI").unwrap_or(format!("{url}/auth/oidc/callback")),
+      scopes: env::var("OIDC_SCOPES").unwrap_or(DEFAULT_OIDC_SCOPES.to_string()),
+    })
+  }
+}
+
+#[derive(Debug, Deserialize, Serialize, Insertable, Queryable)]
+#[diesel(table_name = user_identities)]
+pub struct UserIdentity {
+  pub id: String,
+  pub user_id: String,
+  pub issuer: String,
+  pub subject: String,
+  pub email: Option<String>,
+  pub created_at: i64,
+  pub last_used_at: i64,
+}
+
+#[derive(Debug, Insertable, Queryable)]
+#[diesel(table_name = oidc_logins)]
+pub struct OidcLogin {
+  pub id: String,
+  pub state_hash: String,
+  pub code_verifier: String,
+  pub nonce: String,
+  pub user_id: Option<String>,
+  pub invite: Option<String>,
+  pub created_at: i64,
+  pub expires_at: i64,
+  pub restore: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate)]
+pub struct StartOidcLogin {
+  #[validate(length(min = 1, max = 255))]
+  pub invite: Option<String>,
+  // logging in to an account that is waiting to be deleted cancels the
+  // deletion, like POST /v1/auth/restore
+  #[serde(default)]
+  pub restore: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct OidcAuthorization {
+  pub authorization_url: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate)]
+pub struct OidcCallback {
+  #[validate(length(min = 1, max = 2048))]
+  pub code: String,
+  #[validate(length(min = 1, max = 255))]
+  pub state: String,
+}
+
+pub enum OidcCallbackResult {
+  LoggedIn(SessionWithToken),
+  Linked(UserIdentity),
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ProviderMetadata {
+  issuer: String,
+  authorization_endpoint: String,
+  token_endpoint: String,
+  jwks_uri: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+  id_token: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct IdTokenClaims {
+  sub: String,
+  nonce: Option<String>,
+  email: Option<String>,
+  email_verified: Option<bool>,
+  name: Option<String>,
+}
+
+fn oidc_login_ttl() -> i64 {
+  dotenv().ok();
+
+  match env::var("OIDC_LOGIN_TTL_MS") {
+    Ok(val) => val.parse::<i64>().unwrap_or(DEFAULT_OIDC_LOGIN_TTL_MS),
+    Err(_) => DEFAULT_OIDC_LOGIN_TTL_MS,
+  }
+}
+
+// the S256 PKCE challenge sent along with the authorization request
+pub fn code_challenge(code_verifier: &str) -> String {
+  URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()))
+}
+
+fn provider_error<E: std::fmt::Display>(error: E) -> EphemerideError {
+  tracing::warn!("identity provider request failed: {error}");
+  EphemerideError::OidcProviderError
+}
+
+fn agent() -> ureq::Agent {
+  ureq::AgentBuilder::new().timeout(PROVIDER_TIMEOUT).build()
+}
+
+fn read_json<T: DeserializeOwned>(
+  response: Result<ureq::Response, ureq::Error>,
+) -> Result<T, EphemerideError> {
+  let response = match response {
+    Ok(response) => response,
+    Err(error) => return Err(provider_error(error)),
+  };
+
+  match response.into_json::<T>() {
+    Ok(body) => Ok(body),
+    Err(error) => Err(provider_error(error)),
+  }
+}
+
+// fetched for every login rather than cached, logins are rare enough that
+// picking up a rotated signing key straight away is worth the extra request.
+// this and the other provider requests can take up to PROVIDER_TIMEOUT each,
+// so they are made without holding a database connection
+pub fn discover(config: &OidcConfig) -> Result<ProviderMetadata, EphemerideError> {
+  let url = format!(
+    "{}/.well-known/openid-configuration",
+    config.issuer.trim_end_matches('/')
+  );
+  let metadata: ProviderMetadata = read_json(agent().get(&url).call())?;
+
+  if metadata.issuer != config.issuer {
+    return Err(provider_error(
+      "discovered issuer doesn't match OIDC_ISSUER",
+    ));
+  }
+
+  Ok(metadata)
+}
+
+fn exchange_code(
+  config: &OidcConfig,
+  metadata: &ProviderMetadata,
+  code: &str,
+  code_verifier: &str,
+) -> Result<String, EphemerideError> {
+  let mut form = vec![
+    ("grant_type", "authorization_code"),
+    ("code", code),
+    ("redirect_uri", &config.redirect_uri),
+    ("client_id", &config.client_id),
+    ("code_verifier", code_verifier),
+  ];
+
+  if let Some(client_secret) = &config.client_secret {
+    form.push(("client_secret", client_secret));
+  }
+
+  let token: TokenResponse = read_json(agent().post(&metadata.token_endpoint).send_form(&form))?;
+
+  Ok(token.id_token)
+}
+
+fn verify_id_token(
+  config: &OidcConfig,
+  metadata: &ProviderMetadata,
+  id_token: &str,
+  nonce: &str,
+) -> Result<IdTokenClaims, EphemerideError> {
+  let header = match jsonwebtoken::decode_header(id_token) {
+    Ok(header) => header,
+    Err(error) => return Err(provider_error(error)),
+  };
+
+  // only keys published by the provider are trusted, a token signed with
+  // a shared secret could have been minted by anyone holding the client secret
+  if matches!(
+    header.alg,
+    Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+  ) {
+    return Err(provider_error("id token isn't signed with a provider key"));
+  }
+
+  let jwks: JwkSet = read_json(agent().get(&metadata.jwks_uri).call())?;
+
+  let jwk = match &header.kid {
+    Some(kid) => jwks.find(kid),
+    None if jwks.keys.len() == 1 => jwks.keys.first(),
+    None => None,
+  };
+
+  let key = match jwk.map(DecodingKey::from_jwk) {
+    Some(Ok(key)) => key,
+    Some(Err(error)) => return Err(provider_error(error)),
+    None => return Err(provider_error("no matching signing key")),
+  };
+
+  let mut validation = Validation::new(header.alg);
+  validation.set_audience(&[&config.client_id]);
+  validation.set_issuer(&[&config.issuer]);
+  validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+
+  let claims = match jsonwebtoken::decode::<IdTokenClaims>(id_token, &key, &validation) {
+    Ok(token) => token.claims,
+    Err(error) => return Err(provider_error(error)),
+  };
+
+  if claims.nonce.as_deref() != Some(nonce) {
+    return Err(provider_error("id token nonce doesn't match"));
+  }
+
+  Ok(claims)
+}
+
+// user_id is set when a logged in user is linking another identity to their
+// account, otherwise the callback logs in or signs up. metadata comes from
+// discover
+pub fn start_oidc_login(
+  conn: &mut PgConnection,
+  config: &OidcConfig,
+  metadata: &ProviderMetadata,
+  user_id: Option<String>,
+  start: StartOidcLogin,
+) -> Result<OidcAuthorization, EphemerideError> {
+  match start.validate() {
+    Ok(_) => (),
+    Err(_) => return Err(EphemerideError::BadRequest),
+  }
+
+  let state = util::generate_token();
+  let now = util::unix_time::unix_ms();
+
+  let login = OidcLogin {
+    id: Uuid::new_v4().to_string(),
+    state_hash: util::hash_token(&state),
+    code_verifier: util::generate_token(),
+    nonce: util::generate_token(),
+    user_id,
+    invite: start.invite,
+    created_at: now,
+    expires_at: now + oidc_login_ttl(),
+    restore: start.restore,
+  };
+
+  let authorization_url = match Url::parse_with_params(
+    &metadata.authorization_endpoint,
+    &[
+      ("response_type", "code"),
+      ("client_id", &config.client_id),
+      ("redirect_uri", &config.redirect_uri),
+      ("scope", &config.scopes),
+      ("state", &state),
+      ("nonce", &login.nonce),
+      ("code_challenge", &code_challenge(&login.code_verifier)),
+      ("code_challenge_method", "S256"),
+    ],
+  ) {
+    Ok(url) => url,
+    Err(error) => return Err(provider_error(error)),
+  };
+
+  diesel::insert_into(schema::oidc_logins::table)
+    .values(&login)
+    .execute(conn)?;
+
+  Ok(OidcAuthorization {
+    authorization_url: authorization_url.to_string(),
+  })
+}
+
+fn get_identity(
+  conn: &mut PgConnection,
+  issuer: &str,
+  subject: &str,
+) -> Result<Option<UserIdentity>, EphemerideError> {
+  let identity = schema::user_identities::table
+    .filter(schema::user_identities::issuer.eq(issuer))
+    .filter(schema::user_identities::subject.eq(subject))
+    .first::<UserIdentity>(conn)
+    .optional()?;
+
+  Ok(identity)
+}
+
+fn link_identity(
+  conn: &mut PgConnection,
+  user_id: &str,
+  issuer: &str,
+  claims: &IdTokenClaims,
+) -> Result<UserIdentity, EphemerideError> {
+  let now = util::unix_time::unix_ms();
+
+  let identity = UserIdentity {
+    id: Uuid::new_v4().to_string(),
+    user_id: user_id.to_string(),
+    issuer: issuer.to_string(),
+    subject: claims.sub.clone(),
+    email: claims.email.clone(),
+    created_at: now,
+    last_used_at: now,
+  };
+
+  diesel::insert_into(schema::user_identities::table)
+    .values(&identity)
+    .execute(conn)?;
+
+  Ok(identity)
+}
+
+// signs up a new user for an identity nobody has used before, the account
+// gets an unguessable password that can be replaced with a password reset
+fn provision_user(
+  conn: &mut PgConnection,
+  claims: &IdTokenClaims,
+  invite_code: Option<String>,
+  invite_required: bool,
+) -> Result<String, EphemerideError> {
+  let email = match &claims.email {
+    Some(email) => email.clone(),
+    None => return Err(EphemerideError::BadRequest),
+  };
+  let email_verified = claims.email_verified.unwrap_or(false);
+
+  // an existing account has to link the identity itself, otherwise anyone
+  // controlling an identity with the same email could take the account over
+  if user::get_user_id(conn, &email).is_ok() {
+    return Err(EphemerideError::EmailAlreadyInUse);
+  }
+
+  if email_verification::email_verification_required() && !email_verified {
+    return Err(EphemerideError::EmailNotVerified);
+  }
+
+  let invite_id = match (invite_required, &invite_code) {
+    (true, Some(code)) => Some(invite::use_invite(conn, code)?.id),
+    (true, None) => return Err(EphemerideError::InviteNotFound),
+    (false, _) => None,
+  };
+
+  let name = match &claims.name {
+    Some(name) if !name.trim().is_empty() => name.chars().take(255).collect(),
+    _ => email.split('@').next().unwrap_or(&email).to_string(),
+  };
+
+  let created_user = user::create_user(
+    conn,
+    user::CreateUser {
+      name,
+      email,
+      password: util::generate_token(),
+      invite: invite_id,
+    },
+  )?;
+
+  diesel::update(schema::users::table.filter(schema::users::id.eq(&created_user.id)))
+    .set(schema::users::email_verified.eq(email_verified))
+    .execute(conn)?;
+
+  Ok(created_user.id)
+}
+
+// the state is claimed before talking to the provider, so a callback can't be
+// replayed even if the code exchange fails halfway through.
+// session_user_id is whoever is logged in where the callback came from, a
+// link only goes through for the user that started it, otherwise anyone
+// could get someone else to finish linking their identity to the wrong account
+pub fn claim_oidc_login(
+  conn: &mut PgConnection,
+  callback: &OidcCallback,
+  session_user_id: Option<String>,
+) -> Result<OidcLogin, EphemerideError> {
+  match callback.validate() {
+    Ok(_) => (),
+    Err(_) => return Err(EphemerideError::BadRequest),
+  }
+
+  let login = diesel::delete(
+    schema::oidc_logins::table
+      .filter(schema::oidc_logins::state_hash.eq(util::hash_token(&callback.state)))
+      .filter(schema::oidc_logins::expires_at.gt(util::unix_time::unix_ms())),
+  )
+  .returning(schema::oidc_logins::all_columns)
+  .get_result::<OidcLogin>(conn)
+  .optional()?;
+
+  let login = match login {
+    Some(login) => login,
+    None => return Err(EphemerideError::InvalidOidcState),
+  };
+
+  if login.user_id.is_some() && login.user_id != session_user_id {
+    return Err(EphemerideError::Unauthorized);
+  }
+
+  Ok(login)
+}
+
+// exchanges the code from the callback for the claims of a verified id token,
+// only talks to the provider so it is run without a database connection
+pub fn verify_oidc_callback(
+  config: &OidcConfig,
+  callback: &OidcCallback,
+  login: &OidcLogin,
+) -> Result<IdTokenClaims, EphemerideError> {
+  let provider = discover(config)?;
+  let id_token = exchange_code(config, &provider, &callback.code, &login.code_verifier)?;
+
+  verify_id_token(config, &provider, &id_token, &login.nonce)
+}
+
+pub fn complete_oidc_login(
+  conn: &mut PgConnection,
+  config: &OidcConfig,
+  login: OidcLogin,
+  claims: IdTokenClaims,
+  invite_required: bool,
+  metadata: SessionMetadata,
+) -> Result<OidcCallbackResult, EphemerideError> {
+  conn.transaction::<_, EphemerideError, _>(|conn| {
+    let identity = get_identity(conn, &config.issuer, &claims.sub)?;
+
+    if let Some(user_id) = login.user_id {
+      return match identity {
+        Some(identity) if identity.user_id == user_id => Ok(OidcCallbackResult::Linked(identity)),
+        Some(_) => Err(EphemerideError::IdentityAlreadyLinked),
+        None => Ok(OidcCallbackResult::Linked(link_identity(
+          conn,
+          &user_id,
+          &config.issuer,
+          &claims,
+        )?)),
+      };
+    }
+
+    let user_id = match identity {
+      Some(identity) => {
+        diesel::update(
+          schema::user_identities::table.filter(schema::user_identities::id.eq(&identity.id)),
+        )
+        .set((
+          schema::user_identities::email.eq(&claims.email),
+          schema::user_identities::last_used_at.eq(util::unix_time::unix_ms()),
+        ))
+        .execute(conn)?;
+
+        identity.user_id
+      }
+      None => {
+        let user_id = provision_user(conn, &claims, login.invite, invite_required)?;
+        link_identity(conn, &user_id, &config.issuer, &claims)?;
+        user_id
+      }
+    };
+
+    // the provider handles its own second factor, two-factor here only
+    // applies to password logins
+    if email_verification::email_verification_required()
+      && !user::get_user(conn, &user_id)?.email_verified
+    {
+      return Err(EphemerideError::EmailNotVerified);
+    }
+
+    if login.restore {
+      user::cancel_user_deletion(conn, &user_id)?;
+    }
+
+    Ok(OidcCallbackResult::LoggedIn(start_user_session(
+      conn, user_id, metadata,
+    )?))
+  })
+}
+
+pub fn get_user_identities(
+  conn: &mut PgConnection,
+  user_id: &str,
+) -> Result<Vec<UserIdentity>, EphemerideError> {
+  let identities = schema::user_identities::table
+    .filter(schema::user_identities::user_id.eq(user_id))
+    .order(schema::user_identities::created_at.asc())
+    .load::<UserIdentity>(conn)?;
+
+  Ok(identities)
+}
+
+pub fn delete_user_identity(
+  conn: &mut PgConnection,
+  id: &str,
+  user_id: &str,
+) -> Result<bool, EphemerideError> {
+  let deleted = diesel::delete(
+    schema::user_identities::table
+      .filter(schema::user_identities::id.eq(id))
+      .filter(schema::user_identities::user_id.eq(user_id)),
+  )
+  .execute(conn)?;
+
+  Ok(deleted > 0)
+}
+
+// removes linked identities along with any link the user had in progress
+pub fn delete_user_identities(
+  conn: &mut PgConnection,
+  user_id: &str,
+) -> Result<usize, EphemerideError> {
+  diesel::delete(schema::oidc_logins::table.filter(schema::oidc_logins::user_id.eq(user_id)))
+    .execute(conn)?;
+
+  let deleted = diesel::delete(
+    schema::user_identities::table.filter(schema::user_identities::user_id.eq(user_id)),
+  )
+  .execute(conn)?;
+
+  Ok(deleted)
+}
+
+pub fn delete_expired_oidc_logins(conn: &mut PgConnection) -> Result<usize, EphemerideError> {
+  let deleted = diesel::delete(
+    schema::oidc_logins::table
+      .filter(schema::oidc_logins::expires_at.le(util::unix_time::unix_ms())),
+  )
+  .execute(conn)?;
+
+  Ok(deleted)
+}
+
+#[cfg(test)]
+mod ci_unit {
+  use super::*;
+
+  #[test]
+  fn test_code_challenge() {
+    // the example from RFC 7636 appendix B
+    assert_eq!(
+      code_challenge("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk"),
+      "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+    );
+  }
+}
@@ -0,0 +1,180 @@
+use crate::{
+  mailer::Email,
+  schema::{self, password_resets},
+  services::{audit, delete_all_user_sessions, user, SessionMetadata},
+  util::{self, error::EphemerideError},
+};
+use diesel::{
+  deserialize::Queryable, prelude::Insertable, Connection, ExpressionMethods, OptionalExtension,
+  PgConnection, QueryDsl, RunQueryDsl,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::Validate;
+
+use dotenvy::dotenv;
+use std::env;
+
+const DEFAULT_PASSWORD_RESET_TTL_MS: i64 = 60 * 60 * 1000;
+
+#[derive(Debug, Deserialize, Serialize, Insertable, Queryable)]
+#[diesel(table_name = password_resets)]
+pub struct PasswordReset {
+  pub id: String,
+  pub user_id: String,
+  pub token_hash: String,
+  pub created_at: i64,
+  pub expires_at: i64,
+  pub used_at: Option<i64>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate)]
+pub struct RequestPasswordReset {
+  #[validate(email)]
+  pub email: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate)]
+pub struct ConfirmPasswordReset {
+  #[validate(length(min = 1, max = 255))]
+  pub token: String,
+  #[validate(length(min = 7, max = 1024))]
+  pub password: String,
+}
+
+fn password_reset_ttl() -> i64 {
+  dotenv().ok();
+
+  match env::var("PASSWORD_RESET_TTL_MS") {
+    Ok(val) => val.parse::<i64>().unwrap_or(DEFAULT_PASSWORD_RESET_TTL_MS),
+    Err(_) => DEFAULT_PASSWORD_RESET_TTL_MS,
+  }
+}
+
+fn password_reset_email(to: String, token: &str, ttl: i64) -> Email {
+  dotenv().ok();
+
+  let url = env::var("URL").unwrap_or("http://localhost:3000".to_string());
+  let minutes = ttl / 60 / 1000;
+
+  Email {
+    to,
+    subject: "Reset your Ephemeride password".to_string(),
+    body: format!(
+      "Someone asked to reset the password for your Ephemeride account.\n\n\
+       Open the link below within {minutes} minutes to choose a new password:\n\n\
+       {url}/reset-password?token={token}\n\n\
+       If this wasn't you, you can ignore this email and your password will stay the same."
+    ),
+  }
+}
+
+// succeeds for any well formed email, whether or not an account exists, so
+// the endpoint can't be used to find out who has signed up. the reset email
+// is returned rather than sent, so it goes out after the connection is
+// given back
+pub fn request_password_reset(
+  conn: &mut PgConnection,
+  request: RequestPasswordReset,
+) -> Result<Option<Email>, EphemerideError> {
+  match request.validate() {
+    Ok(_) => (),
+    Err(_) => return Err(EphemerideError::BadRequest),
+  }
+
+  let user_id = match user::get_user_id(conn, &request.email) {
+    Ok(id) => id,
+    Err(_) => return Ok(None),
+  };
+
+  let now = util::unix_time::unix_ms();
+  let ttl = password_reset_ttl();
+  let token = util::generate_token();
+
+  conn.transaction::<_, EphemerideError, _>(|conn| {
+    // only the most recently requested link works
+    diesel::delete(
+      schema::password_resets::table.filter(schema::password_resets::user_id.eq(&user_id)),
+    )
+    .execute(conn)?;
+
+    diesel::insert_into(schema::password_resets::table)
+      .values(PasswordReset {
+        id: Uuid::new_v4().to_string(),
+        user_id: user_id.clone(),
+        token_hash: util::hash_token(&token),
+        created_at: now,
+        expires_at: now.saturating_add(ttl),
+        used_at: None,
+      })
+      .execute(conn)?;
+
+    Ok(())
+  })?;
+
+  Ok(Some(password_reset_email(request.email, &token, ttl)))
+}
+
+pub fn confirm_password_reset(
+  conn: &mut PgConnection,
+  confirm: ConfirmPasswordReset,
+  metadata: &SessionMetadata,
+) -> Result<(), EphemerideError> {
+  match confirm.validate() {
+    Ok(_) => (),
+    Err(_) => return Err(EphemerideError::BadRequest),
+  }
+
+  let password_hash = util::hash_password(&confirm.password)?;
+  let now = util::unix_time::unix_ms();
+
+  conn.transaction::<_, EphemerideError, _>(|conn| {
+    // claiming the token and checking it in one statement keeps it single
+    // use even if the same link is submitted twice at once
+    let user_id = diesel::update(
+      schema::password_resets::table
+        .filter(schema::password_resets::token_hash.eq(util::hash_token(&confirm.token)))
+        .filter(schema::password_resets::used_at.is_null())
+        .filter(schema::password_resets::expires_at.gt(now)),
+    )
+    .set(schema::password_resets::used_at.eq(now))
+    .returning(schema::password_resets::user_id)
+    .get_result::<String>(conn)
+    .optional()?;
+
+    let user_id = match user_id {
+      Some(user_id) => user_id,
+      None => return Err(EphemerideError::InvalidResetToken),
+    };
+
+    diesel::update(schema::users::table.filter(schema::users::id.eq(&user_id)))
+      .set((
+        schema::users::password.eq(password_hash),
+        schema::users::password_changed_at.eq(now),
+      ))
+      .execute(conn)?;
+
+    delete_all_user_sessions(conn, &user_id)?;
+    audit::record_audit_event(
+      conn,
+      &user_id,
+      audit::AuditEventKind::PasswordReset,
+      Some(metadata),
+      None,
+    )?;
+
+    Ok(())
+  })
+}
+
+pub fn delete_user_password_resets(
+  conn: &mut PgConnection,
+  user_id: &str,
+) -> Result<usize, EphemerideError> {
+  let deleted = diesel::delete(
+    schema::password_resets::table.filter(schema::password_resets::user_id.eq(user_id)),
+  )
+  .execute(conn)?;
+
+  Ok(deleted)
+}
@@ -1,12 +1,11 @@
 use crate::{
-  establish_connection,
   schema::tags,
   services::{category::get_category, get_user},
   util::{self, Color, EphemerideError},
 };
 use diesel::{
   prelude::{Insertable, Queryable},
-  ExpressionMethods, QueryDsl, RunQueryDsl,
+  ExpressionMethods, PgConnection, QueryDsl, RunQueryDsl,
 };
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
@@ -47,19 +46,19 @@ pub struct EditTag {
   pub user_id: String,
 }
 
-pub fn create_tag(tag: CreateTag) -> Result<Tag, EphemerideError> {
+pub fn create_tag(conn: &mut PgConnection, tag: CreateTag) -> Result<Tag, EphemerideError> {
   match tag.validate() {
     Ok(_) => (),
     Err(_) => return Err(EphemerideError::BadRequest),
   }
 
-  let user = get_user(&tag.user_id);
+  let user = get_user(conn, &tag.user_id);
 
   if user.is_err() {
     return Err(EphemerideError::UserNotFound);
   }
 
-  let category = get_category(&tag.category_id, &tag.user_id);
+  let category = get_category(conn, &tag.category_id, &tag.user_id);
 
   if category.is_err() {
     return Err(EphemerideError::CategoryNotFound);
@@ -67,8 +66,6 @@ pub fn create_tag(tag: CreateTag) -> Result<Tag, EphemerideError> {
 
   let color_value = Color::from(tag.color.as_str());
 
-  let mut conn = establish_connection();
-
   let tag = Tag {
     id: Uuid::new_v4().to_string(),
     name: tag.name,
@@ -78,9 +75,7 @@ pub fn create_tag(tag: CreateTag) -> Result<Tag, EphemerideError> {
     created_at: util::unix_ms(),
   };
 
-  let tag_result = diesel::insert_into(tags::table)
-    .values(&tag)
-    .execute(&mut conn);
+  let tag_result = diesel::insert_into(tags::table).values(&tag).execute(conn);
 
   match tag_result {
     Ok(_) => Ok(tag),
@@ -88,13 +83,13 @@ pub fn create_tag(tag: CreateTag) -> Result<Tag, EphemerideError> {
   }
 }
 
-pub fn edit_tag(tag: EditTag) -> Result<Tag, EphemerideError> {
+pub fn edit_tag(conn: &mut PgConnection, tag: EditTag) -> Result<Tag, EphemerideError> {
   match tag.validate() {
     Ok(_) => (),
     Err(_) => return Err(EphemerideError::BadRequest),
   }
 
-  let user = get_user(&tag.user_id);
+  let user = get_user(conn, &tag.user_id);
 
   if user.is_err() {
     return Err(EphemerideError::UserNotFound);
@@ -102,8 +97,6 @@ pub fn edit_tag(tag: EditTag) -> Result<Tag, EphemerideError> {
 
   let color_value = Color::from(tag.color.as_str());
 
-  let mut conn = establish_connection();
-
   let result = diesel::update(
     tags::table
       .filter(tags::id.eq(&tag.id))
@@ -113,27 +106,29 @@ pub fn edit_tag(tag: EditTag) -> Result<Tag, EphemerideError> {
     tags::name.eq(&tag.name),
     tags::color.eq(color_value.to_string()),
   ))
-  .execute(&mut conn);
+  .execute(conn);
 
   match result {
-    Ok(_) => get_tag(&tag.id, &tag.user_id),
+    Ok(_) => get_tag(conn, &tag.id, &tag.user_id),
     Err(_) => Err(EphemerideError::DatabaseError),
   }
 }
 
-pub fn get_tag(tag_id: &str, user_id: &str) -> Result<Tag, EphemerideError> {
-  let user = get_user(user_id);
+pub fn get_tag(
+  conn: &mut PgConnection,
+  tag_id: &str,
+  user_id: &str,
+) -> Result<Tag, EphemerideError> {
+  let user = get_user(conn, user_id);
 
   if user.is_err() {
     return Err(EphemerideError::UserNotFound);
   }
 
-  let mut conn = establish_connection();
-
   let result = tags::table
     .filter(tags::id.eq(tag_id))
     .filter(tags::user_id.eq(user_id))
-    .first::<Tag>(&mut conn);
+    .first::<Tag>(conn);
 
   match result {
     Ok(tag) => Ok(tag),
@@ -141,20 +136,22 @@ pub fn get_tag(tag_id: &str, user_id: &str) -> Result<Tag, EphemerideError> {
   }
 }
 
-pub fn get_tags(tag_ids: Vec<&str>, user_id: &str) -> Result<Vec<Tag>, EphemerideError> {
-  let user = get_user(user_id);
+pub fn get_tags(
+  conn: &mut PgConnection,
+  tag_ids: Vec<&str>,
+  user_id: &str,
+) -> Result<Vec<Tag>, EphemerideError> {
+  let user = get_user(conn, user_id);
 
   if user.is_err() {
     return Err(EphemerideError::UserNotFound);
   }
 
-  let mut conn = establish_connection();
-
   let result = tags::table
     .filter(tags::id.eq_any(tag_ids))
     .filter(tags::user_id.eq(user_id))
     .order(tags::name.asc())
-    .load::<Tag>(&mut conn);
+    .load::<Tag>(conn);
 
   match result {
     Ok(tags) => Ok(tags),
@@ -162,21 +159,23 @@ pub fn get_tags(tag_ids: Vec<&str>, user_id: &str) -> Result<Vec<Tag>, Ephemerid
   }
 }
 
-pub fn delete_tag(tag_id: &str, user_id: &str) -> Result<bool, EphemerideError> {
-  let user = get_user(user_id);
+pub fn delete_tag(
+  conn: &mut PgConnection,
+  tag_id: &str,
+  user_id: &str,
+) -> Result<bool, EphemerideError> {
+  let user = get_user(conn, user_id);
 
   if user.is_err() {
     return Err(EphemerideError::UserNotFound);
   }
 
-  let mut conn = establish_connection();
-
   let result = diesel::delete(
     tags::table
       .filter(tags::id.eq(tag_id))
       .filter(tags::user_id.eq(user_id)),
   )
-  .execute(&mut conn);
+  .execute(conn);
 
   match result {
     Ok(count) => Ok(count > 0),
@@ -184,48 +183,54 @@ pub fn delete_tag(tag_id: &str, user_id: &str) -> Result<bool, EphemerideError>
   }
 }
 
-pub fn delete_all_category_tags(category_id: &str, user_id: &str) -> Result<bool, EphemerideError> {
-  let user = get_user(user_id);
+pub fn delete_all_category_tags(
+  conn: &mut PgConnection,
+  category_id: &str,
+  user_id: &str,
+) -> Result<bool, EphemerideError> {
+  let user = get_user(conn, user_id);
 
   if user.is_err() {
     return Err(EphemerideError::UserNotFound);
   }
 
-  let category = get_category(category_id, user_id);
+  let category = get_category(conn, category_id, user_id);
 
   if category.is_err() {
     return Err(EphemerideError::CategoryNotFound);
   }
 
-  let category_tags = get_category_tags(category_id, user_id)?;
+  let category_tags = get_category_tags(conn, category_id, user_id)?;
 
   for tag in category_tags {
-    delete_tag(&tag.id, user_id)?;
+    delete_tag(conn, &tag.id, user_id)?;
   }
 
   Ok(true)
 }
 
-pub fn get_category_tags(category_id: &str, user_id: &str) -> Result<Vec<Tag>, EphemerideError> {
-  let user = get_user(user_id);
+pub fn get_category_tags(
+  conn: &mut PgConnection,
+  category_id: &str,
+  user_id: &str,
+) -> Result<Vec<Tag>, EphemerideError> {
+  let user = get_user(conn, user_id);
 
   if user.is_err() {
     return Err(EphemerideError::UserNotFound);
   }
 
-  let category = get_category(category_id, user_id);
+  let category = get_category(conn, category_id, user_id);
 
   if category.is_err() {
     return Err(EphemerideError::CategoryNotFound);
   }
 
-  let mut conn = establish_connection();
-
   let result = tags::table
     .filter(tags::category_id.eq(category_id))
     .filter(tags::user_id.eq(user_id))
     .order(tags::name.asc())
-    .load::<Tag>(&mut conn);
+    .load::<Tag>(conn);
 
   match result {
     Ok(tags) => Ok(tags),
@@ -0,0 +1,218 @@
+use crate::{
+  schema::{self, auth_throttles},
+  util::{self, error::EphemerideError},
+};
+use diesel::{
+  deserialize::Queryable, prelude::Insertable, Connection, ExpressionMethods, PgConnection,
+  QueryDsl, RunQueryDsl,
+};
+use serde::{Deserialize, Serialize};
+
+use dotenvy::dotenv;
+use std::env;
+
+const DEFAULT_THROTTLE_FREE_ATTEMPTS: i32 = 5;
+const DEFAULT_THROTTLE_BASE_DELAY_MS: i64 = 1000;
+const DEFAULT_THROTTLE_MAX_DELAY_MS: i64 = 15 * 60 * 1000;
+const DEFAULT_THROTTLE_RESET_MS: i64 = 60 * 60 * 1000;
+
+#[derive(Debug, Deserialize, Serialize, Insertable, Queryable)]
+#[diesel(table_name = auth_throttles)]
+pub struct AuthThrottle {
+  pub key: String,
+  pub failures: i32,
+  pub last_failure_at: i64,
+  pub locked_until: i64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ThrottleConfig {
+  // failures allowed before any lockout kicks in
+  pub free_attempts: i32,
+  // first lockout, doubled for every failure after that
+  pub base_delay: i64,
+  pub max_delay: i64,
+  // a key with no failures for this long starts over from zero
+  pub reset_after: i64,
+}
+
+impl ThrottleConfig {
+  pub fn from_env() -> ThrottleConfig {
+    dotenv().ok();
+
+    let free_attempts = match env::var("THROTTLE_FREE_ATTEMPTS") {
+      Ok(val) => val.parse::<i32>().unwrap_or(DEFAULT_THROTTLE_FREE_ATTEMPTS),
+      Err(_) => DEFAULT_THROTTLE_FREE_ATTEMPTS,
+    };
+    let base_delay = match env::var("THROTTLE_BASE_DELAY_MS") {
+      Ok(val) => val.parse::<i64>().unwrap_or(DEFAULT_THROTTLE_BASE_DELAY_MS),
+      Err(_) => DEFAULT_THROTTLE_BASE_DELAY_MS,
+    };
+    let max_delay = match env::var("THROTTLE_MAX_DELAY_MS") {
+      Ok(val) => val.parse::<i64>().unwrap_or(DEFAULT_THROTTLE_MAX_DELAY_MS),
+      Err(_) => DEFAULT_THROTTLE_MAX_DELAY_MS,
+    };
+    let reset_after = match env::var("THROTTLE_RESET_MS") {
+      Ok(val) => val.parse::<i64>().unwrap_or(DEFAULT_THROTTLE_RESET_MS),
+      Err(_) => DEFAULT_THROTTLE_RESET_MS,
+    };
+
+    ThrottleConfig {
+      free_attempts,
+      base_delay,
+      max_delay,
+      reset_after,
+    }
+  }
+
+  pub fn lockout(&self, failures: i32) -> i64 {
+    if failures < self.free_attempts {
+      return 0;
+    }
+
+    let doublings = (failures - self.free_attempts).min(32) as u32;
+
+    self
+      .base_delay
+      .saturating_mul(2i64.saturating_pow(doublings))
+      .min(self.max_delay)
+  }
+}
+
+pub fn ip_key(ip: &str) -> String {
+  util::hash_token(&format!("ip:{ip}"))
+}
+
+pub fn account_key(email: &str) -> String {
+  util::hash_token(&format!("account:{}", email.trim().to_lowercase()))
+}
+
+pub fn signup_key(ip: &str) -> String {
+  util::hash_token(&format!("signup:{ip}"))
+}
+
+// asking for a password reset or verification link, kept apart from the
+// login keys so mailing someone can't lock them out of logging in
+pub fn mail_ip_key(ip: &str) -> String {
+  util::hash_token(&format!("mail:{ip}"))
+}
+
+pub fn mail_account_key(email: &str) -> String {
+  util::hash_token(&format!("mail:{}", email.trim().to_lowercase()))
+}
+
+// returns how many milliseconds are left on the longest lockout among the
+// keys, or None if none of them are locked
+pub fn retry_after(
+  conn: &mut PgConnection,
+  keys: &[String],
+) -> Result<Option<i64>, EphemerideError> {
+  let now = util::unix_time::unix_ms();
+
+  let locked_until = schema::auth_throttles::table
+    .filter(schema::auth_throttles::key.eq_any(keys))
+    .filter(schema::auth_throttles::locked_until.gt(now))
+    .select(diesel::dsl::max(schema::auth_throttles::locked_until))
+    .first::<Option<i64>>(conn)?;
+
+  Ok(locked_until.map(|locked_until| locked_until - now))
+}
+
+pub fn record_attempt(conn: &mut PgConnection, keys: &[String]) -> Result<(), EphemerideError> {
+  let config = ThrottleConfig::from_env();
+  let now = util::unix_time::unix_ms();
+
+  conn.transaction::<_, EphemerideError, _>(|conn| {
+    for key in keys {
+      diesel::insert_into(schema::auth_throttles::table)
+        .values(AuthThrottle {
+          key: key.clone(),
+          failures: 0,
+          last_failure_at: now,
+          locked_until: 0,
+        })
+        .on_conflict_do_nothing()
+        .execute(conn)?;
+
+      // the row is locked so concurrent attempts are all counted
+      let throttle = schema::auth_throttles::table
+        .filter(schema::auth_throttles::key.eq(key))
+        .for_update()
+        .first::<AuthThrottle>(conn)?;
+
+      let failures = match now - throttle.last_failure_at > config.reset_after {
+        true => 1,
+        false => throttle.failures.saturating_add(1),
+      };
+
+      diesel::update(schema::auth_throttles::table.filter(schema::auth_throttles::key.eq(key)))
+        .set((
+          schema::auth_throttles::failures.eq(failures),
+          schema::auth_throttles::last_failure_at.eq(now),
+          schema::auth_throttles::locked_until.eq(now.saturating_add(config.lockout(failures))),
+        ))
+        .execute(conn)?;
+    }
+
+    Ok(())
+  })
+}
+
+pub fn clear(conn: &mut PgConnection, keys: &[String]) -> Result<(), EphemerideError> {
+  diesel::delete(schema::auth_throttles::table.filter(schema::auth_throttles::key.eq_any(keys)))
+    .execute(conn)?;
+
+  Ok(())
+}
+
+pub fn delete_stale_throttles(conn: &mut PgConnection) -> Result<usize, EphemerideError> {
+  let config = ThrottleConfig::from_env();
+  let now = util::unix_time::unix_ms();
+
+  let deleted = diesel::delete(
+    schema::auth_throttles::table
+      .filter(schema::auth_throttles::locked_until.lt(now))
+      .filter(schema::auth_throttles::last_failure_at.lt(now - config.reset_after)),
+  )
+  .execute(conn)?;
+
+  Ok(deleted)
+}
+
+#[cfg(test)]
+mod ci_unit {
+  use super::*;
+
+  fn config() -> ThrottleConfig {
+    ThrottleConfig {
+      free_attempts: 3,
+      base_delay: 1000,
+      max_delay: 10_000,
+      reset_after: 60_000,
+    }
+  }
+
+  #[test]
+  fn free_attempts_are_not_locked() {
+    assert_eq!(config().lockout(0), 0);
+    assert_eq!(config().lockout(2), 0);
+  }
+
+  #[test]
+  fn lockout_doubles_and_is_capped() {
+    assert_eq!(config().lockout(3), 1000);
+    assert_eq!(config().lockout(4), 2000);
+    assert_eq!(config().lockout(6), 8000);
+    assert_eq!(config().lockout(7), 10_000);
+    assert_eq!(config().lockout(i32::MAX), 10_000);
+  }
+
+  #[test]
+  fn keys_do_not_collide() {
+    assert_ne!(ip_key("127.0.0.1"), signup_key("127.0.0.1"));
+    assert_eq!(
+      account_key("User@Example.com "),
+      account_key("user@example.com")
+    );
+  }
+}
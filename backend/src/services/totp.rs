@@ -0,0 +1,347 @@
+use crate::{
+  schema::{self, recovery_codes, user_totp},
+  services::user,
+  util::{self, error::EphemerideError},
+};
+use diesel::{
+  deserialize::Queryable, prelude::Insertable, BoolExpressionMethods, Connection,
+  ExpressionMethods, OptionalExtension, PgConnection, QueryDsl, RunQueryDsl,
+};
+use serde::{Deserialize, Serialize};
+use totp_rs::{Algorithm, Secret, TOTP};
+use uuid::Uuid;
+use validator::Validate;
+
+const TOTP_ISSUER: &str = "Ephemeride";
+const TOTP_DIGITS: usize = 6;
+const TOTP_STEP: u64 = 30;
+// accept the previous and next code as well to allow for clock drift
+const TOTP_SKEW: i64 = 1;
+
+#[derive(Debug, Deserialize, Serialize, Insertable, Queryable)]
+#[diesel(table_name = user_totp)]
+pub struct UserTotp {
+  pub user_id: String,
+  pub secret: String,
+  pub created_at: i64,
+  pub confirmed_at: Option<i64>,
+  pub last_used_step: Option<i64>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Insertable, Queryable)]
+#[diesel(table_name = recovery_codes)]
+pub struct RecoveryCode {
+  pub id: String,
+  pub user_id: String,
+  pub code_hash: String,
+  pub created_at: i64,
+  pub used_at: Option<i64>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct TotpStatus {
+  pub enabled: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct TotpEnrollment {
+  pub secret: String,
+  pub otpauth_uri: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct RecoveryCodes {
+  pub recovery_codes: Vec<String>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate)]
+pub struct ConfirmTotp {
+  #[validate(length(min = 6, max = 6))]
+  pub code: String,
+}
+
+#[derive(Debug, Deserialize, Serialize, Validate)]
+pub struct DisableTotp {
+  #[validate(length(min = 7, max = 1024))]
+  pub password: String,
+}
+
+fn build_totp(secret: &str, account_name: &str) -> Result<TOTP, EphemerideError> {
+  let secret = match Secret::Encoded(secret.to_string()).to_bytes() {
+    Ok(secret) => secret,
+    Err(_) => return Err(EphemerideError::InternalServerError),
+  };
+
+  match TOTP::new(
+    Algorithm::SHA1,
+    TOTP_DIGITS,
+    0,
+    TOTP_STEP,
+    secret,
+    Some(TOTP_ISSUER.to_string()),
+    account_name.to_string(),
+  ) {
+    Ok(totp) => Ok(totp),
+    Err(_) => Err(EphemerideError::InternalServerError),
+  }
+}
+
+// returns the time step the code belongs to, so it can be recorded and the
+// same code can't be replayed within its validity window
+fn matching_step(
+  secret: &str,
+  code: &str,
+  unix_seconds: u64,
+) -> Result<Option<i64>, EphemerideError> {
+  let totp = build_totp(secret, "")?;
+  let current_step = (unix_seconds / TOTP_STEP) as i64;
+
+  for step in (current_step - TOTP_SKEW)..=(current_step + TOTP_SKEW) {
+    if step >= 0 && totp.check(code, step as u64 * TOTP_STEP) {
+      return Ok(Some(step));
+    }
+  }
+
+  Ok(None)
+}
+
+fn unix_seconds() -> u64 {
+  (util::unix_time::unix_ms() / 1000) as u64
+}
+
+fn get_user_totp(
+  conn: &mut PgConnection,
+  user_id: &str,
+) -> Result<Option<UserTotp>, EphemerideError> {
+  let result = schema::user_totp::table
+    .filter(schema::user_totp::user_id.eq(user_id))
+    .first::<UserTotp>(conn)
+    .optional();
+
+  match result {
+    Ok(user_totp) => Ok(user_totp),
+    Err(_) => Err(EphemerideError::DatabaseError),
+  }
+}
+
+pub fn is_totp_enabled(conn: &mut PgConnection, user_id: &str) -> Result<bool, EphemerideError> {
+  match get_user_totp(conn, user_id)? {
+    Some(user_totp) => Ok(user_totp.confirmed_at.is_some()),
+    None => Ok(false),
+  }
+}
+
+pub fn start_totp_enrollment(
+  conn: &mut PgConnection,
+  user_id: &str,
+) -> Result<TotpEnrollment, EphemerideError> {
+  let email = user::get_user(conn, user_id)?.email;
+
+  conn.transaction::<_, EphemerideError, _>(|conn| {
+    if is_totp_enabled(conn, user_id)? {
+      return Err(EphemerideError::TotpAlreadyEnabled);
+    }
+
+    // starting over replaces any secret that was never confirmed
+    diesel::delete(schema::user_totp::table.filter(schema::user_totp::user_id.eq(user_id)))
+      .execute(conn)?;
+
+    let secret = Secret::generate_secret().to_encoded().to_string();
+    let otpauth_uri = build_totp(&secret, &email)?.get_url();
+
+    diesel::insert_into(schema::user_totp::table)
+      .values(UserTotp {
+        user_id: user_id.to_string(),
+        secret: secret.clone(),
+        created_at: util::unix_time::unix_ms(),
+        confirmed_at: None,
+        last_used_step: None,
+      })
+      .execute(conn)?;
+
+    Ok(TotpEnrollment {
+      secret,
+      otpauth_uri,
+    })
+  })
+}
+
+pub fn confirm_totp_enrollment(
+  conn: &mut PgConnection,
+  user_id: &str,
+  confirm: ConfirmTotp,
+) -> Result<RecoveryCodes, EphemerideError> {
+  match confirm.validate() {
+    Ok(_) => (),
+    Err(_) => return Err(EphemerideError::BadRequest),
+  }
+
+  conn.transaction::<_, EphemerideError, _>(|conn| {
+    let user_totp = match get_user_totp(conn, user_id)? {
+      Some(user_totp) => user_totp,
+      None => return Err(EphemerideError::TotpNotFound),
+    };
+
+    if user_totp.confirmed_at.is_some() {
+      return Err(EphemerideError::TotpAlreadyEnabled);
+    }
+
+    let step = match matching_step(&user_totp.secret, &confirm.code, unix_seconds())? {
+      Some(step) => step,
+      None => return Err(EphemerideError::InvalidTotpCode),
+    };
+
+    diesel::update(schema::user_totp::table.filter(schema::user_totp::user_id.eq(user_id)))
+      .set((
+        schema::user_totp::confirmed_at.eq(util::unix_time::unix_ms()),
+        schema::user_totp::last_used_step.eq(step),
+      ))
+      .execute(conn)?;
+
+    let recovery_codes = create_recovery_codes(conn, user_id)?;
+
+    Ok(RecoveryCodes { recovery_codes })
+  })
+}
+
+// recovery codes are only shown once, the database keeps their hashes
+fn create_recovery_codes(
+  conn: &mut PgConnection,
+  user_id: &str,
+) -> Result<Vec<String>, EphemerideError> {
+  diesel::delete(schema::recovery_codes::table.filter(schema::recovery_codes::user_id.eq(user_id)))
+    .execute(conn)?;
+
+  let now = util::unix_time::unix_ms();
+  let codes = (0..util::RECOVERY_CODE_COUNT)
+    .map(|_| util::generate_recovery_code())
+    .collect::<Vec<String>>();
+
+  let rows = codes
+    .iter()
+    .map(|code| RecoveryCode {
+      id: Uuid::new_v4().to_string(),
+      user_id: user_id.to_string(),
+      code_hash: util::hash_recovery_code(code),
+      created_at: now,
+      used_at: None,
+    })
+    .collect::<Vec<RecoveryCode>>();
+
+  diesel::insert_into(schema::recovery_codes::table)
+    .values(&rows)
+    .execute(conn)?;
+
+  Ok(codes)
+}
+
+// accepts either a code from the authenticator app or an unused recovery code
+pub fn verify_second_factor(
+  conn: &mut PgConnection,
+  user_id: &str,
+  code: &str,
+) -> Result<(), EphemerideError> {
+  let user_totp = match get_user_totp(conn, user_id)? {
+    Some(user_totp) if user_totp.confirmed_at.is_some() => user_totp,
+    _ => return Err(EphemerideError::TotpNotFound),
+  };
+
+  if let Some(step) = matching_step(&user_totp.secret, code, unix_seconds())? {
+    // the step is only recorded if it is newer than the last one used, which
+    // also settles two concurrent logins racing with the same code
+    let updated = diesel::update(
+      schema::user_totp::table
+        .filter(schema::user_totp::user_id.eq(user_id))
+        .filter(
+          schema::user_totp::last_used_step
+            .is_null()
+            .or(schema::user_totp::last_used_step.lt(step)),
+        ),
+    )
+    .set(schema::user_totp::last_used_step.eq(step))
+    .execute(conn)?;
+
+    return match updated {
+      0 => Err(EphemerideError::InvalidTotpCode),
+      _ => Ok(()),
+    };
+  }
+
+  let used = diesel::update(
+    schema::recovery_codes::table
+      .filter(schema::recovery_codes::user_id.eq(user_id))
+      .filter(schema::recovery_codes::code_hash.eq(util::hash_recovery_code(code)))
+      .filter(schema::recovery_codes::used_at.is_null()),
+  )
+  .set(schema::recovery_codes::used_at.eq(util::unix_time::unix_ms()))
+  .execute(conn)?;
+
+  match used {
+    0 => Err(EphemerideError::InvalidTotpCode),
+    _ => Ok(()),
+  }
+}
+
+pub fn disable_totp(
+  conn: &mut PgConnection,
+  user_id: &str,
+  disable: DisableTotp,
+) -> Result<bool, EphemerideError> {
+  match disable.validate() {
+    Ok(_) => (),
+    Err(_) => return Err(EphemerideError::BadRequest),
+  }
+
+  user::verify_password(conn, user_id, &disable.password)?;
+
+  conn.transaction::<_, EphemerideError, _>(|conn| {
+    let deleted = delete_user_totp(conn, user_id)?;
+
+    Ok(deleted > 0)
+  })
+}
+
+pub fn delete_user_totp(conn: &mut PgConnection, user_id: &str) -> Result<usize, EphemerideError> {
+  diesel::delete(schema::recovery_codes::table.filter(schema::recovery_codes::user_id.eq(user_id)))
+    .execute(conn)?;
+
+  let deleted =
+    diesel::delete(schema::user_totp::table.filter(schema::user_totp::user_id.eq(user_id)))
+      .execute(conn)?;
+
+  Ok(deleted)
+}
+
+#[cfg(test)]
+mod ci_unit {
+  use super::*;
+
+  #[test]
+  fn matches_codes_within_skew() {
+    let secret = Secret::generate_secret().to_encoded().to_string();
+    let totp = build_totp(&secret, "").unwrap();
+    let now = 1_700_000_000;
+    let step = (now / TOTP_STEP) as i64;
+
+    let current = totp.generate(now);
+    let previous = totp.generate(now - TOTP_STEP);
+    let stale = totp.generate(now - TOTP_STEP * 3);
+
+    assert_eq!(matching_step(&secret, &current, now).unwrap(), Some(step));
+    assert_eq!(
+      matching_step(&secret, &previous, now).unwrap(),
+      Some(step - 1)
+    );
+    assert_eq!(matching_step(&secret, &stale, now).unwrap(), None);
+  }
+
+  #[test]
+  fn builds_otpauth_uri() {
+    let secret = Secret::generate_secret().to_encoded().to_string();
+    let uri = build_totp(&secret, "user@example.com").unwrap().get_url();
+
+    assert!(uri.starts_with("otpauth://totp/Ephemeride:user%40example.com?"));
+    assert!(uri.contains(&format!("secret={secret}")));
+    assert!(uri.contains("issuer=Ephemeride"));
+  }
+}
@@ -1,21 +1,28 @@
 use crate::{
-  establish_connection,
+  mailer::Email,
   schema::{self, users},
-  services::{create_default_data, log},
+  services::{audit, create_default_data, log, SessionMetadata},
   util::{self, error::EphemerideError},
 };
 use diesel::{
-  deserialize::Queryable, prelude::Insertable, AggregateExpressionMethods, ExpressionMethods,
-  JoinOnDsl, QueryDsl, RunQueryDsl,
+  deserialize::Queryable, prelude::Insertable, AggregateExpressionMethods, Connection,
+  ExpressionMethods, JoinOnDsl, PgConnection, QueryDsl, RunQueryDsl,
 };
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use validator::Validate;
 
-use super::delete_all_user_sessions;
+use super::{
+  delete_all_user_sessions, delete_other_user_sessions, delete_user_access_tokens,
+  delete_user_email_verifications, delete_user_identities, delete_user_known_devices,
+  delete_user_password_resets, delete_user_totp, request_email_verification,
+};
 
 use dotenvy::dotenv;
 use std::env;
+use std::sync::OnceLock;
+
+const DEFAULT_ACCOUNT_DELETION_GRACE_MS: i64 = 14 * 24 * 60 * 60 * 1000;
 
 #[derive(Debug, Deserialize, Serialize, Validate)]
 pub struct CreateUser {
@@ -23,7 +30,7 @@ pub struct CreateUser {
   pub name: String,
   #[validate(email)]
   pub email: String,
-  #[validate(length(min = 7, max = 72))]
+  #[validate(length(min = 7, max = 1024))]
   pub password: String,
   pub invite: Option<String>,
 }
@@ -32,8 +39,10 @@ pub struct CreateUser {
 pub struct AuthUser {
   #[validate(email)]
   pub email: String,
-  #[validate(length(min = 7, max = 72))]
+  #[validate(length(min = 7, max = 1024))]
   pub password: String,
+  #[validate(length(min = 1, max = 255))]
+  pub totp_code: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Validate)]
@@ -46,10 +55,25 @@ pub struct UpdateUser {
 
 #[derive(Debug, Deserialize, Serialize, Validate)]
 pub struct UpdatePassword {
-  #[validate(length(min = 7, max = 72))]
+  #[validate(length(min = 1, max = 1024))]
+  pub current_password: String,
+  #[validate(length(min = 7, max = 1024))]
+  pub password: String,
+}
+
+// deleting the account needs the password again, a session left open on
+// someone else's computer shouldn't be enough
+#[derive(Debug, Deserialize, Serialize, Validate)]
+pub struct DeleteUser {
+  #[validate(length(min = 1, max = 1024))]
   pub password: String,
 }
 
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ScheduledDeletion {
+  pub scheduled_deletion_at: i64,
+}
+
 #[derive(Debug, Deserialize, Serialize, Insertable, Queryable)]
 pub struct User {
   pub id: String,
@@ -58,6 +82,11 @@ pub struct User {
   pub email: String,
   pub password: String,
   pub invite: Option<String>,
+  pub email_verified: bool,
+  pub password_changed_at: i64,
+  pub is_admin: bool,
+  pub disabled_at: Option<i64>,
+  pub scheduled_deletion_at: Option<i64>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Queryable)]
@@ -67,25 +96,33 @@ pub struct UserDetails {
   pub name: String,
   pub email: String,
   pub invite: Option<String>,
+  pub email_verified: bool,
+  pub password_changed_at: i64,
+  pub is_admin: bool,
+  pub disabled_at: Option<i64>,
+  pub scheduled_deletion_at: Option<i64>,
 }
 
-fn user_details(user: User) -> UserDetails {
+pub fn user_details(user: User) -> UserDetails {
   UserDetails {
     id: user.id,
     created_at: user.created_at,
     name: user.name,
     email: user.email,
     invite: user.invite,
+    email_verified: user.email_verified,
+    password_changed_at: user.password_changed_at,
+    is_admin: user.is_admin,
+    disabled_at: user.disabled_at,
+    scheduled_deletion_at: user.scheduled_deletion_at,
   }
 }
 
-pub fn get_user_id(email: &str) -> Result<String, EphemerideError> {
-  let mut conn = establish_connection();
-
+pub fn get_user_id(conn: &mut PgConnection, email: &str) -> Result<String, EphemerideError> {
   let result = schema::users::table
     .filter(schema::users::email.eq(email))
     .select(schema::users::id)
-    .first(&mut conn);
+    .first(conn);
 
   match result {
     Ok(id) => Ok(id),
@@ -93,16 +130,14 @@ pub fn get_user_id(email: &str) -> Result<String, EphemerideError> {
   }
 }
 
-pub fn get_user(id: &str) -> Result<UserDetails, EphemerideError> {
-  let mut conn = establish_connection();
-
+pub fn get_user(conn: &mut PgConnection, id: &str) -> Result<UserDetails, EphemerideError> {
   // should only select some fields here not all
   // we remove password has with the user_details function
   // but this could be fixed by only selecting needed fields
   // #TODO: that ^
   let result = schema::users::table
     .filter(schema::users::id.eq(&id))
-    .first(&mut conn);
+    .first(conn);
 
   match result {
     // #TODO: see above todo, but this needs to be fixed
@@ -111,12 +146,10 @@ pub fn get_user(id: &str) -> Result<UserDetails, EphemerideError> {
   }
 }
 
-pub fn get_password_hash(id: &str) -> Result<String, EphemerideError> {
-  let mut conn: diesel::PgConnection = establish_connection();
-
+pub fn get_password_hash(conn: &mut PgConnection, id: &str) -> Result<String, EphemerideError> {
   let result = schema::users::table
     .filter(schema::users::id.eq(&id))
-    .first::<User>(&mut conn);
+    .first::<User>(conn);
 
   match result {
     Ok(user) => Ok(user.password),
@@ -124,118 +157,338 @@ pub fn get_password_hash(id: &str) -> Result<String, EphemerideError> {
   }
 }
 
-pub fn create_user(user: CreateUser) -> Result<UserDetails, EphemerideError> {
+pub fn verify_password(
+  conn: &mut PgConnection,
+  id: &str,
+  password: &str,
+) -> Result<(), EphemerideError> {
+  let password_hash = match get_password_hash(conn, id) {
+    Ok(hash) => hash,
+    Err(_) => return Err(EphemerideError::DatabaseError),
+  };
+
+  match util::verify_password(password, &password_hash)? {
+    true => Ok(()),
+    false => Err(EphemerideError::InvalidPassword),
+  }
+}
+
+// checks the password against a throwaway hash, so a login for an unknown
+// email takes as long as one with a wrong password
+pub fn verify_dummy_password(password: &str) {
+  static DUMMY_HASH: OnceLock<Option<String>> = OnceLock::new();
+
+  let dummy_hash = DUMMY_HASH.get_or_init(|| util::hash_password("dummy password").ok());
+
+  if let Some(dummy_hash) = dummy_hash {
+    let _ = util::verify_password(password, dummy_hash);
+  }
+}
+
+pub fn create_user(
+  conn: &mut PgConnection,
+  user: CreateUser,
+) -> Result<UserDetails, EphemerideError> {
   match user.validate() {
     Ok(_) => (),
     Err(_) => return Err(EphemerideError::BadRequest),
   }
 
-  if get_user_id(&user.email).is_ok() {
+  if get_user_id(conn, &user.email).is_ok() {
     return Err(EphemerideError::EmailAlreadyInUse);
   }
 
-  let mut conn = establish_connection();
-
-  dotenv().ok();
-  let cost = match env::var("BCRYPT_COST") {
-    Ok(val) => match val.parse::<u32>() {
-      Ok(parsed) => parsed,
-      Err(_) => bcrypt::DEFAULT_COST,
-    },
-    Err(_) => bcrypt::DEFAULT_COST,
-  };
+  let password_hash = util::hash_password(&user.password)?;
 
-  let password_hash = match bcrypt::hash(&user.password, cost) {
-    Ok(hash) => hash,
-    Err(_) => return Err(EphemerideError::InternalServerError),
-  };
+  let now = util::unix_time::unix_ms();
 
   let new_user = User {
     id: Uuid::new_v4().to_string(),
-    created_at: util::unix_time::unix_ms(),
+    created_at: now,
     name: user.name,
     email: user.email,
     password: password_hash,
     invite: user.invite,
+    email_verified: false,
+    password_changed_at: now,
+    is_admin: false,
+    disabled_at: None,
+    scheduled_deletion_at: None,
   };
 
-  let result = diesel::insert_into(schema::users::table)
-    .values(&new_user)
-    .execute(&mut conn);
+  // a user without their default categories and tags should never be visible
+  let result = conn.transaction::<_, EphemerideError, _>(|conn| {
+    diesel::insert_into(schema::users::table)
+      .values(&new_user)
+      .execute(conn)?;
+
+    create_default_data(conn, new_user.id.clone())
+  });
 
   if result.is_err() {
     return Err(EphemerideError::DatabaseError);
   }
 
-  let created_user_defaults = create_default_data(new_user.id.clone());
+  Ok(user_details(new_user))
+}
 
-  if created_user_defaults.is_err() {
-    return Err(EphemerideError::DatabaseError);
-  }
+pub fn delete_user(conn: &mut PgConnection, id: &str) -> Result<bool, EphemerideError> {
+  let result = conn.transaction::<_, EphemerideError, _>(|conn| {
+    match delete_all_user_sessions(conn, id) {
+      Ok(_) => (),
+      Err(_) => return Err(EphemerideError::DatabaseError),
+    };
+
+    match delete_user_totp(conn, id) {
+      Ok(_) => (),
+      Err(_) => return Err(EphemerideError::DatabaseError),
+    };
+
+    match delete_user_access_tokens(conn, id) {
+      Ok(_) => (),
+      Err(_) => return Err(EphemerideError::DatabaseError),
+    };
+
+    match delete_user_identities(conn, id) {
+      Ok(_) => (),
+      Err(_) => return Err(EphemerideError::DatabaseError),
+    };
+
+    match delete_user_password_resets(conn, id) {
+      Ok(_) => (),
+      Err(_) => return Err(EphemerideError::DatabaseError),
+    };
+
+    match delete_user_email_verifications(conn, id) {
+      Ok(_) => (),
+      Err(_) => return Err(EphemerideError::DatabaseError),
+    };
+
+    match log::delete_all_user_data(conn, id) {
+      Ok(_) => (),
+      Err(_) => return Err(EphemerideError::DatabaseError),
+    };
+
+    match audit::delete_user_audit_events(conn, id) {
+      Ok(_) => (),
+      Err(_) => return Err(EphemerideError::DatabaseError),
+    };
+
+    match delete_user_known_devices(conn, id) {
+      Ok(_) => (),
+      Err(_) => return Err(EphemerideError::DatabaseError),
+    };
+
+    let rows_affected =
+      diesel::delete(schema::users::table.filter(schema::users::id.eq(id))).execute(conn)?;
+
+    Ok(rows_affected)
+  });
 
-  Ok(user_details(new_user))
+  match result {
+    Ok(rows_affected) => Ok(rows_affected > 0),
+    Err(_) => Err(EphemerideError::DatabaseError),
+  }
 }
 
-pub fn delete_user(id: &str) -> Result<bool, EphemerideError> {
-  let mut conn = establish_connection();
+fn account_deletion_grace_ms() -> i64 {
+  dotenv().ok();
 
-  match delete_all_user_sessions(id) {
+  match env::var("ACCOUNT_DELETION_GRACE_MS") {
+    Ok(val) => val
+      .parse::<i64>()
+      .unwrap_or(DEFAULT_ACCOUNT_DELETION_GRACE_MS),
+    Err(_) => DEFAULT_ACCOUNT_DELETION_GRACE_MS,
+  }
+}
+
+// the account is signed out everywhere and kept for the grace period, so
+// the user can still change their mind by logging in again, after that it
+// is purged with delete_user
+pub fn schedule_user_deletion(
+  conn: &mut PgConnection,
+  id: &str,
+  deletion: DeleteUser,
+) -> Result<ScheduledDeletion, EphemerideError> {
+  match deletion.validate() {
     Ok(_) => (),
-    Err(_) => return Err(EphemerideError::DatabaseError),
-  };
+    Err(_) => return Err(EphemerideError::BadRequest),
+  }
 
-  match log::delete_all_user_data(id) {
+  match get_password_hash(conn, id) {
     Ok(_) => (),
-    Err(_) => return Err(EphemerideError::DatabaseError),
-  };
+    Err(_) => return Err(EphemerideError::UserNotFound),
+  }
+
+  verify_password(conn, id, &deletion.password)?;
 
-  let result =
-    diesel::delete(schema::users::table.filter(schema::users::id.eq(id))).execute(&mut conn);
+  let scheduled_deletion_at = util::unix_time::unix_ms() + account_deletion_grace_ms();
+
+  conn.transaction::<_, EphemerideError, _>(|conn| {
+    diesel::update(schema::users::table.filter(schema::users::id.eq(id)))
+      .set(schema::users::scheduled_deletion_at.eq(scheduled_deletion_at))
+      .execute(conn)?;
+
+    delete_all_user_sessions(conn, id)?;
+    delete_user_password_resets(conn, id)?;
+
+    Ok(ScheduledDeletion {
+      scheduled_deletion_at,
+    })
+  })
+}
+
+pub fn cancel_user_deletion(conn: &mut PgConnection, id: &str) -> Result<bool, EphemerideError> {
+  let updated = diesel::update(schema::users::table.filter(schema::users::id.eq(id)))
+    .set(schema::users::scheduled_deletion_at.eq(None::<i64>))
+    .execute(conn)?;
+
+  Ok(updated > 0)
+}
+
+pub fn is_user_pending_deletion(
+  conn: &mut PgConnection,
+  id: &str,
+) -> Result<bool, EphemerideError> {
+  let result = schema::users::table
+    .filter(schema::users::id.eq(id))
+    .select(schema::users::scheduled_deletion_at)
+    .first::<Option<i64>>(conn);
 
   match result {
-    Ok(rows_affected) => Ok(rows_affected > 0),
-    Err(_) => Err(EphemerideError::DatabaseError),
+    Ok(scheduled_deletion_at) => Ok(scheduled_deletion_at.is_some()),
+    Err(_) => Err(EphemerideError::UserNotFound),
   }
 }
 
-pub fn update_user(id: &str, user: UpdateUser) -> Result<bool, EphemerideError> {
+// hard deletes every account whose grace period is over
+pub fn purge_deleted_users(conn: &mut PgConnection) -> Result<usize, EphemerideError> {
+  let user_ids = schema::users::table
+    .filter(schema::users::scheduled_deletion_at.le(util::unix_time::unix_ms()))
+    .select(schema::users::id)
+    .load::<String>(conn)?;
+
+  let mut purged = 0;
+  for user_id in user_ids {
+    if delete_user(conn, &user_id)? {
+      purged += 1;
+    }
+  }
+
+  Ok(purged)
+}
+
+// a new email only replaces the current one once it has been verified, until
+// then the user keeps logging in with the old address. returns the
+// verification email for a new address, to be sent once this has committed
+pub fn update_user(
+  conn: &mut PgConnection,
+  id: &str,
+  user: UpdateUser,
+  metadata: &SessionMetadata,
+) -> Result<Option<Email>, EphemerideError> {
   match user.validate() {
     Ok(_) => (),
     Err(_) => return Err(EphemerideError::BadRequest),
   }
 
-  if let Ok(existing_user_id) = get_user_id(&user.email) {
+  if let Ok(existing_user_id) = get_user_id(conn, &user.email) {
     if existing_user_id != id {
       return Err(EphemerideError::EmailAlreadyInUse);
     }
   }
 
-  let mut conn = establish_connection();
+  let current = match get_user(conn, id) {
+    Ok(current) => current,
+    Err(_) => return Err(EphemerideError::UserNotFound),
+  };
 
-  let result = diesel::update(schema::users::table.filter(schema::users::id.eq(id)))
-    .set((
-      schema::users::name.eq(&user.name),
-      schema::users::email.eq(&user.email),
-    ))
-    .execute(&mut conn);
+  conn.transaction::<_, EphemerideError, _>(|conn| {
+    diesel::update(schema::users::table.filter(schema::users::id.eq(id)))
+      .set(schema::users::name.eq(&user.name))
+      .execute(conn)?;
+
+    if user.name != current.name {
+      audit::record_audit_event(
+        conn,
+        id,
+        audit::AuditEventKind::NameChanged,
+        Some(metadata),
+        None,
+      )?;
+    }
 
-  match result {
-    Ok(rows_affected) => Ok(rows_affected > 0),
-    Err(_) => Err(EphemerideError::UserNotFound),
-  }
+    if user.email == current.email {
+      return Ok(None);
+    }
+
+    let verification = request_email_verification(conn, id, &user.email)?;
+    audit::record_audit_event(
+      conn,
+      id,
+      audit::AuditEventKind::EmailChangeRequested,
+      Some(metadata),
+      Some(user.email.clone()),
+    )?;
+
+    Ok(Some(verification))
+  })
 }
 
-pub fn update_password(id: &str, password: UpdatePassword) -> Result<bool, EphemerideError> {
-  let mut conn = establish_connection();
+// changing the password needs the current one as well as a session, and
+// signs out every other session so a stolen one stops working
+pub fn update_password(
+  conn: &mut PgConnection,
+  id: &str,
+  current_session_id: &str,
+  password: UpdatePassword,
+  metadata: &SessionMetadata,
+) -> Result<bool, EphemerideError> {
+  match password.validate() {
+    Ok(_) => (),
+    Err(_) => return Err(EphemerideError::BadRequest),
+  }
 
-  let password_hash = match bcrypt::hash(&password.password, bcrypt::DEFAULT_COST) {
-    Ok(hash) => hash,
-    Err(_) => return Err(EphemerideError::InternalServerError),
-  };
+  match get_password_hash(conn, id) {
+    Ok(_) => (),
+    Err(_) => return Ok(false),
+  }
+
+  verify_password(conn, id, &password.current_password)?;
+
+  let password_hash = util::hash_password(&password.password)?;
+
+  conn.transaction::<_, EphemerideError, _>(|conn| {
+    let rows_affected = diesel::update(schema::users::table.filter(schema::users::id.eq(id)))
+      .set((
+        schema::users::password.eq(&password_hash),
+        schema::users::password_changed_at.eq(util::unix_time::unix_ms()),
+      ))
+      .execute(conn)?;
+
+    delete_other_user_sessions(conn, id, current_session_id)?;
+    delete_user_password_resets(conn, id)?;
+    audit::record_audit_event(
+      conn,
+      id,
+      audit::AuditEventKind::PasswordChanged,
+      Some(metadata),
+      None,
+    )?;
+
+    Ok(rows_affected > 0)
+  })
+}
 
+// only swaps the stored hash, used when rehashing an unchanged password
+pub fn update_password_hash(
+  conn: &mut PgConnection,
+  id: &str,
+  password_hash: &str,
+) -> Result<bool, EphemerideError> {
   let result = diesel::update(schema::users::table.filter(schema::users::id.eq(id)))
-    .set(schema::users::password.eq(&password_hash))
-    .execute(&mut conn);
+    .set(schema::users::password.eq(password_hash))
+    .execute(conn);
 
   match result {
     Ok(rows_affected) => Ok(rows_affected > 0),
@@ -243,10 +496,8 @@ pub fn update_password(id: &str, password: UpdatePassword) -> Result<bool, Ephem
   }
 }
 
-pub fn user_count() -> Result<i64, EphemerideError> {
-  let mut conn = establish_connection();
-
-  let result = schema::users::table.count().get_result::<i64>(&mut conn);
+pub fn user_count(conn: &mut PgConnection) -> Result<i64, EphemerideError> {
+  let result = schema::users::table.count().get_result::<i64>(conn);
 
   match result {
     Ok(count) => Ok(count),
@@ -254,14 +505,15 @@ pub fn user_count() -> Result<i64, EphemerideError> {
   }
 }
 
-pub fn active_user_count(since_timestamp: i64) -> Result<i64, EphemerideError> {
-  let mut conn = establish_connection();
-
+pub fn active_user_count(
+  conn: &mut PgConnection,
+  since_timestamp: i64,
+) -> Result<i64, EphemerideError> {
   let result = schema::users::table
     .inner_join(schema::sessions::table.on(schema::users::id.eq(schema::sessions::user_id)))
     .filter(schema::sessions::accessed_at.ge(since_timestamp))
     .select(diesel::dsl::count(schema::users::id).aggregate_distinct())
-    .first::<i64>(&mut conn);
+    .first::<i64>(conn);
 
   match result {
     Ok(count) => Ok(count),
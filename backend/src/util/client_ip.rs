@@ -0,0 +1,299 @@
+use ipnet::IpNet;
+use poem::http::HeaderMap;
+use std::net::{IpAddr, SocketAddr};
+
+use dotenvy::dotenv;
+use std::env;
+
+// the proxies allowed to say who the client is, without any the forwarding
+// headers are ignored since anyone could send them
+#[derive(Debug, Default, Clone)]
+pub struct TrustedProxies {
+  pub networks: Vec<IpNet>,
+}
+
+impl TrustedProxies {
+  // TRUSTED_PROXIES is a comma separated list of addresses and CIDR ranges,
+  // entries that can't be parsed are skipped
+  pub fn from_env() -> TrustedProxies {
+    dotenv().ok();
+
+    match env::var("TRUSTED_PROXIES") {
+      Ok(val) => TrustedProxies::parse(&val),
+      Err(_) => TrustedProxies::default(),
+    }
+  }
+
+  pub fn parse(value: &str) -> TrustedProxies {
+    let networks = value
+      .split(',')
+      .map(|entry| entry.trim())
+      .filter_map(|entry| match entry.parse::<IpNet>() {
+        Ok(network) => Some(network),
+        Err(_) => entry.parse::<IpAddr>().ok().map(IpNet::from),
+      })
+      .collect();
+
+    TrustedProxies { networks }
+  }
+
+  pub fn is_trusted(&self, ip: &IpAddr) -> bool {
+    self.networks.iter().any(|network| network.contains(ip))
+  }
+}
+
+// the header the trusted proxies set, only that one is read, a client can
+// send the other one and the proxy would pass it along untouched
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ProxyHeader {
+  Forwarded,
+  #[default]
+  XForwardedFor,
+}
+
+impl ProxyHeader {
+  // TRUSTED_PROXY_HEADER is `forwarded` or `x-forwarded-for`, anything else
+  // is X-Forwarded-For since that's what most proxies set
+  pub fn from_env() -> ProxyHeader {
+    dotenv().ok();
+
+    match env::var("TRUSTED_PROXY_HEADER").as_deref() {
+      Ok("forwarded") => ProxyHeader::Forwarded,
+      _ => ProxyHeader::XForwardedFor,
+    }
+  }
+
+  pub fn chain(&self, headers: &HeaderMap) -> Vec<Option<IpAddr>> {
+    let name = match self {
+      ProxyHeader::Forwarded => "forwarded",
+      ProxyHeader::XForwardedFor => "x-forwarded-for",
+    };
+
+    let values = headers
+      .get_all(name)
+      .iter()
+      .filter_map(|value| value.to_str().ok())
+      .collect::<Vec<&str>>();
+
+    match self {
+      ProxyHeader::Forwarded => forwarded_for(&values),
+      ProxyHeader::XForwardedFor => x_forwarded_for(&values),
+    }
+  }
+}
+
+// forwarded addresses come bare, with a port, or as a bracketed ipv6
+// address with an optional port
+fn parse_forwarded_ip(value: &str) -> Option<IpAddr> {
+  let value = value.trim().trim_matches('"');
+
+  if let Ok(ip) = value.parse::<IpAddr>() {
+    return Some(ip);
+  }
+  if let Ok(addr) = value.parse::<SocketAddr>() {
+    return Some(addr.ip());
+  }
+
+  value
+    .strip_prefix('[')
+    .and_then(|value| value.split(']').next())
+    .and_then(|ip| ip.parse::<IpAddr>().ok())
+}
+
+// the `for` parameter of every element of RFC 7239 Forwarded headers, in
+// the order the proxies added them
+pub fn forwarded_for(headers: &[&str]) -> Vec<Option<IpAddr>> {
+  headers
+    .iter()
+    .flat_map(|header| header.split(','))
+    .filter_map(|element| {
+      element.split(';').find_map(|pair| {
+        let (name, value) = pair.split_once('=')?;
+        match name.trim().eq_ignore_ascii_case("for") {
+          true => Some(parse_forwarded_ip(value)),
+          false => None,
+        }
+      })
+    })
+    .collect()
+}
+
+pub fn x_forwarded_for(headers: &[&str]) -> Vec<Option<IpAddr>> {
+  headers
+    .iter()
+    .flat_map(|header| header.split(','))
+    .map(parse_forwarded_ip)
+    .collect()
+}
+
+// walks the chain back from the proxy that connected to us, every hop that
+// is a trusted proxy is skipped and the first one that isn't is the client,
+// a hop that can't be read stops the walk at the last proxy that was trusted
+pub fn resolve_client_ip(
+  peer: IpAddr,
+  chain: &[Option<IpAddr>],
+  trusted_proxies: &TrustedProxies,
+) -> IpAddr {
+  let mut client = peer;
+
+  for hop in chain.iter().rev() {
+    if !trusted_proxies.is_trusted(&client) {
+      return client;
+    }
+
+    match hop {
+      Some(ip) => client = *ip,
+      None => return client,
+    }
+  }
+
+  client
+}
+
+pub fn client_ip_from_headers(
+  peer: IpAddr,
+  headers: &HeaderMap,
+  proxy_header: ProxyHeader,
+  trusted_proxies: &TrustedProxies,
+) -> IpAddr {
+  resolve_client_ip(peer, &proxy_header.chain(headers), trusted_proxies)
+}
+
+#[cfg(test)]
+mod ci_unit {
+  use super::*;
+
+  fn ip(value: &str) -> IpAddr {
+    value.parse().unwrap()
+  }
+
+  #[test]
+  fn test_parse_trusted_proxies() {
+    let trusted_proxies = TrustedProxies::parse("10.0.0.0/8, 127.0.0.1,not an ip, fd00::/8");
+
+    assert_eq!(trusted_proxies.networks.len(), 3);
+    assert!(trusted_proxies.is_trusted(&ip("10.1.2.3")));
+    assert!(trusted_proxies.is_trusted(&ip("127.0.0.1")));
+    assert!(trusted_proxies.is_trusted(&ip("fd12::1")));
+    assert!(!trusted_proxies.is_trusted(&ip("127.0.0.2")));
+  }
+
+  #[test]
+  fn test_forwarded_for() {
+    let chain = forwarded_for(&[
+      "for=192.0.2.60;proto=http;by=203.0.113.43, For=\"[2001:db8:cafe::17]:4711\"",
+      "for=unknown, proto=https",
+    ]);
+
+    assert_eq!(
+      chain,
+      vec![Some(ip("192.0.2.60")), Some(ip("2001:db8:cafe::17")), None]
+    );
+  }
+
+  #[test]
+  fn test_x_forwarded_for() {
+    let chain = x_forwarded_for(&["203.0.113.7, 10.0.0.2:8080", "garbage"]);
+
+    assert_eq!(
+      chain,
+      vec![Some(ip("203.0.113.7")), Some(ip("10.0.0.2")), None]
+    );
+  }
+
+  #[test]
+  fn test_resolve_client_ip() {
+    let trusted_proxies = TrustedProxies::parse("10.0.0.0/8");
+
+    // an untrusted peer can't choose its address
+    assert_eq!(
+      resolve_client_ip(
+        ip("198.51.100.1"),
+        &[Some(ip("203.0.113.7"))],
+        &trusted_proxies
+      ),
+      ip("198.51.100.1")
+    );
+
+    // trusted proxies are skipped, anything the client itself added is not
+    assert_eq!(
+      resolve_client_ip(
+        ip("10.0.0.1"),
+        &[
+          Some(ip("1.1.1.1")),
+          Some(ip("203.0.113.7")),
+          Some(ip("10.0.0.2"))
+        ],
+        &trusted_proxies
+      ),
+      ip("203.0.113.7")
+    );
+
+    // a chain of nothing but proxies ends at the first one
+    assert_eq!(
+      resolve_client_ip(ip("10.0.0.1"), &[Some(ip("10.0.0.2"))], &trusted_proxies),
+      ip("10.0.0.2")
+    );
+
+    assert_eq!(
+      resolve_client_ip(
+        ip("10.0.0.1"),
+        &[Some(ip("203.0.113.7")), None],
+        &trusted_proxies
+      ),
+      ip("10.0.0.1")
+    );
+
+    assert_eq!(
+      resolve_client_ip(ip("10.0.0.1"), &[], &TrustedProxies::default()),
+      ip("10.0.0.1")
+    );
+  }
+
+  #[test]
+  fn test_only_the_configured_header_is_read() {
+    let trusted_proxies = TrustedProxies::parse("10.0.0.0/8");
+
+    // the client made up a Forwarded header, the proxy only appended the
+    // address it saw to X-Forwarded-For
+    let mut headers = HeaderMap::new();
+    headers.insert("forwarded", "for=1.1.1.1".parse().unwrap());
+    headers.insert("x-forwarded-for", "203.0.113.7".parse().unwrap());
+
+    assert_eq!(
+      client_ip_from_headers(
+        ip("10.0.0.1"),
+        &headers,
+        ProxyHeader::XForwardedFor,
+        &trusted_proxies
+      ),
+      ip("203.0.113.7")
+    );
+
+    // and the other way around, without falling back to X-Forwarded-For
+    let mut headers = HeaderMap::new();
+    headers.insert("x-forwarded-for", "1.1.1.1".parse().unwrap());
+
+    assert_eq!(
+      client_ip_from_headers(
+        ip("10.0.0.1"),
+        &headers,
+        ProxyHeader::Forwarded,
+        &trusted_proxies
+      ),
+      ip("10.0.0.1")
+    );
+
+    headers.insert("forwarded", "for=203.0.113.7".parse().unwrap());
+
+    assert_eq!(
+      client_ip_from_headers(
+        ip("10.0.0.1"),
+        &headers,
+        ProxyHeader::Forwarded,
+        &trusted_proxies
+      ),
+      ip("203.0.113.7")
+    );
+  }
+}
@@ -0,0 +1,99 @@
+// the value of a cookie from a Cookie header, which lists them as
+// `name=value` pairs separated by semicolons
+pub fn cookie_value<'a>(header: &'a str, name: &str) -> Option<&'a str> {
+  header.split(';').find_map(|pair| {
+    let (cookie_name, value) = pair.split_once('=')?;
+    match cookie_name.trim() == name {
+      true => Some(value.trim().trim_matches('"')),
+      false => None,
+    }
+  })
+}
+
+pub struct SetCookie<'a> {
+  pub name: &'a str,
+  pub value: &'a str,
+  pub max_age_seconds: i64,
+  // cookies the frontend has to read, like the csrf token, can't be http only
+  pub http_only: bool,
+  pub same_site: &'a str,
+}
+
+// cookies are always Secure and for the whole site, browsers treat
+// localhost as secure so this doesn't get in the way of development
+pub fn set_cookie(cookie: SetCookie) -> String {
+  let mut value = format!(
+    "{}={}; Path=/; Max-Age={}; Secure; SameSite={}",
+    cookie.name,
+    cookie.value,
+    cookie.max_age_seconds.max(0),
+    cookie.same_site
+  );
+
+  if cookie.http_only {
+    value.push_str("; HttpOnly");
+  }
+
+  value
+}
+
+// compares every byte no matter where the first difference is, so timing
+// the comparison doesn't tell how much of a guess was right
+pub fn constant_time_eq(a: &str, b: &str) -> bool {
+  if a.len() != b.len() {
+    return false;
+  }
+
+  a.bytes()
+    .zip(b.bytes())
+    .fold(0u8, |difference, (a, b)| difference | (a ^ b))
+    == 0
+}
+
+#[cfg(test)]
+mod ci_unit {
+  use super::*;
+
+  #[test]
+  fn test_cookie_value() {
+    let header = "theme=dark; ephemeride_session=abc123 ;ephemeride_csrf=\"def\"";
+
+    assert_eq!(cookie_value(header, "ephemeride_session"), Some("abc123"));
+    assert_eq!(cookie_value(header, "ephemeride_csrf"), Some("def"));
+    assert_eq!(cookie_value(header, "session"), None);
+    assert_eq!(cookie_value("", "ephemeride_session"), None);
+  }
+
+  #[test]
+  fn test_set_cookie() {
+    assert_eq!(
+      set_cookie(SetCookie {
+        name: "ephemeride_session",
+        value: "abc123",
+        max_age_seconds: 60,
+        http_only: true,
+        same_site: "Strict",
+      }),
+      "ephemeride_session=abc123; Path=/; Max-Age=60; Secure; SameSite=Strict; HttpOnly"
+    );
+
+    assert_eq!(
+      set_cookie(SetCookie {
+        name: "ephemeride_csrf",
+        value: "",
+        max_age_seconds: -5,
+        http_only: false,
+        same_site: "Lax",
+      }),
+      "ephemeride_csrf=; Path=/; Max-Age=0; Secure; SameSite=Lax"
+    );
+  }
+
+  #[test]
+  fn test_constant_time_eq() {
+    assert!(constant_time_eq("abc123", "abc123"));
+    assert!(!constant_time_eq("abc123", "abc124"));
+    assert!(!constant_time_eq("abc123", "abc12"));
+    assert!(constant_time_eq("", ""));
+  }
+}
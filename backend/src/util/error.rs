@@ -1,5 +1,8 @@
 use crate::util::response::response;
-use poem::{http::StatusCode, Response};
+use poem::{
+  http::{header, HeaderValue, StatusCode},
+  Response,
+};
 use serde::Serialize;
 
 #[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
@@ -16,8 +19,37 @@ pub enum EphemerideError {
   EmailAlreadyInUse,
   InvalidPassword,
   InviteUsed,
+  InviteExpired,
+  InviteQuotaExceeded,
+  InviteCodeTaken,
   BadRequest,
   EntryAlreadyExistsForDate,
+  PoolExhausted,
+  SessionExpired,
+  TotpRequired,
+  InvalidTotpCode,
+  TotpAlreadyEnabled,
+  TotpNotFound,
+  InvalidResetToken,
+  InvalidVerificationToken,
+  EmailNotVerified,
+  InvalidCredentials,
+  TooManyRequests,
+  OidcNotConfigured,
+  InvalidOidcState,
+  OidcProviderError,
+  IdentityAlreadyLinked,
+  IdentityNotFound,
+  InsufficientScope,
+  AccessTokenNotFound,
+  AdminRequired,
+  AccountDisabled,
+  AccountPendingDeletion,
+  InvalidCsrfToken,
+  AccessTokensNotConfigured,
+  AccessTokenExpired,
+  InvalidRefreshToken,
+  RefreshTokenReused,
 }
 
 #[derive(Serialize)]
@@ -38,8 +70,37 @@ fn error_message(error: EphemerideError) -> String {
     EphemerideError::EmailAlreadyInUse => "Email already in use",
     EphemerideError::InvalidPassword => "Invalid password",
     EphemerideError::InviteUsed => "Invite already used",
+    EphemerideError::InviteExpired => "Invite expired",
+    EphemerideError::InviteQuotaExceeded => "No invites left",
+    EphemerideError::InviteCodeTaken => "Invite code already taken",
     EphemerideError::BadRequest => "Bad request",
     EphemerideError::EntryAlreadyExistsForDate => "An entry already exists for the given date",
+    EphemerideError::PoolExhausted => "Server is busy, try again later",
+    EphemerideError::SessionExpired => "Session expired",
+    EphemerideError::TotpRequired => "Two-factor code required",
+    EphemerideError::InvalidTotpCode => "Invalid two-factor code",
+    EphemerideError::TotpAlreadyEnabled => "Two-factor authentication is already enabled",
+    EphemerideError::TotpNotFound => "Two-factor authentication is not set up",
+    EphemerideError::InvalidResetToken => "Invalid or expired reset token",
+    EphemerideError::InvalidVerificationToken => "Invalid or expired verification token",
+    EphemerideError::EmailNotVerified => "Email address not verified",
+    EphemerideError::InvalidCredentials => "Invalid email or password",
+    EphemerideError::TooManyRequests => "Too many attempts, try again later",
+    EphemerideError::OidcNotConfigured => "Single sign-on is not configured",
+    EphemerideError::InvalidOidcState => "Invalid or expired single sign-on attempt",
+    EphemerideError::OidcProviderError => "Identity provider error",
+    EphemerideError::IdentityAlreadyLinked => "Identity is already linked to another account",
+    EphemerideError::IdentityNotFound => "Identity not found",
+    EphemerideError::InsufficientScope => "Access token doesn't allow this request",
+    EphemerideError::AccessTokenNotFound => "Access token not found",
+    EphemerideError::AdminRequired => "Admin access required",
+    EphemerideError::AccountDisabled => "Account disabled",
+    EphemerideError::AccountPendingDeletion => "Account is scheduled for deletion",
+    EphemerideError::InvalidCsrfToken => "Missing or invalid CSRF token",
+    EphemerideError::AccessTokensNotConfigured => "Access tokens are not enabled",
+    EphemerideError::AccessTokenExpired => "Access token expired",
+    EphemerideError::InvalidRefreshToken => "Invalid refresh token",
+    EphemerideError::RefreshTokenReused => "Refresh token was already used, log in again",
     _ => "An error occurred",
   }
   .to_string()
@@ -57,12 +118,48 @@ fn status_code(error: EphemerideError) -> StatusCode {
     EphemerideError::EmailAlreadyInUse => StatusCode::CONFLICT,
     EphemerideError::InvalidPassword => StatusCode::UNAUTHORIZED,
     EphemerideError::InviteUsed => StatusCode::CONFLICT,
+    EphemerideError::InviteExpired => StatusCode::GONE,
+    EphemerideError::InviteQuotaExceeded => StatusCode::FORBIDDEN,
+    EphemerideError::InviteCodeTaken => StatusCode::CONFLICT,
     EphemerideError::BadRequest => StatusCode::BAD_REQUEST,
     EphemerideError::EntryAlreadyExistsForDate => StatusCode::CONFLICT,
+    EphemerideError::PoolExhausted => StatusCode::SERVICE_UNAVAILABLE,
+    EphemerideError::SessionExpired => StatusCode::UNAUTHORIZED,
+    EphemerideError::TotpRequired => StatusCode::UNAUTHORIZED,
+    EphemerideError::InvalidTotpCode => StatusCode::UNAUTHORIZED,
+    EphemerideError::TotpAlreadyEnabled => StatusCode::CONFLICT,
+    EphemerideError::TotpNotFound => StatusCode::NOT_FOUND,
+    EphemerideError::InvalidResetToken => StatusCode::BAD_REQUEST,
+    EphemerideError::InvalidVerificationToken => StatusCode::BAD_REQUEST,
+    EphemerideError::EmailNotVerified => StatusCode::FORBIDDEN,
+    EphemerideError::InvalidCredentials => StatusCode::UNAUTHORIZED,
+    EphemerideError::TooManyRequests => StatusCode::TOO_MANY_REQUESTS,
+    EphemerideError::OidcNotConfigured => StatusCode::NOT_FOUND,
+    EphemerideError::InvalidOidcState => StatusCode::BAD_REQUEST,
+    EphemerideError::OidcProviderError => StatusCode::BAD_GATEWAY,
+    EphemerideError::IdentityAlreadyLinked => StatusCode::CONFLICT,
+    EphemerideError::IdentityNotFound => StatusCode::NOT_FOUND,
+    EphemerideError::InsufficientScope => StatusCode::FORBIDDEN,
+    EphemerideError::AccessTokenNotFound => StatusCode::NOT_FOUND,
+    EphemerideError::AdminRequired => StatusCode::FORBIDDEN,
+    EphemerideError::AccountDisabled => StatusCode::FORBIDDEN,
+    EphemerideError::AccountPendingDeletion => StatusCode::FORBIDDEN,
+    EphemerideError::InvalidCsrfToken => StatusCode::FORBIDDEN,
+    EphemerideError::AccessTokensNotConfigured => StatusCode::NOT_FOUND,
+    EphemerideError::AccessTokenExpired => StatusCode::UNAUTHORIZED,
+    EphemerideError::InvalidRefreshToken => StatusCode::UNAUTHORIZED,
+    EphemerideError::RefreshTokenReused => StatusCode::UNAUTHORIZED,
     _ => StatusCode::INTERNAL_SERVER_ERROR,
   }
 }
 
+// lets `?` and `Connection::transaction` surface diesel failures directly
+impl From<diesel::result::Error> for EphemerideError {
+  fn from(_: diesel::result::Error) -> Self {
+    EphemerideError::DatabaseError
+  }
+}
+
 fn error_body(error: EphemerideError) -> ErrorBody {
   ErrorBody {
     code: error,
@@ -74,6 +171,18 @@ pub fn error_response(error: EphemerideError) -> Response {
   response(status_code(error), &error_body(error))
 }
 
+// a locked out client is told how long to wait, rounded up to whole seconds
+pub fn too_many_requests_response(retry_after_ms: i64) -> Response {
+  let retry_after = (retry_after_ms.max(0) + 999) / 1000;
+  let mut response = error_response(EphemerideError::TooManyRequests);
+
+  if let Ok(value) = HeaderValue::from_str(&retry_after.to_string()) {
+    response.headers_mut().insert(header::RETRY_AFTER, value);
+  }
+
+  response
+}
+
 #[cfg(test)]
 mod ci_unit {
   use super::*;
@@ -84,4 +193,11 @@ mod ci_unit {
     let response = error_response(error);
     assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
   }
+
+  #[test]
+  fn test_too_many_requests_response() {
+    let response = too_many_requests_response(1500);
+    assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(response.headers().get(header::RETRY_AFTER).unwrap(), "2");
+  }
 }
@@ -1,8 +1,52 @@
-use uuid::Uuid;
+use rand::{rngs::OsRng, Rng};
 
-// #TODO: generate more human readable but still unique invite codes
+// crockford's base32, without I, L, O and U so codes can't be misread
+const INVITE_CODE_ALPHABET: &[u8] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+const INVITE_CODE_GROUPS: usize = 3;
+const INVITE_CODE_GROUP_LENGTH: usize = 4;
+
+// 60 bits of randomness in groups of four, like 7KQ2-M9XD-4TRE, easy to read
+// out or type in and still far too many to guess
 pub fn generate_invite_code() -> String {
-  Uuid::new_v4().to_string()
+  (0..INVITE_CODE_GROUPS)
+    .map(|_| {
+      (0..INVITE_CODE_GROUP_LENGTH)
+        .map(|_| INVITE_CODE_ALPHABET[OsRng.gen_range(0..INVITE_CODE_ALPHABET.len())] as char)
+        .collect::<String>()
+    })
+    .collect::<Vec<String>>()
+    .join("-")
+}
+
+// generated codes are typed in by hand, so case, spaces and dashes are
+// ignored and the letters that look like digits are read as those digits,
+// anything that isn't shaped like a generated code is left as it is
+pub fn normalize_invite_code(code: &str) -> String {
+  let code = code.trim();
+
+  let symbols = code
+    .chars()
+    .filter(|c| !matches!(c, '-' | ' '))
+    .map(|c| match c.to_ascii_uppercase() {
+      'O' => '0',
+      'I' | 'L' => '1',
+      c => c,
+    })
+    .collect::<Vec<char>>();
+
+  let is_generated = symbols.len() == INVITE_CODE_GROUPS * INVITE_CODE_GROUP_LENGTH
+    && symbols
+      .iter()
+      .all(|c| c.is_ascii() && INVITE_CODE_ALPHABET.contains(&(*c as u8)));
+
+  match is_generated {
+    true => symbols
+      .chunks(INVITE_CODE_GROUP_LENGTH)
+      .map(|group| group.iter().collect::<String>())
+      .collect::<Vec<String>>()
+      .join("-"),
+    false => code.to_string(),
+  }
 }
 
 #[cfg(test)]
@@ -15,4 +59,24 @@ mod ci_unit {
     let code2 = generate_invite_code();
     assert_ne!(code1, code2);
   }
+
+  #[test]
+  fn test_generate_invite_code_format() {
+    let code = generate_invite_code();
+    assert_eq!(code.len(), 14);
+    assert_eq!(code.split('-').count(), 3);
+    assert_eq!(normalize_invite_code(&code), code);
+  }
+
+  #[test]
+  fn test_normalize_invite_code() {
+    assert_eq!(normalize_invite_code(" 7kq2 m9xd 4tre "), "7KQ2-M9XD-4TRE");
+    assert_eq!(normalize_invite_code("oil0-0000-0000"), "0110-0000-0000");
+    assert_eq!(normalize_invite_code("my-custom-code"), "my-custom-code");
+    // codes from before were uuids, which are left alone
+    assert_eq!(
+      normalize_invite_code("67e55044-10b1-426f-9247-bb680e5fe0c8"),
+      "67e55044-10b1-426f-9247-bb680e5fe0c8"
+    );
+  }
 }
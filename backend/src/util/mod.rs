@@ -1,10 +1,22 @@
+pub mod client_ip;
+pub use client_ip::*;
 pub mod color;
 pub use color::*;
+pub mod cookie;
+pub use cookie::*;
 pub mod error;
 pub use error::*;
 pub mod invite_code;
 pub use invite_code::*;
+pub mod password;
+pub use password::*;
+pub mod recovery_code;
+pub use recovery_code::*;
 pub mod response;
 pub use response::*;
+pub mod token;
+pub use token::*;
 pub mod unix_time;
 pub use unix_time::*;
+pub mod user_agent;
+pub use user_agent::*;
@@ -0,0 +1,166 @@
+use crate::util::error::EphemerideError;
+use argon2::{
+  password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+  Algorithm, Argon2, Params, Version,
+};
+
+use dotenvy::dotenv;
+use std::env;
+
+// owasp's minimum recommendation for argon2id
+const DEFAULT_ARGON2_MEMORY_KIB: u32 = 19 * 1024;
+const DEFAULT_ARGON2_ITERATIONS: u32 = 2;
+const DEFAULT_ARGON2_PARALLELISM: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PasswordConfig {
+  pub memory_kib: u32,
+  pub iterations: u32,
+  pub parallelism: u32,
+}
+
+impl PasswordConfig {
+  pub fn from_env() -> PasswordConfig {
+    dotenv().ok();
+
+    let memory_kib = match env::var("ARGON2_MEMORY_KIB") {
+      Ok(val) => val.parse::<u32>().unwrap_or(DEFAULT_ARGON2_MEMORY_KIB),
+      Err(_) => DEFAULT_ARGON2_MEMORY_KIB,
+    };
+    let iterations = match env::var("ARGON2_ITERATIONS") {
+      Ok(val) => val.parse::<u32>().unwrap_or(DEFAULT_ARGON2_ITERATIONS),
+      Err(_) => DEFAULT_ARGON2_ITERATIONS,
+    };
+    let parallelism = match env::var("ARGON2_PARALLELISM") {
+      Ok(val) => val.parse::<u32>().unwrap_or(DEFAULT_ARGON2_PARALLELISM),
+      Err(_) => DEFAULT_ARGON2_PARALLELISM,
+    };
+
+    PasswordConfig {
+      memory_kib,
+      iterations,
+      parallelism,
+    }
+  }
+
+  fn argon2(&self) -> Result<Argon2<'static>, EphemerideError> {
+    match Params::new(self.memory_kib, self.iterations, self.parallelism, None) {
+      Ok(params) => Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params)),
+      Err(_) => Err(EphemerideError::InternalServerError),
+    }
+  }
+
+  // stored as a phc string, which carries its own salt and parameters
+  pub fn hash(&self, password: &str) -> Result<String, EphemerideError> {
+    let salt = SaltString::generate(&mut OsRng);
+
+    match self.argon2()?.hash_password(password.as_bytes(), &salt) {
+      Ok(hash) => Ok(hash.to_string()),
+      Err(_) => Err(EphemerideError::InternalServerError),
+    }
+  }
+
+  // anything that isn't argon2id with the current parameters, including
+  // every legacy bcrypt hash, should be replaced next time it's verified
+  pub fn needs_rehash(&self, hash: &str) -> bool {
+    let parsed = match PasswordHash::new(hash) {
+      Ok(parsed) => parsed,
+      Err(_) => return true,
+    };
+
+    if parsed.algorithm != Algorithm::Argon2id.ident()
+      || parsed.version != Some(Version::V0x13.into())
+    {
+      return true;
+    }
+
+    match Params::try_from(&parsed) {
+      Ok(params) => {
+        params.m_cost() != self.memory_kib
+          || params.t_cost() != self.iterations
+          || params.p_cost() != self.parallelism
+      }
+      Err(_) => true,
+    }
+  }
+}
+
+pub fn hash_password(password: &str) -> Result<String, EphemerideError> {
+  PasswordConfig::from_env().hash(password)
+}
+
+// verifies against either an argon2 phc string or a bcrypt hash from before
+// the switch to argon2
+pub fn verify_password(password: &str, hash: &str) -> Result<bool, EphemerideError> {
+  if hash.starts_with("$2") {
+    return match bcrypt::verify(password, hash) {
+      Ok(valid) => Ok(valid),
+      Err(_) => Err(EphemerideError::InternalServerError),
+    };
+  }
+
+  let parsed = match PasswordHash::new(hash) {
+    Ok(parsed) => parsed,
+    Err(_) => return Err(EphemerideError::InternalServerError),
+  };
+
+  // the parameters come from the hash itself, not the current config
+  Ok(
+    Argon2::default()
+      .verify_password(password.as_bytes(), &parsed)
+      .is_ok(),
+  )
+}
+
+pub fn needs_rehash(hash: &str) -> bool {
+  PasswordConfig::from_env().needs_rehash(hash)
+}
+
+#[cfg(test)]
+mod ci_unit {
+  use super::*;
+
+  fn config() -> PasswordConfig {
+    PasswordConfig {
+      memory_kib: 1024,
+      iterations: 1,
+      parallelism: 1,
+    }
+  }
+
+  #[test]
+  fn test_hash_and_verify_argon2id() {
+    let hash = config().hash("password").unwrap();
+    assert!(hash.starts_with("$argon2id$v=19$m=1024,t=1,p=1$"));
+    assert!(verify_password("password", &hash).unwrap());
+    assert!(!verify_password("wrong password", &hash).unwrap());
+  }
+
+  #[test]
+  fn test_verify_legacy_bcrypt() {
+    let hash = bcrypt::hash("password", 4).unwrap();
+    assert!(verify_password("password", &hash).unwrap());
+    assert!(!verify_password("wrong password", &hash).unwrap());
+    assert!(config().needs_rehash(&hash));
+  }
+
+  #[test]
+  fn test_needs_rehash_when_parameters_change() {
+    let hash = config().hash("password").unwrap();
+    assert!(!config().needs_rehash(&hash));
+
+    let stronger = PasswordConfig {
+      memory_kib: 2048,
+      ..config()
+    };
+    assert!(stronger.needs_rehash(&hash));
+  }
+
+  #[test]
+  fn test_long_passwords_are_not_truncated() {
+    let long = "a".repeat(100);
+    let hash = config().hash(&long).unwrap();
+    assert!(!verify_password(&"a".repeat(99), &hash).unwrap());
+    assert!(verify_password(&long, &hash).unwrap());
+  }
+}
@@ -0,0 +1,51 @@
+use rand::{rngs::OsRng, RngCore};
+use sha2::{Digest, Sha256};
+
+pub const RECOVERY_CODE_COUNT: usize = 10;
+
+// 80 bits of randomness split into groups of five so it can be written down
+pub fn generate_recovery_code() -> String {
+  let mut bytes = [0u8; 10];
+  OsRng.fill_bytes(&mut bytes);
+
+  hex::encode(bytes)
+    .as_bytes()
+    .chunks(5)
+    .map(|chunk| String::from_utf8_lossy(chunk).to_string())
+    .collect::<Vec<String>>()
+    .join("-")
+}
+
+// codes are typed in by hand, so dashes, spaces and case are ignored
+pub fn hash_recovery_code(code: &str) -> String {
+  let normalized = code
+    .chars()
+    .filter(|c| c.is_ascii_alphanumeric())
+    .collect::<String>()
+    .to_lowercase();
+
+  hex::encode(Sha256::digest(normalized.as_bytes()))
+}
+
+#[cfg(test)]
+mod ci_unit {
+  use super::*;
+
+  #[test]
+  fn test_generate_recovery_code() {
+    let code1 = generate_recovery_code();
+    let code2 = generate_recovery_code();
+    assert_ne!(code1, code2);
+    assert_eq!(code1.len(), 23);
+    assert_eq!(code1.split('-').count(), 4);
+  }
+
+  #[test]
+  fn test_hash_recovery_code_normalizes_input() {
+    let code = generate_recovery_code();
+    let hash = hash_recovery_code(&code);
+    assert_eq!(hash_recovery_code(&code.to_uppercase()), hash);
+    assert_eq!(hash_recovery_code(&code.replace('-', " ")), hash);
+    assert_ne!(hash, code);
+  }
+}
@@ -0,0 +1,40 @@
+use rand::{rngs::OsRng, RngCore};
+use sha2::{Digest, Sha256};
+
+// the raw token is only ever handed to the client, the database stores
+// its hash so reading a table of tokens isn't enough to impersonate anyone
+pub fn generate_token() -> String {
+  let mut bytes = [0u8; 32];
+  OsRng.fill_bytes(&mut bytes);
+  hex::encode(bytes)
+}
+
+// tokens are 256 bits of randomness, so a plain digest is sufficient and
+// lets us look them up by hash directly
+pub fn hash_token(token: &str) -> String {
+  hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+#[cfg(test)]
+mod ci_unit {
+  use super::*;
+
+  #[test]
+  fn test_generate_token_uniqueness() {
+    let token1 = generate_token();
+    let token2 = generate_token();
+    assert_ne!(token1, token2);
+    assert_eq!(token1.len(), 64);
+  }
+
+  #[test]
+  fn test_hash_token() {
+    let token = generate_token();
+    assert_eq!(hash_token(&token), hash_token(&token));
+    assert_ne!(hash_token(&token), token);
+    assert_eq!(
+      hash_token("token"),
+      "3c469e9d6c5875d37a43f353d4f88e61fcf812c66eee3457465a40b0da4153e0"
+    );
+  }
+}
@@ -0,0 +1,140 @@
+use sha2::{Digest, Sha256};
+use std::sync::OnceLock;
+use woothee::parser::Parser;
+
+// longer user agents are cut off, nothing useful comes after this much
+pub const MAX_USER_AGENT_LENGTH: usize = 1023;
+
+const UNKNOWN: &str = "unknown";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceInfo {
+  pub browser: String,
+  pub os: String,
+  // desktop, mobile, tablet, bot or unknown
+  pub device_type: String,
+}
+
+pub fn truncate_user_agent(user_agent: &str) -> String {
+  user_agent.chars().take(MAX_USER_AGENT_LENGTH).collect()
+}
+
+fn known(value: &str) -> String {
+  match value {
+    "" | woothee::woothee::VALUE_UNKNOWN => UNKNOWN.to_string(),
+    value => value.to_string(),
+  }
+}
+
+pub fn parse_user_agent(user_agent: &str) -> DeviceInfo {
+  static PARSER: OnceLock<Parser> = OnceLock::new();
+
+  let parsed = match PARSER.get_or_init(Parser::new).parse(user_agent) {
+    Some(parsed) => parsed,
+    None => {
+      return DeviceInfo {
+        browser: UNKNOWN.to_string(),
+        os: UNKNOWN.to_string(),
+        device_type: UNKNOWN.to_string(),
+      }
+    }
+  };
+
+  let device_type = match (parsed.category, parsed.os) {
+    ("smartphone", "iPad") => "tablet",
+    ("smartphone", _) | ("mobilephone", _) => "mobile",
+    ("pc", _) => "desktop",
+    ("crawler", _) => "bot",
+    _ => UNKNOWN,
+  };
+
+  DeviceInfo {
+    browser: known(parsed.name),
+    os: known(parsed.os),
+    device_type: device_type.to_string(),
+  }
+}
+
+// versions are left out so a browser updating itself isn't a new device,
+// it's coarse on purpose, it only has to tell a user's own devices apart
+pub fn device_fingerprint(device: &DeviceInfo) -> String {
+  hex::encode(Sha256::digest(
+    format!("{}\n{}\n{}", device.browser, device.os, device.device_type).as_bytes(),
+  ))
+}
+
+#[cfg(test)]
+mod ci_unit {
+  use super::*;
+
+  #[test]
+  fn test_parse_user_agent() {
+    assert_eq!(
+      parse_user_agent(
+        "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36"
+      ),
+      DeviceInfo {
+        browser: "Chrome".to_string(),
+        os: "Windows 10".to_string(),
+        device_type: "desktop".to_string(),
+      }
+    );
+
+    let iphone = parse_user_agent(
+      "Mozilla/5.0 (iPhone; CPU iPhone OS 17_1 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.1 Mobile/15E148 Safari/604.1",
+    );
+    assert_eq!(iphone.browser, "Safari");
+    assert_eq!(iphone.os, "iPhone");
+    assert_eq!(iphone.device_type, "mobile");
+
+    let ipad = parse_user_agent(
+      "Mozilla/5.0 (iPad; CPU OS 17_1 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.1 Mobile/15E148 Safari/604.1",
+    );
+    assert_eq!(ipad.device_type, "tablet");
+  }
+
+  #[test]
+  fn test_parse_unknown_user_agent() {
+    let device = parse_user_agent("curl/8.4.0");
+    assert_eq!(device.device_type, "unknown");
+
+    assert_eq!(
+      parse_user_agent(""),
+      DeviceInfo {
+        browser: "unknown".to_string(),
+        os: "unknown".to_string(),
+        device_type: "unknown".to_string(),
+      }
+    );
+  }
+
+  #[test]
+  fn test_device_fingerprint_ignores_versions() {
+    let chrome_120 = parse_user_agent(
+      "Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36",
+    );
+    let chrome_121 = parse_user_agent(
+      "Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/121.0.0.0 Safari/537.36",
+    );
+    let firefox =
+      parse_user_agent("Mozilla/5.0 (X11; Linux x86_64; rv:121.0) Gecko/20100101 Firefox/121.0");
+
+    assert_eq!(
+      device_fingerprint(&chrome_120),
+      device_fingerprint(&chrome_121)
+    );
+    assert_ne!(
+      device_fingerprint(&chrome_120),
+      device_fingerprint(&firefox)
+    );
+  }
+
+  #[test]
+  fn test_truncate_user_agent() {
+    assert_eq!(truncate_user_agent("yaak"), "yaak");
+    assert_eq!(
+      truncate_user_agent(&"é".repeat(2000)).chars().count(),
+      MAX_USER_AGENT_LENGTH
+    );
+  }
+}
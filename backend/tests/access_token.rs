@@ -0,0 +1,161 @@
+use diesel::{ExpressionMethods, PgConnection, QueryDsl, RunQueryDsl};
+use ephemeride_backend::{
+  establish_connection, establish_pool, schema,
+  services::{access_token, auth, user, Scope},
+  util::EphemerideError,
+};
+use poem::Request;
+use uuid::Uuid;
+
+fn create_user(conn: &mut PgConnection) -> user::UserDetails {
+  let random_name = Uuid::new_v4().to_string();
+
+  user::create_user(
+    conn,
+    user::CreateUser {
+      name: random_name.clone(),
+      email: format!("{random_name}@example.com"),
+      password: "password".to_string(),
+      invite: None,
+    },
+  )
+  .unwrap()
+}
+
+fn bearer(token: &str) -> Request {
+  Request::builder()
+    .header("Authorization", format!("Bearer {token}"))
+    .extension(auth::SessionSettings::from_env())
+    .finish()
+}
+
+fn create_access_token(
+  conn: &mut PgConnection,
+  user_id: &str,
+  scopes: Vec<Scope>,
+) -> access_token::PersonalAccessTokenWithToken {
+  access_token::create_access_token(
+    conn,
+    user_id,
+    access_token::CreateAccessToken {
+      name: "script".to_string(),
+      scopes,
+      expires_at: None,
+    },
+  )
+  .unwrap()
+}
+
+#[tokio::test]
+async fn access_token_is_limited_to_its_scopes() {
+  let pool = establish_pool();
+  let mut conn = establish_connection();
+
+  let created_user = create_user(&mut conn);
+  let created = create_access_token(&mut conn, &created_user.id, vec![Scope::EntriesRead]);
+
+  assert!(created.token.starts_with(access_token::ACCESS_TOKEN_PREFIX));
+  assert_eq!(created.access_token.scopes, vec!["entries:read"]);
+
+  let request = bearer(&created.token);
+
+  let allowed = auth::authorize_request(&pool, &request, Scope::EntriesRead).await;
+
+  assert_eq!(allowed.unwrap().user_id, created_user.id);
+
+  let not_granted = auth::authorize_request(&pool, &request, Scope::EntriesWrite).await;
+
+  assert_eq!(not_granted.err(), Some(EphemerideError::InsufficientScope));
+
+  // account management always needs a real session
+  let session_only = auth::authorize_session(&pool, &request).await;
+
+  assert_eq!(session_only.err(), Some(EphemerideError::InsufficientScope));
+
+  let listed = access_token::get_user_access_tokens(&mut conn, &created_user.id).unwrap();
+
+  assert_eq!(listed.len(), 1);
+  assert!(listed[0].last_used_at.is_some());
+}
+
+#[tokio::test]
+async fn expired_and_deleted_access_tokens_are_rejected() {
+  let pool = establish_pool();
+  let mut conn = establish_connection();
+
+  let created_user = create_user(&mut conn);
+  let expired = create_access_token(&mut conn, &created_user.id, vec![Scope::TagsRead]);
+
+  diesel::update(
+    schema::personal_access_tokens::table
+      .filter(schema::personal_access_tokens::id.eq(&expired.access_token.id)),
+  )
+  .set(schema::personal_access_tokens::expires_at.eq(Some(0)))
+  .execute(&mut conn)
+  .unwrap();
+
+  let rejected = auth::authorize_request(&pool, &bearer(&expired.token), Scope::TagsRead).await;
+
+  assert_eq!(rejected.err(), Some(EphemerideError::Unauthorized));
+
+  let deleted = create_access_token(&mut conn, &created_user.id, vec![Scope::TagsRead]);
+  let other_user = create_user(&mut conn);
+
+  assert_eq!(
+    access_token::delete_access_token(&mut conn, &deleted.access_token.id, &other_user.id),
+    Ok(false)
+  );
+  assert_eq!(
+    access_token::delete_access_token(&mut conn, &deleted.access_token.id, &created_user.id),
+    Ok(true)
+  );
+
+  let rejected = auth::authorize_request(&pool, &bearer(&deleted.token), Scope::TagsRead).await;
+
+  assert_eq!(rejected.err(), Some(EphemerideError::Unauthorized));
+}
+
+#[test]
+fn access_token_needs_a_future_expiry_and_a_scope() {
+  let mut conn = establish_connection();
+
+  let created_user = create_user(&mut conn);
+
+  let already_expired = access_token::create_access_token(
+    &mut conn,
+    &created_user.id,
+    access_token::CreateAccessToken {
+      name: "script".to_string(),
+      scopes: vec![Scope::EntriesRead],
+      expires_at: Some(1),
+    },
+  );
+
+  assert_eq!(already_expired.err(), Some(EphemerideError::BadRequest));
+
+  let no_scopes = access_token::create_access_token(
+    &mut conn,
+    &created_user.id,
+    access_token::CreateAccessToken {
+      name: "script".to_string(),
+      scopes: vec![],
+      expires_at: None,
+    },
+  );
+
+  assert_eq!(no_scopes.err(), Some(EphemerideError::BadRequest));
+}
+
+#[test]
+fn deleting_a_user_deletes_their_access_tokens() {
+  let mut conn = establish_connection();
+
+  let created_user = create_user(&mut conn);
+  create_access_token(&mut conn, &created_user.id, vec![Scope::EntriesWrite]);
+
+  assert!(user::delete_user(&mut conn, &created_user.id).unwrap());
+
+  let remaining = access_token::get_user_access_tokens(&mut conn, &created_user.id).unwrap();
+
+  assert!(remaining.is_empty());
+}
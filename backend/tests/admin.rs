@@ -0,0 +1,256 @@
+use diesel::{ExpressionMethods, PgConnection, QueryDsl, RunQueryDsl};
+use ephemeride_backend::{
+  establish_connection, establish_pool,
+  mailer::{FileMailer, Mailer},
+  schema,
+  services::{access_token, admin, auth, invite, user, Scope},
+  util::EphemerideError,
+};
+use poem::Request;
+use std::path::PathBuf;
+use uuid::Uuid;
+
+// collects mail in a file unique to the test and removes it afterwards
+struct TestMailbox {
+  path: PathBuf,
+  mailer: FileMailer,
+}
+
+impl TestMailbox {
+  fn new() -> TestMailbox {
+    let path = std::env::temp_dir().join(format!("ephemeride-mail-{}", Uuid::new_v4()));
+
+    TestMailbox {
+      mailer: FileMailer::new(&path),
+      path,
+    }
+  }
+
+  fn contents(&self) -> String {
+    std::fs::read_to_string(&self.path).unwrap_or_default()
+  }
+}
+
+impl Drop for TestMailbox {
+  fn drop(&mut self) {
+    let _ = std::fs::remove_file(&self.path);
+  }
+}
+
+fn create_user(conn: &mut PgConnection) -> user::UserDetails {
+  let random_name = Uuid::new_v4().to_string();
+
+  user::create_user(
+    conn,
+    user::CreateUser {
+      name: random_name.clone(),
+      email: format!("{random_name}@example.com"),
+      password: "password".to_string(),
+      invite: None,
+    },
+  )
+  .unwrap()
+}
+
+fn create_admin(conn: &mut PgConnection) -> user::UserDetails {
+  let created_user = create_user(conn);
+
+  diesel::update(schema::users::table.filter(schema::users::id.eq(&created_user.id)))
+    .set(schema::users::is_admin.eq(true))
+    .execute(conn)
+    .unwrap();
+
+  created_user
+}
+
+fn log_in(conn: &mut PgConnection, email: &str) -> Result<auth::SessionWithToken, EphemerideError> {
+  auth::create_user_session(
+    conn,
+    auth::UserCredentials {
+      email: email.to_string(),
+      password: "password".to_string(),
+      totp_code: None,
+    },
+    auth::SessionMetadata {
+      ip_address: "SYSTEM".to_string(),
+      user_agent: "SYSTEM".to_string(),
+    },
+  )
+}
+
+fn bearer(token: &str) -> Request {
+  Request::builder()
+    .header("Authorization", format!("Bearer {token}"))
+    .extension(auth::SessionSettings::from_env())
+    .finish()
+}
+
+#[tokio::test]
+async fn admin_routes_need_an_admin() {
+  let pool = establish_pool();
+  let mut conn = establish_connection();
+
+  let regular_user = create_user(&mut conn);
+  let regular_session = log_in(&mut conn, &regular_user.email).unwrap();
+
+  let rejected = auth::authorize_admin(&pool, &bearer(&regular_session.token)).await;
+
+  assert_eq!(rejected.err(), Some(EphemerideError::AdminRequired));
+
+  let admin_user = create_admin(&mut conn);
+  let admin_session = log_in(&mut conn, &admin_user.email).unwrap();
+
+  let allowed = auth::authorize_admin(&pool, &bearer(&admin_session.token)).await;
+
+  assert_eq!(allowed.unwrap().user_id, admin_user.id);
+}
+
+#[tokio::test]
+async fn disabled_user_is_signed_out_and_locked_out() {
+  let pool = establish_pool();
+  let mut conn = establish_connection();
+
+  let admin_user = create_admin(&mut conn);
+  let disabled_user = create_user(&mut conn);
+  let session = log_in(&mut conn, &disabled_user.email).unwrap();
+  let access_token = access_token::create_access_token(
+    &mut conn,
+    &disabled_user.id,
+    access_token::CreateAccessToken {
+      name: "script".to_string(),
+      scopes: vec![Scope::EntriesRead],
+      expires_at: None,
+    },
+  )
+  .unwrap();
+
+  assert_eq!(
+    admin::set_user_disabled(&mut conn, &admin_user.id, &admin_user.id, true).err(),
+    Some(EphemerideError::BadRequest)
+  );
+  assert_eq!(
+    admin::set_user_disabled(&mut conn, &admin_user.id, &disabled_user.id, true),
+    Ok(true)
+  );
+
+  assert_eq!(
+    auth::get_user_session_by_token(&mut conn, &session.token).err(),
+    Some(EphemerideError::SessionNotFound)
+  );
+  assert_eq!(
+    log_in(&mut conn, &disabled_user.email).err(),
+    Some(EphemerideError::AccountDisabled)
+  );
+
+  let token_request = bearer(&access_token.token);
+
+  assert_eq!(
+    auth::authorize_request(&pool, &token_request, Scope::EntriesRead)
+      .await
+      .err(),
+    Some(EphemerideError::Unauthorized)
+  );
+
+  assert_eq!(
+    admin::set_user_disabled(&mut conn, &admin_user.id, &disabled_user.id, false),
+    Ok(true)
+  );
+
+  assert!(log_in(&mut conn, &disabled_user.email).is_ok());
+  assert!(
+    auth::authorize_request(&pool, &token_request, Scope::EntriesRead)
+      .await
+      .is_ok()
+  );
+}
+
+#[test]
+fn searches_users_by_name_or_email() {
+  let mut conn = establish_connection();
+
+  let created_user = create_user(&mut conn);
+
+  let found = admin::search_users(
+    &mut conn,
+    admin::SearchUsers {
+      query: Some(created_user.email[..8].to_uppercase()),
+      limit: None,
+      offset: None,
+    },
+  )
+  .unwrap();
+
+  assert_eq!(found.pagination.total_count, 1);
+  assert_eq!(found.data[0].id, created_user.id);
+
+  let page = admin::search_users(
+    &mut conn,
+    admin::SearchUsers {
+      query: None,
+      limit: Some(1),
+      offset: Some(0),
+    },
+  )
+  .unwrap();
+
+  assert_eq!(page.data.len(), 1);
+  assert!(page.pagination.total_count >= 1);
+}
+
+#[test]
+fn forced_password_reset_replaces_the_password() {
+  let mut conn = establish_connection();
+  let mailbox = TestMailbox::new();
+
+  let created_user = create_user(&mut conn);
+  let session = log_in(&mut conn, &created_user.email).unwrap();
+
+  let email = admin::force_password_reset(&mut conn, &created_user.id)
+    .unwrap()
+    .unwrap();
+  mailbox.mailer.send(&email).unwrap();
+
+  assert_eq!(
+    log_in(&mut conn, &created_user.email).err(),
+    Some(EphemerideError::InvalidCredentials)
+  );
+  assert_eq!(
+    auth::get_user_session_by_token(&mut conn, &session.token).err(),
+    Some(EphemerideError::SessionNotFound)
+  );
+  assert!(mailbox.contents().contains(&created_user.email));
+  assert!(mailbox.contents().contains("reset-password?token="));
+
+  assert!(admin::force_password_reset(&mut conn, "unknown")
+    .unwrap()
+    .is_none());
+}
+
+#[test]
+fn only_unused_invites_can_be_revoked() {
+  let mut conn = establish_connection();
+
+  let unused = invite::generate_invite(&mut conn, None).unwrap();
+  let used = invite::generate_invite(&mut conn, None).unwrap();
+  invite::use_invite(&mut conn, &used.code).unwrap();
+
+  assert!(invite::get_invites(&mut conn)
+    .unwrap()
+    .iter()
+    .any(|listed| listed.id == unused.id));
+
+  assert_eq!(invite::revoke_invite(&mut conn, &unused.id), Ok(true));
+  assert_eq!(invite::revoke_invite(&mut conn, &unused.id), Ok(false));
+  assert_eq!(
+    invite::revoke_invite(&mut conn, &used.id).err(),
+    Some(EphemerideError::InviteUsed)
+  );
+
+  let remaining = schema::invites::table
+    .filter(schema::invites::id.eq(&used.id))
+    .count()
+    .get_result::<i64>(&mut conn)
+    .unwrap();
+
+  assert_eq!(remaining, 1);
+}
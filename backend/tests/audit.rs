@@ -0,0 +1,221 @@
+use diesel::{Connection, ExpressionMethods, PgConnection, QueryDsl, RunQueryDsl};
+use ephemeride_backend::{
+  establish_connection, schema,
+  services::{audit, auth, invite, user},
+};
+use std::{thread, time::Duration};
+use uuid::Uuid;
+
+fn create_user(conn: &mut PgConnection) -> user::UserDetails {
+  let random_name = Uuid::new_v4().to_string();
+
+  user::create_user(
+    conn,
+    user::CreateUser {
+      name: random_name.clone(),
+      email: format!("{random_name}@example.com"),
+      password: "password".to_string(),
+      invite: None,
+    },
+  )
+  .unwrap()
+}
+
+fn metadata() -> auth::SessionMetadata {
+  auth::SessionMetadata {
+    ip_address: "203.0.113.7".to_string(),
+    user_agent: "audit test".to_string(),
+  }
+}
+
+fn log_in(conn: &mut PgConnection, email: &str) -> auth::SessionWithToken {
+  auth::create_user_session(
+    conn,
+    auth::UserCredentials {
+      email: email.to_string(),
+      password: "password".to_string(),
+      totp_code: None,
+    },
+    metadata(),
+  )
+  .unwrap()
+}
+
+// events are ordered by when they happened, so each step waits for the
+// clock to move on to keep the order in the test certain
+fn next_ms() {
+  thread::sleep(Duration::from_millis(2));
+}
+
+fn events(conn: &mut PgConnection, user_id: &str) -> Vec<audit::AuditEvent> {
+  audit::get_user_audit_events(
+    conn,
+    user_id,
+    audit::AuditEventOptions {
+      limit: None,
+      offset: None,
+    },
+  )
+  .unwrap()
+  .data
+}
+
+#[test]
+fn records_logins_with_metadata() {
+  let mut conn = establish_connection();
+
+  let created_user = create_user(&mut conn);
+  let session = log_in(&mut conn, &created_user.email);
+
+  let events = events(&mut conn, &created_user.id);
+  assert_eq!(events.len(), 1);
+  assert_eq!(events[0].event, "login");
+  assert_eq!(events[0].ip_address.as_deref(), Some("203.0.113.7"));
+  assert_eq!(events[0].user_agent.as_deref(), Some("audit test"));
+  assert_eq!(events[0].details, Some(session.session.id));
+}
+
+#[test]
+fn records_account_changes_newest_first() {
+  let mut conn = establish_connection();
+
+  let created_user = create_user(&mut conn);
+  let current = log_in(&mut conn, &created_user.email).session;
+  next_ms();
+  let other = log_in(&mut conn, &created_user.email).session;
+  next_ms();
+
+  auth::revoke_user_session(&mut conn, &other.id, &created_user.id, &metadata()).unwrap();
+  // already gone, so there's nothing to record
+  auth::revoke_user_session(&mut conn, &other.id, &created_user.id, &metadata()).unwrap();
+  next_ms();
+
+  user::update_password(
+    &mut conn,
+    &created_user.id,
+    &current.id,
+    user::UpdatePassword {
+      current_password: "password".to_string(),
+      password: "new password".to_string(),
+    },
+    &metadata(),
+  )
+  .unwrap();
+
+  let kinds = events(&mut conn, &created_user.id)
+    .into_iter()
+    .map(|event| event.event)
+    .collect::<Vec<String>>();
+  assert_eq!(
+    kinds,
+    vec!["password_changed", "session_revoked", "login", "login"]
+  );
+}
+
+#[test]
+fn records_invites_for_the_inviter_only() {
+  let mut conn = establish_connection();
+
+  let inviter = create_user(&mut conn);
+  let created = invite::create_user_invite(
+    &mut conn,
+    &inviter.id,
+    5,
+    invite::CreateUserInvite::default(),
+    &metadata(),
+  )
+  .unwrap();
+  next_ms();
+  invite::use_invite(&mut conn, &created.code).unwrap();
+
+  let events = events(&mut conn, &inviter.id);
+  assert_eq!(events.len(), 2);
+  assert_eq!(events[0].event, "invite_redeemed");
+  assert_eq!(events[0].details, Some(created.id.clone()));
+  // whoever redeemed it isn't the inviter's business
+  assert_eq!(events[0].ip_address, None);
+  assert_eq!(events[1].event, "invite_created");
+}
+
+#[test]
+fn limits_the_number_of_events() {
+  let mut conn = establish_connection();
+
+  let created_user = create_user(&mut conn);
+  for _ in 0..3 {
+    log_in(&mut conn, &created_user.email);
+  }
+
+  let page = audit::get_user_audit_events(
+    &mut conn,
+    &created_user.id,
+    audit::AuditEventOptions {
+      limit: Some(2),
+      offset: Some(2),
+    },
+  )
+  .unwrap();
+  assert_eq!(page.data.len(), 1);
+  assert_eq!(page.pagination.total_count, 3);
+
+  let page = audit::get_user_audit_events(
+    &mut conn,
+    &created_user.id,
+    audit::AuditEventOptions {
+      limit: Some(10_000),
+      offset: None,
+    },
+  )
+  .unwrap();
+  assert_eq!(page.pagination.limit, 100);
+}
+
+#[test]
+fn events_cannot_be_changed() {
+  let mut conn = establish_connection();
+
+  let created_user = create_user(&mut conn);
+  log_in(&mut conn, &created_user.email);
+
+  let updated = diesel::update(
+    schema::audit_events::table.filter(schema::audit_events::user_id.eq(&created_user.id)),
+  )
+  .set(schema::audit_events::event.eq("nothing to see here"))
+  .execute(&mut conn);
+  assert!(updated.is_err());
+
+  let deleted = diesel::delete(
+    schema::audit_events::table.filter(schema::audit_events::user_id.eq(&created_user.id)),
+  )
+  .execute(&mut conn);
+  assert!(deleted.is_err());
+
+  // deleting another user doesn't unlock this user's events
+  let other_user = create_user(&mut conn);
+  let deleted = conn.transaction::<_, diesel::result::Error, _>(|conn| {
+    audit::delete_user_audit_events(conn, &other_user.id).unwrap();
+    diesel::delete(
+      schema::audit_events::table.filter(schema::audit_events::user_id.eq(&created_user.id)),
+    )
+    .execute(conn)
+  });
+  assert!(deleted.is_err());
+  assert_eq!(events(&mut conn, &created_user.id).len(), 1);
+}
+
+#[test]
+fn events_are_deleted_with_the_user() {
+  let mut conn = establish_connection();
+
+  let created_user = create_user(&mut conn);
+  log_in(&mut conn, &created_user.email);
+
+  assert_eq!(user::delete_user(&mut conn, &created_user.id), Ok(true));
+
+  let remaining = schema::audit_events::table
+    .filter(schema::audit_events::user_id.eq(&created_user.id))
+    .count()
+    .get_result::<i64>(&mut conn)
+    .unwrap();
+  assert_eq!(remaining, 0);
+}
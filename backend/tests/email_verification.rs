@@ -0,0 +1,312 @@
+use diesel::{ExpressionMethods, PgConnection, QueryDsl, RunQueryDsl};
+use ephemeride_backend::{
+  establish_connection,
+  mailer::{FileMailer, Mailer},
+  schema,
+  services::{audit, auth, email_verification, user},
+  util::EphemerideError,
+};
+use std::path::PathBuf;
+use uuid::Uuid;
+
+// collects mail in a file unique to the test and removes it afterwards
+struct TestMailbox {
+  path: PathBuf,
+  mailer: FileMailer,
+}
+
+impl TestMailbox {
+  fn new() -> TestMailbox {
+    let path = std::env::temp_dir().join(format!("ephemeride-mail-{}", Uuid::new_v4()));
+
+    TestMailbox {
+      mailer: FileMailer::new(&path),
+      path,
+    }
+  }
+
+  fn contents(&self) -> String {
+    std::fs::read_to_string(&self.path).unwrap_or_default()
+  }
+
+  fn last_token(&self) -> String {
+    let contents = self.contents();
+    let link = contents
+      .lines()
+      .rfind(|line| line.contains("verify-email?token="))
+      .expect("No verification link was sent");
+
+    link.split("token=").nth(1).unwrap().trim().to_string()
+  }
+}
+
+impl Drop for TestMailbox {
+  fn drop(&mut self) {
+    let _ = std::fs::remove_file(&self.path);
+  }
+}
+
+fn create_user(conn: &mut PgConnection) -> user::UserDetails {
+  let random_name = Uuid::new_v4().to_string();
+
+  user::create_user(
+    conn,
+    user::CreateUser {
+      name: random_name.clone(),
+      email: format!("{random_name}@example.com"),
+      password: "password".to_string(),
+      invite: None,
+    },
+  )
+  .expect("Failed to create test user")
+}
+
+// sends the verification email the way the handlers do, once the
+// verification has been saved
+fn request_verification(conn: &mut PgConnection, mailbox: &TestMailbox, user: &user::UserDetails) {
+  let verification =
+    email_verification::request_email_verification(conn, &user.id, &user.email).unwrap();
+  mailbox.mailer.send(&verification).unwrap();
+}
+
+fn confirm(conn: &mut PgConnection, token: &str) -> Result<(), EphemerideError> {
+  email_verification::confirm_email_verification(
+    conn,
+    email_verification::ConfirmEmailVerification {
+      token: token.to_string(),
+    },
+    &auth::SessionMetadata {
+      ip_address: "SYSTEM".to_string(),
+      user_agent: "SYSTEM".to_string(),
+    },
+  )
+}
+
+fn change_email(
+  conn: &mut PgConnection,
+  mailbox: &TestMailbox,
+  user: &user::UserDetails,
+  email: &str,
+) {
+  let verification = user::update_user(
+    conn,
+    &user.id,
+    user::UpdateUser {
+      name: user.name.clone(),
+      email: email.to_string(),
+    },
+    &auth::SessionMetadata {
+      ip_address: "SYSTEM".to_string(),
+      user_agent: "SYSTEM".to_string(),
+    },
+  )
+  .unwrap();
+
+  if let Some(verification) = verification {
+    mailbox.mailer.send(&verification).unwrap();
+  }
+}
+
+#[test]
+fn new_users_start_unverified() {
+  let mut conn = establish_connection();
+  let mailbox = TestMailbox::new();
+
+  let user = create_user(&mut conn);
+
+  assert!(!user.email_verified);
+
+  request_verification(&mut conn, &mailbox, &user);
+
+  assert!(mailbox.contents().contains(&format!("To: {}", user.email)));
+  assert!(confirm(&mut conn, &mailbox.last_token()).is_ok());
+  assert!(user::get_user(&mut conn, &user.id).unwrap().email_verified);
+  assert!(audit::get_all_user_audit_events(&mut conn, &user.id)
+    .unwrap()
+    .is_empty());
+}
+
+#[test]
+fn verification_token_is_single_use() {
+  let mut conn = establish_connection();
+  let mailbox = TestMailbox::new();
+
+  let user = create_user(&mut conn);
+
+  request_verification(&mut conn, &mailbox, &user);
+  let token = mailbox.last_token();
+
+  assert!(confirm(&mut conn, &token).is_ok());
+  assert_eq!(
+    confirm(&mut conn, &token).err(),
+    Some(EphemerideError::InvalidVerificationToken)
+  );
+}
+
+#[test]
+fn verification_token_expires() {
+  let mut conn = establish_connection();
+  let mailbox = TestMailbox::new();
+
+  let user = create_user(&mut conn);
+
+  request_verification(&mut conn, &mailbox, &user);
+
+  diesel::update(
+    schema::email_verifications::table.filter(schema::email_verifications::user_id.eq(&user.id)),
+  )
+  .set(schema::email_verifications::expires_at.eq(0))
+  .execute(&mut conn)
+  .unwrap();
+
+  assert_eq!(
+    confirm(&mut conn, &mailbox.last_token()).err(),
+    Some(EphemerideError::InvalidVerificationToken)
+  );
+  assert!(!user::get_user(&mut conn, &user.id).unwrap().email_verified);
+}
+
+#[test]
+fn email_change_waits_for_verification() {
+  let mut conn = establish_connection();
+  let mailbox = TestMailbox::new();
+
+  let user = create_user(&mut conn);
+  let new_email = format!("{}@example.com", Uuid::new_v4());
+
+  change_email(&mut conn, &mailbox, &user, &new_email);
+
+  // the old address stays in use until the new one is confirmed
+  assert_eq!(
+    user::get_user(&mut conn, &user.id).unwrap().email,
+    user.email
+  );
+  assert!(user::get_user_id(&mut conn, &new_email).is_err());
+  assert!(mailbox.contents().contains(&format!("To: {new_email}")));
+
+  assert!(confirm(&mut conn, &mailbox.last_token()).is_ok());
+
+  let updated = user::get_user(&mut conn, &user.id).unwrap();
+
+  assert_eq!(updated.email, new_email);
+  assert!(updated.email_verified);
+  assert!(user::get_user_id(&mut conn, &user.email).is_err());
+
+  let events = audit::get_all_user_audit_events(&mut conn, &user.id).unwrap();
+  let changed = events
+    .iter()
+    .find(|event| event.event == "email_changed")
+    .unwrap();
+  assert_eq!(changed.details.as_deref(), Some(new_email.as_str()));
+}
+
+#[test]
+fn email_change_fails_if_address_was_taken() {
+  let mut conn = establish_connection();
+  let mailbox = TestMailbox::new();
+
+  let user = create_user(&mut conn);
+  let new_email = format!("{}@example.com", Uuid::new_v4());
+
+  change_email(&mut conn, &mailbox, &user, &new_email);
+
+  user::create_user(
+    &mut conn,
+    user::CreateUser {
+      name: "Other".to_string(),
+      email: new_email.clone(),
+      password: "password".to_string(),
+      invite: None,
+    },
+  )
+  .unwrap();
+
+  assert_eq!(
+    confirm(&mut conn, &mailbox.last_token()).err(),
+    Some(EphemerideError::EmailAlreadyInUse)
+  );
+  assert_eq!(
+    user::get_user(&mut conn, &user.id).unwrap().email,
+    user.email
+  );
+}
+
+#[test]
+fn resend_skips_unknown_and_verified_addresses() {
+  let mut conn = establish_connection();
+  let mailbox = TestMailbox::new();
+
+  let resend = |conn: &mut PgConnection, email: &str| {
+    let verification = email_verification::resend_email_verification(
+      conn,
+      email_verification::ResendEmailVerification {
+        email: email.to_string(),
+      },
+    )
+    .unwrap();
+
+    if let Some(verification) = verification {
+      mailbox.mailer.send(&verification).unwrap();
+    }
+  };
+
+  resend(&mut conn, &format!("{}@example.com", Uuid::new_v4()));
+
+  assert!(mailbox.contents().is_empty());
+
+  let user = create_user(&mut conn);
+  resend(&mut conn, &user.email);
+  confirm(&mut conn, &mailbox.last_token()).unwrap();
+  let sent = mailbox.contents();
+  resend(&mut conn, &user.email);
+
+  assert_eq!(mailbox.contents(), sent);
+}
+
+// the only test in this file that logs in, so setting the variable can't
+// affect anything running alongside it
+#[test]
+fn login_requires_verified_email_when_configured() {
+  let mut conn = establish_connection();
+  let mailbox = TestMailbox::new();
+
+  std::env::set_var("EMAIL_VERIFICATION_REQUIRED", "true");
+
+  let user = create_user(&mut conn);
+  let log_in = |conn: &mut PgConnection| {
+    auth::create_user_session(
+      conn,
+      auth::UserCredentials {
+        email: user.email.clone(),
+        password: "password".to_string(),
+        totp_code: None,
+      },
+      auth::SessionMetadata {
+        ip_address: "SYSTEM".to_string(),
+        user_agent: "SYSTEM".to_string(),
+      },
+    )
+  };
+
+  assert_eq!(
+    log_in(&mut conn).err(),
+    Some(EphemerideError::EmailNotVerified)
+  );
+
+  request_verification(&mut conn, &mailbox, &user);
+  confirm(&mut conn, &mailbox.last_token()).unwrap();
+
+  assert!(log_in(&mut conn).is_ok());
+}
+
+#[test]
+fn deletes_user_with_pending_verification() {
+  let mut conn = establish_connection();
+  let mailbox = TestMailbox::new();
+
+  let user = create_user(&mut conn);
+
+  request_verification(&mut conn, &mailbox, &user);
+
+  assert!(user::delete_user(&mut conn, &user.id).unwrap());
+}
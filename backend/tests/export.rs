@@ -0,0 +1,208 @@
+use diesel::{
+  r2d2::{ConnectionManager, Pool},
+  PgConnection,
+};
+use ephemeride_backend::{
+  establish_connection, establish_pool,
+  services::{category, entry, export, tag, user},
+  util::EphemerideError,
+  DbPool,
+};
+use std::{
+  env,
+  io::{self, Cursor, Read, Write},
+  sync::OnceLock,
+};
+use uuid::Uuid;
+
+fn pool() -> &'static DbPool {
+  static POOL: OnceLock<DbPool> = OnceLock::new();
+  POOL.get_or_init(establish_pool)
+}
+
+fn create_user(conn: &mut PgConnection) -> user::UserDetails {
+  let random_name = Uuid::new_v4().to_string();
+
+  user::create_user(
+    conn,
+    user::CreateUser {
+      name: random_name.clone(),
+      email: format!("{random_name}@example.com"),
+      password: "password".to_string(),
+      invite: None,
+    },
+  )
+  .unwrap()
+}
+
+fn create_tag(conn: &mut PgConnection, user_id: &str) -> tag::Tag {
+  let created_category = category::create_category(
+    conn,
+    category::CreateCategory {
+      name: "Export".to_string(),
+      user_id: user_id.to_string(),
+    },
+  )
+  .unwrap();
+
+  tag::create_tag(
+    conn,
+    tag::CreateTag {
+      name: "Exported, with a comma".to_string(),
+      color: "blue".to_string(),
+      category_id: created_category.id,
+      user_id: user_id.to_string(),
+    },
+  )
+  .unwrap()
+}
+
+fn create_entries(conn: &mut PgConnection, user_id: &str, tag_id: &str, count: i64) {
+  let first_date = chrono::NaiveDate::from_ymd_opt(2020, 1, 1).unwrap();
+
+  for day in 0..count {
+    entry::create_entry(
+      conn,
+      entry::CreateEntry {
+        date: (first_date + chrono::Duration::days(day))
+          .format("%Y-%m-%d")
+          .to_string(),
+        mood: 3,
+        entry: Some(format!("day {day}")),
+        selected_tags: vec![tag_id.to_string()],
+        user_id: user_id.to_string(),
+      },
+    )
+    .unwrap();
+  }
+}
+
+fn export(user_id: &str, format: export::ExportFormat) -> Vec<u8> {
+  let mut exported = vec![];
+  export::write_export(pool(), user_id, format, &mut exported).unwrap();
+  exported
+}
+
+#[test]
+fn exports_the_account_as_json() {
+  let mut conn = establish_connection();
+
+  let created_user = create_user(&mut conn);
+  let created_tag = create_tag(&mut conn, &created_user.id);
+  // more than one batch of entries
+  create_entries(&mut conn, &created_user.id, &created_tag.id, 501);
+
+  let exported: serde_json::Value =
+    serde_json::from_slice(&export(&created_user.id, export::ExportFormat::Json)).unwrap();
+
+  assert_eq!(exported["version"], export::EXPORT_VERSION);
+  assert_eq!(exported["user"]["id"], created_user.id);
+  assert!(exported["user"].get("password").is_none());
+  assert!(exported["tags"]
+    .as_array()
+    .unwrap()
+    .iter()
+    .any(|exported_tag| exported_tag["id"] == created_tag.id && exported_tag["color"] == "blue"));
+
+  let entries = exported["entries"].as_array().unwrap();
+  assert_eq!(entries.len(), 501);
+  assert_eq!(entries[0]["date"], "2020-01-01");
+  assert_eq!(entries[500]["entry"], "day 500");
+  assert_eq!(entries[500]["selected_tags"][0], created_tag.id);
+}
+
+#[test]
+fn exports_an_account_without_entries() {
+  let mut conn = establish_connection();
+
+  let created_user = create_user(&mut conn);
+
+  let exported: serde_json::Value =
+    serde_json::from_slice(&export(&created_user.id, export::ExportFormat::Json)).unwrap();
+
+  assert_eq!(exported["entries"], serde_json::json!([]));
+  assert!(!exported["categories"].as_array().unwrap().is_empty());
+}
+
+#[test]
+fn exports_the_account_as_a_zip_of_csv_files() {
+  let mut conn = establish_connection();
+
+  let created_user = create_user(&mut conn);
+  let created_tag = create_tag(&mut conn, &created_user.id);
+  create_entries(&mut conn, &created_user.id, &created_tag.id, 2);
+
+  let exported = export(&created_user.id, export::ExportFormat::Zip);
+  let mut archive = zip::ZipArchive::new(Cursor::new(exported)).unwrap();
+
+  let mut read_file = |name: &str| {
+    let mut contents = String::new();
+    archive
+      .by_name(name)
+      .unwrap()
+      .read_to_string(&mut contents)
+      .unwrap();
+    contents
+  };
+
+  let account: serde_json::Value = serde_json::from_str(&read_file("account.json")).unwrap();
+  assert_eq!(account["user"]["email"], created_user.email);
+
+  let tags = read_file("tags.csv");
+  assert!(tags.contains("\"Exported, with a comma\",blue"));
+
+  let entries = read_file("entries.csv");
+  assert_eq!(entries.lines().count(), 3);
+  assert!(entries.contains(&format!("2020-01-02,3,day 1,{}", created_tag.id)));
+
+  assert!(read_file("categories.csv").starts_with("id,name,created_at"));
+  assert!(read_file("sessions.csv").starts_with("id,created_at"));
+}
+
+#[test]
+fn export_of_unknown_user_fails() {
+  let mut exported = vec![];
+  assert_eq!(
+    export::write_export(pool(), "unknown", export::ExportFormat::Json, &mut exported),
+    Err(EphemerideError::UserNotFound)
+  );
+}
+
+// a writer that checks a connection is free every time it's written to, like
+// a slow client would be holding the export up
+struct PoolCheckingWriter<'a> {
+  pool: &'a DbPool,
+}
+
+impl Write for PoolCheckingWriter<'_> {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    assert!(self.pool.try_get().is_some());
+    Ok(buf.len())
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    Ok(())
+  }
+}
+
+#[test]
+fn export_does_not_hold_a_connection_while_writing() {
+  let mut conn = establish_connection();
+
+  let created_user = create_user(&mut conn);
+  let created_tag = create_tag(&mut conn, &created_user.id);
+  create_entries(&mut conn, &created_user.id, &created_tag.id, 501);
+
+  let database_url = env::var("DATABASE_URL").unwrap();
+  let single_connection = Pool::builder()
+    .max_size(1)
+    .build(ConnectionManager::<PgConnection>::new(database_url))
+    .unwrap();
+
+  for format in [export::ExportFormat::Json, export::ExportFormat::Zip] {
+    let mut writer = PoolCheckingWriter {
+      pool: &single_connection,
+    };
+    export::write_export(&single_connection, &created_user.id, format, &mut writer).unwrap();
+  }
+}
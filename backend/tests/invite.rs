@@ -1,30 +1,375 @@
-use ephemeride_backend::services::invite;
+use diesel::{ExpressionMethods, PgConnection, QueryDsl, RunQueryDsl};
+use ephemeride_backend::{
+  establish_connection, schema,
+  services::{auth, invite, user},
+  util::{self, unix_ms, EphemerideError},
+};
+use std::thread;
 use uuid::Uuid;
 
+fn create_user(conn: &mut PgConnection, invite_id: Option<String>) -> user::UserDetails {
+  let random_name = Uuid::new_v4().to_string();
+
+  user::create_user(
+    conn,
+    user::CreateUser {
+      name: random_name.clone(),
+      email: format!("{random_name}@example.com"),
+      password: "password".to_string(),
+      invite: invite_id,
+    },
+  )
+  .unwrap()
+}
+
+fn metadata() -> auth::SessionMetadata {
+  auth::SessionMetadata {
+    ip_address: "SYSTEM".to_string(),
+    user_agent: "SYSTEM".to_string(),
+  }
+}
+
 #[test]
 fn generates_an_invite() {
-  let code = invite::generate_invite(None);
+  let mut conn = establish_connection();
+
+  let code = invite::generate_invite(&mut conn, None);
   assert!(code.is_ok());
 }
 
 #[test]
 fn generates_unique_invites() {
-  let code1 = invite::generate_invite(None).unwrap();
-  let code2 = invite::generate_invite(None).unwrap();
+  let mut conn = establish_connection();
+
+  let code1 = invite::generate_invite(&mut conn, None).unwrap();
+  let code2 = invite::generate_invite(&mut conn, None).unwrap();
   assert_ne!(code1.code, code2.code);
 }
 
 #[test]
 fn generates_an_invite_with_code() {
+  let mut conn = establish_connection();
+
   let value = Uuid::new_v4().to_string();
-  let code = invite::generate_invite(Some(&value)).unwrap();
+  let code = invite::generate_invite(&mut conn, Some(&value)).unwrap();
   assert_eq!(code.code, value);
 }
 
 #[test]
 fn generates_unique_invites_with_code() {
+  let mut conn = establish_connection();
+
   let value = Uuid::new_v4().to_string();
-  let code1 = invite::generate_invite(Some(&value)).unwrap();
-  let code2 = invite::generate_invite(Some(&value)).unwrap();
-  assert_ne!(code1.code, code2.code);
+  invite::generate_invite(&mut conn, Some(&value)).unwrap();
+  assert_eq!(
+    invite::generate_invite(&mut conn, Some(&value)).err(),
+    Some(EphemerideError::InviteCodeTaken)
+  );
+
+  // the same code typed differently is still taken
+  let created = invite::generate_invite(&mut conn, None).unwrap();
+  assert_eq!(
+    invite::generate_invite(&mut conn, Some(&created.code.to_lowercase())).err(),
+    Some(EphemerideError::InviteCodeTaken)
+  );
+}
+
+#[test]
+fn generates_readable_invite_codes() {
+  let mut conn = establish_connection();
+
+  let created = invite::generate_invite(&mut conn, None).unwrap();
+  assert_eq!(created.code.len(), 14);
+
+  // typed in lowercase and without dashes it still finds the invite
+  let typed = created.code.replace('-', " ").to_lowercase();
+  let found = invite::get_invite(&mut conn, &typed).unwrap();
+  assert_eq!(found.id, created.id);
+}
+
+// codes used to be uuids or whatever was given, stored without normalizing
+fn create_legacy_invite(conn: &mut PgConnection, code: &str) -> invite::Invite {
+  let legacy = invite::Invite {
+    id: Uuid::new_v4().to_string(),
+    created_at: unix_ms(),
+    code: code.to_string(),
+    expires_at: None,
+    max_uses: 1,
+    use_count: 0,
+    created_by: None,
+    note: None,
+    user_invite: false,
+  };
+
+  diesel::insert_into(schema::invites::table)
+    .values(&legacy)
+    .execute(conn)
+    .unwrap();
+
+  legacy
+}
+
+#[test]
+fn redeems_invites_created_before_readable_codes() {
+  let mut conn = establish_connection();
+
+  let legacy = create_legacy_invite(&mut conn, &Uuid::new_v4().to_string());
+  let redeemed = invite::use_invite(&mut conn, &legacy.code).unwrap();
+  assert_eq!(redeemed.id, legacy.id);
+  create_user(&mut conn, Some(redeemed.id));
+
+  // a custom code that happens to look like a generated one
+  let code = util::generate_invite_code().replace('-', "").to_lowercase();
+  let legacy = create_legacy_invite(&mut conn, &code);
+  let redeemed = invite::use_invite(&mut conn, &code).unwrap();
+  assert_eq!(redeemed.id, legacy.id);
+  assert_eq!(
+    invite::use_invite(&mut conn, &code).err(),
+    Some(EphemerideError::InviteUsed)
+  );
+}
+
+#[test]
+fn invites_can_only_be_used_max_uses_times() {
+  let mut conn = establish_connection();
+
+  let single = invite::generate_invite(&mut conn, None).unwrap();
+  assert_eq!(single.max_uses, 1);
+  assert!(invite::use_invite(&mut conn, &single.code).is_ok());
+  assert_eq!(
+    invite::use_invite(&mut conn, &single.code).err(),
+    Some(EphemerideError::InviteUsed)
+  );
+
+  let multiple = invite::create_invite(
+    &mut conn,
+    None,
+    invite::CreateInvite {
+      max_uses: Some(3),
+      note: Some("for the book club".to_string()),
+      ..Default::default()
+    },
+  )
+  .unwrap();
+
+  for use_count in 1..=3 {
+    let used = invite::use_invite(&mut conn, &multiple.code).unwrap();
+    assert_eq!(used.use_count, use_count);
+  }
+  assert_eq!(
+    invite::use_invite(&mut conn, &multiple.code).err(),
+    Some(EphemerideError::InviteUsed)
+  );
+}
+
+#[test]
+fn expired_invites_cannot_be_used() {
+  let mut conn = establish_connection();
+
+  let past = unix_ms() - 1000;
+  assert_eq!(
+    invite::create_invite(
+      &mut conn,
+      None,
+      invite::CreateInvite {
+        expires_at: Some(past),
+        ..Default::default()
+      },
+    )
+    .err(),
+    Some(EphemerideError::BadRequest)
+  );
+
+  let created = invite::create_invite(
+    &mut conn,
+    None,
+    invite::CreateInvite {
+      expires_at: Some(unix_ms() + 60_000),
+      ..Default::default()
+    },
+  )
+  .unwrap();
+
+  diesel::update(schema::invites::table.filter(schema::invites::id.eq(&created.id)))
+    .set(schema::invites::expires_at.eq(past))
+    .execute(&mut conn)
+    .unwrap();
+
+  assert_eq!(
+    invite::use_invite(&mut conn, &created.code).err(),
+    Some(EphemerideError::InviteExpired)
+  );
+}
+
+#[test]
+fn rejects_invalid_invites() {
+  let mut conn = establish_connection();
+
+  assert_eq!(
+    invite::create_invite(
+      &mut conn,
+      None,
+      invite::CreateInvite {
+        max_uses: Some(0),
+        ..Default::default()
+      },
+    )
+    .err(),
+    Some(EphemerideError::BadRequest)
+  );
+  assert_eq!(
+    invite::use_invite(&mut conn, "unknown").err(),
+    Some(EphemerideError::InviteNotFound)
+  );
+}
+
+#[test]
+fn concurrent_redemptions_use_an_invite_once() {
+  let mut conn = establish_connection();
+
+  let created = invite::generate_invite(&mut conn, None).unwrap();
+
+  let redemptions = (0..8)
+    .map(|_| {
+      let code = created.code.clone();
+      thread::spawn(move || {
+        let mut conn = establish_connection();
+        invite::use_invite(&mut conn, &code).is_ok()
+      })
+    })
+    .collect::<Vec<_>>()
+    .into_iter()
+    .map(|redemption| redemption.join().unwrap())
+    .filter(|redeemed| *redeemed)
+    .count();
+
+  assert_eq!(redemptions, 1);
+  assert_eq!(
+    invite::get_invite(&mut conn, &created.code)
+      .unwrap()
+      .use_count,
+    1
+  );
+}
+
+#[test]
+fn users_can_create_invites_up_to_their_quota() {
+  let mut conn = establish_connection();
+
+  let created_user = create_user(&mut conn, None);
+
+  for _ in 0..2 {
+    let created = invite::create_user_invite(
+      &mut conn,
+      &created_user.id,
+      2,
+      invite::CreateUserInvite::default(),
+      &metadata(),
+    )
+    .unwrap();
+    assert_eq!(created.created_by, Some(created_user.id.clone()));
+    assert_eq!(created.max_uses, 1);
+  }
+
+  assert_eq!(
+    invite::create_user_invite(
+      &mut conn,
+      &created_user.id,
+      2,
+      invite::CreateUserInvite::default(),
+      &metadata(),
+    )
+    .err(),
+    Some(EphemerideError::InviteQuotaExceeded)
+  );
+
+  let invites = invite::get_user_invites(&mut conn, &created_user.id, 2).unwrap();
+  assert_eq!(invites.invites.len(), 2);
+  assert_eq!(invites.remaining, 0);
+}
+
+#[test]
+fn lists_who_redeemed_a_users_invites() {
+  let mut conn = establish_connection();
+
+  let inviter = create_user(&mut conn, None);
+  let created = invite::create_user_invite(
+    &mut conn,
+    &inviter.id,
+    5,
+    invite::CreateUserInvite {
+      note: Some("for my sister".to_string()),
+      ..Default::default()
+    },
+    &metadata(),
+  )
+  .unwrap();
+  let unused = invite::create_user_invite(
+    &mut conn,
+    &inviter.id,
+    5,
+    invite::CreateUserInvite::default(),
+    &metadata(),
+  )
+  .unwrap();
+
+  let redeemed = invite::use_invite(&mut conn, &created.code).unwrap();
+  let invited = create_user(&mut conn, Some(redeemed.id));
+
+  let invites = invite::get_user_invites(&mut conn, &inviter.id, 5).unwrap();
+  assert_eq!(invites.quota, 5);
+  assert_eq!(invites.remaining, 3);
+
+  let listed = invites
+    .invites
+    .iter()
+    .find(|user_invite| user_invite.invite.id == created.id)
+    .unwrap();
+  assert_eq!(listed.invite.use_count, 1);
+  assert_eq!(listed.redeemed_by.len(), 1);
+  assert_eq!(listed.redeemed_by[0].id, invited.id);
+
+  let listed = invites
+    .invites
+    .iter()
+    .find(|user_invite| user_invite.invite.id == unused.id)
+    .unwrap();
+  assert!(listed.redeemed_by.is_empty());
+
+  // nobody else sees them
+  let other = create_user(&mut conn, None);
+  assert!(invite::get_user_invites(&mut conn, &other.id, 5)
+    .unwrap()
+    .invites
+    .is_empty());
+}
+
+#[test]
+fn admin_invites_dont_count_against_the_user_quota() {
+  let mut conn = establish_connection();
+
+  let admin = create_user(&mut conn, None);
+
+  // made through POST /v1/admin/invites
+  for _ in 0..3 {
+    invite::create_invite(&mut conn, Some(&admin.id), invite::CreateInvite::default()).unwrap();
+  }
+
+  let invites = invite::get_user_invites(&mut conn, &admin.id, 2).unwrap();
+  assert!(invites.invites.is_empty());
+  assert_eq!(invites.remaining, 2);
+
+  let created = invite::create_user_invite(
+    &mut conn,
+    &admin.id,
+    2,
+    invite::CreateUserInvite::default(),
+    &metadata(),
+  )
+  .unwrap();
+  assert!(created.user_invite);
+
+  let invites = invite::get_user_invites(&mut conn, &admin.id, 2).unwrap();
+  assert_eq!(invites.invites.len(), 1);
+  assert_eq!(invites.remaining, 1);
 }
@@ -1,10 +1,12 @@
+use diesel::PgConnection;
 use ephemeride_backend::{
+  establish_connection,
   services::{log, user},
   util::EphemerideError,
 };
 use uuid::Uuid;
 
-fn create_user() -> user::UserDetails {
+fn create_user(conn: &mut PgConnection) -> user::UserDetails {
   let random_name = Uuid::new_v4().to_string();
   let email = format!("{random_name}@example.com");
 
@@ -15,17 +17,22 @@ fn create_user() -> user::UserDetails {
     invite: None,
   };
 
-  user::create_user(user_data).expect("Failed to create test user")
+  user::create_user(conn, user_data).expect("Failed to create test user")
 }
 
 #[test]
 fn create_category() {
-  let user = create_user();
+  let mut conn = establish_connection();
 
-  let category = log::create_category(log::CreateCategory {
-    name: "Test Category".to_string(),
-    user_id: user.id.clone(),
-  });
+  let user = create_user(&mut conn);
+
+  let category = log::create_category(
+    &mut conn,
+    log::CreateCategory {
+      name: "Test Category".to_string(),
+      user_id: user.id.clone(),
+    },
+  );
 
   assert!(category.is_ok());
   let category = category.unwrap();
@@ -35,19 +42,27 @@ fn create_category() {
 
 #[test]
 fn edit_category() {
-  let user = create_user();
+  let mut conn = establish_connection();
 
-  let category = log::create_category(log::CreateCategory {
-    name: "Original Name".to_string(),
-    user_id: user.id.clone(),
-  })
+  let user = create_user(&mut conn);
+
+  let category = log::create_category(
+    &mut conn,
+    log::CreateCategory {
+      name: "Original Name".to_string(),
+      user_id: user.id.clone(),
+    },
+  )
   .unwrap();
 
-  let edited = log::edit_category(log::EditCategory {
-    id: category.id.clone(),
-    name: "Updated Name".to_string(),
-    user_id: user.id.clone(),
-  });
+  let edited = log::edit_category(
+    &mut conn,
+    log::EditCategory {
+      id: category.id.clone(),
+      name: "Updated Name".to_string(),
+      user_id: user.id.clone(),
+    },
+  );
 
   assert!(edited.is_ok());
   let edited = edited.unwrap();
@@ -57,15 +72,20 @@ fn edit_category() {
 
 #[test]
 fn get_category() {
-  let user = create_user();
+  let mut conn = establish_connection();
 
-  let category = log::create_category(log::CreateCategory {
-    name: "Get Test".to_string(),
-    user_id: user.id.clone(),
-  })
+  let user = create_user(&mut conn);
+
+  let category = log::create_category(
+    &mut conn,
+    log::CreateCategory {
+      name: "Get Test".to_string(),
+      user_id: user.id.clone(),
+    },
+  )
   .unwrap();
 
-  let found = log::get_category(&category.id, &user.id);
+  let found = log::get_category(&mut conn, &category.id, &user.id);
 
   assert!(found.is_ok());
   let found = found.unwrap();
@@ -75,20 +95,28 @@ fn get_category() {
 
 #[test]
 fn get_all_categories() {
-  let user = create_user();
+  let mut conn = establish_connection();
 
-  log::create_category(log::CreateCategory {
-    name: "Category 1".to_string(),
-    user_id: user.id.clone(),
-  })
+  let user = create_user(&mut conn);
+
+  log::create_category(
+    &mut conn,
+    log::CreateCategory {
+      name: "Category 1".to_string(),
+      user_id: user.id.clone(),
+    },
+  )
   .unwrap();
-  log::create_category(log::CreateCategory {
-    name: "Category 2".to_string(),
-    user_id: user.id.clone(),
-  })
+  log::create_category(
+    &mut conn,
+    log::CreateCategory {
+      name: "Category 2".to_string(),
+      user_id: user.id.clone(),
+    },
+  )
   .unwrap();
 
-  let categories = log::get_all_categories(&user.id);
+  let categories = log::get_all_categories(&mut conn, &user.id);
 
   assert!(categories.is_ok());
   let categories = categories.unwrap();
@@ -97,29 +125,40 @@ fn get_all_categories() {
 
 #[test]
 fn get_category_with_tags() {
-  let user = create_user();
+  let mut conn = establish_connection();
 
-  let category = log::create_category(log::CreateCategory {
-    name: "Category with Tags".to_string(),
-    user_id: user.id.clone(),
-  })
-  .unwrap();
-  log::create_tag(log::CreateTag {
-    name: "Tag 1".to_string(),
-    color: "blue".to_string(),
-    category_id: category.id.clone(),
-    user_id: user.id.clone(),
-  })
-  .unwrap();
-  log::create_tag(log::CreateTag {
-    name: "Tag 2".to_string(),
-    color: "red".to_string(),
-    category_id: category.id.clone(),
-    user_id: user.id.clone(),
-  })
+  let user = create_user(&mut conn);
+
+  let category = log::create_category(
+    &mut conn,
+    log::CreateCategory {
+      name: "Category with Tags".to_string(),
+      user_id: user.id.clone(),
+    },
+  )
+  .unwrap();
+  log::create_tag(
+    &mut conn,
+    log::CreateTag {
+      name: "Tag 1".to_string(),
+      color: "blue".to_string(),
+      category_id: category.id.clone(),
+      user_id: user.id.clone(),
+    },
+  )
+  .unwrap();
+  log::create_tag(
+    &mut conn,
+    log::CreateTag {
+      name: "Tag 2".to_string(),
+      color: "red".to_string(),
+      category_id: category.id.clone(),
+      user_id: user.id.clone(),
+    },
+  )
   .unwrap();
 
-  let category_with_tags = log::get_category_with_tags(&category.id, &user.id);
+  let category_with_tags = log::get_category_with_tags(&mut conn, &category.id, &user.id);
 
   assert!(category_with_tags.is_ok());
   let category_with_tags = category_with_tags.unwrap();
@@ -129,9 +168,11 @@ fn get_category_with_tags() {
 
 #[test]
 fn get_category_with_tags_not_found() {
-  let user = create_user();
+  let mut conn = establish_connection();
 
-  let result = log::get_category_with_tags("nonexistent_id", &user.id);
+  let user = create_user(&mut conn);
+
+  let result = log::get_category_with_tags(&mut conn, "nonexistent_id", &user.id);
 
   assert!(result.is_err());
   assert_eq!(result.err().unwrap(), EphemerideError::CategoryNotFound);
@@ -139,35 +180,49 @@ fn get_category_with_tags_not_found() {
 
 #[test]
 fn get_user_categories_with_tags() {
-  let user = create_user();
+  let mut conn = establish_connection();
+
+  let user = create_user(&mut conn);
 
-  let cat1 = log::create_category(log::CreateCategory {
-    name: "Category 1".to_string(),
-    user_id: user.id.clone(),
-  })
+  let cat1 = log::create_category(
+    &mut conn,
+    log::CreateCategory {
+      name: "Category 1".to_string(),
+      user_id: user.id.clone(),
+    },
+  )
   .unwrap();
-  let cat2 = log::create_category(log::CreateCategory {
-    name: "Category 2".to_string(),
-    user_id: user.id.clone(),
-  })
+  let cat2 = log::create_category(
+    &mut conn,
+    log::CreateCategory {
+      name: "Category 2".to_string(),
+      user_id: user.id.clone(),
+    },
+  )
   .unwrap();
 
-  log::create_tag(log::CreateTag {
-    name: "Tag 1".to_string(),
-    color: "blue".to_string(),
-    category_id: cat1.id.clone(),
-    user_id: user.id.clone(),
-  })
-  .unwrap();
-  log::create_tag(log::CreateTag {
-    name: "Tag 2".to_string(),
-    color: "red".to_string(),
-    category_id: cat2.id.clone(),
-    user_id: user.id.clone(),
-  })
+  log::create_tag(
+    &mut conn,
+    log::CreateTag {
+      name: "Tag 1".to_string(),
+      color: "blue".to_string(),
+      category_id: cat1.id.clone(),
+      user_id: user.id.clone(),
+    },
+  )
+  .unwrap();
+  log::create_tag(
+    &mut conn,
+    log::CreateTag {
+      name: "Tag 2".to_string(),
+      color: "red".to_string(),
+      category_id: cat2.id.clone(),
+      user_id: user.id.clone(),
+    },
+  )
   .unwrap();
 
-  let categories_with_tags = log::get_user_categories_with_tags(&user.id);
+  let categories_with_tags = log::get_user_categories_with_tags(&mut conn, &user.id);
 
   assert!(categories_with_tags.is_ok());
   let categories_with_tags = categories_with_tags.unwrap();
@@ -182,45 +237,61 @@ fn get_user_categories_with_tags() {
 
 #[test]
 fn delete_category() {
-  let user = create_user();
+  let mut conn = establish_connection();
 
-  let category = log::create_category(log::CreateCategory {
-    name: "To Delete".to_string(),
-    user_id: user.id.clone(),
-  })
-  .unwrap();
-  log::create_tag(log::CreateTag {
-    name: "Tag".to_string(),
-    color: "blue".to_string(),
-    category_id: category.id.clone(),
-    user_id: user.id.clone(),
-  })
+  let user = create_user(&mut conn);
+
+  let category = log::create_category(
+    &mut conn,
+    log::CreateCategory {
+      name: "To Delete".to_string(),
+      user_id: user.id.clone(),
+    },
+  )
+  .unwrap();
+  log::create_tag(
+    &mut conn,
+    log::CreateTag {
+      name: "Tag".to_string(),
+      color: "blue".to_string(),
+      category_id: category.id.clone(),
+      user_id: user.id.clone(),
+    },
+  )
   .unwrap();
 
-  let deleted = log::delete_category(&category.id, &user.id);
+  let deleted = log::delete_category(&mut conn, &category.id, &user.id);
 
   assert!(deleted.is_ok());
   assert!(deleted.unwrap());
 
-  let found = log::get_category(&category.id, &user.id);
+  let found = log::get_category(&mut conn, &category.id, &user.id);
   assert!(found.is_err());
 }
 
 #[test]
 fn create_tag() {
-  let user = create_user();
-  let category = log::create_category(log::CreateCategory {
-    name: "Test Category".to_string(),
-    user_id: user.id.clone(),
-  })
+  let mut conn = establish_connection();
+
+  let user = create_user(&mut conn);
+  let category = log::create_category(
+    &mut conn,
+    log::CreateCategory {
+      name: "Test Category".to_string(),
+      user_id: user.id.clone(),
+    },
+  )
   .unwrap();
 
-  let tag = log::create_tag(log::CreateTag {
-    name: "Test Tag".to_string(),
-    color: "blue".to_string(),
-    category_id: category.id.clone(),
-    user_id: user.id.clone(),
-  });
+  let tag = log::create_tag(
+    &mut conn,
+    log::CreateTag {
+      name: "Test Tag".to_string(),
+      color: "blue".to_string(),
+      category_id: category.id.clone(),
+      user_id: user.id.clone(),
+    },
+  );
 
   assert!(tag.is_ok());
   let tag = tag.unwrap();
@@ -231,26 +302,37 @@ fn create_tag() {
 
 #[test]
 fn edit_tag() {
-  let user = create_user();
-  let category = log::create_category(log::CreateCategory {
-    name: "Test Category".to_string(),
-    user_id: user.id.clone(),
-  })
-  .unwrap();
-  let tag = log::create_tag(log::CreateTag {
-    name: "Original".to_string(),
-    color: "blue".to_string(),
-    category_id: category.id.clone(),
-    user_id: user.id.clone(),
-  })
-  .unwrap();
-
-  let edited = log::edit_tag(log::EditTag {
-    id: tag.id.clone(),
-    name: "Updated".to_string(),
-    color: "red".to_string(),
-    user_id: user.id.clone(),
-  });
+  let mut conn = establish_connection();
+
+  let user = create_user(&mut conn);
+  let category = log::create_category(
+    &mut conn,
+    log::CreateCategory {
+      name: "Test Category".to_string(),
+      user_id: user.id.clone(),
+    },
+  )
+  .unwrap();
+  let tag = log::create_tag(
+    &mut conn,
+    log::CreateTag {
+      name: "Original".to_string(),
+      color: "blue".to_string(),
+      category_id: category.id.clone(),
+      user_id: user.id.clone(),
+    },
+  )
+  .unwrap();
+
+  let edited = log::edit_tag(
+    &mut conn,
+    log::EditTag {
+      id: tag.id.clone(),
+      name: "Updated".to_string(),
+      color: "red".to_string(),
+      user_id: user.id.clone(),
+    },
+  );
 
   assert!(edited.is_ok());
   let edited = edited.unwrap();
@@ -260,21 +342,29 @@ fn edit_tag() {
 
 #[test]
 fn get_tag() {
-  let user = create_user();
-  let category = log::create_category(log::CreateCategory {
-    name: "Test Category".to_string(),
-    user_id: user.id.clone(),
-  })
-  .unwrap();
-  let tag = log::create_tag(log::CreateTag {
-    name: "Test Tag".to_string(),
-    color: "blue".to_string(),
-    category_id: category.id.clone(),
-    user_id: user.id.clone(),
-  })
+  let mut conn = establish_connection();
+
+  let user = create_user(&mut conn);
+  let category = log::create_category(
+    &mut conn,
+    log::CreateCategory {
+      name: "Test Category".to_string(),
+      user_id: user.id.clone(),
+    },
+  )
+  .unwrap();
+  let tag = log::create_tag(
+    &mut conn,
+    log::CreateTag {
+      name: "Test Tag".to_string(),
+      color: "blue".to_string(),
+      category_id: category.id.clone(),
+      user_id: user.id.clone(),
+    },
+  )
   .unwrap();
 
-  let found = log::get_tag(&tag.id, &user.id);
+  let found = log::get_tag(&mut conn, &tag.id, &user.id);
 
   assert!(found.is_ok());
   let found = found.unwrap();
@@ -284,29 +374,40 @@ fn get_tag() {
 
 #[test]
 fn get_tags() {
-  let user = create_user();
-  let category = log::create_category(log::CreateCategory {
-    name: "Test Category".to_string(),
-    user_id: user.id.clone(),
-  })
-  .unwrap();
-  let tag1 = log::create_tag(log::CreateTag {
-    name: "Tag 1".to_string(),
-    color: "blue".to_string(),
-    category_id: category.id.clone(),
-    user_id: user.id.clone(),
-  })
-  .unwrap();
-  let tag2 = log::create_tag(log::CreateTag {
-    name: "Tag 2".to_string(),
-    color: "red".to_string(),
-    category_id: category.id.clone(),
-    user_id: user.id.clone(),
-  })
+  let mut conn = establish_connection();
+
+  let user = create_user(&mut conn);
+  let category = log::create_category(
+    &mut conn,
+    log::CreateCategory {
+      name: "Test Category".to_string(),
+      user_id: user.id.clone(),
+    },
+  )
+  .unwrap();
+  let tag1 = log::create_tag(
+    &mut conn,
+    log::CreateTag {
+      name: "Tag 1".to_string(),
+      color: "blue".to_string(),
+      category_id: category.id.clone(),
+      user_id: user.id.clone(),
+    },
+  )
+  .unwrap();
+  let tag2 = log::create_tag(
+    &mut conn,
+    log::CreateTag {
+      name: "Tag 2".to_string(),
+      color: "red".to_string(),
+      category_id: category.id.clone(),
+      user_id: user.id.clone(),
+    },
+  )
   .unwrap();
 
   let tag_ids = vec![tag1.id.as_str(), tag2.id.as_str()];
-  let tags = log::get_tags(tag_ids, &user.id);
+  let tags = log::get_tags(&mut conn, tag_ids, &user.id);
 
   assert!(tags.is_ok());
   let tags = tags.unwrap();
@@ -315,28 +416,39 @@ fn get_tags() {
 
 #[test]
 fn get_category_tags() {
-  let user = create_user();
-  let category = log::create_category(log::CreateCategory {
-    name: "Test Category".to_string(),
-    user_id: user.id.clone(),
-  })
-  .unwrap();
-  log::create_tag(log::CreateTag {
-    name: "Tag 1".to_string(),
-    color: "blue".to_string(),
-    category_id: category.id.clone(),
-    user_id: user.id.clone(),
-  })
-  .unwrap();
-  log::create_tag(log::CreateTag {
-    name: "Tag 2".to_string(),
-    color: "red".to_string(),
-    category_id: category.id.clone(),
-    user_id: user.id.clone(),
-  })
+  let mut conn = establish_connection();
+
+  let user = create_user(&mut conn);
+  let category = log::create_category(
+    &mut conn,
+    log::CreateCategory {
+      name: "Test Category".to_string(),
+      user_id: user.id.clone(),
+    },
+  )
+  .unwrap();
+  log::create_tag(
+    &mut conn,
+    log::CreateTag {
+      name: "Tag 1".to_string(),
+      color: "blue".to_string(),
+      category_id: category.id.clone(),
+      user_id: user.id.clone(),
+    },
+  )
+  .unwrap();
+  log::create_tag(
+    &mut conn,
+    log::CreateTag {
+      name: "Tag 2".to_string(),
+      color: "red".to_string(),
+      category_id: category.id.clone(),
+      user_id: user.id.clone(),
+    },
+  )
   .unwrap();
 
-  let tags = log::get_category_tags(&category.id, &user.id);
+  let tags = log::get_category_tags(&mut conn, &category.id, &user.id);
 
   assert!(tags.is_ok());
   let tags = tags.unwrap();
@@ -345,85 +457,115 @@ fn get_category_tags() {
 
 #[test]
 fn delete_tag() {
-  let user = create_user();
-  let category = log::create_category(log::CreateCategory {
-    name: "Test Category".to_string(),
-    user_id: user.id.clone(),
-  })
-  .unwrap();
-  let tag = log::create_tag(log::CreateTag {
-    name: "To Delete".to_string(),
-    color: "blue".to_string(),
-    category_id: category.id.clone(),
-    user_id: user.id.clone(),
-  })
+  let mut conn = establish_connection();
+
+  let user = create_user(&mut conn);
+  let category = log::create_category(
+    &mut conn,
+    log::CreateCategory {
+      name: "Test Category".to_string(),
+      user_id: user.id.clone(),
+    },
+  )
+  .unwrap();
+  let tag = log::create_tag(
+    &mut conn,
+    log::CreateTag {
+      name: "To Delete".to_string(),
+      color: "blue".to_string(),
+      category_id: category.id.clone(),
+      user_id: user.id.clone(),
+    },
+  )
   .unwrap();
 
-  let deleted = log::delete_tag(&tag.id, &user.id);
+  let deleted = log::delete_tag(&mut conn, &tag.id, &user.id);
 
   assert!(deleted.is_ok());
   assert!(deleted.unwrap());
 
-  let found = log::get_tag(&tag.id, &user.id);
+  let found = log::get_tag(&mut conn, &tag.id, &user.id);
   assert!(found.is_err());
 }
 
 #[test]
 fn delete_all_category_tags() {
-  let user = create_user();
-  let category = log::create_category(log::CreateCategory {
-    name: "Test Category".to_string(),
-    user_id: user.id.clone(),
-  })
-  .unwrap();
-  log::create_tag(log::CreateTag {
-    name: "Tag 1".to_string(),
-    color: "blue".to_string(),
-    category_id: category.id.clone(),
-    user_id: user.id.clone(),
-  })
-  .unwrap();
-  log::create_tag(log::CreateTag {
-    name: "Tag 2".to_string(),
-    color: "red".to_string(),
-    category_id: category.id.clone(),
-    user_id: user.id.clone(),
-  })
+  let mut conn = establish_connection();
+
+  let user = create_user(&mut conn);
+  let category = log::create_category(
+    &mut conn,
+    log::CreateCategory {
+      name: "Test Category".to_string(),
+      user_id: user.id.clone(),
+    },
+  )
+  .unwrap();
+  log::create_tag(
+    &mut conn,
+    log::CreateTag {
+      name: "Tag 1".to_string(),
+      color: "blue".to_string(),
+      category_id: category.id.clone(),
+      user_id: user.id.clone(),
+    },
+  )
+  .unwrap();
+  log::create_tag(
+    &mut conn,
+    log::CreateTag {
+      name: "Tag 2".to_string(),
+      color: "red".to_string(),
+      category_id: category.id.clone(),
+      user_id: user.id.clone(),
+    },
+  )
   .unwrap();
 
-  let deleted = log::delete_all_category_tags(&category.id, &user.id);
+  let deleted = log::delete_all_category_tags(&mut conn, &category.id, &user.id);
 
   assert!(deleted.is_ok());
   assert!(deleted.unwrap());
 
-  let tags = log::get_category_tags(&category.id, &user.id);
+  let tags = log::get_category_tags(&mut conn, &category.id, &user.id);
   assert!(tags.is_ok());
   assert_eq!(tags.unwrap().len(), 0);
 }
 
 #[test]
 fn create_entry() {
-  let user = create_user();
-  let category = log::create_category(log::CreateCategory {
-    name: "Test Category".to_string(),
-    user_id: user.id.clone(),
-  })
-  .unwrap();
-  let tag = log::create_tag(log::CreateTag {
-    name: "Test Tag".to_string(),
-    color: "blue".to_string(),
-    category_id: category.id.clone(),
-    user_id: user.id.clone(),
-  })
-  .unwrap();
-
-  let entry = log::create_entry(log::CreateEntry {
-    date: "2025-10-17".to_string(),
-    mood: 5,
-    entry: Some("Test entry content".to_string()),
-    selected_tags: vec![tag.id.clone()],
-    user_id: user.id.clone(),
-  });
+  let mut conn = establish_connection();
+
+  let user = create_user(&mut conn);
+  let category = log::create_category(
+    &mut conn,
+    log::CreateCategory {
+      name: "Test Category".to_string(),
+      user_id: user.id.clone(),
+    },
+  )
+  .unwrap();
+  let tag = log::create_tag(
+    &mut conn,
+    log::CreateTag {
+      name: "Test Tag".to_string(),
+      color: "blue".to_string(),
+      category_id: category.id.clone(),
+      user_id: user.id.clone(),
+    },
+  )
+  .unwrap();
+
+  let entry = log::create_entry(
+    &mut conn,
+    log::CreateEntry {
+      date: "2025-10-17".to_string(),
+      mood: 5,
+      entry: Some("Test entry content".to_string()),
+      selected_tags: vec![tag.id.clone()],
+      user_id: user.id.clone(),
+    },
+  );
 
   assert!(entry.is_ok());
   let entry = entry.unwrap();
@@ -439,15 +581,20 @@ fn create_entry() {
 
 #[test]
 fn create_entry_without_content() {
-  let user = create_user();
+  let mut conn = establish_connection();
 
-  let entry = log::create_entry(log::CreateEntry {
-    date: "2025-10-17".to_string(),
-    mood: 3,
-    entry: None,
-    selected_tags: vec![],
-    user_id: user.id.clone(),
-  });
+  let user = create_user(&mut conn);
+
+  let entry = log::create_entry(
+    &mut conn,
+    log::CreateEntry {
+      date: "2025-10-17".to_string(),
+      mood: 3,
+      entry: None,
+      selected_tags: vec![],
+      user_id: user.id.clone(),
+    },
+  );
 
   assert!(entry.is_ok());
   let entry = entry.unwrap();
@@ -457,44 +604,61 @@ fn create_entry_without_content() {
 
 #[test]
 fn edit_entry() {
-  let user = create_user();
-  let category = log::create_category(log::CreateCategory {
-    name: "Test Category".to_string(),
-    user_id: user.id.clone(),
-  })
-  .unwrap();
-  let tag1 = log::create_tag(log::CreateTag {
-    name: "Tag 1".to_string(),
-    color: "blue".to_string(),
-    category_id: category.id.clone(),
-    user_id: user.id.clone(),
-  })
-  .unwrap();
-  let tag2 = log::create_tag(log::CreateTag {
-    name: "Tag 2".to_string(),
-    color: "red".to_string(),
-    category_id: category.id.clone(),
-    user_id: user.id.clone(),
-  })
-  .unwrap();
-
-  let entry = log::create_entry(log::CreateEntry {
-    date: "2025-10-17".to_string(),
-    mood: 3,
-    entry: Some("Original content".to_string()),
-    selected_tags: vec![tag1.id.clone()],
-    user_id: user.id.clone(),
-  })
-  .unwrap();
-
-  let edited = log::edit_entry(log::EditEntry {
-    id: entry.id.clone(),
-    date: "2025-10-18".to_string(),
-    mood: 4,
-    entry: Some("Updated content".to_string()),
-    selected_tags: vec![tag2.id.clone()],
-    user_id: user.id.clone(),
-  });
+  let mut conn = establish_connection();
+
+  let user = create_user(&mut conn);
+  let category = log::create_category(
+    &mut conn,
+    log::CreateCategory {
+      name: "Test Category".to_string(),
+      user_id: user.id.clone(),
+    },
+  )
+  .unwrap();
+  let tag1 = log::create_tag(
+    &mut conn,
+    log::CreateTag {
+      name: "Tag 1".to_string(),
+      color: "blue".to_string(),
+      category_id: category.id.clone(),
+      user_id: user.id.clone(),
+    },
+  )
+  .unwrap();
+  let tag2 = log::create_tag(
+    &mut conn,
+    log::CreateTag {
+      name: "Tag 2".to_string(),
+      color: "red".to_string(),
+      category_id: category.id.clone(),
+      user_id: user.id.clone(),
+    },
+  )
+  .unwrap();
+
+  let entry = log::create_entry(
+    &mut conn,
+    log::CreateEntry {
+      date: "2025-10-17".to_string(),
+      mood: 3,
+      entry: Some("Original content".to_string()),
+      selected_tags: vec![tag1.id.clone()],
+      user_id: user.id.clone(),
+    },
+  )
+  .unwrap();
+
+  let edited = log::edit_entry(
+    &mut conn,
+    log::EditEntry {
+      id: entry.id.clone(),
+      date: "2025-10-18".to_string(),
+      mood: 4,
+      entry: Some("Updated content".to_string()),
+      selected_tags: vec![tag2.id.clone()],
+      user_id: user.id.clone(),
+    },
+  );
 
   assert!(edited.is_ok());
   let edited = edited.unwrap();
@@ -510,30 +674,41 @@ fn edit_entry() {
 
 #[test]
 fn get_entry_with_tags() {
-  let user = create_user();
-  let category = log::create_category(log::CreateCategory {
-    name: "Test Category".to_string(),
-    user_id: user.id.clone(),
-  })
-  .unwrap();
-  let tag = log::create_tag(log::CreateTag {
-    name: "Test Tag".to_string(),
-    color: "blue".to_string(),
-    category_id: category.id.clone(),
-    user_id: user.id.clone(),
-  })
+  let mut conn = establish_connection();
+
+  let user = create_user(&mut conn);
+  let category = log::create_category(
+    &mut conn,
+    log::CreateCategory {
+      name: "Test Category".to_string(),
+      user_id: user.id.clone(),
+    },
+  )
+  .unwrap();
+  let tag = log::create_tag(
+    &mut conn,
+    log::CreateTag {
+      name: "Test Tag".to_string(),
+      color: "blue".to_string(),
+      category_id: category.id.clone(),
+      user_id: user.id.clone(),
+    },
+  )
   .unwrap();
 
-  let entry = log::create_entry(log::CreateEntry {
-    date: "2025-10-17".to_string(),
-    mood: 5,
-    entry: Some("Test entry".to_string()),
-    selected_tags: vec![tag.id.clone()],
-    user_id: user.id.clone(),
-  })
+  let entry = log::create_entry(
+    &mut conn,
+    log::CreateEntry {
+      date: "2025-10-17".to_string(),
+      mood: 5,
+      entry: Some("Test entry".to_string()),
+      selected_tags: vec![tag.id.clone()],
+      user_id: user.id.clone(),
+    },
+  )
   .unwrap();
 
-  let found = log::get_entry_with_tags(&entry.id, &user.id);
+  let found = log::get_entry_with_tags(&mut conn, &entry.id, &user.id);
 
   assert!(found.is_ok());
   let found = found.unwrap();
@@ -544,13 +719,15 @@ fn get_entry_with_tags() {
 
 #[test]
 fn create_default_data() {
-  let user = create_user();
+  let mut conn = establish_connection();
+
+  let user = create_user(&mut conn);
 
-  let result = log::create_default_data(user.id.clone());
+  let result = log::create_default_data(&mut conn, user.id.clone());
 
   assert!(result.is_ok());
 
-  let categories = log::get_all_categories(&user.id).unwrap();
+  let categories = log::get_all_categories(&mut conn, &user.id).unwrap();
   assert!(categories.len() >= 2);
 
   let activities_cat = categories.iter().find(|c| c.name == "Activities");
@@ -560,12 +737,12 @@ fn create_default_data() {
   assert!(tags_cat.is_some());
 
   if let Some(activities) = activities_cat {
-    let tags = log::get_category_tags(&activities.id, &user.id).unwrap();
+    let tags = log::get_category_tags(&mut conn, &activities.id, &user.id).unwrap();
     assert!(tags.len() >= 6);
   }
 
   if let Some(tags) = tags_cat {
-    let tag_list = log::get_category_tags(&tags.id, &user.id).unwrap();
+    let tag_list = log::get_category_tags(&mut conn, &tags.id, &user.id).unwrap();
     assert!(tag_list.len() >= 3);
   }
 }
@@ -574,476 +751,654 @@ fn create_default_data() {
 
 #[test]
 fn create_category_empty_name() {
-  let user = create_user();
+  let mut conn = establish_connection();
 
-  let category = log::create_category(log::CreateCategory {
-    name: "".to_string(),
-    user_id: user.id.clone(),
-  });
+  let user = create_user(&mut conn);
+
+  let category = log::create_category(
+    &mut conn,
+    log::CreateCategory {
+      name: "".to_string(),
+      user_id: user.id.clone(),
+    },
+  );
 
   assert!(category.is_err());
 }
 
 #[test]
 fn create_category_name_too_long() {
-  let user = create_user();
+  let mut conn = establish_connection();
+
+  let user = create_user(&mut conn);
 
   let long_name = "a".repeat(256);
-  let category = log::create_category(log::CreateCategory {
-    name: long_name,
-    user_id: user.id.clone(),
-  });
+  let category = log::create_category(
+    &mut conn,
+    log::CreateCategory {
+      name: long_name,
+      user_id: user.id.clone(),
+    },
+  );
 
   assert!(category.is_err());
 }
 
 #[test]
 fn edit_category_empty_name() {
-  let user = create_user();
-  let category = log::create_category(log::CreateCategory {
-    name: "Original Name".to_string(),
-    user_id: user.id.clone(),
-  })
+  let mut conn = establish_connection();
+
+  let user = create_user(&mut conn);
+  let category = log::create_category(
+    &mut conn,
+    log::CreateCategory {
+      name: "Original Name".to_string(),
+      user_id: user.id.clone(),
+    },
+  )
   .unwrap();
 
-  let edited = log::edit_category(log::EditCategory {
-    id: category.id.clone(),
-    name: "".to_string(),
-    user_id: user.id.clone(),
-  });
+  let edited = log::edit_category(
+    &mut conn,
+    log::EditCategory {
+      id: category.id.clone(),
+      name: "".to_string(),
+      user_id: user.id.clone(),
+    },
+  );
 
   assert!(edited.is_err());
 }
 
 #[test]
 fn edit_category_name_too_long() {
-  let user = create_user();
-  let category = log::create_category(log::CreateCategory {
-    name: "Original Name".to_string(),
-    user_id: user.id.clone(),
-  })
+  let mut conn = establish_connection();
+
+  let user = create_user(&mut conn);
+  let category = log::create_category(
+    &mut conn,
+    log::CreateCategory {
+      name: "Original Name".to_string(),
+      user_id: user.id.clone(),
+    },
+  )
   .unwrap();
 
   let long_name = "a".repeat(256);
-  let edited = log::edit_category(log::EditCategory {
-    id: category.id.clone(),
-    name: long_name,
-    user_id: user.id.clone(),
-  });
+  let edited = log::edit_category(
+    &mut conn,
+    log::EditCategory {
+      id: category.id.clone(),
+      name: long_name,
+      user_id: user.id.clone(),
+    },
+  );
 
   assert!(edited.is_err());
 }
 
 #[test]
 fn create_tag_empty_name() {
-  let user = create_user();
-  let category = log::create_category(log::CreateCategory {
-    name: "Test Category".to_string(),
-    user_id: user.id.clone(),
-  })
+  let mut conn = establish_connection();
+
+  let user = create_user(&mut conn);
+  let category = log::create_category(
+    &mut conn,
+    log::CreateCategory {
+      name: "Test Category".to_string(),
+      user_id: user.id.clone(),
+    },
+  )
   .unwrap();
 
-  let tag = log::create_tag(log::CreateTag {
-    name: "".to_string(),
-    color: "blue".to_string(),
-    category_id: category.id.clone(),
-    user_id: user.id.clone(),
-  });
+  let tag = log::create_tag(
+    &mut conn,
+    log::CreateTag {
+      name: "".to_string(),
+      color: "blue".to_string(),
+      category_id: category.id.clone(),
+      user_id: user.id.clone(),
+    },
+  );
 
   assert!(tag.is_err());
 }
 
 #[test]
 fn create_tag_name_too_long() {
-  let user = create_user();
-  let category = log::create_category(log::CreateCategory {
-    name: "Test Category".to_string(),
-    user_id: user.id.clone(),
-  })
+  let mut conn = establish_connection();
+
+  let user = create_user(&mut conn);
+  let category = log::create_category(
+    &mut conn,
+    log::CreateCategory {
+      name: "Test Category".to_string(),
+      user_id: user.id.clone(),
+    },
+  )
   .unwrap();
 
   let long_name = "a".repeat(256);
-  let tag = log::create_tag(log::CreateTag {
-    name: long_name,
-    color: "blue".to_string(),
-    category_id: category.id.clone(),
-    user_id: user.id.clone(),
-  });
+  let tag = log::create_tag(
+    &mut conn,
+    log::CreateTag {
+      name: long_name,
+      color: "blue".to_string(),
+      category_id: category.id.clone(),
+      user_id: user.id.clone(),
+    },
+  );
 
   assert!(tag.is_err());
 }
 
 #[test]
 fn create_tag_empty_color() {
-  let user = create_user();
-  let category = log::create_category(log::CreateCategory {
-    name: "Test Category".to_string(),
-    user_id: user.id.clone(),
-  })
+  let mut conn = establish_connection();
+
+  let user = create_user(&mut conn);
+  let category = log::create_category(
+    &mut conn,
+    log::CreateCategory {
+      name: "Test Category".to_string(),
+      user_id: user.id.clone(),
+    },
+  )
   .unwrap();
 
-  let tag = log::create_tag(log::CreateTag {
-    name: "Test Tag".to_string(),
-    color: "".to_string(),
-    category_id: category.id.clone(),
-    user_id: user.id.clone(),
-  });
+  let tag = log::create_tag(
+    &mut conn,
+    log::CreateTag {
+      name: "Test Tag".to_string(),
+      color: "".to_string(),
+      category_id: category.id.clone(),
+      user_id: user.id.clone(),
+    },
+  );
 
   assert!(tag.is_err());
 }
 
 #[test]
 fn create_tag_color_too_long() {
-  let user = create_user();
-  let category = log::create_category(log::CreateCategory {
-    name: "Test Category".to_string(),
-    user_id: user.id.clone(),
-  })
+  let mut conn = establish_connection();
+
+  let user = create_user(&mut conn);
+  let category = log::create_category(
+    &mut conn,
+    log::CreateCategory {
+      name: "Test Category".to_string(),
+      user_id: user.id.clone(),
+    },
+  )
   .unwrap();
 
   let long_color = "a".repeat(17);
-  let tag = log::create_tag(log::CreateTag {
-    name: "Test Tag".to_string(),
-    color: long_color,
-    category_id: category.id.clone(),
-    user_id: user.id.clone(),
-  });
+  let tag = log::create_tag(
+    &mut conn,
+    log::CreateTag {
+      name: "Test Tag".to_string(),
+      color: long_color,
+      category_id: category.id.clone(),
+      user_id: user.id.clone(),
+    },
+  );
 
   assert!(tag.is_err());
 }
 
 #[test]
 fn edit_tag_empty_name() {
-  let user = create_user();
-  let category = log::create_category(log::CreateCategory {
-    name: "Test Category".to_string(),
-    user_id: user.id.clone(),
-  })
-  .unwrap();
-  let tag = log::create_tag(log::CreateTag {
-    name: "Original".to_string(),
-    color: "blue".to_string(),
-    category_id: category.id.clone(),
-    user_id: user.id.clone(),
-  })
-  .unwrap();
-
-  let edited = log::edit_tag(log::EditTag {
-    id: tag.id.clone(),
-    name: "".to_string(),
-    color: "red".to_string(),
-    user_id: user.id.clone(),
-  });
+  let mut conn = establish_connection();
+
+  let user = create_user(&mut conn);
+  let category = log::create_category(
+    &mut conn,
+    log::CreateCategory {
+      name: "Test Category".to_string(),
+      user_id: user.id.clone(),
+    },
+  )
+  .unwrap();
+  let tag = log::create_tag(
+    &mut conn,
+    log::CreateTag {
+      name: "Original".to_string(),
+      color: "blue".to_string(),
+      category_id: category.id.clone(),
+      user_id: user.id.clone(),
+    },
+  )
+  .unwrap();
+
+  let edited = log::edit_tag(
+    &mut conn,
+    log::EditTag {
+      id: tag.id.clone(),
+      name: "".to_string(),
+      color: "red".to_string(),
+      user_id: user.id.clone(),
+    },
+  );
 
   assert!(edited.is_err());
 }
 
 #[test]
 fn edit_tag_name_too_long() {
-  let user = create_user();
-  let category = log::create_category(log::CreateCategory {
-    name: "Test Category".to_string(),
-    user_id: user.id.clone(),
-  })
-  .unwrap();
-  let tag = log::create_tag(log::CreateTag {
-    name: "Original".to_string(),
-    color: "blue".to_string(),
-    category_id: category.id.clone(),
-    user_id: user.id.clone(),
-  })
+  let mut conn = establish_connection();
+
+  let user = create_user(&mut conn);
+  let category = log::create_category(
+    &mut conn,
+    log::CreateCategory {
+      name: "Test Category".to_string(),
+      user_id: user.id.clone(),
+    },
+  )
+  .unwrap();
+  let tag = log::create_tag(
+    &mut conn,
+    log::CreateTag {
+      name: "Original".to_string(),
+      color: "blue".to_string(),
+      category_id: category.id.clone(),
+      user_id: user.id.clone(),
+    },
+  )
   .unwrap();
 
   let long_name = "a".repeat(256);
-  let edited = log::edit_tag(log::EditTag {
-    id: tag.id.clone(),
-    name: long_name,
-    color: "red".to_string(),
-    user_id: user.id.clone(),
-  });
+  let edited = log::edit_tag(
+    &mut conn,
+    log::EditTag {
+      id: tag.id.clone(),
+      name: long_name,
+      color: "red".to_string(),
+      user_id: user.id.clone(),
+    },
+  );
 
   assert!(edited.is_err());
 }
 
 #[test]
 fn edit_tag_empty_color() {
-  let user = create_user();
-  let category = log::create_category(log::CreateCategory {
-    name: "Test Category".to_string(),
-    user_id: user.id.clone(),
-  })
-  .unwrap();
-  let tag = log::create_tag(log::CreateTag {
-    name: "Original".to_string(),
-    color: "blue".to_string(),
-    category_id: category.id.clone(),
-    user_id: user.id.clone(),
-  })
-  .unwrap();
-
-  let edited = log::edit_tag(log::EditTag {
-    id: tag.id.clone(),
-    name: "Updated".to_string(),
-    color: "".to_string(),
-    user_id: user.id.clone(),
-  });
+  let mut conn = establish_connection();
+
+  let user = create_user(&mut conn);
+  let category = log::create_category(
+    &mut conn,
+    log::CreateCategory {
+      name: "Test Category".to_string(),
+      user_id: user.id.clone(),
+    },
+  )
+  .unwrap();
+  let tag = log::create_tag(
+    &mut conn,
+    log::CreateTag {
+      name: "Original".to_string(),
+      color: "blue".to_string(),
+      category_id: category.id.clone(),
+      user_id: user.id.clone(),
+    },
+  )
+  .unwrap();
+
+  let edited = log::edit_tag(
+    &mut conn,
+    log::EditTag {
+      id: tag.id.clone(),
+      name: "Updated".to_string(),
+      color: "".to_string(),
+      user_id: user.id.clone(),
+    },
+  );
 
   assert!(edited.is_err());
 }
 
 #[test]
 fn edit_tag_color_too_long() {
-  let user = create_user();
-  let category = log::create_category(log::CreateCategory {
-    name: "Test Category".to_string(),
-    user_id: user.id.clone(),
-  })
-  .unwrap();
-  let tag = log::create_tag(log::CreateTag {
-    name: "Original".to_string(),
-    color: "blue".to_string(),
-    category_id: category.id.clone(),
-    user_id: user.id.clone(),
-  })
+  let mut conn = establish_connection();
+
+  let user = create_user(&mut conn);
+  let category = log::create_category(
+    &mut conn,
+    log::CreateCategory {
+      name: "Test Category".to_string(),
+      user_id: user.id.clone(),
+    },
+  )
+  .unwrap();
+  let tag = log::create_tag(
+    &mut conn,
+    log::CreateTag {
+      name: "Original".to_string(),
+      color: "blue".to_string(),
+      category_id: category.id.clone(),
+      user_id: user.id.clone(),
+    },
+  )
   .unwrap();
 
   let long_color = "a".repeat(17);
-  let edited = log::edit_tag(log::EditTag {
-    id: tag.id.clone(),
-    name: "Updated".to_string(),
-    color: long_color,
-    user_id: user.id.clone(),
-  });
+  let edited = log::edit_tag(
+    &mut conn,
+    log::EditTag {
+      id: tag.id.clone(),
+      name: "Updated".to_string(),
+      color: long_color,
+      user_id: user.id.clone(),
+    },
+  );
 
   assert!(edited.is_err());
 }
 
 #[test]
 fn create_entry_empty_date() {
-  let user = create_user();
+  let mut conn = establish_connection();
 
-  let entry = log::create_entry(log::CreateEntry {
-    date: "".to_string(),
-    mood: 3,
-    entry: Some("Test content".to_string()),
-    selected_tags: vec![],
-    user_id: user.id.clone(),
-  });
+  let user = create_user(&mut conn);
+
+  let entry = log::create_entry(
+    &mut conn,
+    log::CreateEntry {
+      date: "".to_string(),
+      mood: 3,
+      entry: Some("Test content".to_string()),
+      selected_tags: vec![],
+      user_id: user.id.clone(),
+    },
+  );
 
   assert!(entry.is_err());
 }
 
 #[test]
 fn create_entry_date_too_long() {
-  let user = create_user();
+  let mut conn = establish_connection();
+
+  let user = create_user(&mut conn);
 
   let long_date = "a".repeat(256);
-  let entry = log::create_entry(log::CreateEntry {
-    date: long_date,
-    mood: 3,
-    entry: Some("Test content".to_string()),
-    selected_tags: vec![],
-    user_id: user.id.clone(),
-  });
+  let entry = log::create_entry(
+    &mut conn,
+    log::CreateEntry {
+      date: long_date,
+      mood: 3,
+      entry: Some("Test content".to_string()),
+      selected_tags: vec![],
+      user_id: user.id.clone(),
+    },
+  );
 
   assert!(entry.is_err());
 }
 
 #[test]
 fn create_entry_mood_too_low() {
-  let user = create_user();
+  let mut conn = establish_connection();
 
-  let entry = log::create_entry(log::CreateEntry {
-    date: "2025-10-17".to_string(),
-    mood: 0,
-    entry: Some("Test content".to_string()),
-    selected_tags: vec![],
-    user_id: user.id.clone(),
-  });
+  let user = create_user(&mut conn);
+
+  let entry = log::create_entry(
+    &mut conn,
+    log::CreateEntry {
+      date: "2025-10-17".to_string(),
+      mood: 0,
+      entry: Some("Test content".to_string()),
+      selected_tags: vec![],
+      user_id: user.id.clone(),
+    },
+  );
 
   assert!(entry.is_err());
 }
 
 #[test]
 fn create_entry_mood_too_high() {
-  let user = create_user();
+  let mut conn = establish_connection();
+
+  let user = create_user(&mut conn);
 
-  let entry = log::create_entry(log::CreateEntry {
-    date: "2025-10-17".to_string(),
-    mood: 6,
-    entry: Some("Test content".to_string()),
-    selected_tags: vec![],
-    user_id: user.id.clone(),
-  });
+  let entry = log::create_entry(
+    &mut conn,
+    log::CreateEntry {
+      date: "2025-10-17".to_string(),
+      mood: 6,
+      entry: Some("Test content".to_string()),
+      selected_tags: vec![],
+      user_id: user.id.clone(),
+    },
+  );
 
   assert!(entry.is_err());
 }
 
 #[test]
 fn create_entry_content_too_long() {
-  let user = create_user();
+  let mut conn = establish_connection();
+
+  let user = create_user(&mut conn);
 
   let long_content = "a".repeat(1001);
-  let entry = log::create_entry(log::CreateEntry {
-    date: "2025-10-17".to_string(),
-    mood: 3,
-    entry: Some(long_content),
-    selected_tags: vec![],
-    user_id: user.id.clone(),
-  });
+  let entry = log::create_entry(
+    &mut conn,
+    log::CreateEntry {
+      date: "2025-10-17".to_string(),
+      mood: 3,
+      entry: Some(long_content),
+      selected_tags: vec![],
+      user_id: user.id.clone(),
+    },
+  );
 
   assert!(entry.is_err());
 }
 
 #[test]
 fn edit_entry_empty_date() {
-  let user = create_user();
-  let entry = log::create_entry(log::CreateEntry {
-    date: "2025-10-17".to_string(),
-    mood: 3,
-    entry: Some("Original content".to_string()),
-    selected_tags: vec![],
-    user_id: user.id.clone(),
-  })
-  .unwrap();
-
-  let edited = log::edit_entry(log::EditEntry {
-    id: entry.id.clone(),
-    date: "".to_string(),
-    mood: 4,
-    entry: Some("Updated content".to_string()),
-    selected_tags: vec![],
-    user_id: user.id.clone(),
-  });
+  let mut conn = establish_connection();
+
+  let user = create_user(&mut conn);
+  let entry = log::create_entry(
+    &mut conn,
+    log::CreateEntry {
+      date: "2025-10-17".to_string(),
+      mood: 3,
+      entry: Some("Original content".to_string()),
+      selected_tags: vec![],
+      user_id: user.id.clone(),
+    },
+  )
+  .unwrap();
+
+  let edited = log::edit_entry(
+    &mut conn,
+    log::EditEntry {
+      id: entry.id.clone(),
+      date: "".to_string(),
+      mood: 4,
+      entry: Some("Updated content".to_string()),
+      selected_tags: vec![],
+      user_id: user.id.clone(),
+    },
+  );
 
   assert!(edited.is_err());
 }
 
 #[test]
 fn edit_entry_date_too_long() {
-  let user = create_user();
-  let entry = log::create_entry(log::CreateEntry {
-    date: "2025-10-17".to_string(),
-    mood: 3,
-    entry: Some("Original content".to_string()),
-    selected_tags: vec![],
-    user_id: user.id.clone(),
-  })
+  let mut conn = establish_connection();
+
+  let user = create_user(&mut conn);
+  let entry = log::create_entry(
+    &mut conn,
+    log::CreateEntry {
+      date: "2025-10-17".to_string(),
+      mood: 3,
+      entry: Some("Original content".to_string()),
+      selected_tags: vec![],
+      user_id: user.id.clone(),
+    },
+  )
   .unwrap();
 
   let long_date = "a".repeat(256);
-  let edited = log::edit_entry(log::EditEntry {
-    id: entry.id.clone(),
-    date: long_date,
-    mood: 4,
-    entry: Some("Updated content".to_string()),
-    selected_tags: vec![],
-    user_id: user.id.clone(),
-  });
+  let edited = log::edit_entry(
+    &mut conn,
+    log::EditEntry {
+      id: entry.id.clone(),
+      date: long_date,
+      mood: 4,
+      entry: Some("Updated content".to_string()),
+      selected_tags: vec![],
+      user_id: user.id.clone(),
+    },
+  );
 
   assert!(edited.is_err());
 }
 
 #[test]
 fn edit_entry_mood_too_low() {
-  let user = create_user();
-  let entry = log::create_entry(log::CreateEntry {
-    date: "2025-10-17".to_string(),
-    mood: 3,
-    entry: Some("Original content".to_string()),
-    selected_tags: vec![],
-    user_id: user.id.clone(),
-  })
-  .unwrap();
-
-  let edited = log::edit_entry(log::EditEntry {
-    id: entry.id.clone(),
-    date: "2025-10-18".to_string(),
-    mood: 0,
-    entry: Some("Updated content".to_string()),
-    selected_tags: vec![],
-    user_id: user.id.clone(),
-  });
+  let mut conn = establish_connection();
+
+  let user = create_user(&mut conn);
+  let entry = log::create_entry(
+    &mut conn,
+    log::CreateEntry {
+      date: "2025-10-17".to_string(),
+      mood: 3,
+      entry: Some("Original content".to_string()),
+      selected_tags: vec![],
+      user_id: user.id.clone(),
+    },
+  )
+  .unwrap();
+
+  let edited = log::edit_entry(
+    &mut conn,
+    log::EditEntry {
+      id: entry.id.clone(),
+      date: "2025-10-18".to_string(),
+      mood: 0,
+      entry: Some("Updated content".to_string()),
+      selected_tags: vec![],
+      user_id: user.id.clone(),
+    },
+  );
 
   assert!(edited.is_err());
 }
 
 #[test]
 fn edit_entry_mood_too_high() {
-  let user = create_user();
-  let entry = log::create_entry(log::CreateEntry {
-    date: "2025-10-17".to_string(),
-    mood: 3,
-    entry: Some("Original content".to_string()),
-    selected_tags: vec![],
-    user_id: user.id.clone(),
-  })
-  .unwrap();
-
-  let edited = log::edit_entry(log::EditEntry {
-    id: entry.id.clone(),
-    date: "2025-10-18".to_string(),
-    mood: 6,
-    entry: Some("Updated content".to_string()),
-    selected_tags: vec![],
-    user_id: user.id.clone(),
-  });
+  let mut conn = establish_connection();
+
+  let user = create_user(&mut conn);
+  let entry = log::create_entry(
+    &mut conn,
+    log::CreateEntry {
+      date: "2025-10-17".to_string(),
+      mood: 3,
+      entry: Some("Original content".to_string()),
+      selected_tags: vec![],
+      user_id: user.id.clone(),
+    },
+  )
+  .unwrap();
+
+  let edited = log::edit_entry(
+    &mut conn,
+    log::EditEntry {
+      id: entry.id.clone(),
+      date: "2025-10-18".to_string(),
+      mood: 6,
+      entry: Some("Updated content".to_string()),
+      selected_tags: vec![],
+      user_id: user.id.clone(),
+    },
+  );
 
   assert!(edited.is_err());
 }
 
 #[test]
 fn edit_entry_content_too_long() {
-  let user = create_user();
-  let entry = log::create_entry(log::CreateEntry {
-    date: "2025-10-17".to_string(),
-    mood: 3,
-    entry: Some("Original content".to_string()),
-    selected_tags: vec![],
-    user_id: user.id.clone(),
-  })
+  let mut conn = establish_connection();
+
+  let user = create_user(&mut conn);
+  let entry = log::create_entry(
+    &mut conn,
+    log::CreateEntry {
+      date: "2025-10-17".to_string(),
+      mood: 3,
+      entry: Some("Original content".to_string()),
+      selected_tags: vec![],
+      user_id: user.id.clone(),
+    },
+  )
   .unwrap();
 
   let long_content = "a".repeat(1001);
-  let edited = log::edit_entry(log::EditEntry {
-    id: entry.id.clone(),
-    date: "2025-10-18".to_string(),
-    mood: 4,
-    entry: Some(long_content),
-    selected_tags: vec![],
-    user_id: user.id.clone(),
-  });
+  let edited = log::edit_entry(
+    &mut conn,
+    log::EditEntry {
+      id: entry.id.clone(),
+      date: "2025-10-18".to_string(),
+      mood: 4,
+      entry: Some(long_content),
+      selected_tags: vec![],
+      user_id: user.id.clone(),
+    },
+  );
 
   assert!(edited.is_err());
 }
 
 #[test]
 fn delete_tag_in_use_by_entry() {
-  let user = create_user();
-  let category = log::create_category(log::CreateCategory {
-    name: "Test Category".to_string(),
-    user_id: user.id.clone(),
-  })
-  .unwrap();
-  let tag = log::create_tag(log::CreateTag {
-    name: "In Use".to_string(),
-    color: "blue".to_string(),
-    category_id: category.id.clone(),
-    user_id: user.id.clone(),
-  })
-  .unwrap();
-  let entry = log::create_entry(log::CreateEntry {
-    date: "2025-10-17".to_string(),
-    mood: 5,
-    entry: Some("Test entry".to_string()),
-    selected_tags: vec![tag.id.clone()],
-    user_id: user.id.clone(),
-  })
+  let mut conn = establish_connection();
+
+  let user = create_user(&mut conn);
+  let category = log::create_category(
+    &mut conn,
+    log::CreateCategory {
+      name: "Test Category".to_string(),
+      user_id: user.id.clone(),
+    },
+  )
+  .unwrap();
+  let tag = log::create_tag(
+    &mut conn,
+    log::CreateTag {
+      name: "In Use".to_string(),
+      color: "blue".to_string(),
+      category_id: category.id.clone(),
+      user_id: user.id.clone(),
+    },
+  )
+  .unwrap();
+  let entry = log::create_entry(
+    &mut conn,
+    log::CreateEntry {
+      date: "2025-10-17".to_string(),
+      mood: 5,
+      entry: Some("Test entry".to_string()),
+      selected_tags: vec![tag.id.clone()],
+      user_id: user.id.clone(),
+    },
+  )
   .unwrap();
 
-  let deleted = log::delete_tag(&tag.id, &user.id);
+  let deleted = log::delete_tag(&mut conn, &tag.id, &user.id);
 
-  let get_entry_again = log::get_entry_with_tags(&entry.id, &user.id);
+  let get_entry_again = log::get_entry_with_tags(&mut conn, &entry.id, &user.id);
 
   assert!(deleted.is_ok());
   assert!(deleted.unwrap());
@@ -1054,23 +1409,31 @@ fn delete_tag_in_use_by_entry() {
 
 #[test]
 fn delete_category_with_tags() {
-  let user = create_user();
-  let category = log::create_category(log::CreateCategory {
-    name: "To Delete".to_string(),
-    user_id: user.id.clone(),
-  })
-  .unwrap();
-  let tag = log::create_tag(log::CreateTag {
-    name: "Tag".to_string(),
-    color: "blue".to_string(),
-    category_id: category.id.clone(),
-    user_id: user.id.clone(),
-  })
+  let mut conn = establish_connection();
+
+  let user = create_user(&mut conn);
+  let category = log::create_category(
+    &mut conn,
+    log::CreateCategory {
+      name: "To Delete".to_string(),
+      user_id: user.id.clone(),
+    },
+  )
+  .unwrap();
+  let tag = log::create_tag(
+    &mut conn,
+    log::CreateTag {
+      name: "Tag".to_string(),
+      color: "blue".to_string(),
+      category_id: category.id.clone(),
+      user_id: user.id.clone(),
+    },
+  )
   .unwrap();
 
-  let deleted = log::delete_category(&category.id, &user.id);
+  let deleted = log::delete_category(&mut conn, &category.id, &user.id);
 
-  let found_tag = log::get_tag(&tag.id, &user.id);
+  let found_tag = log::get_tag(&mut conn, &tag.id, &user.id);
 
   assert!(deleted.is_ok());
   assert!(deleted.unwrap());
@@ -1079,36 +1442,50 @@ fn delete_category_with_tags() {
 
 #[test]
 fn create_entry_date_validation() {
-  let user = create_user();
-
-  let string_date = log::create_entry(log::CreateEntry {
-    date: "invalid-date".to_string(),
-    mood: 3,
-    entry: Some("Test content".to_string()),
-    selected_tags: vec![],
-    user_id: user.id.clone(),
-  });
-  let empty_date = log::create_entry(log::CreateEntry {
-    date: "".to_string(),
-    mood: 3,
-    entry: Some("Test content".to_string()),
-    selected_tags: vec![],
-    user_id: user.id.clone(),
-  });
-  let invalid_format_date = log::create_entry(log::CreateEntry {
-    date: "2025/10/17".to_string(),
-    mood: 3,
-    entry: Some("Test content".to_string()),
-    selected_tags: vec![],
-    user_id: user.id.clone(),
-  });
-  let american_format_date = log::create_entry(log::CreateEntry {
-    date: "10-17-2025".to_string(),
-    mood: 3,
-    entry: Some("Test content".to_string()),
-    selected_tags: vec![],
-    user_id: user.id.clone(),
-  });
+  let mut conn = establish_connection();
+
+  let user = create_user(&mut conn);
+
+  let string_date = log::create_entry(
+    &mut conn,
+    log::CreateEntry {
+      date: "invalid-date".to_string(),
+      mood: 3,
+      entry: Some("Test content".to_string()),
+      selected_tags: vec![],
+      user_id: user.id.clone(),
+    },
+  );
+  let empty_date = log::create_entry(
+    &mut conn,
+    log::CreateEntry {
+      date: "".to_string(),
+      mood: 3,
+      entry: Some("Test content".to_string()),
+      selected_tags: vec![],
+      user_id: user.id.clone(),
+    },
+  );
+  let invalid_format_date = log::create_entry(
+    &mut conn,
+    log::CreateEntry {
+      date: "2025/10/17".to_string(),
+      mood: 3,
+      entry: Some("Test content".to_string()),
+      selected_tags: vec![],
+      user_id: user.id.clone(),
+    },
+  );
+  let american_format_date = log::create_entry(
+    &mut conn,
+    log::CreateEntry {
+      date: "10-17-2025".to_string(),
+      mood: 3,
+      entry: Some("Test content".to_string()),
+      selected_tags: vec![],
+      user_id: user.id.clone(),
+    },
+  );
 
   assert!(string_date.is_err());
   assert!(empty_date.is_err());
@@ -1118,19 +1495,27 @@ fn create_entry_date_validation() {
 
 #[test]
 fn tag_color_default() {
-  let user = create_user();
-  let category = log::create_category(log::CreateCategory {
-    name: "Test Category".to_string(),
-    user_id: user.id.clone(),
-  })
+  let mut conn = establish_connection();
+
+  let user = create_user(&mut conn);
+  let category = log::create_category(
+    &mut conn,
+    log::CreateCategory {
+      name: "Test Category".to_string(),
+      user_id: user.id.clone(),
+    },
+  )
   .unwrap();
 
-  let invalid_color_tag = log::create_tag(log::CreateTag {
-    name: "Invalid Color".to_string(),
-    color: "invalid".to_string(),
-    category_id: category.id.clone(),
-    user_id: user.id.clone(),
-  });
+  let invalid_color_tag = log::create_tag(
+    &mut conn,
+    log::CreateTag {
+      name: "Invalid Color".to_string(),
+      color: "invalid".to_string(),
+      category_id: category.id.clone(),
+      user_id: user.id.clone(),
+    },
+  );
 
   assert!(invalid_color_tag.is_ok());
   let tag = invalid_color_tag.unwrap();
@@ -1139,42 +1524,56 @@ fn tag_color_default() {
 
 #[test]
 fn get_entries_in_range() {
-  let user = create_user();
+  let mut conn = establish_connection();
+
+  let user = create_user(&mut conn);
 
-  let entry1 = log::create_entry(log::CreateEntry {
-    date: "2025-10-15".to_string(),
-    mood: 4,
-    entry: Some("Entry 1".to_string()),
-    selected_tags: vec![],
-    user_id: user.id.clone(),
-  })
+  let entry1 = log::create_entry(
+    &mut conn,
+    log::CreateEntry {
+      date: "2025-10-15".to_string(),
+      mood: 4,
+      entry: Some("Entry 1".to_string()),
+      selected_tags: vec![],
+      user_id: user.id.clone(),
+    },
+  )
   .unwrap();
 
-  let entry2 = log::create_entry(log::CreateEntry {
-    date: "2025-10-16".to_string(),
-    mood: 5,
-    entry: Some("Entry 2".to_string()),
-    selected_tags: vec![],
-    user_id: user.id.clone(),
-  })
+  let entry2 = log::create_entry(
+    &mut conn,
+    log::CreateEntry {
+      date: "2025-10-16".to_string(),
+      mood: 5,
+      entry: Some("Entry 2".to_string()),
+      selected_tags: vec![],
+      user_id: user.id.clone(),
+    },
+  )
   .unwrap();
 
-  let entry3 = log::create_entry(log::CreateEntry {
-    date: "2025-10-17".to_string(),
-    mood: 3,
-    entry: Some("Entry 3".to_string()),
-    selected_tags: vec![],
-    user_id: user.id.clone(),
-  })
+  let entry3 = log::create_entry(
+    &mut conn,
+    log::CreateEntry {
+      date: "2025-10-17".to_string(),
+      mood: 3,
+      entry: Some("Entry 3".to_string()),
+      selected_tags: vec![],
+      user_id: user.id.clone(),
+    },
+  )
   .unwrap();
 
-  let entry4 = log::create_entry(log::CreateEntry {
-    date: "2025-10-14".to_string(),
-    mood: 2,
-    entry: Some("Entry 4".to_string()),
-    selected_tags: vec![],
-    user_id: user.id.clone(),
-  })
+  let entry4 = log::create_entry(
+    &mut conn,
+    log::CreateEntry {
+      date: "2025-10-14".to_string(),
+      mood: 2,
+      entry: Some("Entry 4".to_string()),
+      selected_tags: vec![],
+      user_id: user.id.clone(),
+    },
+  )
   .unwrap();
 
   let options = log::GetEntriesOptions {
@@ -1182,7 +1581,7 @@ fn get_entries_in_range() {
     to_date: Some("2025-10-16".to_string()),
     ..Default::default()
   };
-  let entries = log::get_entries(&user.id, Some(options));
+  let entries = log::get_entries(&mut conn, &user.id, Some(options));
 
   assert!(entries.is_ok());
   let entries = entries.unwrap();
@@ -1196,27 +1595,35 @@ fn get_entries_in_range() {
 
 #[test]
 fn get_all_entries_no_options() {
-  let user = create_user();
+  let mut conn = establish_connection();
+
+  let user = create_user(&mut conn);
 
-  let entry1 = log::create_entry(log::CreateEntry {
-    date: "2025-10-15".to_string(),
-    mood: 4,
-    entry: Some("Entry 1".to_string()),
-    selected_tags: vec![],
-    user_id: user.id.clone(),
-  })
+  let entry1 = log::create_entry(
+    &mut conn,
+    log::CreateEntry {
+      date: "2025-10-15".to_string(),
+      mood: 4,
+      entry: Some("Entry 1".to_string()),
+      selected_tags: vec![],
+      user_id: user.id.clone(),
+    },
+  )
   .unwrap();
 
-  let entry2 = log::create_entry(log::CreateEntry {
-    date: "2025-10-16".to_string(),
-    mood: 5,
-    entry: Some("Entry 2".to_string()),
-    selected_tags: vec![],
-    user_id: user.id.clone(),
-  })
+  let entry2 = log::create_entry(
+    &mut conn,
+    log::CreateEntry {
+      date: "2025-10-16".to_string(),
+      mood: 5,
+      entry: Some("Entry 2".to_string()),
+      selected_tags: vec![],
+      user_id: user.id.clone(),
+    },
+  )
   .unwrap();
 
-  let entries = log::get_entries(&user.id, None);
+  let entries = log::get_entries(&mut conn, &user.id, None);
 
   assert!(entries.is_ok());
   let entries = entries.unwrap();
@@ -1228,33 +1635,44 @@ fn get_all_entries_no_options() {
 
 #[test]
 fn get_entries_in_mood_range() {
-  let user = create_user();
+  let mut conn = establish_connection();
+
+  let user = create_user(&mut conn);
 
-  let entry1 = log::create_entry(log::CreateEntry {
-    date: "2025-10-15".to_string(),
-    mood: 2,
-    entry: Some("Entry 1".to_string()),
-    selected_tags: vec![],
-    user_id: user.id.clone(),
-  })
+  let entry1 = log::create_entry(
+    &mut conn,
+    log::CreateEntry {
+      date: "2025-10-15".to_string(),
+      mood: 2,
+      entry: Some("Entry 1".to_string()),
+      selected_tags: vec![],
+      user_id: user.id.clone(),
+    },
+  )
   .unwrap();
 
-  let entry2 = log::create_entry(log::CreateEntry {
-    date: "2025-10-16".to_string(),
-    mood: 4,
-    entry: Some("Entry 2".to_string()),
-    selected_tags: vec![],
-    user_id: user.id.clone(),
-  })
+  let entry2 = log::create_entry(
+    &mut conn,
+    log::CreateEntry {
+      date: "2025-10-16".to_string(),
+      mood: 4,
+      entry: Some("Entry 2".to_string()),
+      selected_tags: vec![],
+      user_id: user.id.clone(),
+    },
+  )
   .unwrap();
 
-  let entry3 = log::create_entry(log::CreateEntry {
-    date: "2025-10-17".to_string(),
-    mood: 5,
-    entry: Some("Entry 3".to_string()),
-    selected_tags: vec![],
-    user_id: user.id.clone(),
-  })
+  let entry3 = log::create_entry(
+    &mut conn,
+    log::CreateEntry {
+      date: "2025-10-17".to_string(),
+      mood: 5,
+      entry: Some("Entry 3".to_string()),
+      selected_tags: vec![],
+      user_id: user.id.clone(),
+    },
+  )
   .unwrap();
 
   let options = log::GetEntriesOptions {
@@ -1262,7 +1680,7 @@ fn get_entries_in_mood_range() {
     to_mood: Some(5),
     ..Default::default()
   };
-  let entries = log::get_entries(&user.id, Some(options));
+  let entries = log::get_entries(&mut conn, &user.id, Some(options));
 
   assert!(entries.is_ok());
   let entries = entries.unwrap();
@@ -1275,43 +1693,57 @@ fn get_entries_in_mood_range() {
 
 #[test]
 fn get_entries_with_tags() {
-  let user = create_user();
-  let category = log::create_category(log::CreateCategory {
-    name: "Test Category".to_string(),
-    user_id: user.id.clone(),
-  })
-  .unwrap();
-  let tag = log::create_tag(log::CreateTag {
-    name: "Test Tag".to_string(),
-    color: "blue".to_string(),
-    category_id: category.id.clone(),
-    user_id: user.id.clone(),
-  })
-  .unwrap();
-
-  let entry = log::create_entry(log::CreateEntry {
-    date: "2025-10-17".to_string(),
-    mood: 5,
-    entry: Some("Test entry".to_string()),
-    selected_tags: vec![tag.id.clone()],
-    user_id: user.id.clone(),
-  })
-  .unwrap();
-
-  let _entry_no_tag = log::create_entry(log::CreateEntry {
-    date: "2025-10-18".to_string(),
-    mood: 4,
-    entry: Some("Another entry".to_string()),
-    selected_tags: vec![],
-    user_id: user.id.clone(),
-  })
+  let mut conn = establish_connection();
+
+  let user = create_user(&mut conn);
+  let category = log::create_category(
+    &mut conn,
+    log::CreateCategory {
+      name: "Test Category".to_string(),
+      user_id: user.id.clone(),
+    },
+  )
+  .unwrap();
+  let tag = log::create_tag(
+    &mut conn,
+    log::CreateTag {
+      name: "Test Tag".to_string(),
+      color: "blue".to_string(),
+      category_id: category.id.clone(),
+      user_id: user.id.clone(),
+    },
+  )
+  .unwrap();
+
+  let entry = log::create_entry(
+    &mut conn,
+    log::CreateEntry {
+      date: "2025-10-17".to_string(),
+      mood: 5,
+      entry: Some("Test entry".to_string()),
+      selected_tags: vec![tag.id.clone()],
+      user_id: user.id.clone(),
+    },
+  )
+  .unwrap();
+
+  let _entry_no_tag = log::create_entry(
+    &mut conn,
+    log::CreateEntry {
+      date: "2025-10-18".to_string(),
+      mood: 4,
+      entry: Some("Another entry".to_string()),
+      selected_tags: vec![],
+      user_id: user.id.clone(),
+    },
+  )
   .unwrap();
 
   let options = log::GetEntriesOptions {
     tags: Some(vec![tag.id.clone()]),
     ..Default::default()
   };
-  let entries = log::get_entries(&user.id, Some(options));
+  let entries = log::get_entries(&mut conn, &user.id, Some(options));
 
   assert!(entries.is_ok());
   let entries = entries.unwrap();
@@ -1321,32 +1753,43 @@ fn get_entries_with_tags() {
 
 #[test]
 fn delete_category_with_tags_where_tags_are_also_in_use() {
-  let user = create_user();
-  let category = log::create_category(log::CreateCategory {
-    name: "To Delete".to_string(),
-    user_id: user.id.clone(),
-  })
-  .unwrap();
-  let tag = log::create_tag(log::CreateTag {
-    name: "Tag".to_string(),
-    color: "blue".to_string(),
-    category_id: category.id.clone(),
-    user_id: user.id.clone(),
-  })
-  .unwrap();
-  let entry = log::create_entry(log::CreateEntry {
-    date: "2025-10-17".to_string(),
-    mood: 5,
-    entry: Some("Test entry".to_string()),
-    selected_tags: vec![tag.id.clone()],
-    user_id: user.id.clone(),
-  })
+  let mut conn = establish_connection();
+
+  let user = create_user(&mut conn);
+  let category = log::create_category(
+    &mut conn,
+    log::CreateCategory {
+      name: "To Delete".to_string(),
+      user_id: user.id.clone(),
+    },
+  )
+  .unwrap();
+  let tag = log::create_tag(
+    &mut conn,
+    log::CreateTag {
+      name: "Tag".to_string(),
+      color: "blue".to_string(),
+      category_id: category.id.clone(),
+      user_id: user.id.clone(),
+    },
+  )
+  .unwrap();
+  let entry = log::create_entry(
+    &mut conn,
+    log::CreateEntry {
+      date: "2025-10-17".to_string(),
+      mood: 5,
+      entry: Some("Test entry".to_string()),
+      selected_tags: vec![tag.id.clone()],
+      user_id: user.id.clone(),
+    },
+  )
   .unwrap();
 
-  let deleted = log::delete_category(&category.id, &user.id);
+  let deleted = log::delete_category(&mut conn, &category.id, &user.id);
 
-  let found_tag = log::get_tag(&tag.id, &user.id);
-  let found_entry = log::get_entry_with_tags(&entry.id, &user.id);
+  let found_tag = log::get_tag(&mut conn, &tag.id, &user.id);
+  let found_entry = log::get_entry_with_tags(&mut conn, &entry.id, &user.id);
 
   assert!(deleted.is_ok());
   assert!(deleted.unwrap());
@@ -1358,16 +1801,21 @@ fn delete_category_with_tags_where_tags_are_also_in_use() {
 
 #[test]
 fn get_entries_limit_and_offset() {
-  let user = create_user();
+  let mut conn = establish_connection();
+
+  let user = create_user(&mut conn);
 
   for i in 1..=10 {
-    let _ = log::create_entry(log::CreateEntry {
-      date: format!("2025-10-{i:02}"),
-      mood: (i % 5) + 1,
-      entry: Some(format!("Entry {i}")),
-      selected_tags: vec![],
-      user_id: user.id.clone(),
-    });
+    let _ = log::create_entry(
+      &mut conn,
+      log::CreateEntry {
+        date: format!("2025-10-{i:02}"),
+        mood: (i % 5) + 1,
+        entry: Some(format!("Entry {i}")),
+        selected_tags: vec![],
+        user_id: user.id.clone(),
+      },
+    );
   }
 
   let options = log::GetEntriesOptions {
@@ -1375,7 +1823,7 @@ fn get_entries_limit_and_offset() {
     offset: Some(4),
     ..Default::default()
   };
-  let entries = log::get_entries(&user.id, Some(options));
+  let entries = log::get_entries(&mut conn, &user.id, Some(options));
 
   assert!(entries.is_ok());
   let entries = entries.unwrap();
@@ -1390,24 +1838,29 @@ fn get_entries_limit_and_offset() {
 
 #[test]
 fn get_entries_limit_0_selects_more_than_31() {
-  let user = create_user();
+  let mut conn = establish_connection();
+
+  let user = create_user(&mut conn);
 
   for i in 1..=35 {
     let year = 1998 + i;
-    let _ = log::create_entry(log::CreateEntry {
-      date: format!("{year}-10-01"),
-      mood: (i % 5) + 1,
-      entry: Some(format!("Entry {i}")),
-      selected_tags: vec![],
-      user_id: user.id.clone(),
-    });
+    let _ = log::create_entry(
+      &mut conn,
+      log::CreateEntry {
+        date: format!("{year}-10-01"),
+        mood: (i % 5) + 1,
+        entry: Some(format!("Entry {i}")),
+        selected_tags: vec![],
+        user_id: user.id.clone(),
+      },
+    );
   }
 
   let options = log::GetEntriesOptions {
     limit: Some(0),
     ..Default::default()
   };
-  let entries = log::get_entries(&user.id, Some(options));
+  let entries = log::get_entries(&mut conn, &user.id, Some(options));
 
   assert!(entries.is_ok());
   let entries = entries.unwrap();
@@ -1416,33 +1869,44 @@ fn get_entries_limit_0_selects_more_than_31() {
 
 #[test]
 fn delete_user_deletes_all_data() {
-  let user = create_user();
-  let category = log::create_category(log::CreateCategory {
-    name: "Test Category".to_string(),
-    user_id: user.id.clone(),
-  })
-  .unwrap();
-  let tag = log::create_tag(log::CreateTag {
-    name: "Test Tag".to_string(),
-    color: "blue".to_string(),
-    category_id: category.id.clone(),
-    user_id: user.id.clone(),
-  })
-  .unwrap();
-  let entry = log::create_entry(log::CreateEntry {
-    date: "2025-10-17".to_string(),
-    mood: 5,
-    entry: Some("Test entry".to_string()),
-    selected_tags: vec![tag.id.clone()],
-    user_id: user.id.clone(),
-  })
-  .unwrap();
-
-  let deleted = user::delete_user(&user.id);
-
-  let found_category = log::get_category(&category.id, &user.id);
-  let found_tag = log::get_tag(&tag.id, &user.id);
-  let found_entry = log::get_entry_with_tags(&entry.id, &user.id);
+  let mut conn = establish_connection();
+
+  let user = create_user(&mut conn);
+  let category = log::create_category(
+    &mut conn,
+    log::CreateCategory {
+      name: "Test Category".to_string(),
+      user_id: user.id.clone(),
+    },
+  )
+  .unwrap();
+  let tag = log::create_tag(
+    &mut conn,
+    log::CreateTag {
+      name: "Test Tag".to_string(),
+      color: "blue".to_string(),
+      category_id: category.id.clone(),
+      user_id: user.id.clone(),
+    },
+  )
+  .unwrap();
+  let entry = log::create_entry(
+    &mut conn,
+    log::CreateEntry {
+      date: "2025-10-17".to_string(),
+      mood: 5,
+      entry: Some("Test entry".to_string()),
+      selected_tags: vec![tag.id.clone()],
+      user_id: user.id.clone(),
+    },
+  )
+  .unwrap();
+
+  let deleted = user::delete_user(&mut conn, &user.id);
+
+  let found_category = log::get_category(&mut conn, &category.id, &user.id);
+  let found_tag = log::get_tag(&mut conn, &tag.id, &user.id);
+  let found_entry = log::get_entry_with_tags(&mut conn, &entry.id, &user.id);
 
   assert!(deleted.is_ok());
   assert!(deleted.unwrap());
@@ -1453,34 +1917,45 @@ fn delete_user_deletes_all_data() {
 
 #[test]
 fn delete_tag_in_use() {
-  let user = create_user();
-  let category = log::create_category(log::CreateCategory {
-    name: "Test Category".to_string(),
-    user_id: user.id.clone(),
-  })
-  .unwrap();
-  let tag = log::create_tag(log::CreateTag {
-    name: "In Use".to_string(),
-    color: "blue".to_string(),
-    category_id: category.id.clone(),
-    user_id: user.id.clone(),
-  })
-  .unwrap();
-  let entry = log::create_entry(log::CreateEntry {
-    date: "2025-10-17".to_string(),
-    mood: 5,
-    entry: Some("Test entry".to_string()),
-    selected_tags: vec![tag.id.clone()],
-    user_id: user.id.clone(),
-  })
-  .unwrap();
-
-  let deleted = log::delete_tag(&tag.id, &user.id);
+  let mut conn = establish_connection();
+
+  let user = create_user(&mut conn);
+  let category = log::create_category(
+    &mut conn,
+    log::CreateCategory {
+      name: "Test Category".to_string(),
+      user_id: user.id.clone(),
+    },
+  )
+  .unwrap();
+  let tag = log::create_tag(
+    &mut conn,
+    log::CreateTag {
+      name: "In Use".to_string(),
+      color: "blue".to_string(),
+      category_id: category.id.clone(),
+      user_id: user.id.clone(),
+    },
+  )
+  .unwrap();
+  let entry = log::create_entry(
+    &mut conn,
+    log::CreateEntry {
+      date: "2025-10-17".to_string(),
+      mood: 5,
+      entry: Some("Test entry".to_string()),
+      selected_tags: vec![tag.id.clone()],
+      user_id: user.id.clone(),
+    },
+  )
+  .unwrap();
+
+  let deleted = log::delete_tag(&mut conn, &tag.id, &user.id);
 
   assert!(deleted.is_ok());
   assert!(deleted.unwrap());
 
-  let found_entry = log::get_entry_with_tags(&entry.id, &user.id);
+  let found_entry = log::get_entry_with_tags(&mut conn, &entry.id, &user.id);
   assert!(found_entry.is_ok());
   let entry_with_tags = found_entry.unwrap();
   assert!(entry_with_tags.selected_tags.is_empty());
@@ -1488,33 +1963,44 @@ fn delete_tag_in_use() {
 
 #[test]
 fn delete_entry_with_tags() {
-  let user = create_user();
-  let category = log::create_category(log::CreateCategory {
-    name: "Test Category".to_string(),
-    user_id: user.id.clone(),
-  })
-  .unwrap();
-  let tag = log::create_tag(log::CreateTag {
-    name: "Test Tag".to_string(),
-    color: "blue".to_string(),
-    category_id: category.id.clone(),
-    user_id: user.id.clone(),
-  })
+  let mut conn = establish_connection();
+
+  let user = create_user(&mut conn);
+  let category = log::create_category(
+    &mut conn,
+    log::CreateCategory {
+      name: "Test Category".to_string(),
+      user_id: user.id.clone(),
+    },
+  )
+  .unwrap();
+  let tag = log::create_tag(
+    &mut conn,
+    log::CreateTag {
+      name: "Test Tag".to_string(),
+      color: "blue".to_string(),
+      category_id: category.id.clone(),
+      user_id: user.id.clone(),
+    },
+  )
   .unwrap();
 
-  let entry = log::create_entry(log::CreateEntry {
-    date: "2025-10-17".to_string(),
-    mood: 5,
-    entry: Some("Test entry".to_string()),
-    selected_tags: vec![tag.id.clone()],
-    user_id: user.id.clone(),
-  })
+  let entry = log::create_entry(
+    &mut conn,
+    log::CreateEntry {
+      date: "2025-10-17".to_string(),
+      mood: 5,
+      entry: Some("Test entry".to_string()),
+      selected_tags: vec![tag.id.clone()],
+      user_id: user.id.clone(),
+    },
+  )
   .unwrap();
 
-  let deleted = log::delete_entry(&entry.id, &user.id);
+  let deleted = log::delete_entry(&mut conn, &entry.id, &user.id);
 
-  let found_entry = log::get_entry_with_tags(&entry.id, &user.id);
-  let found_tag = log::get_tag(&tag.id, &user.id);
+  let found_entry = log::get_entry_with_tags(&mut conn, &entry.id, &user.id);
+  let found_tag = log::get_tag(&mut conn, &tag.id, &user.id);
 
   assert!(deleted.is_ok());
   assert!(deleted.unwrap());
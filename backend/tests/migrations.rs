@@ -0,0 +1,55 @@
+use diesel::{
+  dsl::sql,
+  sql_types::{BigInt, Bool},
+  PgConnection, RunQueryDsl,
+};
+use ephemeride_backend::{establish_connection, migrations};
+use std::fs;
+
+fn try_lock(conn: &mut PgConnection) -> bool {
+  diesel::select(
+    sql::<Bool>("pg_try_advisory_lock(")
+      .bind::<BigInt, _>(migrations::MIGRATION_LOCK_KEY)
+      .sql(")"),
+  )
+  .get_result::<bool>(conn)
+  .unwrap()
+}
+
+#[test]
+fn every_migration_is_embedded_and_applied() {
+  let mut conn = establish_connection();
+
+  let directories = fs::read_dir("migrations")
+    .unwrap()
+    .filter(|entry| entry.as_ref().unwrap().path().is_dir())
+    .count();
+  let status = migrations::migration_status(&mut conn).unwrap();
+
+  assert_eq!(status.len(), directories);
+  assert!(status.iter().all(|migration| migration.applied));
+  assert!(migrations::pending_migrations(&mut conn)
+    .unwrap()
+    .is_empty());
+}
+
+#[test]
+fn running_migrations_again_applies_nothing() {
+  let mut conn = establish_connection();
+
+  assert!(migrations::run_pending_migrations(&mut conn)
+    .unwrap()
+    .is_empty());
+}
+
+#[test]
+fn migrations_hold_a_lock_while_running() {
+  let mut conn = establish_connection();
+  let mut other = establish_connection();
+
+  let locked_elsewhere =
+    migrations::with_migration_lock(&mut conn, |_| Ok(try_lock(&mut other))).unwrap();
+
+  // another replica would have to wait for the first one to finish
+  assert!(!locked_elsewhere);
+}
@@ -0,0 +1,243 @@
+use diesel::{ExpressionMethods, PgConnection, QueryDsl, RunQueryDsl};
+use ephemeride_backend::{
+  establish_connection,
+  mailer::{FileMailer, Mailer},
+  schema,
+  services::{auth, password_reset, user},
+  util::EphemerideError,
+};
+use std::path::PathBuf;
+use uuid::Uuid;
+
+// collects mail in a file unique to the test and removes it afterwards
+struct TestMailbox {
+  path: PathBuf,
+  mailer: FileMailer,
+}
+
+impl TestMailbox {
+  fn new() -> TestMailbox {
+    let path = std::env::temp_dir().join(format!("ephemeride-mail-{}", Uuid::new_v4()));
+
+    TestMailbox {
+      mailer: FileMailer::new(&path),
+      path,
+    }
+  }
+
+  fn contents(&self) -> String {
+    std::fs::read_to_string(&self.path).unwrap_or_default()
+  }
+
+  fn last_token(&self) -> String {
+    let contents = self.contents();
+    let link = contents
+      .lines()
+      .rfind(|line| line.contains("reset-password?token="))
+      .expect("No reset link was sent");
+
+    link.split("token=").nth(1).unwrap().trim().to_string()
+  }
+}
+
+impl Drop for TestMailbox {
+  fn drop(&mut self) {
+    let _ = std::fs::remove_file(&self.path);
+  }
+}
+
+fn create_user(conn: &mut PgConnection) -> user::UserDetails {
+  let random_name = Uuid::new_v4().to_string();
+
+  user::create_user(
+    conn,
+    user::CreateUser {
+      name: random_name.clone(),
+      email: format!("{random_name}@example.com"),
+      password: "password".to_string(),
+      invite: None,
+    },
+  )
+  .expect("Failed to create test user")
+}
+
+fn log_in(
+  conn: &mut PgConnection,
+  email: &str,
+  password: &str,
+) -> Result<auth::SessionWithToken, EphemerideError> {
+  auth::create_user_session(
+    conn,
+    auth::UserCredentials {
+      email: email.to_string(),
+      password: password.to_string(),
+      totp_code: None,
+    },
+    auth::SessionMetadata {
+      ip_address: "SYSTEM".to_string(),
+      user_agent: "SYSTEM".to_string(),
+    },
+  )
+}
+
+// sends the reset email the way the handler does, once the request is done
+fn request_reset(conn: &mut PgConnection, mailbox: &TestMailbox, email: &str) {
+  let reset_email = password_reset::request_password_reset(
+    conn,
+    password_reset::RequestPasswordReset {
+      email: email.to_string(),
+    },
+  )
+  .unwrap();
+
+  if let Some(reset_email) = reset_email {
+    mailbox.mailer.send(&reset_email).unwrap();
+  }
+}
+
+fn confirm_reset(
+  conn: &mut PgConnection,
+  token: &str,
+  password: &str,
+) -> Result<(), EphemerideError> {
+  password_reset::confirm_password_reset(
+    conn,
+    password_reset::ConfirmPasswordReset {
+      token: token.to_string(),
+      password: password.to_string(),
+    },
+    &auth::SessionMetadata {
+      ip_address: "SYSTEM".to_string(),
+      user_agent: "SYSTEM".to_string(),
+    },
+  )
+}
+
+#[test]
+fn resets_password_and_revokes_sessions() {
+  let mut conn = establish_connection();
+  let mailbox = TestMailbox::new();
+
+  let user = create_user(&mut conn);
+  let session = log_in(&mut conn, &user.email, "password").unwrap();
+
+  request_reset(&mut conn, &mailbox, &user.email);
+
+  assert!(mailbox.contents().contains(&format!("To: {}", user.email)));
+
+  let token = mailbox.last_token();
+
+  assert!(confirm_reset(&mut conn, &token, "new password").is_ok());
+
+  let revoked = auth::get_user_session_by_token(&mut conn, &session.token);
+
+  assert_eq!(revoked.err(), Some(EphemerideError::SessionNotFound));
+  assert_eq!(
+    log_in(&mut conn, &user.email, "password").err(),
+    Some(EphemerideError::InvalidCredentials)
+  );
+  assert!(log_in(&mut conn, &user.email, "new password").is_ok());
+}
+
+#[test]
+fn reset_token_is_single_use() {
+  let mut conn = establish_connection();
+  let mailbox = TestMailbox::new();
+
+  let user = create_user(&mut conn);
+
+  request_reset(&mut conn, &mailbox, &user.email);
+  let token = mailbox.last_token();
+
+  assert!(confirm_reset(&mut conn, &token, "new password").is_ok());
+  assert_eq!(
+    confirm_reset(&mut conn, &token, "another password").err(),
+    Some(EphemerideError::InvalidResetToken)
+  );
+}
+
+#[test]
+fn reset_token_expires() {
+  let mut conn = establish_connection();
+  let mailbox = TestMailbox::new();
+
+  let user = create_user(&mut conn);
+
+  request_reset(&mut conn, &mailbox, &user.email);
+  let token = mailbox.last_token();
+
+  diesel::update(
+    schema::password_resets::table.filter(schema::password_resets::user_id.eq(&user.id)),
+  )
+  .set(schema::password_resets::expires_at.eq(0))
+  .execute(&mut conn)
+  .unwrap();
+
+  assert_eq!(
+    confirm_reset(&mut conn, &token, "new password").err(),
+    Some(EphemerideError::InvalidResetToken)
+  );
+}
+
+#[test]
+fn only_latest_reset_token_works() {
+  let mut conn = establish_connection();
+  let mailbox = TestMailbox::new();
+
+  let user = create_user(&mut conn);
+
+  request_reset(&mut conn, &mailbox, &user.email);
+  let first = mailbox.last_token();
+  request_reset(&mut conn, &mailbox, &user.email);
+  let second = mailbox.last_token();
+
+  assert_ne!(first, second);
+  assert_eq!(
+    confirm_reset(&mut conn, &first, "new password").err(),
+    Some(EphemerideError::InvalidResetToken)
+  );
+  assert!(confirm_reset(&mut conn, &second, "new password").is_ok());
+}
+
+#[test]
+fn stores_only_a_hash_of_the_reset_token() {
+  let mut conn = establish_connection();
+  let mailbox = TestMailbox::new();
+
+  let user = create_user(&mut conn);
+
+  request_reset(&mut conn, &mailbox, &user.email);
+  let token = mailbox.last_token();
+
+  let stored = schema::password_resets::table
+    .filter(schema::password_resets::user_id.eq(&user.id))
+    .select(schema::password_resets::token_hash)
+    .first::<String>(&mut conn)
+    .unwrap();
+
+  assert_ne!(stored, token);
+}
+
+#[test]
+fn unknown_email_sends_nothing() {
+  let mut conn = establish_connection();
+  let mailbox = TestMailbox::new();
+
+  let email = format!("{}@example.com", Uuid::new_v4());
+
+  request_reset(&mut conn, &mailbox, &email);
+
+  assert!(mailbox.contents().is_empty());
+}
+
+#[test]
+fn deletes_user_with_pending_reset() {
+  let mut conn = establish_connection();
+  let mailbox = TestMailbox::new();
+
+  let user = create_user(&mut conn);
+
+  request_reset(&mut conn, &mailbox, &user.email);
+
+  assert!(user::delete_user(&mut conn, &user.id).unwrap());
+}
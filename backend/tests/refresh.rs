@@ -0,0 +1,184 @@
+use diesel::{ExpressionMethods, PgConnection, QueryDsl, RunQueryDsl};
+use ephemeride_backend::{
+  establish_connection, establish_pool, schema,
+  services::{admin, audit, auth, jwt, user, Scope},
+  util::EphemerideError,
+};
+use poem::Request;
+use uuid::Uuid;
+
+fn metadata() -> auth::SessionMetadata {
+  auth::SessionMetadata {
+    ip_address: "SYSTEM".to_string(),
+    user_agent: "SYSTEM".to_string(),
+  }
+}
+
+fn create_session(conn: &mut PgConnection) -> auth::SessionWithToken {
+  let random_name = Uuid::new_v4().to_string();
+  let email = format!("{random_name}@example.com");
+
+  user::create_user(
+    conn,
+    user::CreateUser {
+      name: random_name,
+      email: email.clone(),
+      password: "password".to_string(),
+      invite: None,
+    },
+  )
+  .unwrap();
+
+  auth::create_user_session(
+    conn,
+    auth::UserCredentials {
+      email,
+      password: "password".to_string(),
+      totp_code: None,
+    },
+    metadata(),
+  )
+  .unwrap()
+}
+
+fn refresh(
+  conn: &mut PgConnection,
+  refresh_token: &str,
+) -> Result<auth::SessionWithToken, EphemerideError> {
+  jwt::refresh_session(
+    conn,
+    jwt::RefreshSession {
+      refresh_token: refresh_token.to_string(),
+    },
+    &metadata(),
+  )
+}
+
+#[test]
+fn rotates_the_refresh_token() {
+  let mut conn = establish_connection();
+
+  let session = create_session(&mut conn);
+  let refreshed = refresh(&mut conn, &session.token).unwrap();
+
+  assert_eq!(refreshed.session.id, session.session.id);
+  assert_ne!(refreshed.token, session.token);
+  assert!(jwt::is_refresh_token(&refreshed.token));
+
+  // the new token can be refreshed in turn
+  let refreshed_again = refresh(&mut conn, &refreshed.token).unwrap();
+  assert_eq!(refreshed_again.session.id, session.session.id);
+}
+
+#[test]
+fn reusing_a_refresh_token_revokes_the_session() {
+  let mut conn = establish_connection();
+
+  let session = create_session(&mut conn);
+  let refreshed = refresh(&mut conn, &session.token).unwrap();
+
+  assert_eq!(
+    refresh(&mut conn, &session.token).err(),
+    Some(EphemerideError::RefreshTokenReused)
+  );
+
+  // the token the legitimate client holds stops working as well
+  assert_eq!(
+    refresh(&mut conn, &refreshed.token).err(),
+    Some(EphemerideError::InvalidRefreshToken)
+  );
+  assert_eq!(
+    auth::get_user_session_by_id(&mut conn, &session.session.id, &session.session.user_id).err(),
+    Some(EphemerideError::SessionNotFound)
+  );
+
+  let revoked = schema::audit_events::table
+    .filter(schema::audit_events::user_id.eq(&session.session.user_id))
+    .filter(schema::audit_events::event.eq(audit::AuditEventKind::RefreshTokenReused.as_str()))
+    .count()
+    .get_result::<i64>(&mut conn)
+    .unwrap();
+  assert_eq!(revoked, 1);
+}
+
+#[test]
+fn rejects_unknown_refresh_tokens() {
+  let mut conn = establish_connection();
+
+  assert_eq!(
+    refresh(&mut conn, &jwt::generate_refresh_token()).err(),
+    Some(EphemerideError::InvalidRefreshToken)
+  );
+}
+
+#[test]
+fn expired_sessions_cannot_be_refreshed() {
+  let mut conn = establish_connection();
+
+  let session = create_session(&mut conn);
+  diesel::update(schema::sessions::table.filter(schema::sessions::id.eq(&session.session.id)))
+    .set((
+      schema::sessions::created_at.eq(0),
+      schema::sessions::accessed_at.eq(0),
+    ))
+    .execute(&mut conn)
+    .unwrap();
+
+  assert_eq!(
+    refresh(&mut conn, &session.token).err(),
+    Some(EphemerideError::SessionExpired)
+  );
+}
+
+#[test]
+fn gets_a_session_by_id() {
+  let mut conn = establish_connection();
+
+  let session = create_session(&mut conn).session;
+  let other = create_session(&mut conn).session;
+
+  let found = auth::get_user_session_by_id(&mut conn, &session.id, &session.user_id).unwrap();
+  assert_eq!(found.id, session.id);
+
+  assert_eq!(
+    auth::get_user_session_by_id(&mut conn, &session.id, &other.user_id).err(),
+    Some(EphemerideError::SessionNotFound)
+  );
+}
+
+#[tokio::test]
+async fn access_tokens_stop_working_when_the_account_is_disabled() {
+  let pool = establish_pool();
+  let mut conn = establish_connection();
+
+  let jwt_config = jwt::JwtConfig::new("secret", 15 * 60 * 1000);
+  let settings = auth::SessionSettings {
+    jwt: Some(jwt_config.clone()),
+    ..auth::SessionSettings::from_env()
+  };
+
+  let admin_session = create_session(&mut conn);
+  let session = jwt::with_tokens(&jwt_config, create_session(&mut conn)).unwrap();
+  let request = Request::builder()
+    .header("Authorization", format!("Bearer {}", session.access_token))
+    .extension(settings)
+    .finish();
+
+  let authorized = auth::authorize_request(&pool, &request, Scope::EntriesRead).await;
+  assert_eq!(authorized.unwrap().user_id, session.session.user_id);
+
+  admin::set_user_disabled(
+    &mut conn,
+    &admin_session.session.user_id,
+    &session.session.user_id,
+    true,
+  )
+  .unwrap();
+
+  assert_eq!(
+    auth::authorize_request(&pool, &request, Scope::EntriesRead)
+      .await
+      .err(),
+    Some(EphemerideError::AccountDisabled)
+  );
+}
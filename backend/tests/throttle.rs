@@ -0,0 +1,93 @@
+use ephemeride_backend::{establish_connection, services::throttle};
+use uuid::Uuid;
+
+fn random_keys() -> Vec<String> {
+  vec![
+    throttle::ip_key(&Uuid::new_v4().to_string()),
+    throttle::account_key(&format!("{}@example.com", Uuid::new_v4())),
+  ]
+}
+
+#[test]
+fn locks_out_after_free_attempts() {
+  let mut conn = establish_connection();
+  let config = throttle::ThrottleConfig::from_env();
+  let keys = random_keys();
+
+  for _ in 0..config.free_attempts - 1 {
+    throttle::record_attempt(&mut conn, &keys).unwrap();
+  }
+
+  assert_eq!(throttle::retry_after(&mut conn, &keys).unwrap(), None);
+
+  throttle::record_attempt(&mut conn, &keys).unwrap();
+
+  let retry_after = throttle::retry_after(&mut conn, &keys).unwrap();
+
+  assert!(retry_after.is_some());
+  assert!(retry_after.unwrap() <= config.base_delay);
+}
+
+#[test]
+fn lockout_grows_with_each_failure() {
+  let mut conn = establish_connection();
+  let config = throttle::ThrottleConfig::from_env();
+  let keys = random_keys();
+
+  for _ in 0..config.free_attempts + 3 {
+    throttle::record_attempt(&mut conn, &keys).unwrap();
+  }
+
+  let retry_after = throttle::retry_after(&mut conn, &keys).unwrap().unwrap();
+
+  assert!(retry_after > config.base_delay * 4);
+}
+
+#[test]
+fn any_locked_key_locks_the_request() {
+  let mut conn = establish_connection();
+  let config = throttle::ThrottleConfig::from_env();
+  let keys = random_keys();
+
+  for _ in 0..config.free_attempts {
+    throttle::record_attempt(&mut conn, &keys[..1]).unwrap();
+  }
+
+  assert!(throttle::retry_after(&mut conn, &keys[1..])
+    .unwrap()
+    .is_none());
+  assert!(throttle::retry_after(&mut conn, &keys).unwrap().is_some());
+}
+
+#[test]
+fn clearing_removes_the_lockout() {
+  let mut conn = establish_connection();
+  let config = throttle::ThrottleConfig::from_env();
+  let keys = random_keys();
+
+  for _ in 0..config.free_attempts {
+    throttle::record_attempt(&mut conn, &keys).unwrap();
+  }
+
+  throttle::clear(&mut conn, &keys).unwrap();
+
+  assert_eq!(throttle::retry_after(&mut conn, &keys).unwrap(), None);
+}
+
+#[test]
+fn mail_keys_are_kept_apart_from_login_keys() {
+  let email = format!("{}@example.com", Uuid::new_v4());
+
+  assert_ne!(
+    throttle::mail_ip_key("203.0.113.7"),
+    throttle::ip_key("203.0.113.7")
+  );
+  assert_ne!(
+    throttle::mail_account_key(&email),
+    throttle::account_key(&email)
+  );
+  assert_eq!(
+    throttle::mail_account_key(&email),
+    throttle::mail_account_key(&format!(" {} ", email.to_uppercase()))
+  );
+}
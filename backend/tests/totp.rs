@@ -0,0 +1,235 @@
+use diesel::PgConnection;
+use ephemeride_backend::{
+  establish_connection,
+  services::{admin, auth, totp, user},
+  util::EphemerideError,
+};
+use std::time::{SystemTime, UNIX_EPOCH};
+use totp_rs::{Algorithm, Secret, TOTP};
+use uuid::Uuid;
+
+fn create_user(conn: &mut PgConnection) -> user::UserDetails {
+  let random_name = Uuid::new_v4().to_string();
+
+  user::create_user(
+    conn,
+    user::CreateUser {
+      name: random_name.clone(),
+      email: format!("{random_name}@example.com"),
+      password: "password".to_string(),
+      invite: None,
+    },
+  )
+  .expect("Failed to create test user")
+}
+
+fn log_in(
+  conn: &mut PgConnection,
+  email: &str,
+  totp_code: Option<String>,
+) -> Result<auth::SessionWithToken, EphemerideError> {
+  auth::create_user_session(
+    conn,
+    auth::UserCredentials {
+      email: email.to_string(),
+      password: "password".to_string(),
+      totp_code,
+    },
+    auth::SessionMetadata {
+      ip_address: "SYSTEM".to_string(),
+      user_agent: "SYSTEM".to_string(),
+    },
+  )
+}
+
+// code for the current time step, offset by `steps` steps
+fn code_at(secret: &str, steps: i64) -> String {
+  let totp = TOTP::new(
+    Algorithm::SHA1,
+    6,
+    0,
+    30,
+    Secret::Encoded(secret.to_string()).to_bytes().unwrap(),
+    None,
+    "".to_string(),
+  )
+  .unwrap();
+  let now = SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .unwrap()
+    .as_secs() as i64;
+
+  totp.generate((now + steps * 30) as u64)
+}
+
+fn enable_totp(conn: &mut PgConnection, user_id: &str) -> (String, Vec<String>) {
+  let enrollment = totp::start_totp_enrollment(conn, user_id).unwrap();
+  let confirmed = totp::confirm_totp_enrollment(
+    conn,
+    user_id,
+    totp::ConfirmTotp {
+      code: code_at(&enrollment.secret, 0),
+    },
+  )
+  .unwrap();
+
+  (enrollment.secret, confirmed.recovery_codes)
+}
+
+#[test]
+fn enrollment_requires_confirmation() {
+  let mut conn = establish_connection();
+
+  let user = create_user(&mut conn);
+  let enrollment = totp::start_totp_enrollment(&mut conn, &user.id).unwrap();
+
+  assert!(enrollment.otpauth_uri.starts_with("otpauth://totp/"));
+  assert!(!totp::is_totp_enabled(&mut conn, &user.id).unwrap());
+  assert!(log_in(&mut conn, &user.email, None).is_ok());
+
+  let wrong = totp::confirm_totp_enrollment(
+    &mut conn,
+    &user.id,
+    totp::ConfirmTotp {
+      code: code_at(&enrollment.secret, 5),
+    },
+  );
+
+  assert_eq!(wrong.err(), Some(EphemerideError::InvalidTotpCode));
+  assert!(!totp::is_totp_enabled(&mut conn, &user.id).unwrap());
+
+  let confirmed = totp::confirm_totp_enrollment(
+    &mut conn,
+    &user.id,
+    totp::ConfirmTotp {
+      code: code_at(&enrollment.secret, 0),
+    },
+  )
+  .unwrap();
+
+  assert_eq!(confirmed.recovery_codes.len(), 10);
+  assert!(totp::is_totp_enabled(&mut conn, &user.id).unwrap());
+
+  let again = totp::start_totp_enrollment(&mut conn, &user.id);
+
+  assert_eq!(again.err(), Some(EphemerideError::TotpAlreadyEnabled));
+}
+
+#[test]
+fn log_in_requires_second_factor() {
+  let mut conn = establish_connection();
+
+  let user = create_user(&mut conn);
+  let (secret, _) = enable_totp(&mut conn, &user.id);
+
+  let missing = log_in(&mut conn, &user.email, None);
+
+  assert_eq!(missing.err(), Some(EphemerideError::TotpRequired));
+
+  let wrong = log_in(&mut conn, &user.email, Some("000000".to_string()));
+
+  assert_eq!(wrong.err(), Some(EphemerideError::InvalidTotpCode));
+
+  let session = log_in(&mut conn, &user.email, Some(code_at(&secret, 1)));
+
+  assert!(session.is_ok());
+}
+
+#[test]
+fn totp_code_cannot_be_replayed() {
+  let mut conn = establish_connection();
+
+  let user = create_user(&mut conn);
+  let (secret, _) = enable_totp(&mut conn, &user.id);
+
+  // the code used to confirm enrollment is already spent
+  let replayed = log_in(&mut conn, &user.email, Some(code_at(&secret, 0)));
+
+  assert_eq!(replayed.err(), Some(EphemerideError::InvalidTotpCode));
+
+  let code = code_at(&secret, 1);
+
+  assert!(log_in(&mut conn, &user.email, Some(code.clone())).is_ok());
+
+  let replayed = log_in(&mut conn, &user.email, Some(code));
+
+  assert_eq!(replayed.err(), Some(EphemerideError::InvalidTotpCode));
+}
+
+#[test]
+fn recovery_codes_work_once() {
+  let mut conn = establish_connection();
+
+  let user = create_user(&mut conn);
+  let (_, recovery_codes) = enable_totp(&mut conn, &user.id);
+
+  let code = recovery_codes[0].to_uppercase();
+
+  assert!(log_in(&mut conn, &user.email, Some(code.clone())).is_ok());
+
+  let reused = log_in(&mut conn, &user.email, Some(code));
+
+  assert_eq!(reused.err(), Some(EphemerideError::InvalidTotpCode));
+  assert!(log_in(&mut conn, &user.email, Some(recovery_codes[1].clone())).is_ok());
+}
+
+#[test]
+fn recovery_code_is_kept_when_the_login_is_turned_away() {
+  let mut conn = establish_connection();
+
+  let admin = create_user(&mut conn);
+  let user = create_user(&mut conn);
+  let (_, recovery_codes) = enable_totp(&mut conn, &user.id);
+
+  admin::set_user_disabled(&mut conn, &admin.id, &user.id, true).unwrap();
+
+  let disabled = log_in(&mut conn, &user.email, Some(recovery_codes[0].clone()));
+  assert_eq!(disabled.err(), Some(EphemerideError::AccountDisabled));
+
+  admin::set_user_disabled(&mut conn, &admin.id, &user.id, false).unwrap();
+
+  assert!(log_in(&mut conn, &user.email, Some(recovery_codes[0].clone())).is_ok());
+}
+
+#[test]
+fn disabling_requires_password() {
+  let mut conn = establish_connection();
+
+  let user = create_user(&mut conn);
+  enable_totp(&mut conn, &user.id);
+
+  let wrong = totp::disable_totp(
+    &mut conn,
+    &user.id,
+    totp::DisableTotp {
+      password: "wrong password".to_string(),
+    },
+  );
+
+  assert_eq!(wrong.err(), Some(EphemerideError::InvalidPassword));
+  assert!(totp::is_totp_enabled(&mut conn, &user.id).unwrap());
+
+  let disabled = totp::disable_totp(
+    &mut conn,
+    &user.id,
+    totp::DisableTotp {
+      password: "password".to_string(),
+    },
+  );
+
+  assert!(disabled.unwrap());
+  assert!(!totp::is_totp_enabled(&mut conn, &user.id).unwrap());
+  assert!(log_in(&mut conn, &user.email, None).is_ok());
+}
+
+#[test]
+fn deletes_user_with_totp() {
+  let mut conn = establish_connection();
+
+  let user = create_user(&mut conn);
+  enable_totp(&mut conn, &user.id);
+
+  let deleted = user::delete_user(&mut conn, &user.id);
+
+  assert!(deleted.unwrap());
+}
@@ -0,0 +1,240 @@
+use diesel::{sql_query, PgConnection, RunQueryDsl};
+use ephemeride_backend::{
+  establish_connection,
+  services::{log, user},
+};
+use uuid::Uuid;
+
+// installs a trigger that raises an exception whenever `condition` holds,
+// letting tests fail a single statement in the middle of a multi-step write
+struct InjectedFailure {
+  name: String,
+  table: String,
+}
+
+impl InjectedFailure {
+  fn new(conn: &mut PgConnection, table: &str, event: &str, condition: &str) -> InjectedFailure {
+    let name = format!("inject_failure_{}", Uuid::new_v4().simple());
+
+    sql_query(format!(
+      "CREATE FUNCTION {name}() RETURNS trigger AS $$
+       BEGIN
+         IF {condition} THEN
+           RAISE EXCEPTION 'injected failure';
+         END IF;
+         RETURN COALESCE(NEW, OLD);
+       END;
+       $$ LANGUAGE plpgsql"
+    ))
+    .execute(conn)
+    .expect("Failed to create failure function");
+
+    sql_query(format!(
+      "CREATE TRIGGER {name} BEFORE {event} ON {table} FOR EACH ROW EXECUTE FUNCTION {name}()"
+    ))
+    .execute(conn)
+    .expect("Failed to create failure trigger");
+
+    InjectedFailure {
+      name,
+      table: table.to_string(),
+    }
+  }
+}
+
+impl Drop for InjectedFailure {
+  fn drop(&mut self) {
+    let mut conn = establish_connection();
+    let name = &self.name;
+    let table = &self.table;
+
+    let _ = sql_query(format!("DROP TRIGGER IF EXISTS {name} ON {table}")).execute(&mut conn);
+    let _ = sql_query(format!("DROP FUNCTION IF EXISTS {name}()")).execute(&mut conn);
+  }
+}
+
+fn create_user(conn: &mut PgConnection) -> user::UserDetails {
+  let random_name = Uuid::new_v4().to_string();
+
+  user::create_user(
+    conn,
+    user::CreateUser {
+      name: random_name.clone(),
+      email: format!("{random_name}@example.com"),
+      password: "password".to_string(),
+      invite: None,
+    },
+  )
+  .expect("Failed to create test user")
+}
+
+fn create_category_with_tags(
+  conn: &mut PgConnection,
+  user_id: &str,
+) -> (log::Category, log::Tag, log::Tag) {
+  let category = log::create_category(
+    conn,
+    log::CreateCategory {
+      name: "Category".to_string(),
+      user_id: user_id.to_string(),
+    },
+  )
+  .unwrap();
+  let tag1 = log::create_tag(
+    conn,
+    log::CreateTag {
+      name: "Tag 1".to_string(),
+      color: "blue".to_string(),
+      category_id: category.id.clone(),
+      user_id: user_id.to_string(),
+    },
+  )
+  .unwrap();
+  let tag2 = log::create_tag(
+    conn,
+    log::CreateTag {
+      name: "Tag 2".to_string(),
+      color: "red".to_string(),
+      category_id: category.id.clone(),
+      user_id: user_id.to_string(),
+    },
+  )
+  .unwrap();
+
+  (category, tag1, tag2)
+}
+
+#[test]
+fn create_entry_rolls_back_on_failure() {
+  let mut conn = establish_connection();
+
+  let user = create_user(&mut conn);
+  let (_, tag1, tag2) = create_category_with_tags(&mut conn, &user.id);
+
+  let _failure = InjectedFailure::new(
+    &mut conn,
+    "entry_tags",
+    "INSERT",
+    &format!("NEW.tag_id = '{}'", tag2.id),
+  );
+
+  let created = log::create_entry(
+    &mut conn,
+    log::CreateEntry {
+      date: "2024-01-01".to_string(),
+      mood: 3,
+      entry: Some("Half written".to_string()),
+      selected_tags: vec![tag1.id.clone(), tag2.id.clone()],
+      user_id: user.id.clone(),
+    },
+  );
+
+  assert!(created.is_err());
+
+  let date = chrono::NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+  let found = log::get_entry_by_date(&mut conn, date, &user.id);
+
+  assert!(found.is_err());
+}
+
+#[test]
+fn edit_entry_rolls_back_on_failure() {
+  let mut conn = establish_connection();
+
+  let user = create_user(&mut conn);
+  let (_, tag1, tag2) = create_category_with_tags(&mut conn, &user.id);
+
+  let entry = log::create_entry(
+    &mut conn,
+    log::CreateEntry {
+      date: "2024-01-01".to_string(),
+      mood: 3,
+      entry: Some("Original".to_string()),
+      selected_tags: vec![tag1.id.clone()],
+      user_id: user.id.clone(),
+    },
+  )
+  .unwrap();
+
+  let _failure = InjectedFailure::new(
+    &mut conn,
+    "entry_tags",
+    "INSERT",
+    &format!("NEW.tag_id = '{}'", tag2.id),
+  );
+
+  let edited = log::edit_entry(
+    &mut conn,
+    log::EditEntry {
+      id: entry.id.clone(),
+      date: "2024-01-01".to_string(),
+      mood: 5,
+      entry: Some("Edited".to_string()),
+      selected_tags: vec![tag1.id.clone(), tag2.id.clone()],
+      user_id: user.id.clone(),
+    },
+  );
+
+  assert!(edited.is_err());
+
+  let found = log::get_entry_with_tags(&mut conn, &entry.id, &user.id).unwrap();
+
+  assert_eq!(found.mood, 3);
+  assert_eq!(found.entry, Some("Original".to_string()));
+  assert_eq!(found.selected_tags, vec![tag1.id]);
+}
+
+#[test]
+fn create_user_rolls_back_on_failure() {
+  let mut conn = establish_connection();
+
+  let random_name = Uuid::new_v4().to_string();
+  let email = format!("{random_name}@example.com");
+
+  // fail while creating the default tags, after the user row is inserted
+  let _failure = InjectedFailure::new(
+    &mut conn,
+    "tags",
+    "INSERT",
+    &format!("EXISTS (SELECT 1 FROM users WHERE id = NEW.user_id AND email = '{email}')"),
+  );
+
+  let created = user::create_user(
+    &mut conn,
+    user::CreateUser {
+      name: random_name,
+      email: email.clone(),
+      password: "password".to_string(),
+      invite: None,
+    },
+  );
+
+  assert!(created.is_err());
+
+  let found = user::get_user_id(&mut conn, &email);
+
+  assert!(found.is_err());
+}
+
+#[test]
+fn delete_category_rolls_back_on_failure() {
+  let mut conn = establish_connection();
+
+  let user = create_user(&mut conn);
+  let (category, _, _) = create_category_with_tags(&mut conn, &user.id);
+
+  let _failure = InjectedFailure::new(
+    &mut conn,
+    "categories",
+    "DELETE",
+    &format!("OLD.id = '{}'", category.id),
+  );
+
+  let deleted = log::delete_category(&mut conn, &category.id, &user.id);
+
+  assert!(deleted.is_err());
+
+  let tags = log::get_category_tags(&mut conn, &category.id, &user.id).unwrap();
+
+  assert_eq!(tags.len(), 2);
+}
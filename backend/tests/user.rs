@@ -1,8 +1,16 @@
-use ephemeride_backend::services::{auth, log, user};
+use diesel::{ExpressionMethods, PgConnection, QueryDsl, RunQueryDsl};
+use ephemeride_backend::{
+  establish_connection, establish_pool, schema,
+  services::{auth, log, user, Scope},
+  util::EphemerideError,
+};
+use poem::Request;
 use uuid::Uuid;
 
 #[test]
 fn create_user() {
+  let mut conn = establish_connection();
+
   let random_name = Uuid::new_v4().to_string();
   let email = format!("{random_name}@example.com");
 
@@ -13,24 +21,24 @@ fn create_user() {
     invite: None,
   };
 
-  let created_user = user::create_user(user);
+  let created_user = user::create_user(&mut conn, user);
 
   assert!(created_user.is_ok());
 
-  let found_user = user::get_user(&created_user.unwrap().id);
+  let found_user = user::get_user(&mut conn, &created_user.unwrap().id);
 
   assert!(found_user.is_ok());
   let found_user = found_user.unwrap();
   assert_eq!(found_user.name, random_name);
 
-  let found_categories = log::get_all_categories(&found_user.id);
+  let found_categories = log::get_all_categories(&mut conn, &found_user.id);
 
   assert!(found_categories.is_ok());
   let categories = found_categories.unwrap();
   assert!(!categories.is_empty());
 
   for category in categories {
-    let found_tags = log::get_category_tags(&category.id, &found_user.id);
+    let found_tags = log::get_category_tags(&mut conn, &category.id, &found_user.id);
     assert!(found_tags.is_ok());
     assert!(!found_tags.unwrap().is_empty());
   }
@@ -38,6 +46,8 @@ fn create_user() {
 
 #[test]
 fn create_user_and_delete() {
+  let mut conn = establish_connection();
+
   let random_name = Uuid::new_v4().to_string();
   let email = format!("{random_name}@example.com");
 
@@ -48,32 +58,36 @@ fn create_user_and_delete() {
     invite: None,
   };
 
-  let created_user = user::create_user(user);
+  let created_user = user::create_user(&mut conn, user);
 
   assert!(created_user.is_ok());
 
-  let found_user = user::get_user(&created_user.unwrap().id);
+  let found_user = user::get_user(&mut conn, &created_user.unwrap().id);
 
   assert!(found_user.is_ok());
 
-  let deleted = user::delete_user(&found_user.unwrap().id).unwrap_or_default();
+  let deleted = user::delete_user(&mut conn, &found_user.unwrap().id).unwrap_or_default();
 
   assert!(deleted);
 
-  let found_user = user::get_user_id(&email);
+  let found_user = user::get_user_id(&mut conn, &email);
 
   assert!(found_user.is_err());
 }
 
 #[test]
 fn delete_user_that_does_not_exist() {
-  let deleted = user::delete_user("INVALID_ID").unwrap_or_default();
+  let mut conn = establish_connection();
+
+  let deleted = user::delete_user(&mut conn, "INVALID_ID").unwrap_or_default();
 
   assert!(!deleted);
 }
 
 #[test]
 fn updates_user() {
+  let mut conn = establish_connection();
+
   let random_name = Uuid::new_v4().to_string();
   let email = format!("{random_name}@example.com");
 
@@ -84,11 +98,11 @@ fn updates_user() {
     invite: None,
   };
 
-  let created_user = user::create_user(user);
+  let created_user = user::create_user(&mut conn, user);
 
   assert!(created_user.is_ok());
 
-  let found_user = user::get_user(&created_user.unwrap().id);
+  let found_user = user::get_user(&mut conn, &created_user.unwrap().id);
 
   assert!(found_user.is_ok());
   let found_user = found_user.unwrap();
@@ -103,11 +117,11 @@ fn updates_user() {
     email: new_email.clone(),
   };
 
-  let updated = user::update_user(&found_user.id, updated_user);
+  let updated = user::update_user(&mut conn, &found_user.id, updated_user, &metadata());
 
   assert!(updated.is_ok());
 
-  let found_user = user::get_user(&found_user.id);
+  let found_user = user::get_user(&mut conn, &found_user.id);
 
   assert!(found_user.is_ok());
   assert_eq!(found_user.unwrap().name, new_random_name);
@@ -115,6 +129,8 @@ fn updates_user() {
 
 #[test]
 fn creates_a_session() {
+  let mut conn = establish_connection();
+
   let random_name = Uuid::new_v4().to_string();
   let email = format!("{random_name}@example.com");
   let password = "password".to_string();
@@ -126,34 +142,37 @@ fn creates_a_session() {
     invite: None,
   };
 
-  let created_user = user::create_user(user);
+  let created_user = user::create_user(&mut conn, user);
 
   assert!(created_user.is_ok());
 
-  let user_id = user::get_user_id(&email);
+  let user_id = user::get_user_id(&mut conn, &email);
 
   assert!(user_id.is_ok());
 
   let credentials = auth::UserCredentials {
     email: email.clone(),
     password: password.clone(),
+    totp_code: None,
   };
   let metadata = auth::SessionMetadata {
     ip_address: "SYSTEM".to_string(),
     user_agent: "SYSTEM".to_string(),
   };
 
-  let session = auth::create_user_session(credentials, metadata);
+  let session = auth::create_user_session(&mut conn, credentials, metadata);
 
   assert!(session.is_ok());
 
-  let found_session = auth::get_user_session_by_id(&session.unwrap().id);
+  let found_session = auth::get_user_session_by_token(&mut conn, &session.unwrap().token);
 
   assert!(found_session.is_ok());
 }
 
 #[test]
 fn deletes_a_session() {
+  let mut conn = establish_connection();
+
   let random_name = Uuid::new_v4().to_string();
   let email = format!("{random_name}@example.com");
   let password = "password".to_string();
@@ -165,53 +184,59 @@ fn deletes_a_session() {
     invite: None,
   };
 
-  let created_user = user::create_user(user);
+  let created_user = user::create_user(&mut conn, user);
 
   assert!(created_user.is_ok());
 
-  let found_user = user::get_user_id(&email);
+  let found_user = user::get_user_id(&mut conn, &email);
 
   assert!(found_user.is_ok());
 
   let credentials = auth::UserCredentials {
     email: email.clone(),
     password: password.clone(),
+    totp_code: None,
   };
   let metadata = auth::SessionMetadata {
     ip_address: "SYSTEM".to_string(),
     user_agent: "SYSTEM".to_string(),
   };
 
-  let session = auth::create_user_session(credentials, metadata);
+  let session = auth::create_user_session(&mut conn, credentials, metadata);
 
   assert!(session.is_ok());
 
-  let session_id = session.unwrap().id;
-  let found_session = auth::get_user_session_by_id(&session_id);
+  let session = session.unwrap();
+  let found_session = auth::get_user_session_by_token(&mut conn, &session.token);
 
   assert!(found_session.is_ok());
 
-  let deleted = auth::delete_user_session(&session_id);
+  let deleted = auth::delete_user_session(&mut conn, &session.session.id, &found_user.unwrap());
 
   assert!(deleted.unwrap());
 
-  let found_session = auth::get_user_session_by_id(&session_id);
+  let found_session = auth::get_user_session_by_token(&mut conn, &session.token);
 
   assert!(found_session.is_err());
 }
 
 #[test]
 fn user_count() {
-  let _created_user = user::create_user(user::CreateUser {
-    name: Uuid::new_v4().to_string(),
-    email: format!("{}@example.com", Uuid::new_v4()),
-    password: "password".to_string(),
-    invite: None,
-  });
-
-  let count = user::user_count();
-  let active_count = user::active_user_count(0);
-  let active_count_max_i64 = user::active_user_count(i64::MAX);
+  let mut conn = establish_connection();
+
+  let _created_user = user::create_user(
+    &mut conn,
+    user::CreateUser {
+      name: Uuid::new_v4().to_string(),
+      email: format!("{}@example.com", Uuid::new_v4()),
+      password: "password".to_string(),
+      invite: None,
+    },
+  );
+
+  let count = user::user_count(&mut conn);
+  let active_count = user::active_user_count(&mut conn, 0);
+  let active_count_max_i64 = user::active_user_count(&mut conn, i64::MAX);
 
   assert!(count.is_ok());
   assert!(count.unwrap() > 0);
@@ -223,9 +248,11 @@ fn user_count() {
 
 #[test]
 fn too_long_password() {
+  let mut conn = establish_connection();
+
   let random_name = Uuid::new_v4().to_string();
   let email = format!("{random_name}@example.com");
-  let long_password = "p".repeat(73); // 73 characters, exceeding the 72 character limit
+  let long_password = "p".repeat(1025); // 1025 characters, exceeding the 1024 character limit
 
   let user = user::CreateUser {
     name: random_name.clone(),
@@ -234,7 +261,602 @@ fn too_long_password() {
     invite: None,
   };
 
-  let created_user = user::create_user(user);
+  let created_user = user::create_user(&mut conn, user);
 
   assert!(created_user.is_err());
 }
+
+#[tokio::test]
+async fn authorizes_a_request() {
+  let pool = establish_pool();
+  let mut conn = establish_connection();
+
+  let random_name = Uuid::new_v4().to_string();
+  let email = format!("{random_name}@example.com");
+  let password = "password".to_string();
+
+  let user = user::CreateUser {
+    name: random_name.clone(),
+    email: email.clone(),
+    password: password.clone(),
+    invite: None,
+  };
+
+  let created_user = user::create_user(&mut conn, user).unwrap();
+
+  let credentials = auth::UserCredentials {
+    email: email.clone(),
+    password: password.clone(),
+    totp_code: None,
+  };
+  let metadata = auth::SessionMetadata {
+    ip_address: "SYSTEM".to_string(),
+    user_agent: "SYSTEM".to_string(),
+  };
+
+  let session = auth::create_user_session(&mut conn, credentials, metadata).unwrap();
+
+  let request = Request::builder()
+    .header("Authorization", format!("Bearer {}", session.token))
+    .extension(auth::SessionSettings::from_env())
+    .finish();
+  let authorized = auth::authorize_request(&pool, &request, Scope::UserRead).await;
+
+  assert!(authorized.is_ok());
+  assert_eq!(authorized.unwrap().user_id, created_user.id);
+
+  let request = Request::builder()
+    .extension(auth::SessionSettings::from_env())
+    .finish();
+  let unauthorized = auth::authorize_request(&pool, &request, Scope::UserRead).await;
+
+  assert_eq!(unauthorized.err(), Some(EphemerideError::Unauthorized));
+}
+
+fn log_in(conn: &mut PgConnection, email: &str) -> auth::SessionWithToken {
+  auth::create_user_session(
+    conn,
+    auth::UserCredentials {
+      email: email.to_string(),
+      password: "password".to_string(),
+      totp_code: None,
+    },
+    auth::SessionMetadata {
+      ip_address: "SYSTEM".to_string(),
+      user_agent: "SYSTEM".to_string(),
+    },
+  )
+  .unwrap()
+}
+
+fn create_session(conn: &mut PgConnection) -> auth::SessionWithToken {
+  let random_name = Uuid::new_v4().to_string();
+  let email = format!("{random_name}@example.com");
+
+  user::create_user(
+    conn,
+    user::CreateUser {
+      name: random_name.clone(),
+      email: email.clone(),
+      password: "password".to_string(),
+      invite: None,
+    },
+  )
+  .unwrap();
+
+  log_in(conn, &email)
+}
+
+#[test]
+fn session_has_expiry() {
+  let mut conn = establish_connection();
+
+  let session = create_session(&mut conn).session;
+
+  assert!(session.expires_at > session.created_at);
+}
+
+#[test]
+fn expired_session_is_rejected_and_deleted() {
+  let mut conn = establish_connection();
+
+  let auth::SessionWithToken { session, token } = create_session(&mut conn);
+
+  diesel::update(schema::sessions::table.filter(schema::sessions::id.eq(&session.id)))
+    .set((
+      schema::sessions::created_at.eq(0),
+      schema::sessions::accessed_at.eq(0),
+    ))
+    .execute(&mut conn)
+    .unwrap();
+
+  let expired = auth::get_user_session_by_token(&mut conn, &token);
+
+  assert_eq!(expired.err(), Some(EphemerideError::SessionExpired));
+
+  let deleted = auth::get_user_session_by_token(&mut conn, &token);
+
+  assert_eq!(deleted.err(), Some(EphemerideError::SessionNotFound));
+}
+
+#[test]
+fn using_a_session_renews_it() {
+  let mut conn = establish_connection();
+
+  let auth::SessionWithToken { session, token } = create_session(&mut conn);
+
+  diesel::update(schema::sessions::table.filter(schema::sessions::id.eq(&session.id)))
+    .set(schema::sessions::accessed_at.eq(session.accessed_at - 1000))
+    .execute(&mut conn)
+    .unwrap();
+
+  let renewed = auth::get_user_session_by_token(&mut conn, &token).unwrap();
+
+  assert!(renewed.accessed_at >= session.accessed_at);
+  assert!(renewed.expires_at >= session.expires_at);
+}
+
+#[test]
+fn deletes_expired_sessions() {
+  let mut conn = establish_connection();
+
+  let session = create_session(&mut conn).session;
+
+  diesel::update(schema::sessions::table.filter(schema::sessions::id.eq(&session.id)))
+    .set(schema::sessions::expires_at.eq(0))
+    .execute(&mut conn)
+    .unwrap();
+
+  let purged = auth::delete_expired_sessions(&mut conn);
+
+  assert!(purged.unwrap() > 0);
+
+  let found = auth::get_all_user_sessions(&mut conn, &session.user_id).unwrap();
+
+  assert!(found.is_empty());
+}
+
+#[test]
+fn cannot_delete_another_users_session() {
+  let mut conn = establish_connection();
+
+  let session = create_session(&mut conn).session;
+  let other = create_session(&mut conn);
+
+  let deleted = auth::delete_user_session(&mut conn, &other.session.id, &session.user_id);
+
+  assert!(!deleted.unwrap());
+
+  let found = auth::get_user_session_by_token(&mut conn, &other.token);
+
+  assert!(found.is_ok());
+}
+
+#[test]
+fn deletes_other_sessions() {
+  let mut conn = establish_connection();
+
+  let session = create_session(&mut conn).session;
+  let email = user::get_user(&mut conn, &session.user_id).unwrap().email;
+  log_in(&mut conn, &email);
+  log_in(&mut conn, &email);
+
+  let deleted = auth::delete_other_user_sessions(&mut conn, &session.user_id, &session.id);
+
+  assert_eq!(deleted.unwrap(), 2);
+
+  let remaining = auth::get_all_user_sessions(&mut conn, &session.user_id).unwrap();
+
+  assert_eq!(remaining.len(), 1);
+  assert_eq!(remaining[0].id, session.id);
+}
+
+#[test]
+fn stores_only_a_hash_of_the_session_token() {
+  let mut conn = establish_connection();
+
+  let auth::SessionWithToken { session, token } = create_session(&mut conn);
+
+  assert_ne!(session.token_hash, token);
+  assert_ne!(session.id, token);
+
+  let stored = schema::sessions::table
+    .filter(schema::sessions::token_hash.eq(&token))
+    .count()
+    .get_result::<i64>(&mut conn)
+    .unwrap();
+
+  assert_eq!(stored, 0);
+
+  let by_id = auth::get_user_session_by_token(&mut conn, &session.id);
+
+  assert_eq!(by_id.err(), Some(EphemerideError::SessionNotFound));
+
+  let by_token = auth::get_user_session_by_token(&mut conn, &token);
+
+  assert_eq!(by_token.unwrap().id, session.id);
+}
+
+#[test]
+fn login_errors_do_not_reveal_accounts() {
+  let mut conn = establish_connection();
+
+  let session = create_session(&mut conn).session;
+  let email = user::get_user(&mut conn, &session.user_id).unwrap().email;
+
+  let log_in = |conn: &mut PgConnection, email: &str, password: &str| {
+    auth::create_user_session(
+      conn,
+      auth::UserCredentials {
+        email: email.to_string(),
+        password: password.to_string(),
+        totp_code: None,
+      },
+      auth::SessionMetadata {
+        ip_address: "SYSTEM".to_string(),
+        user_agent: "SYSTEM".to_string(),
+      },
+    )
+  };
+
+  let wrong_password = log_in(&mut conn, &email, "wrong password");
+  let unknown_email = log_in(
+    &mut conn,
+    &format!("{}@example.com", Uuid::new_v4()),
+    "password",
+  );
+
+  assert_eq!(
+    wrong_password.err(),
+    Some(EphemerideError::InvalidCredentials)
+  );
+  assert_eq!(
+    unknown_email.err(),
+    Some(EphemerideError::InvalidCredentials)
+  );
+}
+
+#[test]
+fn login_upgrades_legacy_password_hash() {
+  let mut conn = establish_connection();
+
+  let session = create_session(&mut conn).session;
+  let email = user::get_user(&mut conn, &session.user_id).unwrap().email;
+
+  let legacy_hash = bcrypt::hash("password", 4).unwrap();
+  user::update_password_hash(&mut conn, &session.user_id, &legacy_hash).unwrap();
+
+  log_in(&mut conn, &email);
+
+  let upgraded_hash = user::get_password_hash(&mut conn, &session.user_id).unwrap();
+
+  assert!(upgraded_hash.starts_with("$argon2id$"));
+
+  log_in(&mut conn, &email);
+
+  assert_eq!(
+    user::get_password_hash(&mut conn, &session.user_id).unwrap(),
+    upgraded_hash
+  );
+}
+
+#[test]
+fn update_password_requires_current_password() {
+  let mut conn = establish_connection();
+
+  let session = create_session(&mut conn).session;
+
+  let wrong_current = user::update_password(
+    &mut conn,
+    &session.user_id,
+    &session.id,
+    user::UpdatePassword {
+      current_password: "wrong password".to_string(),
+      password: "new password".to_string(),
+    },
+    &metadata(),
+  );
+
+  assert_eq!(wrong_current.err(), Some(EphemerideError::InvalidPassword));
+
+  let too_short = user::update_password(
+    &mut conn,
+    &session.user_id,
+    &session.id,
+    user::UpdatePassword {
+      current_password: "password".to_string(),
+      password: "short".to_string(),
+    },
+    &metadata(),
+  );
+
+  assert_eq!(too_short.err(), Some(EphemerideError::BadRequest));
+
+  assert!(user::verify_password(&mut conn, &session.user_id, "password").is_ok());
+}
+
+#[test]
+fn update_password_revokes_other_sessions() {
+  let mut conn = establish_connection();
+
+  let current = create_session(&mut conn).session;
+  let email = user::get_user(&mut conn, &current.user_id).unwrap().email;
+  let other = log_in(&mut conn, &email);
+
+  let before = user::get_user(&mut conn, &current.user_id).unwrap();
+
+  let updated = user::update_password(
+    &mut conn,
+    &current.user_id,
+    &current.id,
+    user::UpdatePassword {
+      current_password: "password".to_string(),
+      password: "new password".to_string(),
+    },
+    &metadata(),
+  );
+
+  assert_eq!(updated, Ok(true));
+
+  let sessions = auth::get_all_user_sessions(&mut conn, &current.user_id).unwrap();
+
+  assert_eq!(sessions.len(), 1);
+  assert_eq!(sessions[0].id, current.id);
+  assert_eq!(
+    auth::get_user_session_by_token(&mut conn, &other.token).err(),
+    Some(EphemerideError::SessionNotFound)
+  );
+
+  let after = user::get_user(&mut conn, &current.user_id).unwrap();
+
+  assert!(after.password_changed_at >= before.password_changed_at);
+  assert!(user::verify_password(&mut conn, &current.user_id, "new password").is_ok());
+}
+
+fn credentials(email: &str) -> auth::UserCredentials {
+  auth::UserCredentials {
+    email: email.to_string(),
+    password: "password".to_string(),
+    totp_code: None,
+  }
+}
+
+fn metadata() -> auth::SessionMetadata {
+  auth::SessionMetadata {
+    ip_address: "SYSTEM".to_string(),
+    user_agent: "SYSTEM".to_string(),
+  }
+}
+
+#[test]
+fn schedule_deletion_requires_password() {
+  let mut conn = establish_connection();
+
+  let auth::SessionWithToken { session, token } = create_session(&mut conn);
+
+  let scheduled = user::schedule_user_deletion(
+    &mut conn,
+    &session.user_id,
+    user::DeleteUser {
+      password: "wrong password".to_string(),
+    },
+  );
+
+  assert_eq!(scheduled.err(), Some(EphemerideError::InvalidPassword));
+  assert_eq!(
+    user::is_user_pending_deletion(&mut conn, &session.user_id),
+    Ok(false)
+  );
+  assert!(auth::get_user_session_by_token(&mut conn, &token).is_ok());
+}
+
+#[test]
+fn scheduled_deletion_signs_out_and_can_be_restored() {
+  let mut conn = establish_connection();
+
+  let auth::SessionWithToken { session, token } = create_session(&mut conn);
+  let email = user::get_user(&mut conn, &session.user_id).unwrap().email;
+
+  let scheduled = user::schedule_user_deletion(
+    &mut conn,
+    &session.user_id,
+    user::DeleteUser {
+      password: "password".to_string(),
+    },
+  )
+  .unwrap();
+
+  assert!(scheduled.scheduled_deletion_at > session.created_at);
+  assert_eq!(
+    user::get_user(&mut conn, &session.user_id)
+      .unwrap()
+      .scheduled_deletion_at,
+    Some(scheduled.scheduled_deletion_at)
+  );
+  assert_eq!(
+    auth::get_user_session_by_token(&mut conn, &token).err(),
+    Some(EphemerideError::SessionNotFound)
+  );
+
+  assert_eq!(
+    auth::create_user_session(&mut conn, credentials(&email), metadata()).err(),
+    Some(EphemerideError::AccountPendingDeletion)
+  );
+
+  // restoring needs the right password like any other login
+  assert_eq!(
+    auth::restore_user_session(
+      &mut conn,
+      auth::UserCredentials {
+        password: "wrong password".to_string(),
+        ..credentials(&email)
+      },
+      metadata()
+    )
+    .err(),
+    Some(EphemerideError::InvalidCredentials)
+  );
+
+  let restored = auth::restore_user_session(&mut conn, credentials(&email), metadata()).unwrap();
+
+  assert_eq!(restored.session.user_id, session.user_id);
+  assert_eq!(
+    user::is_user_pending_deletion(&mut conn, &session.user_id),
+    Ok(false)
+  );
+  assert!(auth::create_user_session(&mut conn, credentials(&email), metadata()).is_ok());
+}
+
+#[test]
+fn purges_users_once_the_grace_period_is_over() {
+  let mut conn = establish_connection();
+
+  let pending = create_session(&mut conn).session;
+  let expired = create_session(&mut conn).session;
+
+  for user_id in [&pending.user_id, &expired.user_id] {
+    user::schedule_user_deletion(
+      &mut conn,
+      user_id,
+      user::DeleteUser {
+        password: "password".to_string(),
+      },
+    )
+    .unwrap();
+  }
+
+  diesel::update(schema::users::table.filter(schema::users::id.eq(&expired.user_id)))
+    .set(schema::users::scheduled_deletion_at.eq(Some(0)))
+    .execute(&mut conn)
+    .unwrap();
+
+  assert!(user::purge_deleted_users(&mut conn).unwrap() >= 1);
+
+  assert_eq!(
+    user::get_user(&mut conn, &expired.user_id).err(),
+    Some(EphemerideError::UserNotFound)
+  );
+  assert!(user::get_user(&mut conn, &pending.user_id).is_ok());
+}
+
+fn log_in_with(conn: &mut PgConnection, email: &str, user_agent: &str) -> auth::SessionWithToken {
+  auth::create_user_session(
+    conn,
+    credentials(email),
+    auth::session_metadata(&Request::builder().header("user-agent", user_agent).finish()),
+  )
+  .unwrap()
+}
+
+const CHROME_ON_WINDOWS: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36";
+const FIREFOX_ON_LINUX: &str =
+  "Mozilla/5.0 (X11; Linux x86_64; rv:121.0) Gecko/20100101 Firefox/121.0";
+
+#[test]
+fn sessions_have_device_info() {
+  let mut conn = establish_connection();
+
+  let user_id = create_session(&mut conn).session.user_id;
+  let email = user::get_user(&mut conn, &user_id).unwrap().email;
+  let session = log_in_with(&mut conn, &email, CHROME_ON_WINDOWS).session;
+
+  assert_eq!(session.browser, "Chrome");
+  assert_eq!(session.os, "Windows 10");
+  assert_eq!(session.device_type, "desktop");
+  assert_eq!(session.name, None);
+}
+
+#[test]
+fn flags_sessions_from_new_devices() {
+  let mut conn = establish_connection();
+
+  let random_name = Uuid::new_v4().to_string();
+  let email = format!("{random_name}@example.com");
+  user::create_user(
+    &mut conn,
+    user::CreateUser {
+      name: random_name,
+      email: email.clone(),
+      password: "password".to_string(),
+      invite: None,
+    },
+  )
+  .unwrap();
+
+  // the first device an account is used from isn't new to anyone
+  let first = log_in_with(&mut conn, &email, CHROME_ON_WINDOWS).session;
+  assert!(!first.new_device);
+
+  // a newer version of the same browser is the same device
+  let updated = log_in_with(
+    &mut conn,
+    &email,
+    &CHROME_ON_WINDOWS.replace("Chrome/120", "Chrome/121"),
+  )
+  .session;
+  assert!(!updated.new_device);
+
+  let other = log_in_with(&mut conn, &email, FIREFOX_ON_LINUX).session;
+  assert!(other.new_device);
+
+  // devices are remembered after their sessions are gone
+  auth::delete_all_user_sessions(&mut conn, &other.user_id).unwrap();
+  let again = log_in_with(&mut conn, &email, FIREFOX_ON_LINUX).session;
+  assert!(!again.new_device);
+}
+
+#[test]
+fn truncates_long_user_agents() {
+  let mut conn = establish_connection();
+
+  let user_id = create_session(&mut conn).session.user_id;
+  let email = user::get_user(&mut conn, &user_id).unwrap().email;
+  let session = log_in_with(&mut conn, &email, &"a".repeat(5000)).session;
+
+  assert_eq!(session.user_agent.len(), 1023);
+}
+
+#[test]
+fn renames_a_session() {
+  let mut conn = establish_connection();
+
+  let session = create_session(&mut conn).session;
+  let other_user = create_session(&mut conn).session;
+
+  let rename = |name: Option<&str>| auth::RenameSession {
+    name: name.map(|name| name.to_string()),
+  };
+
+  assert_eq!(
+    auth::rename_user_session(
+      &mut conn,
+      &session.id,
+      &session.user_id,
+      rename(Some("work laptop"))
+    ),
+    Ok(true)
+  );
+  let sessions = auth::get_all_user_sessions(&mut conn, &session.user_id).unwrap();
+  assert_eq!(sessions[0].name.as_deref(), Some("work laptop"));
+
+  assert_eq!(
+    auth::rename_user_session(&mut conn, &session.id, &session.user_id, rename(Some(""))),
+    Err(EphemerideError::BadRequest)
+  );
+  assert_eq!(
+    auth::rename_user_session(
+      &mut conn,
+      &session.id,
+      &other_user.user_id,
+      rename(Some("not yours"))
+    ),
+    Ok(false)
+  );
+
+  assert_eq!(
+    auth::rename_user_session(&mut conn, &session.id, &session.user_id, rename(None)),
+    Ok(true)
+  );
+  let sessions = auth::get_all_user_sessions(&mut conn, &session.user_id).unwrap();
+  assert_eq!(sessions[0].name, None);
+}
@@ -23,7 +23,11 @@ import {
   sortCategories,
   sortEntries,
 } from '$lib/utils/log'
-import { useUserStore, type UserState } from './userStore.svelte'
+import {
+  authFetch,
+  useUserStore,
+  type UserState,
+} from './userStore.svelte'
 
 let userStore: UserState | null = null
 
@@ -71,9 +75,7 @@ let loaded: boolean = $state(false)
 let fetchedAt: number = $state(0)
 
 const fetchCategories = async () => {
-  await fetch(`${env.PUBLIC_VITE_API_URL}/v1/user/categories`, {
-    headers: { Authorization: `Bearer ${userStore?.sessionId}` },
-  })
+  await authFetch(`${env.PUBLIC_VITE_API_URL}/v1/user/categories`)
     .then(res => {
       if (!res.ok) {
         throw new Error('Failed to fetch categories')
@@ -92,7 +94,6 @@ const fetchEntries = async (options?: FetchEntriesOptions) => {
   const { firstDate, lastDate } = monthDateRange()
   if (userStore?.sessionId) {
     const data = await getEntries(
-      userStore.sessionId,
       // if no options provided, fetch current month
       options || {
         from_date: firstDate,
@@ -115,7 +116,7 @@ const fetchEntries = async (options?: FetchEntriesOptions) => {
 }
 
 const fetchEntry = async (date: string) => {
-  await getEntries(userStore?.sessionId || '', {
+  await getEntries({
     from_date: date,
     to_date: date,
   }).then(data => {
@@ -171,11 +172,10 @@ const getEntry = (date: string): Entry | null => {
 }
 
 const createEntry = async (entry: NewEntry): Promise<Entry | null> => {
-  return fetch(`${env.PUBLIC_VITE_API_URL}/v1/entry`, {
+  return authFetch(`${env.PUBLIC_VITE_API_URL}/v1/entry`, {
     method: 'POST',
     headers: {
       'Content-Type': 'application/json',
-      Authorization: `Bearer ${userStore?.sessionId}`,
     },
     body: JSON.stringify(entry),
   })
@@ -201,11 +201,10 @@ const createEntry = async (entry: NewEntry): Promise<Entry | null> => {
 }
 
 const updateEntry = async (entry: EditEntry): Promise<Entry | null> => {
-  return fetch(`${env.PUBLIC_VITE_API_URL}/v1/entry/${entry.id}`, {
+  return authFetch(`${env.PUBLIC_VITE_API_URL}/v1/entry/${entry.id}`, {
     method: 'PATCH',
     headers: {
       'Content-Type': 'application/json',
-      Authorization: `Bearer ${userStore?.sessionId}`,
     },
     body: JSON.stringify(entry),
   })
@@ -233,11 +232,8 @@ const updateEntry = async (entry: EditEntry): Promise<Entry | null> => {
 }
 
 const deleteEntry = async (id: string): Promise<boolean | null> => {
-  return fetch(`${env.PUBLIC_VITE_API_URL}/v1/entry/${id}`, {
+  return authFetch(`${env.PUBLIC_VITE_API_URL}/v1/entry/${id}`, {
     method: 'DELETE',
-    headers: {
-      Authorization: `Bearer ${userStore?.sessionId}`,
-    },
   })
     .then(res => {
       if (!res.ok) {
@@ -266,11 +262,10 @@ const deleteEntry = async (id: string): Promise<boolean | null> => {
 const createCategory = async (
   category: NewCategory,
 ): Promise<CategoryWithTags | null> => {
-  return fetch(`${env.PUBLIC_VITE_API_URL}/v1/category`, {
+  return authFetch(`${env.PUBLIC_VITE_API_URL}/v1/category`, {
     method: 'POST',
     headers: {
       'Content-Type': 'application/json',
-      Authorization: `Bearer ${userStore?.sessionId}`,
     },
     body: JSON.stringify(category),
   })
@@ -295,11 +290,10 @@ const createCategory = async (
 const updateCategory = async (
   category: EditCategory,
 ): Promise<CategoryWithTags | null> => {
-  return fetch(`${env.PUBLIC_VITE_API_URL}/v1/category/${category.id}`, {
+  return authFetch(`${env.PUBLIC_VITE_API_URL}/v1/category/${category.id}`, {
     method: 'PATCH',
     headers: {
       'Content-Type': 'application/json',
-      Authorization: `Bearer ${userStore?.sessionId}`,
     },
     body: JSON.stringify({ name: category.name }),
   })
@@ -330,11 +324,8 @@ const updateCategory = async (
 }
 
 const deleteCategory = async (id: string): Promise<boolean | null> => {
-  return fetch(`${env.PUBLIC_VITE_API_URL}/v1/category/${id}`, {
+  return authFetch(`${env.PUBLIC_VITE_API_URL}/v1/category/${id}`, {
     method: 'DELETE',
-    headers: {
-      Authorization: `Bearer ${userStore?.sessionId}`,
-    },
   })
     .then(res => {
       if (!res.ok) {
@@ -360,11 +351,10 @@ const deleteCategory = async (id: string): Promise<boolean | null> => {
 }
 
 const createTag = async (tag: NewTag): Promise<Tag | null> => {
-  return fetch(`${env.PUBLIC_VITE_API_URL}/v1/tag`, {
+  return authFetch(`${env.PUBLIC_VITE_API_URL}/v1/tag`, {
     method: 'POST',
     headers: {
       'Content-Type': 'application/json',
-      Authorization: `Bearer ${userStore?.sessionId}`,
     },
     body: JSON.stringify(tag),
   })
@@ -390,11 +380,10 @@ const createTag = async (tag: NewTag): Promise<Tag | null> => {
 }
 
 const updateTag = async (tag: EditTag): Promise<Tag | null> => {
-  return fetch(`${env.PUBLIC_VITE_API_URL}/v1/tag/${tag.id}`, {
+  return authFetch(`${env.PUBLIC_VITE_API_URL}/v1/tag/${tag.id}`, {
     method: 'PATCH',
     headers: {
       'Content-Type': 'application/json',
-      Authorization: `Bearer ${userStore?.sessionId}`,
     },
     body: JSON.stringify({
       name: tag.name,
@@ -426,11 +415,8 @@ const updateTag = async (tag: EditTag): Promise<Tag | null> => {
 }
 
 const deleteTag = async (id: string): Promise<boolean | null> => {
-  return fetch(`${env.PUBLIC_VITE_API_URL}/v1/tag/${id}`, {
+  return authFetch(`${env.PUBLIC_VITE_API_URL}/v1/tag/${id}`, {
     method: 'DELETE',
-    headers: {
-      Authorization: `Bearer ${userStore?.sessionId}`,
-    },
   })
     .then(res => {
       if (!res.ok) {
@@ -1,5 +1,11 @@
 import { env } from '$env/dynamic/public'
-import type { EditUserDetails, UserDetails } from '$lib/types/user'
+import type {
+  AuthMode,
+  EditUserDetails,
+  Session,
+  SessionCredentials,
+  UserDetails,
+} from '$lib/types/user'
 import { goto } from '$app/navigation'
 import { useDataStore, type DataState } from './dataStore.svelte'
 
@@ -7,20 +13,124 @@ let dataStore: DataState | null = null
 
 export type UserState = {
   sessionId: string | null
+  credentials: SessionCredentials | null
   userDetails: UserDetails | null
   logOut: () => void
-  logIn: (sessionId: string) => void
+  logIn: (session: Session, mode: AuthMode) => void
   updateUserDetails: (
     details: Partial<UserDetails>,
   ) => Promise<UserDetails | null>
-  deleteAccount: () => Promise<boolean>
+  deleteAccount: (password: string) => Promise<number | null>
 }
 
 let sessionId: string | null = $state(null)
+let credentials: SessionCredentials | null = $state(null)
 let userDetails: UserDetails | null = $state(null)
 
+// adds whatever the current session needs to a request, cookies are sent
+// with every request but only count as credentials in cookie mode, and
+// anything that isn't a read needs the csrf token along with them
+const withCredentials = (init: RequestInit = {}): RequestInit => {
+  const headers = new Headers(init.headers)
+  const method = (init.method || 'GET').toUpperCase()
+
+  if (credentials?.mode === 'token') {
+    headers.set('Authorization', `Bearer ${credentials.token}`)
+  } else if (credentials?.mode === 'access_token') {
+    headers.set('Authorization', `Bearer ${credentials.accessToken}`)
+  } else if (
+    credentials?.mode === 'cookie' &&
+    !['GET', 'HEAD', 'OPTIONS'].includes(method)
+  ) {
+    headers.set('X-CSRF-Token', credentials.csrfToken)
+  }
+
+  return { ...init, headers, credentials: 'include' }
+}
+
+// access tokens only last a few minutes, when one runs out the refresh
+// token is traded for a new pair. every refresh token can only be used
+// once, so requests that fail at the same time wait on the same refresh
+let refreshing: Promise<boolean> | null = null
+
+const refreshSession = (): Promise<boolean> => {
+  if (!refreshing) {
+    refreshing = requestRefresh().finally(() => {
+      refreshing = null
+    })
+  }
+  return refreshing
+}
+
+const requestRefresh = async (): Promise<boolean> => {
+  if (credentials?.mode !== 'access_token') {
+    return false
+  }
+
+  return fetch(`${env.PUBLIC_VITE_API_URL}/v1/auth/refresh`, {
+    method: 'POST',
+    headers: {
+      'Content-Type': 'application/json',
+    },
+    body: JSON.stringify({ refresh_token: credentials.refreshToken }),
+  })
+    .then(res => {
+      if (!res.ok) {
+        throw new Error('Failed to refresh session')
+      }
+      return res.json()
+    })
+    .then((data: Session) => {
+      credentials = {
+        mode: 'access_token',
+        accessToken: data.access_token || '',
+        refreshToken: data.refresh_token || '',
+      }
+      return true
+    })
+    .catch(err => {
+      // the session is over, or the refresh token was used somewhere else
+      console.error('Error refreshing session:', err)
+      logOut()
+      return false
+    })
+}
+
+// fetch for anything that needs the user to be logged in, a request made
+// with an expired access token is sent again once the session is refreshed
+export const authFetch = async (
+  input: string | URL,
+  init?: RequestInit,
+): Promise<Response> => {
+  const res = await fetch(input, withCredentials(init))
+
+  if (res.status === 401 && credentials?.mode === 'access_token') {
+    const error = await res
+      .clone()
+      .json()
+      .catch(() => null)
+    if (error?.code === 'AccessTokenExpired' && (await refreshSession())) {
+      return fetch(input, withCredentials(init))
+    }
+  }
+
+  return res
+}
+
 const logOut = () => {
+  // the session has to be ended on the server for the cookies to be
+  // cleared, it may already be gone so the result doesn't matter
+  if (credentials) {
+    fetch(
+      `${env.PUBLIC_VITE_API_URL}/v1/auth/logout`,
+      withCredentials({ method: 'POST' }),
+    ).catch(err => {
+      console.error('Error logging out:', err)
+    })
+  }
+
   sessionId = null
+  credentials = null
   userDetails = null
   if (dataStore) {
     dataStore.deleteData()
@@ -28,8 +138,19 @@ const logOut = () => {
   goto('/')
 }
 
-const logIn = (newSessionId: string) => {
-  sessionId = newSessionId
+const logIn = (session: Session, mode: AuthMode) => {
+  if (mode === 'cookie') {
+    credentials = { mode, csrfToken: session.csrf_token || '' }
+  } else if (mode === 'access_token') {
+    credentials = {
+      mode,
+      accessToken: session.access_token || '',
+      refreshToken: session.refresh_token || '',
+    }
+  } else {
+    credentials = { mode, token: session.token || '' }
+  }
+  sessionId = session.id
   if (dataStore) {
     dataStore.fetchCategories()
   }
@@ -43,11 +164,10 @@ const updateUserDetails = async (details: Partial<UserDetails>) => {
       name: updatedDetails.name,
       email: updatedDetails.email,
     }
-    const res = await fetch(`${env.PUBLIC_VITE_API_URL}/v1/user`, {
+    const res = await authFetch(`${env.PUBLIC_VITE_API_URL}/v1/user`, {
       method: 'PATCH',
       headers: {
         'Content-Type': 'application/json',
-        Authorization: `Bearer ${sessionId}`,
       },
       body: JSON.stringify(body),
     })
@@ -74,33 +194,34 @@ const updateUserDetails = async (details: Partial<UserDetails>) => {
   return null
 }
 
-const deleteAccount = async (): Promise<boolean> => {
+// every session is logged out by the deletion, the caller logs out once it
+// has shown when the account will be deleted
+const deleteAccount = async (password: string): Promise<number | null> => {
   if (userDetails) {
-    const res = await fetch(`${env.PUBLIC_VITE_API_URL}/v1/user`, {
+    const res = await authFetch(`${env.PUBLIC_VITE_API_URL}/v1/user`, {
       method: 'DELETE',
       headers: {
-        Authorization: `Bearer ${sessionId}`,
+        'Content-Type': 'application/json',
       },
+      body: JSON.stringify({ password }),
     })
-      .then(res => {
+      .then(async res => {
         if (!res.ok) {
           throw new Error('Failed to delete user account')
         }
-        if (res.status === 204) {
-          return true
+        if (res.status === 202) {
+          const data = await res.json()
+          return data.scheduled_deletion_at as number
         }
         throw new Error('Unexpected response status')
       })
       .catch(err => {
         console.error('Error deleting user account:', err)
-        return false
+        return null
       })
-    if (res) {
-      logOut()
-    }
     return res
   }
-  return false
+  return null
 }
 
 export const useUserStore: () => UserState = () => {
@@ -112,10 +233,12 @@ export const useUserStore: () => UserState = () => {
 
   $effect(() => {
     if (sessionId) {
-      fetch(`${env.PUBLIC_VITE_API_URL}/v1/user`, {
-        headers: { Authorization: `Bearer ${sessionId}` },
-      })
+      authFetch(`${env.PUBLIC_VITE_API_URL}/v1/user`)
         .then(res => {
+          // the session expired or was signed out somewhere else
+          if (res.status === 401) {
+            logOut()
+          }
           if (!res.ok) {
             throw new Error('Failed to fetch user details')
           }
@@ -137,6 +260,12 @@ export const useUserStore: () => UserState = () => {
     set sessionId(value) {
       sessionId = value
     },
+    get credentials() {
+      return credentials
+    },
+    set credentials(value) {
+      credentials = value
+    },
     get userDetails() {
       return userDetails
     },
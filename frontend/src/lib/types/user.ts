@@ -4,6 +4,11 @@ export type UserDetails = {
   name: string
   email: string
   invite?: string
+  email_verified: boolean
+  password_changed_at: string
+  is_admin: boolean
+  disabled_at?: string
+  scheduled_deletion_at?: string
 }
 
 export type EditUserDetails = {
@@ -18,4 +23,109 @@ export type Session = {
   accessed_at: string
   ip_address: string
   user_agent: string
+  expires_at: string
+  name?: string
+  browser: string
+  os: string
+  device_type: 'desktop' | 'mobile' | 'tablet' | 'bot' | 'unknown'
+  new_device: boolean
+  token?: string
+  csrf_token?: string
+  access_token?: string
+  access_token_expires_at?: number
+  refresh_token?: string
+  current?: boolean
+}
+
+export type AuthConfig = {
+  invite_required: boolean
+  email_verification_required: boolean
+  oidc_enabled: boolean
+  session_cookies: boolean
+  access_tokens: boolean
+}
+
+// how the backend hands out sessions, see GET /v1/auth/config
+export type AuthMode = 'token' | 'cookie' | 'access_token'
+
+// in cookie mode the session token is an HttpOnly cookie the browser sends
+// by itself, only the csrf token that has to go along with it is kept
+export type SessionCredentials =
+  | { mode: 'token'; token: string }
+  | { mode: 'cookie'; csrfToken: string }
+  | { mode: 'access_token'; accessToken: string; refreshToken: string }
+
+export type UserIdentity = {
+  id: string
+  user_id: string
+  issuer: string
+  subject: string
+  email?: string
+  created_at: string
+  last_used_at: string
+}
+
+export type AccessTokenScope =
+  | 'entries:read'
+  | 'entries:write'
+  | 'tags:read'
+  | 'tags:write'
+  | 'user:read'
+
+export type PersonalAccessToken = {
+  id: string
+  user_id: string
+  name: string
+  scopes: AccessTokenScope[]
+  created_at: string
+  expires_at?: string
+  last_used_at?: string
+  token?: string
+}
+
+export type Invite = {
+  id: string
+  created_at: string
+  code: string
+  expires_at?: string
+  max_uses: number
+  use_count: number
+  created_by?: string
+  note?: string
+  user_invite: boolean
+}
+
+export type InvitedUser = {
+  id: string
+  name: string
+  created_at: string
+}
+
+export type UserInvites = {
+  quota: number
+  remaining: number
+  invites: (Invite & { redeemed_by: InvitedUser[] })[]
+}
+
+export type SecurityEventType =
+  | 'login'
+  | 'password_changed'
+  | 'password_reset'
+  | 'name_changed'
+  | 'email_change_requested'
+  | 'email_changed'
+  | 'session_revoked'
+  | 'other_sessions_revoked'
+  | 'invite_created'
+  | 'invite_redeemed'
+  | 'refresh_token_reused'
+
+export type SecurityEvent = {
+  id: string
+  user_id: string
+  event: SecurityEventType
+  created_at: string
+  ip_address?: string
+  user_agent?: string
+  details?: string
 }
@@ -1,7 +1,8 @@
 import { env } from '$env/dynamic/public'
+import { authFetch } from '$lib/store/userStore.svelte'
 import type { Entry } from '$lib/types/log'
 import type { Paginated } from '$lib/types/paginated'
-import type { Session } from '$lib/types/user'
+import type { AuthConfig, AuthMode, Session } from '$lib/types/user'
 
 export type FetchEntriesOptions = {
   from_date?: string
@@ -15,7 +16,6 @@ export type FetchEntriesOptions = {
 }
 
 export const getEntries = async (
-  sessionId: string,
   options?: FetchEntriesOptions,
 ): Promise<Paginated<Entry> | void> => {
   const params = new URLSearchParams()
@@ -49,9 +49,7 @@ export const getEntries = async (
   const url = new URL(`${env.PUBLIC_VITE_API_URL}/v1/entries`)
   url.search = params.toString()
 
-  return fetch(url, {
-    headers: { Authorization: `Bearer ${sessionId}` },
-  })
+  return authFetch(url)
     .then(res => {
       if (!res.ok) {
         throw new Error('Failed to fetch entries')
@@ -66,10 +64,26 @@ export const getEntries = async (
     })
 }
 
-export const getSessions = (sessionId: string) => {
-  return fetch(`${env.PUBLIC_VITE_API_URL}/v1/sessions`, {
-    headers: { Authorization: `Bearer ${sessionId}` },
+export const getAuthConfig = (): Promise<AuthConfig> => {
+  return fetch(`${env.PUBLIC_VITE_API_URL}/v1/auth/config`).then(res => {
+    if (!res.ok) {
+      throw new Error('Failed to fetch auth config')
+    }
+    return res.json()
   })
+}
+
+// access tokens win over session cookies, the same way they do on the
+// backend
+export const authMode = (config: AuthConfig): AuthMode => {
+  if (config.access_tokens) {
+    return 'access_token'
+  }
+  return config.session_cookies ? 'cookie' : 'token'
+}
+
+export const getSessions = () => {
+  return authFetch(`${env.PUBLIC_VITE_API_URL}/v1/sessions`)
     .then(res => {
       if (!res.ok) {
         throw new Error('Failed to fetch sessions')